@@ -0,0 +1,1240 @@
+//! Assembler
+//!
+//! [`Assembler`] turns source in the syntax [`Instruction`] is displayed with back into a rom
+//! loaded at 0x200, along with a [`SymbolMap`] for debuggers:
+//!
+//! ```text
+//! SPEED equ 2             ; constant
+//! start:
+//!     LD V0, 0x12
+//!     LD I, sprite
+//!     CALL draw
+//! loop:
+//!     JP loop
+//! draw:
+//!     DRW V0, V1, 5
+//!     ADD V0, SPEED
+//!     RET
+//! sprite:
+//!     db 0xF0, 0x90, 0x90, 0x90, 0xF0
+//!     dw 0x1234, start + 2
+//! include "font.asm"
+//! ```
+//!
+//! - mnemonics, registers and directives are case insensitive, symbols are not
+//! - numbers are decimal, `0x` hexadecimal or `0b` binary, operands can add and subtract
+//!   numbers, labels and constants
+//! - `db` takes bytes and `"strings"`, `dw` big endian words
+//! - `include` paths are relative to the including file
+//! - `SHR VX` and `SHL VX` without VY shift VX into itself, whichever shift quirk is active
+//!
+//! Symbols can be named like the operand keywords `I`, `DT`, `ST`, `K`, `F`, `HF`, `B`, `R` and
+//! `PITCH`, e.g. `CALL f`, the keyword wins where an instruction accepts both.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use chip8_core::{
+    Instruction,
+    symbols::SymbolMap,
+    types::{Address, Index, SpriteHeight},
+};
+
+use crate::PROGRAM_START;
+
+/// Deepest chain of includes, deeper ones are most likely a cycle through different paths
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Deepest chain of constants defined in terms of other constants
+const MAX_CONSTANT_DEPTH: usize = 32;
+
+/// Mnemonics of all instructions
+const MNEMONICS: [&str; 31] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "PLANE", "SYS", "JP",
+    "CALL", "SE", "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR",
+    "SHL", "RND", "DRW", "SKP", "SKNP",
+];
+
+/// Enum for all possible assembling errors
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    #[error("unexpected character `{0}`")]
+    /// Character that doesn't start any token
+    UnexpectedCharacter(char),
+    #[error("unterminated string")]
+    /// String without its closing quote
+    UnterminatedString,
+    #[error("invalid number `{0}`")]
+    /// Number with invalid digits or too big
+    InvalidNumber(String),
+    #[error("expected {0}")]
+    /// Token missing or in the wrong place
+    Expected(&'static str),
+    #[error("unknown mnemonic `{0}`")]
+    /// Neither an instruction nor a directive
+    UnknownMnemonic(String),
+    #[error("invalid operands for `{0}`")]
+    /// The instruction exists, but not with these operands
+    InvalidOperands(String),
+    #[error("unknown symbol `{0}`")]
+    /// Label or constant that is never defined
+    UnknownSymbol(String),
+    #[error("`{0}` is already defined")]
+    /// Label or constant defined twice
+    DuplicateSymbol(String),
+    #[error("`{0}` is defined in terms of itself")]
+    /// Constants referring to each other in a loop
+    RecursiveConstant(String),
+    #[error("value {value} doesn't fit in {bits} bits")]
+    /// Operand too big for its field
+    OutOfRange {
+        /// Value of the operand
+        value: i64,
+        /// Size of the field
+        bits: u32,
+    },
+    #[error("can't include `{path}`: {message}")]
+    /// Included file can't be read
+    Include {
+        /// Path of the included file
+        path: String,
+        /// Reason it can't be read
+        message: String,
+    },
+    #[error("includes are nested too deeply, `{0}` probably includes itself")]
+    /// Cycle of includes
+    IncludeCycle(String),
+    #[error("program doesn't fit in memory")]
+    /// The program goes past the end of the 64 KiB address space
+    TooLarge,
+}
+
+/// Assembling error with its position in the source
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("{file}:{line}:{column}: {kind}")]
+pub struct AsmError {
+    /// Source file, relative to the main file
+    pub file: String,
+    /// Line, starting at 1
+    pub line: usize,
+    /// Column, starting at 1
+    pub column: usize,
+    /// What went wrong
+    pub kind: AsmErrorKind,
+}
+
+/// Line of a source file
+#[derive(Debug, Clone)]
+struct Location {
+    /// Source file, relative to the main file
+    file: String,
+    /// Line, starting at 1
+    line: usize,
+}
+
+impl Location {
+    /// Error at `column` of this line
+    fn error(&self, column: usize, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            column,
+            kind,
+        }
+    }
+}
+
+/// Piece of a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    /// Name of a mnemonic, register, directive or symbol
+    Ident(String),
+    /// Numeric literal
+    Number(i64),
+    /// Quoted string
+    Str(Vec<u8>),
+    /// `,`
+    Comma,
+    /// `:`
+    Colon,
+    /// `+`
+    Plus,
+    /// `-`
+    Minus,
+    /// `[`
+    LBracket,
+    /// `]`
+    RBracket,
+}
+
+/// Token with the column it starts at
+#[derive(Debug, Clone)]
+struct Token {
+    /// What it is
+    kind: TokenKind,
+    /// Column, starting at 1
+    column: usize,
+}
+
+/// Parse a numeric literal
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// Split a line into tokens, up to its comment
+fn tokenize(line: &str) -> Result<Vec<Token>, (usize, AsmErrorKind)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let single = match c {
+            ',' => Some(TokenKind::Comma),
+            ':' => Some(TokenKind::Colon),
+            '+' => Some(TokenKind::Plus),
+            '-' => Some(TokenKind::Minus),
+            '[' => Some(TokenKind::LBracket),
+            ']' => Some(TokenKind::RBracket),
+            _ => None,
+        };
+
+        if let Some(kind) = single {
+            tokens.push(Token { kind, column });
+            i += 1;
+        } else if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let mut bytes = Vec::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err((column, AsmErrorKind::UnterminatedString)),
+                    Some('"') => break,
+                    Some('\\') if matches!(chars.get(i + 1), Some('"' | '\\')) => {
+                        bytes.push(chars[i + 1] as u8);
+                        i += 2;
+                        continue;
+                    }
+                    Some(c) => {
+                        let mut buffer = [0; 4];
+                        bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+                    }
+                }
+                i += 1;
+            }
+            tokens.push(Token {
+                kind: TokenKind::Str(bytes),
+                column,
+            });
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '_' | '.'))
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let kind = if c.is_ascii_digit() {
+                TokenKind::Number(
+                    parse_number(&text).ok_or((column, AsmErrorKind::InvalidNumber(text)))?,
+                )
+            } else {
+                TokenKind::Ident(text)
+            };
+            tokens.push(Token { kind, column });
+        } else {
+            return Err((column, AsmErrorKind::UnexpectedCharacter(c)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Term of an expression
+#[derive(Debug, Clone)]
+enum Term {
+    /// Literal
+    Number(i64),
+    /// Label or constant, with its column
+    Symbol(String, usize),
+}
+
+/// Sum of terms, each one added or subtracted
+#[derive(Debug, Clone)]
+struct Expr {
+    /// Terms with their sign, true for subtracted ones
+    terms: Vec<(bool, Term)>,
+    /// Column of the first term
+    column: usize,
+}
+
+/// Register or keyword operand that isn't a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keyword {
+    /// `I`
+    I,
+    /// `DT`
+    Dt,
+    /// `ST`
+    St,
+    /// `K`
+    K,
+    /// `F`
+    F,
+    /// `HF`
+    Hf,
+    /// `B`
+    B,
+    /// `R`
+    R,
+    /// `PITCH`
+    Pitch,
+}
+
+impl Keyword {
+    /// Keyword named `name`, case insensitive
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_uppercase().as_str() {
+            "I" => Keyword::I,
+            "DT" => Keyword::Dt,
+            "ST" => Keyword::St,
+            "K" => Keyword::K,
+            "F" => Keyword::F,
+            "HF" => Keyword::Hf,
+            "B" => Keyword::B,
+            "R" => Keyword::R,
+            "PITCH" => Keyword::Pitch,
+            _ => return None,
+        })
+    }
+}
+
+/// Register `VX`, case insensitive
+fn parse_register(name: &str) -> Option<Index> {
+    let digit = name.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    Index::try_new(u8::from_str_radix(digit, 16).ok()?).ok()
+}
+
+/// Operand of an instruction
+#[derive(Debug, Clone)]
+enum Operand {
+    /// `VX`
+    Register(Index),
+    /// Keyword like `I` or `DT`, along with the symbol of the same name
+    Keyword(Keyword, Expr),
+    /// `[I]`
+    Indirect,
+    /// `LONG` followed by a 16-bit address
+    Long(Expr),
+    /// Number, symbol or sum of them
+    Value(Expr),
+}
+
+/// Item of a `db` directive
+#[derive(Debug, Clone)]
+enum Datum {
+    /// One byte
+    Byte(Expr),
+    /// Bytes of a string
+    Str(Vec<u8>),
+}
+
+/// What a line does
+#[derive(Debug, Clone)]
+enum Statement {
+    /// `NAME equ VALUE`
+    Constant(String, usize, Expr),
+    /// `db` directive
+    Bytes(Vec<Datum>),
+    /// `dw` directive
+    Words(Vec<Expr>),
+    /// `include "path"`
+    Include(String, usize),
+    /// Instruction with its mnemonic, the column of the mnemonic and the operands
+    Instruction(String, usize, Vec<Operand>),
+}
+
+impl Statement {
+    /// Bytes the statement adds to the rom
+    fn size(&self) -> usize {
+        match self {
+            Statement::Constant(..) | Statement::Include(..) => 0,
+            Statement::Bytes(data) => data
+                .iter()
+                .map(|datum| match datum {
+                    Datum::Byte(_) => 1,
+                    Datum::Str(bytes) => bytes.len(),
+                })
+                .sum(),
+            Statement::Words(words) => words.len() * 2,
+            Statement::Instruction(_, _, operands) => {
+                if operands
+                    .iter()
+                    .any(|operand| matches!(operand, Operand::Long(_)))
+                {
+                    4
+                } else {
+                    2
+                }
+            }
+        }
+    }
+}
+
+/// Label and statement of a line, both optional
+#[derive(Debug, Default)]
+struct Line {
+    /// Label defined on the line, with its column
+    label: Option<(String, usize)>,
+    /// Directive or instruction
+    statement: Option<Statement>,
+}
+
+/// Tokens of a line being parsed
+struct Parser {
+    /// Tokens of the line
+    tokens: Vec<Token>,
+    /// Index of the next token
+    position: usize,
+    /// Column just past the end of the line, for errors at the end
+    end: usize,
+}
+
+impl Parser {
+    /// Next token, without consuming it
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    /// Column of the next token
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |token| token.column)
+    }
+
+    /// Consume the next token
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Consume the next token if it is `kind`
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == Some(kind) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Error unless the whole line was consumed
+    fn finish(&self) -> Result<(), (usize, AsmErrorKind)> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err((self.column(), AsmErrorKind::Expected("end of line"))),
+        }
+    }
+
+    /// Parse a whole line
+    fn line(&mut self) -> Result<Line, (usize, AsmErrorKind)> {
+        let mut line = Line::default();
+        if let (Some(TokenKind::Ident(name)), Some(TokenKind::Colon)) = (
+            self.peek().cloned(),
+            self.tokens.get(self.position + 1).map(|token| &token.kind),
+        ) {
+            line.label = Some((name, self.column()));
+            self.position += 2;
+        }
+
+        let column = self.column();
+        let Some(token) = self.next() else {
+            return Ok(line);
+        };
+        let TokenKind::Ident(name) = token.kind else {
+            return Err((column, AsmErrorKind::Expected("mnemonic or directive")));
+        };
+
+        let statement = match name.to_ascii_lowercase().as_str() {
+            _ if matches!(self.peek(), Some(TokenKind::Ident(equ)) if equ.eq_ignore_ascii_case("equ")) =>
+            {
+                self.position += 1;
+                Statement::Constant(name, column, self.expr()?)
+            }
+            "db" => {
+                let mut data = Vec::new();
+                loop {
+                    let datum = match self.peek() {
+                        Some(TokenKind::Str(bytes)) => {
+                            let bytes = bytes.clone();
+                            self.position += 1;
+                            Datum::Str(bytes)
+                        }
+                        _ => Datum::Byte(self.expr()?),
+                    };
+                    data.push(datum);
+                    if !self.eat(&TokenKind::Comma) {
+                        break;
+                    }
+                }
+                Statement::Bytes(data)
+            }
+            "dw" => {
+                let mut words = vec![self.expr()?];
+                while self.eat(&TokenKind::Comma) {
+                    words.push(self.expr()?);
+                }
+                Statement::Words(words)
+            }
+            "include" => {
+                let column = self.column();
+                match self.next().map(|token| token.kind) {
+                    Some(TokenKind::Str(path)) => {
+                        Statement::Include(String::from_utf8_lossy(&path).into_owned(), column)
+                    }
+                    _ => return Err((column, AsmErrorKind::Expected("quoted path"))),
+                }
+            }
+            _ => {
+                let mut operands = Vec::new();
+                if self.peek().is_some() {
+                    operands.push(self.operand()?);
+                    while self.eat(&TokenKind::Comma) {
+                        operands.push(self.operand()?);
+                    }
+                }
+                Statement::Instruction(name, column, operands)
+            }
+        };
+
+        self.finish()?;
+        line.statement = Some(statement);
+        Ok(line)
+    }
+
+    /// Parse an operand of an instruction
+    fn operand(&mut self) -> Result<Operand, (usize, AsmErrorKind)> {
+        if self.eat(&TokenKind::LBracket) {
+            let column = self.column();
+            match self.next().map(|token| token.kind) {
+                Some(TokenKind::Ident(name)) if name.eq_ignore_ascii_case("i") => {}
+                _ => return Err((column, AsmErrorKind::Expected("`I`"))),
+            }
+            let column = self.column();
+            if !self.eat(&TokenKind::RBracket) {
+                return Err((column, AsmErrorKind::Expected("`]`")));
+            }
+            return Ok(Operand::Indirect);
+        }
+
+        if let Some(TokenKind::Ident(name)) = self.peek() {
+            if let Some(register) = parse_register(name) {
+                self.position += 1;
+                return Ok(Operand::Register(register));
+            }
+            let sum = matches!(
+                self.tokens.get(self.position + 1).map(|token| &token.kind),
+                Some(TokenKind::Plus | TokenKind::Minus)
+            );
+            if let Some(keyword) = Keyword::parse(name).filter(|_| !sum) {
+                return Ok(Operand::Keyword(keyword, self.expr()?));
+            }
+            if name.eq_ignore_ascii_case("long") {
+                self.position += 1;
+                return Ok(Operand::Long(self.expr()?));
+            }
+        }
+
+        Ok(Operand::Value(self.expr()?))
+    }
+
+    /// Parse a sum of numbers and symbols
+    fn expr(&mut self) -> Result<Expr, (usize, AsmErrorKind)> {
+        let column = self.column();
+        let mut terms = Vec::new();
+        let mut negative = self.eat(&TokenKind::Minus);
+        if !negative {
+            self.eat(&TokenKind::Plus);
+        }
+
+        loop {
+            let column = self.column();
+            let term = match self.next().map(|token| token.kind) {
+                Some(TokenKind::Number(value)) => Term::Number(value),
+                Some(TokenKind::Ident(name)) if parse_register(&name).is_none() => {
+                    Term::Symbol(name, column)
+                }
+                _ => return Err((column, AsmErrorKind::Expected("number or symbol"))),
+            };
+            terms.push((negative, term));
+
+            if self.eat(&TokenKind::Plus) {
+                negative = false;
+            } else if self.eat(&TokenKind::Minus) {
+                negative = true;
+            } else {
+                break;
+            }
+        }
+
+        Ok(Expr { terms, column })
+    }
+}
+
+/// Parse one source line
+fn parse_line(text: &str) -> Result<Line, (usize, AsmErrorKind)> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        position: 0,
+        end: text.chars().count() + 1,
+    };
+    parser.line()
+}
+
+/// What a symbol stands for
+#[derive(Debug, Clone)]
+enum Symbol {
+    /// Address of a label
+    Label(u16),
+    /// Value of a constant, with the line it is defined on
+    Constant(Expr, Location),
+}
+
+/// Statement producing bytes, placed at its address
+#[derive(Debug)]
+struct Placed {
+    /// Line of the statement
+    location: Location,
+    /// Address of its first byte
+    address: u16,
+    /// Data or instruction
+    statement: Statement,
+}
+
+/// Assembled rom and its symbols
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// Bytes to load at 0x200
+    rom: Vec<u8>,
+    /// Labels and the source line of every instruction
+    symbols: SymbolMap,
+}
+
+impl Assembly {
+    /// Bundle a compiled rom with its symbols
+    pub(crate) fn new(rom: Vec<u8>, symbols: SymbolMap) -> Self {
+        Self { rom, symbols }
+    }
+
+    /// Get the rom
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Get the symbol map, its source paths are relative to the main file
+    pub fn symbols(&self) -> &SymbolMap {
+        &self.symbols
+    }
+}
+
+/// Symbols and statements collected by the first pass
+#[derive(Debug, Default)]
+struct Program {
+    /// Labels and constants by name
+    symbols: HashMap<String, Symbol>,
+    /// Labels in definition order, for the symbol map
+    labels: Vec<(String, u16)>,
+    /// Statements producing bytes
+    placed: Vec<Placed>,
+    /// Address of the next byte
+    address: usize,
+}
+
+impl Program {
+    /// Define a label or constant
+    fn define(
+        &mut self,
+        name: String,
+        symbol: Symbol,
+        location: &Location,
+        column: usize,
+    ) -> Result<(), AsmError> {
+        if self.symbols.contains_key(&name) {
+            return Err(location.error(column, AsmErrorKind::DuplicateSymbol(name)));
+        }
+        if let Symbol::Label(address) = symbol {
+            self.labels.push((name.clone(), address));
+        }
+        self.symbols.insert(name, symbol);
+        Ok(())
+    }
+
+    /// Value of an expression
+    fn eval(&self, expr: &Expr, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        let mut value = 0i64;
+        for (negative, term) in &expr.terms {
+            let term = match term {
+                Term::Number(number) => *number,
+                Term::Symbol(name, column) => match self.symbols.get(name) {
+                    None => {
+                        return Err(
+                            location.error(*column, AsmErrorKind::UnknownSymbol(name.clone()))
+                        );
+                    }
+                    Some(Symbol::Label(address)) => i64::from(*address),
+                    Some(Symbol::Constant(expr, defined)) => {
+                        if depth >= MAX_CONSTANT_DEPTH {
+                            return Err(location
+                                .error(*column, AsmErrorKind::RecursiveConstant(name.clone())));
+                        }
+                        self.eval(expr, defined, depth + 1)?
+                    }
+                },
+            };
+            value = if *negative {
+                value.wrapping_sub(term)
+            } else {
+                value.wrapping_add(term)
+            };
+        }
+        Ok(value)
+    }
+
+    /// Value of an expression that must fit in `bits` bits, negative values are two's complement
+    fn eval_bits(&self, expr: &Expr, location: &Location, bits: u32) -> Result<u16, AsmError> {
+        let value = self.eval(expr, location, 0)?;
+        let limit = 1i64 << bits;
+        if value >= limit || value < -(limit / 2) {
+            return Err(location.error(expr.column, AsmErrorKind::OutOfRange { value, bits }));
+        }
+        Ok((value & (limit - 1)) as u16)
+    }
+
+    /// Encode an instruction
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        column: usize,
+        operands: &[Operand],
+        location: &Location,
+    ) -> Result<Instruction, AsmError> {
+        let name = mnemonic.to_ascii_uppercase();
+
+        // keywords double as symbols, try reading the fewest of them as symbols first
+        let keywords = operands
+            .iter()
+            .filter(|operand| matches!(operand, Operand::Keyword(..)))
+            .count();
+        let mut masks: Vec<u32> = (0..1 << keywords).collect();
+        masks.sort_by_key(|mask| mask.count_ones());
+        for mask in masks {
+            let mut keyword = 0;
+            let candidate: Vec<Operand> = operands
+                .iter()
+                .map(|operand| match operand {
+                    Operand::Keyword(_, symbol) => {
+                        keyword += 1;
+                        if mask & (1 << (keyword - 1)) != 0 {
+                            Operand::Value(symbol.clone())
+                        } else {
+                            operand.clone()
+                        }
+                    }
+                    other => other.clone(),
+                })
+                .collect();
+            if let Some(instruction) = self.select(&name, &candidate, location)? {
+                return Ok(instruction);
+            }
+        }
+
+        let kind = if MNEMONICS.contains(&name.as_str()) {
+            AsmErrorKind::InvalidOperands(name)
+        } else {
+            AsmErrorKind::UnknownMnemonic(mnemonic.to_owned())
+        };
+        Err(location.error(column, kind))
+    }
+
+    /// Instruction matching an uppercase mnemonic and its operands, if there is one
+    fn select(
+        &self,
+        name: &str,
+        operands: &[Operand],
+        location: &Location,
+    ) -> Result<Option<Instruction>, AsmError> {
+        use Keyword as K;
+        use Operand::{Indirect, Keyword as Kw, Long, Register as V, Value};
+
+        let address = |expr: &Expr| -> Result<Address, AsmError> {
+            Ok(Address::new(self.eval_bits(expr, location, 12)?))
+        };
+        let byte =
+            |expr: &Expr| -> Result<u8, AsmError> { Ok(self.eval_bits(expr, location, 8)? as u8) };
+        let nibble =
+            |expr: &Expr| -> Result<u8, AsmError> { Ok(self.eval_bits(expr, location, 4)? as u8) };
+
+        let instruction = match (name, operands) {
+            ("CLS", []) => Instruction::ClearDisplay,
+            ("RET", []) => Instruction::Return,
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            ("SCD", [Value(rows)]) => Instruction::ScrollDown {
+                rows: nibble(rows)?,
+            },
+            ("SCU", [Value(rows)]) => Instruction::ScrollUp {
+                rows: nibble(rows)?,
+            },
+            ("PLANE", [Value(planes)]) => Instruction::SelectPlanes {
+                planes: nibble(planes)?,
+            },
+            ("SYS", [Value(target)]) => Instruction::CallMachineCode {
+                address: address(target)?,
+            },
+            ("JP", [Value(target)]) => Instruction::Goto {
+                address: address(target)?,
+            },
+            ("JP", [V(x), Value(target)]) if x.into_inner() == 0 => Instruction::GotoPlusV0 {
+                address: address(target)?,
+            },
+            ("CALL", [Value(target)]) => Instruction::CallSubroutine {
+                address: address(target)?,
+            },
+            ("SE", [V(x), Value(value)]) => Instruction::EqConst {
+                x: *x,
+                value: byte(value)?,
+            },
+            ("SE", [V(x), V(y)]) => Instruction::EqReg { x: *x, y: *y },
+            ("SNE", [V(x), Value(value)]) => Instruction::NeqConst {
+                x: *x,
+                value: byte(value)?,
+            },
+            ("SNE", [V(x), V(y)]) => Instruction::NeqReg { x: *x, y: *y },
+            ("SAVE", [V(x), V(y)]) => Instruction::SaveRange { x: *x, y: *y },
+            ("LOAD", [V(x), V(y)]) => Instruction::LoadRange { x: *x, y: *y },
+            ("LD", [V(x), Value(value)]) => Instruction::AssignConst {
+                x: *x,
+                value: byte(value)?,
+            },
+            ("LD", [V(x), V(y)]) => Instruction::AssignReg { x: *x, y: *y },
+            ("ADD", [V(x), Value(value)]) => Instruction::AddAssignConst {
+                x: *x,
+                value: byte(value)?,
+            },
+            ("ADD", [V(x), V(y)]) => Instruction::AddAssignReg { x: *x, y: *y },
+            ("ADD", [Kw(K::I, _), V(x)]) => Instruction::AddAssignAddress { x: *x },
+            ("OR", [V(x), V(y)]) => Instruction::OrReg { x: *x, y: *y },
+            ("AND", [V(x), V(y)]) => Instruction::AndReg { x: *x, y: *y },
+            ("XOR", [V(x), V(y)]) => Instruction::XorReg { x: *x, y: *y },
+            ("SUB", [V(x), V(y)]) => Instruction::SubAssignReg { x: *x, y: *y },
+            ("SUBN", [V(x), V(y)]) => Instruction::SubAssignRegInverse { x: *x, y: *y },
+            ("SHR", [V(x)]) => Instruction::RShift { x: *x, y: *x },
+            ("SHR", [V(x), V(y)]) => Instruction::RShift { x: *x, y: *y },
+            ("SHL", [V(x)]) => Instruction::LShift { x: *x, y: *x },
+            ("SHL", [V(x), V(y)]) => Instruction::LShift { x: *x, y: *y },
+            ("LD", [Kw(K::I, _), Value(target)]) => Instruction::SetI {
+                address: address(target)?,
+            },
+            ("LD", [Kw(K::I, _), Long(target)]) => Instruction::LongSetI {
+                address: Address::new(self.eval_bits(target, location, 16)?),
+            },
+            ("RND", [V(x), Value(value)]) => Instruction::Rand {
+                x: *x,
+                value: byte(value)?,
+            },
+            ("DRW", [V(x), V(y), Value(height)]) => Instruction::DrawSprite {
+                x: *x,
+                y: *y,
+                height: SpriteHeight::try_new(nibble(height)?)
+                    .expect("4-bit values are valid sprite heights"),
+            },
+            ("SKP", [V(x)]) => Instruction::KeyPressedSkip { x: *x },
+            ("SKNP", [V(x)]) => Instruction::KeyReleasedSkip { x: *x },
+            ("LD", [V(x), Kw(K::Dt, _)]) => Instruction::GetDelayTimer { x: *x },
+            ("LD", [V(x), Kw(K::K, _)]) => Instruction::AwaitKeyPress { x: *x },
+            ("LD", [Kw(K::Dt, _), V(x)]) => Instruction::SetDelayTimer { x: *x },
+            ("LD", [Kw(K::St, _), V(x)]) => Instruction::SetSoundTimer { x: *x },
+            ("LD", [Kw(K::F, _), V(x)]) => Instruction::SetSpriteAddr { x: *x },
+            ("LD", [Kw(K::Hf, _), V(x)]) => Instruction::SetBigSpriteAddr { x: *x },
+            ("LD", [Kw(K::B, _), V(x)]) => Instruction::SetBCD { x: *x },
+            ("LD", [Kw(K::Pitch, _), V(x)]) => Instruction::SetPitch { x: *x },
+            ("LD", [Indirect, V(x)]) => Instruction::DumpRegisters { x: *x },
+            ("LD", [V(x), Indirect]) => Instruction::LoadRegisters { x: *x },
+            ("LD", [Kw(K::R, _), V(x)]) => Instruction::SaveFlags { x: *x },
+            ("LD", [V(x), Kw(K::R, _)]) => Instruction::LoadFlags { x: *x },
+            _ => return Ok(None),
+        };
+        Ok(Some(instruction))
+    }
+
+    /// Bytes of a placed statement
+    fn encode(&self, placed: &Placed) -> Result<Vec<u8>, AsmError> {
+        let location = &placed.location;
+        match &placed.statement {
+            Statement::Constant(..) | Statement::Include(..) => Ok(Vec::new()),
+            Statement::Bytes(data) => {
+                let mut bytes = Vec::new();
+                for datum in data {
+                    match datum {
+                        Datum::Byte(expr) => bytes.push(self.eval_bits(expr, location, 8)? as u8),
+                        Datum::Str(text) => bytes.extend(text),
+                    }
+                }
+                Ok(bytes)
+            }
+            Statement::Words(words) => {
+                let mut bytes = Vec::new();
+                for expr in words {
+                    bytes.extend(self.eval_bits(expr, location, 16)?.to_be_bytes());
+                }
+                Ok(bytes)
+            }
+            Statement::Instruction(mnemonic, column, operands) => Ok(self
+                .instruction(mnemonic, *column, operands, location)?
+                .encode()),
+        }
+    }
+}
+
+/// Assembler reading sources from disk or from memory
+#[derive(Debug, Default, Clone)]
+pub struct Assembler {
+    /// Sources used instead of the files with the same path
+    sources: HashMap<PathBuf, String>,
+}
+
+impl Assembler {
+    /// Create an assembler reading every source from disk
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `text` as the source at `path` instead of reading the file
+    pub fn add_source(&mut self, path: impl Into<PathBuf>, text: impl Into<String>) -> &mut Self {
+        self.sources.insert(path.into(), text.into());
+        self
+    }
+
+    /// Assemble the file at `path` and the files it includes
+    ///
+    /// The paths in errors and in the symbol map are relative to the directory of `path`
+    pub fn assemble(&self, path: &Path) -> Result<Assembly, AsmError> {
+        let base = path.parent().unwrap_or(Path::new(""));
+        let main = path.file_name().map(PathBuf::from).unwrap_or_default();
+        let text = self.read(base, &main).map_err(|message| AsmError {
+            file: main.display().to_string(),
+            line: 0,
+            column: 0,
+            kind: AsmErrorKind::Include {
+                path: main.display().to_string(),
+                message,
+            },
+        })?;
+
+        let mut program = Program {
+            address: usize::from(PROGRAM_START),
+            ..Program::default()
+        };
+        self.collect(&mut program, base, &main, &text, 0)?;
+
+        let mut rom = Vec::new();
+        let mut symbols = SymbolMap::new();
+        for placed in &program.placed {
+            let bytes = program.encode(placed)?;
+            if matches!(placed.statement, Statement::Instruction(..)) {
+                symbols.add_line(
+                    placed.address,
+                    &placed.location.file,
+                    placed.location.line as u32,
+                );
+            }
+            rom.extend(bytes);
+        }
+        for (name, address) in program.labels {
+            symbols.add_label(name, address);
+        }
+
+        Ok(Assembly { rom, symbols })
+    }
+
+    /// Source of `file`, relative to `base`
+    fn read(&self, base: &Path, file: &Path) -> Result<String, String> {
+        let path = base.join(file);
+        match self.sources.get(&path) {
+            Some(text) => Ok(text.clone()),
+            None => fs::read_to_string(path).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// First pass over a file: define the symbols and place the statements
+    fn collect(
+        &self,
+        program: &mut Program,
+        base: &Path,
+        file: &Path,
+        text: &str,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let name = file.display().to_string();
+
+        for (index, text) in text.lines().enumerate() {
+            let location = Location {
+                file: name.clone(),
+                line: index + 1,
+            };
+            let line = parse_line(text).map_err(|(column, kind)| location.error(column, kind))?;
+
+            if let Some((label, column)) = line.label {
+                // a label past the last byte would wrap around to 0x000
+                let address = u16::try_from(program.address)
+                    .map_err(|_| location.error(column, AsmErrorKind::TooLarge))?;
+                program.define(label, Symbol::Label(address), &location, column)?;
+            }
+
+            match line.statement {
+                None => {}
+                Some(Statement::Constant(constant, column, expr)) => {
+                    let symbol = Symbol::Constant(expr, location.clone());
+                    program.define(constant, symbol, &location, column)?;
+                }
+                Some(Statement::Include(path, column)) => {
+                    let included = file.parent().unwrap_or(Path::new("")).join(&path);
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(location.error(column, AsmErrorKind::IncludeCycle(path)));
+                    }
+                    let text = self.read(base, &included).map_err(|message| {
+                        location.error(column, AsmErrorKind::Include { path, message })
+                    })?;
+                    self.collect(program, base, &included, &text, depth + 1)?;
+                }
+                Some(statement) => {
+                    let size = statement.size();
+                    if program.address + size > 1 << 16 {
+                        return Err(location.error(1, AsmErrorKind::TooLarge));
+                    }
+                    program.placed.push(Placed {
+                        location,
+                        address: program.address as u16,
+                        statement,
+                    });
+                    program.address += size;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Assemble the file at `path`, reading the includes from disk
+pub fn assemble_file(path: &Path) -> Result<Assembly, AsmError> {
+    Assembler::new().assemble(path)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::disasm::Disassembly;
+
+    fn assemble(source: &str) -> Result<Assembly, AsmError> {
+        Assembler::new()
+            .add_source("game.asm", source)
+            .assemble(Path::new("game.asm"))
+    }
+
+    fn error(source: &str) -> (usize, usize, AsmErrorKind) {
+        let error = assemble(source).unwrap_err();
+        (error.line, error.column, error.kind)
+    }
+
+    #[test]
+    fn test_program() {
+        let source = "\
+SPEED equ 2 ; constant
+start:  LD V0, 0x12
+        ld i, sprite
+        CALL draw
+loop:   JP loop
+draw:
+        DRW V0, V1, 5
+        ADD V0, SPEED
+        SHR V3
+        LD [I], V2
+        LD I, LONG 0xABCD
+        RET
+sprite: db 0xF0, 0b1001, -1, \"AB\"
+        dw 0x1234, start + SPEED
+";
+        let assembly = assemble(source).unwrap();
+
+        assert_eq!(
+            assembly.rom(),
+            [
+                0x60, 0x12, 0xA2, 0x16, 0x22, 0x08, 0x12, 0x06, // start..loop
+                0xD0, 0x15, 0x70, 0x02, 0x83, 0x36, 0xF2, 0x55, // draw
+                0xF0, 0x00, 0xAB, 0xCD, 0x00, 0xEE, // long, ret
+                0xF0, 0x09, 0xFF, b'A', b'B', // sprite
+                0x12, 0x34, 0x02, 0x02,
+            ]
+        );
+
+        let symbols = assembly.symbols();
+        assert_eq!(symbols.label_address("draw"), Some(0x208));
+        assert_eq!(symbols.label_address("SPEED"), None);
+        assert_eq!(symbols.source_line(0x20A).unwrap().line, 8);
+        assert_eq!(symbols.source_line(0x214).unwrap().line, 12);
+        assert_eq!(symbols.source_line(0x216), None);
+    }
+
+    #[test]
+    fn test_keywords_as_symbols() {
+        let source = "\
+        CALL f
+        JP b + 2
+        LD V0, K
+        LD F, V0
+f:      RET
+b:      LD I, f
+";
+        let assembly = assemble(source).unwrap();
+
+        assert_eq!(
+            assembly.rom(),
+            [
+                0x22, 0x08, 0x12, 0x0C, 0xF0, 0x0A, 0xF0, 0x29, 0x00, 0xEE, 0xA2, 0x08
+            ]
+        );
+    }
+
+    #[test]
+    fn test_include() {
+        let mut assembler = Assembler::new();
+        assembler
+            .add_source(
+                "src/main.asm",
+                "CALL sub\nJP 0x202\ninclude \"lib/sub.asm\"\n",
+            )
+            .add_source("src/lib/sub.asm", "sub: CLS\nRET\n");
+        let assembly = assembler.assemble(Path::new("src/main.asm")).unwrap();
+
+        assert_eq!(
+            assembly.rom(),
+            [0x22, 0x04, 0x12, 0x02, 0x00, 0xE0, 0x00, 0xEE]
+        );
+        let line = assembly.symbols().source_line(0x204).unwrap();
+        assert_eq!((line.file.as_str(), line.line), ("lib/sub.asm", 1));
+
+        let mut assembler = Assembler::new();
+        assembler.add_source("loop.asm", "include \"loop.asm\"");
+        let error = assembler.assemble(Path::new("loop.asm")).unwrap_err();
+        assert!(matches!(error.kind, AsmErrorKind::IncludeCycle(_)));
+
+        let error = Assembler::new()
+            .add_source("main.asm", "CLS\n  include \"missing.asm\"")
+            .assemble(Path::new("main.asm"))
+            .unwrap_err();
+        assert_eq!((error.line, error.column), (2, 11));
+        assert!(
+            error
+                .to_string()
+                .starts_with("main.asm:2:11: can't include `missing.asm`")
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error("CLS\n  FOO V0"),
+            (2, 3, AsmErrorKind::UnknownMnemonic("FOO".to_owned()))
+        );
+        assert_eq!(
+            error("LD V0, 256"),
+            (
+                1,
+                8,
+                AsmErrorKind::OutOfRange {
+                    value: 256,
+                    bits: 8
+                }
+            )
+        );
+        assert_eq!(
+            error("JP nowhere"),
+            (1, 4, AsmErrorKind::UnknownSymbol("nowhere".to_owned()))
+        );
+        assert_eq!(
+            error("a: CLS\na: CLS"),
+            (2, 1, AsmErrorKind::DuplicateSymbol("a".to_owned()))
+        );
+        assert_eq!(
+            error("DRW V0, I, 5"),
+            (1, 1, AsmErrorKind::InvalidOperands("DRW".to_owned()))
+        );
+        assert_eq!(
+            error("LD V0, 0x1G"),
+            (1, 8, AsmErrorKind::InvalidNumber("0x1G".to_owned()))
+        );
+        assert_eq!(error("db \"open"), (1, 4, AsmErrorKind::UnterminatedString));
+        assert_eq!(error("CLS V0 V1").1, 8);
+        assert_eq!(
+            error("FOO equ BAR\nBAR equ FOO\nLD V0, FOO"),
+            (2, 9, AsmErrorKind::RecursiveConstant("FOO".to_owned()))
+        );
+        assert_eq!(
+            error("SKP I").2,
+            AsmErrorKind::InvalidOperands("SKP".to_owned())
+        );
+        assert_eq!(
+            error("LD V0, @"),
+            (1, 8, AsmErrorKind::UnexpectedCharacter('@'))
+        );
+    }
+
+    #[test]
+    fn test_label_at_the_end_of_memory() {
+        let filler = "CLS\n".repeat((0x1_0000 - 0x200) / 2);
+        assert!(assemble(&format!("{filler}\n  last:")).is_err_and(|error| {
+            error.kind == AsmErrorKind::TooLarge && (error.line, error.column) == (0x7F02, 3)
+        }));
+        let rom = assemble(&format!("{}last: CLS", &filler[4..])).unwrap();
+        assert_eq!(rom.symbols().label_address("last"), Some(0xFFFE));
+    }
+
+    proptest! {
+        #[test]
+        fn test_disassembly_round_trip(rom in prop::collection::vec(any::<u8>(), 0..96)) {
+            let listing = Disassembly::new(&rom).to_string();
+            let assembly = assemble(&listing).unwrap();
+            prop_assert_eq!(assembly.rom(), &rom[..]);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Recursive disassembler
+//!
+//! [`Disassembly`] follows the control flow from 0x200 instead of decoding the rom front to
+//! back, so sprites and other data mixed with the code stay data:
+//!
+//! - `JP` continues at its target, `CALL` at its target and after the call
+//! - skips continue after the next instruction and after the one following it
+//! - `JP V0` can go anywhere, only its base address is followed, which is the first entry of
+//!   the usual jump tables
+//! - `RET` and `EXIT` end the path, like invalid opcodes
+//!
+//! Call targets are labeled `sub_XXX`, the other jump targets `label_XXX`. Bytes that are
+//! never reached are printed as `db` directives, so the listing assembles back into the same
+//! rom:
+//!
+//! ```text
+//!     CALL sub_206            ; 0200: 2206
+//! label_202:
+//!     JP label_202            ; 0202: 1202
+//! sub_206:
+//!     LD I, 0x20C             ; 0206: A20C
+//!     RET                     ; 0208: 00EE
+//!     db 0x3C, 0x42           ; 020A
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+use chip8_core::Instruction;
+
+use crate::PROGRAM_START;
+
+/// Bytes per `db` directive
+const DATA_PER_LINE: usize = 8;
+
+/// Width the instructions are padded to before the address comments
+const TEXT_WIDTH: usize = 23;
+
+/// Rom split into reachable instructions and data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembly {
+    /// The rom, loaded at 0x200
+    rom: Vec<u8>,
+    /// Instructions reached from the entry point, by address
+    code: BTreeMap<u16, Instruction>,
+    /// Names of the jump and call targets
+    labels: BTreeMap<u16, String>,
+}
+
+/// How a target address is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    /// `JP` or `JP V0`
+    Jump,
+    /// `CALL`, wins over jumps for naming
+    Call,
+}
+
+impl Disassembly {
+    /// Walk the rom from 0x200
+    pub fn new(rom: &[u8]) -> Self {
+        let mut disassembly = Self {
+            rom: rom.to_vec(),
+            code: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        };
+        let mut claimed = vec![false; rom.len()];
+        let mut targets = BTreeMap::new();
+        let mut pending = vec![PROGRAM_START];
+
+        while let Some(address) = pending.pop() {
+            if disassembly.code.contains_key(&address) {
+                continue;
+            }
+            let Some(instruction) = disassembly.decode(address) else {
+                continue;
+            };
+            // a path running into the middle of another instruction is not followed
+            let offset = usize::from(address - PROGRAM_START);
+            let bytes = offset..offset + usize::from(instruction.size());
+            if claimed[bytes.clone()].iter().any(|claimed| *claimed) {
+                continue;
+            }
+            claimed[bytes].fill(true);
+            disassembly.code.insert(address, instruction);
+
+            let next = address.checked_add(instruction.size());
+            match instruction {
+                Instruction::Goto { address } | Instruction::GotoPlusV0 { address } => {
+                    let target = address.into_inner();
+                    targets.entry(target).or_insert(Target::Jump);
+                    pending.push(target);
+                }
+                Instruction::CallSubroutine { address } => {
+                    let target = address.into_inner();
+                    targets.insert(target, Target::Call);
+                    pending.push(target);
+                    pending.extend(next);
+                }
+                Instruction::Return | Instruction::Exit => {}
+                Instruction::EqConst { .. }
+                | Instruction::NeqConst { .. }
+                | Instruction::EqReg { .. }
+                | Instruction::NeqReg { .. }
+                | Instruction::KeyPressedSkip { .. }
+                | Instruction::KeyReleasedSkip { .. } => {
+                    pending.extend(next);
+                    let skipped = next.and_then(|next| {
+                        let size = disassembly.decode(next).map_or(2, |skipped| skipped.size());
+                        next.checked_add(size)
+                    });
+                    pending.extend(skipped);
+                }
+                _ => pending.extend(next),
+            }
+        }
+
+        // only addresses starting a line of the listing can carry a label
+        for (address, target) in targets {
+            let starts_line = disassembly.code.contains_key(&address)
+                || disassembly
+                    .offset(address)
+                    .is_some_and(|offset| !claimed[offset]);
+            if starts_line {
+                let prefix = match target {
+                    Target::Jump => "label",
+                    Target::Call => "sub",
+                };
+                disassembly
+                    .labels
+                    .insert(address, format!("{prefix}_{address:03X}"));
+            }
+        }
+
+        disassembly
+    }
+
+    /// Instruction reached at `address`
+    pub fn instruction_at(&self, address: u16) -> Option<&Instruction> {
+        self.code.get(&address)
+    }
+
+    /// Label generated for `address`
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// Reached instructions, sorted by address
+    pub fn instructions(&self) -> impl Iterator<Item = (u16, &Instruction)> + '_ {
+        self.code
+            .iter()
+            .map(|(address, instruction)| (*address, instruction))
+    }
+
+    /// Offset of `address` in the rom
+    fn offset(&self, address: u16) -> Option<usize> {
+        let offset = usize::from(address.checked_sub(PROGRAM_START)?);
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    /// Big endian word at `address`
+    fn word(&self, address: u16) -> Option<u16> {
+        let offset = self.offset(address)?;
+        Some(u16::from_be_bytes([
+            self.rom[offset],
+            *self.rom.get(offset + 1)?,
+        ]))
+    }
+
+    /// Decode the instruction at `address`, `None` past the end of the rom or for invalid opcodes
+    fn decode(&self, address: u16) -> Option<Instruction> {
+        let opcode = self.word(address)?;
+        if Instruction::has_operand(opcode) {
+            let operand = self.word(address.checked_add(2)?)?;
+            Instruction::decode(opcode, operand).ok()
+        } else {
+            Instruction::try_from(opcode).ok()
+        }
+    }
+
+    /// Mnemonic with the label of the target instead of its address
+    fn mnemonic(&self, instruction: &Instruction) -> String {
+        let label = match instruction {
+            Instruction::Goto { address }
+            | Instruction::CallSubroutine { address }
+            | Instruction::GotoPlusV0 { address } => self.labels.get(&address.into_inner()),
+            _ => None,
+        };
+
+        match (instruction, label) {
+            (Instruction::Goto { .. }, Some(label)) => format!("JP {label}"),
+            (Instruction::CallSubroutine { .. }, Some(label)) => format!("CALL {label}"),
+            (Instruction::GotoPlusV0 { .. }, Some(label)) => format!("JP V0, {label}"),
+            _ => instruction.to_string(),
+        }
+    }
+
+    /// Address after the data line starting at `address`
+    ///
+    /// Lines end at the next instruction or label, or after [`DATA_PER_LINE`] bytes
+    fn data_end(&self, address: usize, end: usize) -> usize {
+        let mut stop = address + 1;
+        while stop < end
+            && stop - address < DATA_PER_LINE
+            && !self.code.contains_key(&(stop as u16))
+            && !self.labels.contains_key(&(stop as u16))
+        {
+            stop += 1;
+        }
+        stop
+    }
+}
+
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = usize::from(PROGRAM_START);
+        let end = start + self.rom.len();
+        let mut address = start;
+
+        while address < end {
+            let at = address as u16;
+            if let Some(label) = self.labels.get(&at) {
+                writeln!(f, "{label}:")?;
+            }
+
+            if let Some(instruction) = self.code.get(&at) {
+                let size = usize::from(instruction.size());
+                let opcode: String = self.rom[address - start..address - start + size]
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect();
+                let text = self.mnemonic(instruction);
+                writeln!(f, "    {text:<TEXT_WIDTH$} ; {at:04X}: {opcode}")?;
+                address += size;
+            } else {
+                let stop = self.data_end(address, end);
+                let bytes = self.rom[address - start..stop - start]
+                    .iter()
+                    .map(|byte| format!("{byte:#04X}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let text = format!("db {bytes}");
+                writeln!(f, "    {text:<TEXT_WIDTH$} ; {at:04X}")?;
+                address = stop;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_and_data() {
+        let rom = [
+            0x22, 0x08, // 200: CALL 208
+            0x3A, 0x01, // 202: SE VA, 1
+            0x12, 0x02, // 204: JP 202
+            0x12, 0x06, // 206: JP 206
+            0xA2, 0x0E, // 208: LD I, 20E
+            0xD0, 0x12, // 20A: DRW V0, V1, 2
+            0x00, 0xEE, // 20C: RET
+            0x3C, 0x42, // 20E: sprite
+            0x99, // 210: trailing byte
+        ];
+        let disassembly = Disassembly::new(&rom);
+
+        assert_eq!(
+            disassembly.to_string(),
+            "    CALL sub_208            ; 0200: 2208
+label_202:
+    SE VA, 0x01             ; 0202: 3A01
+    JP label_202            ; 0204: 1202
+label_206:
+    JP label_206            ; 0206: 1206
+sub_208:
+    LD I, 0x20E             ; 0208: A20E
+    DRW V0, V1, 2           ; 020A: D012
+    RET                     ; 020C: 00EE
+    db 0x3C, 0x42, 0x99     ; 020E
+"
+        );
+        assert_eq!(disassembly.instructions().count(), 7);
+        assert_eq!(disassembly.label_at(0x208), Some("sub_208"));
+        assert!(disassembly.instruction_at(0x20E).is_none());
+    }
+
+    #[test]
+    fn test_jump_table_and_invalid_opcodes() {
+        let rom = [
+            0xB2, 0x04, // 200: JP V0, 204
+            0x50, 0x01, // 202: never reached
+            0x12, 0x08, // 204: JP 208
+            0x12, 0x0A, // 206: not followed, JP V0 only goes to the base
+            0x50, 0x01, // 208: invalid, data
+            0x12, 0x08, // 20A: unreached
+        ];
+        let disassembly = Disassembly::new(&rom);
+
+        assert_eq!(
+            disassembly.to_string(),
+            "    JP V0, label_204        ; 0200: B204
+    db 0x50, 0x01           ; 0202
+label_204:
+    JP label_208            ; 0204: 1208
+    db 0x12, 0x0A           ; 0206
+label_208:
+    db 0x50, 0x01, 0x12, 0x08 ; 0208
+"
+        );
+    }
+
+    #[test]
+    fn test_jump_into_an_instruction() {
+        let rom = [
+            0x3A, 0x01, // 200: SE VA, 1
+            0x12, 0x05, // 202: JP 205, would decode 0x0012 overlapping 204
+            0x61, 0x00, // 204: LD V1, 0
+            0x12, 0x06, // 206: JP 206
+        ];
+        let disassembly = Disassembly::new(&rom);
+
+        assert_eq!(
+            disassembly.to_string(),
+            "    SE VA, 0x01             ; 0200: 3A01
+    JP 0x205                ; 0202: 1205
+    LD V1, 0x00             ; 0204: 6100
+label_206:
+    JP label_206            ; 0206: 1206
+"
+        );
+        assert_eq!(disassembly.label_at(0x205), None);
+    }
+
+    #[test]
+    fn test_skip_over_long_instruction() {
+        let rom = [
+            0xE1, 0x9E, // 200: SKP V1
+            0xF0, 0x00, 0x12, 0x34, // 202: LD I, LONG 0x1234
+            0x00, 0xFD, // 206: EXIT
+        ];
+        let disassembly = Disassembly::new(&rom);
+
+        assert_eq!(
+            disassembly.to_string(),
+            "    SKP V1                  ; 0200: E19E
+    LD I, LONG 0x1234       ; 0202: F0001234
+    EXIT                    ; 0206: 00FD
+"
+        );
+    }
+}
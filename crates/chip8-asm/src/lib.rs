@@ -0,0 +1,17 @@
+//! Assembly tools for [`chip8_core`]
+//!
+//! [`asm`] assembles mnemonic source into a rom and a symbol map, [`octo`] compiles the Octo
+//! language into the same. [`disasm`] walks a rom from its entry point and turns it back into
+//! source, with labels for jump and call targets and `db` directives for the bytes that are never
+//! executed.
+
+#![warn(missing_docs)]
+#![warn(clippy::missing_docs_in_private_items)]
+#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+
+pub mod asm;
+pub mod disasm;
+pub mod octo;
+
+/// Address the rom is loaded at and starts running from
+const PROGRAM_START: u16 = 0x200;
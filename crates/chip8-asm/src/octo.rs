@@ -0,0 +1,1490 @@
+//! Octo compiler
+//!
+//! [`compile`] turns [Octo](https://github.com/JohnEarnest/Octo) source into a rom for
+//! `Chip8::load_program`. Tokens are separated by whitespace and `#` starts a comment:
+//!
+//! ```text
+//! :const SPEED 2
+//! :alias x v3
+//! :macro step reg { reg += SPEED }
+//!
+//! : main
+//!     i := ball
+//!     loop
+//!         sprite x x 4
+//!         step x
+//!         if x == 60 then x := 0
+//!         while v0 != 1
+//!     again
+//!
+//! : ball 0x60 0xF0 0xF0 0x60
+//! ```
+//!
+//! Supported are:
+//!
+//! - `: name` labels, `:next name` (the second byte of the next instruction), `:org`, `:byte`,
+//!   `:pointer`, `:unpack`, `:call` and bare numbers as sprite data
+//! - `:alias`, `:const`, `:calc name { expr }`, `:macro name args { body }` and `:breakpoint`,
+//!   which only matters to Octo's debugger
+//! - `if ... then`, `if ... begin ... else ... end`, `loop ... while ... again`, with the `<`,
+//!   `>`, `<=` and `>=` comparisons using vF as a temporary
+//! - the CHIP-8, SUPER-CHIP and XO-CHIP statements, from `vx := random NN` to `plane`, `audio`
+//!   and `i := long NNNN`
+//!
+//! `:calc` expressions are evaluated right to left without precedence like in Octo, e.g.
+//! `{ 2 * 3 + 1 }` is 8, parentheses group. Execution starts at `main`, a jump to it is placed at
+//! 0x200 unless it comes first.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+};
+
+use thiserror::Error;
+
+use chip8_core::{
+    Instruction,
+    symbols::SymbolMap,
+    types::{Address, Index, SpriteHeight},
+};
+
+use crate::{PROGRAM_START, asm::Assembly};
+
+/// Register the comparison pseudo-ops use as a temporary
+const VF: Index = unsafe { Index::new_unchecked(0xF) };
+
+/// Register `:unpack` writes the high byte to
+const V0: Index = unsafe { Index::new_unchecked(0) };
+
+/// Register `:unpack` writes the low byte to
+const V1: Index = unsafe { Index::new_unchecked(1) };
+
+/// Most macro expansions in one program, more means a macro expands itself
+const MAX_EXPANSIONS: usize = 100_000;
+
+/// Binary operators of `:calc` expressions
+const BINARY_OPERATORS: [&str; 19] = [
+    "-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=",
+    ">=", ">",
+];
+
+/// Unary operators of `:calc` expressions
+const UNARY_OPERATORS: [&str; 14] = [
+    "-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor", "@",
+];
+
+/// Enum for all possible compiling errors
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum OctoErrorKind {
+    #[error("can't read the source: {0}")]
+    /// The source file can't be read
+    Read(String),
+    #[error("expected {expected}, found {found}")]
+    /// Token missing or in the wrong place
+    Expected {
+        /// What would have fit
+        expected: String,
+        /// The token, or the end of the file
+        found: String,
+    },
+    #[error("unknown name `{0}`")]
+    /// Label or constant that is never defined
+    UnknownName(String),
+    #[error("`{0}` is already defined")]
+    /// Label, constant, alias or macro defined twice
+    DuplicateName(String),
+    #[error("value {value} doesn't fit in {bits} bits")]
+    /// Operand too big for its field
+    OutOfRange {
+        /// Value of the operand
+        value: i64,
+        /// Size of the field
+        bits: u32,
+    },
+    #[error("`{0}` without a matching block")]
+    /// `else`, `end`, `while` or `again` outside of their block
+    Unmatched(String),
+    #[error("`{0}` is never closed")]
+    /// Block or macro still open at the end of the file
+    Unclosed(String),
+    #[error("macros expand forever")]
+    /// A macro keeps expanding itself
+    ExpansionLimit,
+    #[error("the program has no `main` label")]
+    /// Nowhere to start
+    MissingMain,
+    #[error("program doesn't fit in memory")]
+    /// The program goes past the end of the 64 KiB address space
+    TooLarge,
+}
+
+/// Compiling error with its position in the source
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[error("{file}:{line}:{column}: {kind}")]
+pub struct OctoError {
+    /// Source file
+    pub file: String,
+    /// Line, starting at 1
+    pub line: usize,
+    /// Column, starting at 1
+    pub column: usize,
+    /// What went wrong
+    pub kind: OctoErrorKind,
+}
+
+/// Word of the source
+#[derive(Debug, Clone)]
+struct Token {
+    /// The word itself
+    text: String,
+    /// Line, starting at 1
+    line: usize,
+    /// Column, starting at 1
+    column: usize,
+}
+
+/// Split the source into whitespace separated tokens, without the comments
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut start = None;
+        for (column, c) in line.chars().chain([' ']).enumerate() {
+            match (start, c.is_whitespace()) {
+                (None, false) if c == '#' => break,
+                (None, false) => start = Some(column),
+                (Some(from), true) => {
+                    tokens.push_back(Token {
+                        text: line.chars().skip(from).take(column - from).collect(),
+                        line: index + 1,
+                        column: from + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    tokens
+}
+
+/// Parse a decimal, `0x` hexadecimal or `0b` binary number, optionally negative
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// Apply a binary `:calc` operator
+fn binary(operator: &str, left: f64, right: f64) -> f64 {
+    let (l, r) = (left as i64, right as i64);
+    match operator {
+        "-" => left - right,
+        "+" => left + right,
+        "*" => left * right,
+        "/" => left / right,
+        "%" => left % right,
+        "&" => (l & r) as f64,
+        "|" => (l | r) as f64,
+        "^" => (l ^ r) as f64,
+        "<<" => l.checked_shl(r as u32).unwrap_or(0) as f64,
+        ">>" => l.checked_shr(r as u32).unwrap_or(0) as f64,
+        "pow" => left.powf(right),
+        "min" => left.min(right),
+        "max" => left.max(right),
+        "<" => f64::from(u8::from(left < right)),
+        "<=" => f64::from(u8::from(left <= right)),
+        "==" => f64::from(u8::from(left == right)),
+        "!=" => f64::from(u8::from(left != right)),
+        ">=" => f64::from(u8::from(left >= right)),
+        _ => f64::from(u8::from(left > right)),
+    }
+}
+
+/// Apply a unary `:calc` operator, except `@` which needs the rom
+fn unary(operator: &str, value: f64) -> f64 {
+    match operator {
+        "-" => -value,
+        "~" => !(value as i64) as f64,
+        "!" => f64::from(u8::from(value == 0.0)),
+        "sin" => value.sin(),
+        "cos" => value.cos(),
+        "tan" => value.tan(),
+        "exp" => value.exp(),
+        "log" => value.ln(),
+        "abs" => value.abs(),
+        "sqrt" => value.sqrt(),
+        "sign" if value == 0.0 => 0.0,
+        "sign" => value.signum(),
+        "ceil" => value.ceil(),
+        _ => value.floor(),
+    }
+}
+
+/// Value of an operand
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    /// Number, constant or label defined earlier
+    Known(i64),
+    /// Name defined later, hopefully a label
+    Forward,
+}
+
+/// Right hand side of an assignment or a comparison
+#[derive(Debug, Clone, Copy)]
+enum Rhs {
+    /// `vy`
+    Register(Index),
+    /// Byte
+    Byte(u8),
+}
+
+/// Part of an instruction filled in once a forward label is defined
+#[derive(Debug, Clone, Copy)]
+enum FixupKind {
+    /// Low 12 bits of the instruction
+    Address,
+    /// Whole word
+    Word,
+    /// Byte of `v0 := NN` from `:unpack`, the given nibble followed by the high bits
+    UnpackHigh(u8),
+    /// Byte of `v1 := NN` from `:unpack`, the low byte
+    UnpackLow,
+}
+
+/// Forward reference to patch at the end
+#[derive(Debug, Clone)]
+struct Fixup {
+    /// Address of the instruction or word
+    address: usize,
+    /// What to patch
+    kind: FixupKind,
+    /// Name of the label
+    token: Token,
+}
+
+/// Open structured block
+#[derive(Debug, Clone)]
+enum Block {
+    /// `if ... begin`, with the jump over the branch and whether `else` was seen
+    If {
+        /// Address of the jump to patch at `else` or `end`
+        jump: usize,
+        /// In the `else` branch
+        has_else: bool,
+    },
+    /// `loop`, with its start and the jumps of its `while`s
+    Loop {
+        /// Address `again` jumps back to
+        start: usize,
+        /// Addresses of the jumps out of the loop
+        exits: Vec<usize>,
+    },
+}
+
+/// Macro definition
+#[derive(Debug, Clone)]
+struct Macro {
+    /// Parameter names
+    params: Vec<String>,
+    /// Tokens of the body
+    body: Vec<Token>,
+}
+
+/// State of a compilation
+#[derive(Debug)]
+struct Compiler {
+    /// Source file, for errors and the symbol map
+    file: String,
+    /// Tokens left, macro expansions are pushed to the front
+    tokens: VecDeque<Token>,
+    /// Line and column after the last token
+    end: (usize, usize),
+    /// Rom, starting at 0x200
+    rom: Vec<u8>,
+    /// Address of the next byte
+    here: usize,
+    /// Labels by name
+    labels: HashMap<String, u16>,
+    /// Constants by name
+    constants: HashMap<String, f64>,
+    /// Register aliases by name
+    aliases: HashMap<String, Index>,
+    /// Macros by name
+    macros: HashMap<String, Macro>,
+    /// Forward references
+    fixups: Vec<Fixup>,
+    /// Open blocks with the token opening them
+    blocks: Vec<(Block, Token)>,
+    /// Source line of every instruction
+    symbols: SymbolMap,
+    /// Macro expansions so far
+    expansions: usize,
+    /// Whether 0x200 holds the jump to `main`
+    main_jump: bool,
+}
+
+/// Result of the compiler steps
+type Result<T> = std::result::Result<T, OctoError>;
+
+impl Compiler {
+    /// Compiler for the tokens of `file`
+    fn new(file: &str, tokens: VecDeque<Token>) -> Self {
+        let end = tokens.back().map_or((1, 1), |token| {
+            (token.line, token.column + token.text.chars().count())
+        });
+        Self {
+            file: file.to_owned(),
+            tokens,
+            end,
+            rom: Vec::new(),
+            here: usize::from(PROGRAM_START),
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            symbols: SymbolMap::new(),
+            expansions: 0,
+            main_jump: false,
+        }
+    }
+
+    /// Error at `token`
+    fn error(&self, token: &Token, kind: OctoErrorKind) -> OctoError {
+        OctoError {
+            file: self.file.clone(),
+            line: token.line,
+            column: token.column,
+            kind,
+        }
+    }
+
+    /// Error for `token` not being `expected`
+    fn expected(&self, token: &Token, expected: &str) -> OctoError {
+        self.error(
+            token,
+            OctoErrorKind::Expected {
+                expected: expected.to_owned(),
+                found: format!("`{}`", token.text),
+            },
+        )
+    }
+
+    /// Next token, `expected` describes it for the error at the end of the file
+    fn next(&mut self, expected: &str) -> Result<Token> {
+        self.tokens.pop_front().ok_or_else(|| OctoError {
+            file: self.file.clone(),
+            line: self.end.0,
+            column: self.end.1,
+            kind: OctoErrorKind::Expected {
+                expected: expected.to_owned(),
+                found: "end of file".to_owned(),
+            },
+        })
+    }
+
+    /// Text of the next token
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    /// Consume the token `text`
+    fn expect(&mut self, text: &str) -> Result<Token> {
+        let expected = format!("`{text}`");
+        let token = self.next(&expected)?;
+        if token.text != text {
+            return Err(self.expected(&token, &expected));
+        }
+        Ok(token)
+    }
+
+    /// Register named `text`, `v0` to `vF` or an alias
+    fn register_index(&self, text: &str) -> Option<Index> {
+        if let Some(index) = self.aliases.get(text) {
+            return Some(*index);
+        }
+        let digit = text.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        Index::try_new(u8::from_str_radix(digit, 16).ok()?).ok()
+    }
+
+    /// Consume a register
+    fn register(&mut self) -> Result<Index> {
+        let token = self.next("register")?;
+        self.register_index(&token.text)
+            .ok_or_else(|| self.expected(&token, "register"))
+    }
+
+    /// Consume a name that isn't defined yet
+    fn new_name(&mut self) -> Result<Token> {
+        let token = self.next("name")?;
+        if parse_number(&token.text).is_some()
+            || self.register_index(&token.text).is_some()
+            || token.text.starts_with(':')
+        {
+            return Err(self.expected(&token, "name"));
+        }
+        let name = token.text.as_str();
+        if self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.aliases.contains_key(name)
+            || self.macros.contains_key(name)
+        {
+            return Err(self.error(&token, OctoErrorKind::DuplicateName(token.text.clone())));
+        }
+        Ok(token)
+    }
+
+    /// Check that `value` fits in `bits` bits, negative values are two's complement
+    fn fit(&self, value: i64, bits: u32, token: &Token) -> Result<u16> {
+        let limit = 1i64 << bits;
+        if value >= limit || value < -(limit / 2) {
+            return Err(self.error(token, OctoErrorKind::OutOfRange { value, bits }));
+        }
+        Ok((value & (limit - 1)) as u16)
+    }
+
+    /// Consume a number, a `{ expr }`, a constant or a label
+    fn operand(&mut self, expected: &str) -> Result<(Operand, Token)> {
+        let token = self.next(expected)?;
+        let text = token.text.as_str();
+        let value = if text == "{" {
+            self.calc()?
+        } else if let Some(value) = parse_number(text) {
+            value
+        } else if let Some(value) = self.constants.get(text) {
+            *value
+        } else if let Some(address) = self.labels.get(text) {
+            f64::from(*address)
+        } else if self.register_index(text).is_some() || text.starts_with(':') {
+            return Err(self.expected(&token, expected));
+        } else {
+            return Ok((Operand::Forward, token));
+        };
+        Ok((Operand::Known(value.floor() as i64), token))
+    }
+
+    /// Consume a value known at this point that fits in `bits` bits
+    fn immediate(&mut self, bits: u32) -> Result<u16> {
+        match self.operand("number")? {
+            (Operand::Known(value), token) => self.fit(value, bits, &token),
+            (Operand::Forward, token) => {
+                Err(self.error(&token, OctoErrorKind::UnknownName(token.text.clone())))
+            }
+        }
+    }
+
+    /// Consume an address, labels can be defined later and are patched into `address`
+    fn address(&mut self, kind: FixupKind, address: usize) -> Result<u16> {
+        let bits = if matches!(kind, FixupKind::Word) {
+            16
+        } else {
+            12
+        };
+        match self.operand("address")? {
+            (Operand::Known(value), token) => self.fit(value, bits, &token),
+            (Operand::Forward, token) => {
+                self.fixups.push(Fixup {
+                    address,
+                    kind,
+                    token,
+                });
+                Ok(0)
+            }
+        }
+    }
+
+    /// Consume a register or a byte
+    fn rhs(&mut self) -> Result<Rhs> {
+        if let Some(y) = self.peek().and_then(|text| self.register_index(text)) {
+            self.tokens.pop_front();
+            return Ok(Rhs::Register(y));
+        }
+        Ok(Rhs::Byte(self.immediate(8)? as u8))
+    }
+
+    /// Append a byte
+    fn emit(&mut self, byte: u8, at: &Token) -> Result<()> {
+        if self.here > usize::from(u16::MAX) {
+            return Err(self.error(at, OctoErrorKind::TooLarge));
+        }
+        let offset = self.here - usize::from(PROGRAM_START);
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    /// Append an instruction compiled from the line of `at`
+    fn instruction(&mut self, instruction: Instruction, at: &Token) -> Result<()> {
+        let address = self.here;
+        for byte in instruction.encode() {
+            self.emit(byte, at)?;
+        }
+        self.symbols
+            .add_line(address as u16, &self.file, at.line as u32);
+        Ok(())
+    }
+
+    /// Append a jump to be patched later, returning its address
+    fn placeholder(&mut self, at: &Token) -> Result<usize> {
+        let address = self.here;
+        self.instruction(
+            Instruction::Goto {
+                address: Address::new(0),
+            },
+            at,
+        )?;
+        Ok(address)
+    }
+
+    /// Point the jump at `jump` to `target`
+    fn patch(&mut self, jump: usize, target: usize, at: &Token) -> Result<()> {
+        let target = self.fit(target as i64, 12, at)?;
+        let opcode = u16::from(Instruction::Goto {
+            address: Address::new(target),
+        });
+        let offset = jump - usize::from(PROGRAM_START);
+        self.rom[offset..offset + 2].copy_from_slice(&opcode.to_be_bytes());
+        Ok(())
+    }
+
+    /// Evaluate a `:calc` expression, after its `{`
+    fn calc(&mut self) -> Result<f64> {
+        let value = self.expr()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    /// Evaluate an expression, right to left
+    fn expr(&mut self) -> Result<f64> {
+        let left = self.term()?;
+        if !self
+            .peek()
+            .is_some_and(|text| BINARY_OPERATORS.contains(&text))
+        {
+            return Ok(left);
+        }
+        let operator = self.next("operator")?;
+        let right = self.expr()?;
+        Ok(binary(&operator.text, left, right))
+    }
+
+    /// Evaluate a number, a name, a parenthesized expression or a unary operator
+    fn term(&mut self) -> Result<f64> {
+        let token = self.next("value")?;
+        let text = token.text.as_str();
+        if text == "(" {
+            let value = self.expr()?;
+            self.expect(")")?;
+            Ok(value)
+        } else if text == "@" {
+            let address = self.term()? as usize;
+            let byte = address
+                .checked_sub(usize::from(PROGRAM_START))
+                .and_then(|offset| self.rom.get(offset));
+            Ok(f64::from(byte.copied().unwrap_or(0)))
+        } else if UNARY_OPERATORS.contains(&text) {
+            let operator = token.text.clone();
+            Ok(unary(&operator, self.term()?))
+        } else if text == "HERE" {
+            Ok(self.here as f64)
+        } else if let Some(value) = parse_number(text) {
+            Ok(value)
+        } else if let Some(value) = self.constants.get(text) {
+            Ok(*value)
+        } else if let Some(address) = self.labels.get(text) {
+            Ok(f64::from(*address))
+        } else {
+            Err(self.error(&token, OctoErrorKind::UnknownName(token.text.clone())))
+        }
+    }
+
+    /// Define a label
+    fn define_label(&mut self, name: Token, address: usize) -> Result<()> {
+        if address > usize::from(u16::MAX) {
+            return Err(self.error(&name, OctoErrorKind::TooLarge));
+        }
+        self.labels.insert(name.text, address as u16);
+        Ok(())
+    }
+
+    /// Read a `:macro` definition
+    fn define_macro(&mut self, at: &Token) -> Result<()> {
+        let name = self.new_name()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next("`{`")?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 0usize;
+        loop {
+            let Some(token) = self.tokens.pop_front() else {
+                return Err(self.error(at, OctoErrorKind::Unclosed(at.text.clone())));
+            };
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replace a macro invocation with its body
+    fn expand(&mut self, at: &Token) -> Result<()> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(at, OctoErrorKind::ExpansionLimit));
+        }
+
+        let definition = self.macros[&at.text].clone();
+        let mut args = HashMap::new();
+        for param in definition.params {
+            let arg = self.next("macro argument")?;
+            args.insert(param, arg.text);
+        }
+        // the expansion is reported at the invocation
+        for token in definition.body.iter().rev() {
+            let text = args.get(&token.text).unwrap_or(&token.text);
+            self.tokens.push_front(Token {
+                text: text.clone(),
+                line: at.line,
+                column: at.column,
+            });
+        }
+        Ok(())
+    }
+
+    /// Consume a condition, returning the instructions computing it, the skip taken when it
+    /// holds and the one taken when it doesn't
+    fn condition(&mut self) -> Result<(Vec<Instruction>, Instruction, Instruction)> {
+        let x = self.register()?;
+        let operator = self.next("comparison")?;
+        // vF - vX and vX - vF set vF to 1 without borrow
+        let (setup, holds_on_borrow) = match operator.text.as_str() {
+            "key" => {
+                return Ok((
+                    Vec::new(),
+                    Instruction::KeyPressedSkip { x },
+                    Instruction::KeyReleasedSkip { x },
+                ));
+            }
+            "-key" => {
+                return Ok((
+                    Vec::new(),
+                    Instruction::KeyReleasedSkip { x },
+                    Instruction::KeyPressedSkip { x },
+                ));
+            }
+            "==" | "!=" => {
+                let (equal, not_equal) = match self.rhs()? {
+                    Rhs::Register(y) => (Instruction::EqReg { x, y }, Instruction::NeqReg { x, y }),
+                    Rhs::Byte(value) => (
+                        Instruction::EqConst { x, value },
+                        Instruction::NeqConst { x, value },
+                    ),
+                };
+                return Ok(if operator.text == "==" {
+                    (Vec::new(), equal, not_equal)
+                } else {
+                    (Vec::new(), not_equal, equal)
+                });
+            }
+            // vF = vX - rhs, borrows when vX < rhs
+            "<" | ">=" => {
+                let setup = match self.rhs()? {
+                    Rhs::Register(y) => vec![
+                        Instruction::AssignReg { x: VF, y: x },
+                        Instruction::SubAssignReg { x: VF, y },
+                    ],
+                    Rhs::Byte(value) => vec![
+                        Instruction::AssignConst { x: VF, value },
+                        Instruction::SubAssignRegInverse { x: VF, y: x },
+                    ],
+                };
+                (setup, operator.text == "<")
+            }
+            // vF = rhs - vX, borrows when vX > rhs
+            ">" | "<=" => {
+                let setup = match self.rhs()? {
+                    Rhs::Register(y) => vec![
+                        Instruction::AssignReg { x: VF, y },
+                        Instruction::SubAssignReg { x: VF, y: x },
+                    ],
+                    Rhs::Byte(value) => vec![
+                        Instruction::AssignConst { x: VF, value },
+                        Instruction::SubAssignReg { x: VF, y: x },
+                    ],
+                };
+                (setup, operator.text == ">")
+            }
+            _ => return Err(self.expected(&operator, "comparison")),
+        };
+
+        let borrow = Instruction::EqConst { x: VF, value: 0 };
+        let no_borrow = Instruction::EqConst { x: VF, value: 1 };
+        Ok(if holds_on_borrow {
+            (setup, borrow, no_borrow)
+        } else {
+            (setup, no_borrow, borrow)
+        })
+    }
+
+    /// Compile `vx OP ...`
+    fn assignment(&mut self, x: Index, at: &Token) -> Result<()> {
+        let operator = self.next("assignment")?;
+        let instruction = match operator.text.as_str() {
+            ":=" => match self.peek() {
+                Some("delay") => {
+                    self.tokens.pop_front();
+                    Instruction::GetDelayTimer { x }
+                }
+                Some("key") => {
+                    self.tokens.pop_front();
+                    Instruction::AwaitKeyPress { x }
+                }
+                Some("random") => {
+                    self.tokens.pop_front();
+                    Instruction::Rand {
+                        x,
+                        value: self.immediate(8)? as u8,
+                    }
+                }
+                _ => match self.rhs()? {
+                    Rhs::Register(y) => Instruction::AssignReg { x, y },
+                    Rhs::Byte(value) => Instruction::AssignConst { x, value },
+                },
+            },
+            "+=" => match self.rhs()? {
+                Rhs::Register(y) => Instruction::AddAssignReg { x, y },
+                Rhs::Byte(value) => Instruction::AddAssignConst { x, value },
+            },
+            "-=" => match self.rhs()? {
+                Rhs::Register(y) => Instruction::SubAssignReg { x, y },
+                Rhs::Byte(value) => Instruction::AddAssignConst {
+                    x,
+                    value: value.wrapping_neg(),
+                },
+            },
+            "=-" => Instruction::SubAssignRegInverse {
+                x,
+                y: self.register()?,
+            },
+            "|=" => Instruction::OrReg {
+                x,
+                y: self.register()?,
+            },
+            "&=" => Instruction::AndReg {
+                x,
+                y: self.register()?,
+            },
+            "^=" => Instruction::XorReg {
+                x,
+                y: self.register()?,
+            },
+            ">>=" => Instruction::RShift {
+                x,
+                y: self.register()?,
+            },
+            "<<=" => Instruction::LShift {
+                x,
+                y: self.register()?,
+            },
+            _ => return Err(self.expected(&operator, "assignment")),
+        };
+        self.instruction(instruction, at)
+    }
+
+    /// Compile `i := ...` and `i += vx`
+    fn index_assignment(&mut self, at: &Token) -> Result<()> {
+        let operator = self.next("`:=` or `+=`")?;
+        let instruction = match (operator.text.as_str(), self.peek()) {
+            ("+=", _) => Instruction::AddAssignAddress {
+                x: self.register()?,
+            },
+            (":=", Some("hex")) => {
+                self.tokens.pop_front();
+                Instruction::SetSpriteAddr {
+                    x: self.register()?,
+                }
+            }
+            (":=", Some("bighex")) => {
+                self.tokens.pop_front();
+                Instruction::SetBigSpriteAddr {
+                    x: self.register()?,
+                }
+            }
+            (":=", Some("long")) => {
+                self.tokens.pop_front();
+                let address = self.address(FixupKind::Word, self.here + 2)?;
+                Instruction::LongSetI {
+                    address: Address::new(address),
+                }
+            }
+            (":=", _) => {
+                let address = self.address(FixupKind::Address, self.here)?;
+                Instruction::SetI {
+                    address: Address::new(address),
+                }
+            }
+            _ => return Err(self.expected(&operator, "`:=` or `+=`")),
+        };
+        self.instruction(instruction, at)
+    }
+
+    /// Compile the part of `if` before its statement or body
+    fn conditional(&mut self, at: &Token) -> Result<()> {
+        let (setup, skip_if_true, skip_if_false) = self.condition()?;
+        let form = self.next("`then` or `begin`")?;
+        for instruction in setup {
+            self.instruction(instruction, at)?;
+        }
+        match form.text.as_str() {
+            // the next statement is the body
+            "then" => self.instruction(skip_if_false, at),
+            "begin" => {
+                self.instruction(skip_if_true, at)?;
+                let jump = self.placeholder(at)?;
+                self.blocks.push((
+                    Block::If {
+                        jump,
+                        has_else: false,
+                    },
+                    at.clone(),
+                ));
+                Ok(())
+            }
+            _ => Err(self.expected(&form, "`then` or `begin`")),
+        }
+    }
+
+    /// Compile one statement starting with `token`
+    fn statement(&mut self, token: Token) -> Result<()> {
+        if let Some(x) = self.register_index(&token.text) {
+            return self.assignment(x, &token);
+        }
+
+        let simple = |instruction| Some(instruction);
+        let instruction = match token.text.as_str() {
+            ";" | "return" => simple(Instruction::Return),
+            "clear" => simple(Instruction::ClearDisplay),
+            "hires" => simple(Instruction::HighRes),
+            "lores" => simple(Instruction::LowRes),
+            "exit" => simple(Instruction::Exit),
+            "scroll-right" => simple(Instruction::ScrollRight),
+            "scroll-left" => simple(Instruction::ScrollLeft),
+            "audio" => simple(Instruction::LoadAudioPattern),
+            "scroll-down" => simple(Instruction::ScrollDown {
+                rows: self.immediate(4)? as u8,
+            }),
+            "scroll-up" => simple(Instruction::ScrollUp {
+                rows: self.immediate(4)? as u8,
+            }),
+            "plane" => simple(Instruction::SelectPlanes {
+                planes: self.immediate(4)? as u8,
+            }),
+            "jump" => simple(Instruction::Goto {
+                address: Address::new(self.address(FixupKind::Address, self.here)?),
+            }),
+            "jump0" => simple(Instruction::GotoPlusV0 {
+                address: Address::new(self.address(FixupKind::Address, self.here)?),
+            }),
+            "native" => simple(Instruction::CallMachineCode {
+                address: Address::new(self.address(FixupKind::Address, self.here)?),
+            }),
+            ":call" => simple(Instruction::CallSubroutine {
+                address: Address::new(self.address(FixupKind::Address, self.here)?),
+            }),
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.immediate(4)? as u8;
+                simple(Instruction::DrawSprite {
+                    x,
+                    y,
+                    height: SpriteHeight::try_new(height)
+                        .expect("4-bit values are valid sprite heights"),
+                })
+            }
+            "bcd" => simple(Instruction::SetBCD {
+                x: self.register()?,
+            }),
+            "saveflags" => simple(Instruction::SaveFlags {
+                x: self.register()?,
+            }),
+            "loadflags" => simple(Instruction::LoadFlags {
+                x: self.register()?,
+            }),
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token.text == "save";
+                if self.peek() == Some("-") {
+                    self.tokens.pop_front();
+                    let y = self.register()?;
+                    simple(if save {
+                        Instruction::SaveRange { x, y }
+                    } else {
+                        Instruction::LoadRange { x, y }
+                    })
+                } else {
+                    simple(if save {
+                        Instruction::DumpRegisters { x }
+                    } else {
+                        Instruction::LoadRegisters { x }
+                    })
+                }
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                simple(match token.text.as_str() {
+                    "delay" => Instruction::SetDelayTimer { x },
+                    "buzzer" => Instruction::SetSoundTimer { x },
+                    _ => Instruction::SetPitch { x },
+                })
+            }
+            _ => None,
+        };
+        if let Some(instruction) = instruction {
+            return self.instruction(instruction, &token);
+        }
+
+        match token.text.as_str() {
+            "i" => self.index_assignment(&token),
+            "if" => self.conditional(&token),
+            "else" => match self.blocks.pop() {
+                Some((
+                    Block::If {
+                        jump,
+                        has_else: false,
+                    },
+                    opening,
+                )) => {
+                    let end_jump = self.placeholder(&token)?;
+                    self.patch(jump, self.here, &token)?;
+                    self.blocks.push((
+                        Block::If {
+                            jump: end_jump,
+                            has_else: true,
+                        },
+                        opening,
+                    ));
+                    Ok(())
+                }
+                _ => Err(self.error(&token, OctoErrorKind::Unmatched(token.text.clone()))),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If { jump, .. }, _)) => self.patch(jump, self.here, &token),
+                _ => Err(self.error(&token, OctoErrorKind::Unmatched(token.text.clone()))),
+            },
+            "loop" => {
+                self.blocks.push((
+                    Block::Loop {
+                        start: self.here,
+                        exits: Vec::new(),
+                    },
+                    token,
+                ));
+                Ok(())
+            }
+            "while" => {
+                if !self
+                    .blocks
+                    .iter()
+                    .any(|(block, _)| matches!(block, Block::Loop { .. }))
+                {
+                    return Err(self.error(&token, OctoErrorKind::Unmatched(token.text.clone())));
+                }
+                let (setup, skip_if_true, _) = self.condition()?;
+                for instruction in setup {
+                    self.instruction(instruction, &token)?;
+                }
+                self.instruction(skip_if_true, &token)?;
+                let exit = self.placeholder(&token)?;
+                if let Some((Block::Loop { exits, .. }, _)) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|(block, _)| matches!(block, Block::Loop { .. }))
+                {
+                    exits.push(exit);
+                }
+                Ok(())
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, exits }, _)) => {
+                    let back = self.placeholder(&token)?;
+                    self.patch(back, start, &token)?;
+                    for exit in exits {
+                        self.patch(exit, self.here, &token)?;
+                    }
+                    Ok(())
+                }
+                _ => Err(self.error(&token, OctoErrorKind::Unmatched(token.text.clone()))),
+            },
+            ":" => {
+                let name = self.new_name()?;
+                // `main` right after the jump to it doesn't need the jump
+                if name.text == "main"
+                    && self.main_jump
+                    && self.rom.len() == 2
+                    && self.here == usize::from(PROGRAM_START) + 2
+                    && self.labels.is_empty()
+                {
+                    self.rom.clear();
+                    self.fixups.clear();
+                    self.here = usize::from(PROGRAM_START);
+                    self.main_jump = false;
+                }
+                self.define_label(name, self.here)
+            }
+            ":next" => {
+                let name = self.new_name()?;
+                self.define_label(name, self.here + 1)
+            }
+            ":alias" => {
+                let name = self.new_name()?;
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+                Ok(())
+            }
+            ":const" => {
+                let name = self.new_name()?;
+                match self.operand("value")? {
+                    (Operand::Known(value), _) => {
+                        self.constants.insert(name.text, value as f64);
+                        Ok(())
+                    }
+                    (Operand::Forward, token) => {
+                        Err(self.error(&token, OctoErrorKind::UnknownName(token.text.clone())))
+                    }
+                }
+            }
+            ":calc" => {
+                let name = self.new_name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name.text, value);
+                Ok(())
+            }
+            ":byte" => {
+                let byte = self.immediate(8)? as u8;
+                self.emit(byte, &token)
+            }
+            ":pointer" => {
+                let address = self.address(FixupKind::Word, self.here)?;
+                for byte in address.to_be_bytes() {
+                    self.emit(byte, &token)?;
+                }
+                Ok(())
+            }
+            ":org" => {
+                let address = self.immediate(16)?;
+                if address < PROGRAM_START {
+                    return Err(self.error(
+                        &token,
+                        OctoErrorKind::OutOfRange {
+                            value: i64::from(address),
+                            bits: 16,
+                        },
+                    ));
+                }
+                self.here = usize::from(address);
+                Ok(())
+            }
+            ":unpack" => {
+                let nibble = self.immediate(4)? as u8;
+                let (high, low) = match self.operand("address")? {
+                    (Operand::Known(value), target) => {
+                        let [high, low] = self.fit(value, 12, &target)?.to_be_bytes();
+                        (high, low)
+                    }
+                    (Operand::Forward, target) => {
+                        self.fixups.push(Fixup {
+                            address: self.here,
+                            kind: FixupKind::UnpackHigh(nibble),
+                            token: target.clone(),
+                        });
+                        self.fixups.push(Fixup {
+                            address: self.here + 2,
+                            kind: FixupKind::UnpackLow,
+                            token: target,
+                        });
+                        (0, 0)
+                    }
+                };
+                self.instruction(
+                    Instruction::AssignConst {
+                        x: V0,
+                        value: (nibble << 4) | high,
+                    },
+                    &token,
+                )?;
+                self.instruction(Instruction::AssignConst { x: V1, value: low }, &token)
+            }
+            // only meaningful to Octo's debugger
+            ":breakpoint" => self.next("name").map(|_| ()),
+            ":macro" => self.define_macro(&token),
+            text if self.macros.contains_key(text) => self.expand(&token),
+            text if parse_number(text).is_some() || self.constants.contains_key(text) => {
+                self.tokens.push_front(token.clone());
+                let byte = self.immediate(8)? as u8;
+                self.emit(byte, &token)
+            }
+            text if text.starts_with(':') || text == "{" || text == "}" => {
+                Err(self.expected(&token, "statement"))
+            }
+            // anything else calls a label
+            _ => {
+                self.tokens.push_front(token.clone());
+                let address = self.address(FixupKind::Address, self.here)?;
+                self.instruction(
+                    Instruction::CallSubroutine {
+                        address: Address::new(address),
+                    },
+                    &token,
+                )
+            }
+        }
+    }
+
+    /// Patch the forward references
+    fn resolve(&mut self) -> Result<()> {
+        for fixup in std::mem::take(&mut self.fixups) {
+            let target = match (
+                self.labels.get(&fixup.token.text),
+                self.constants.get(&fixup.token.text),
+            ) {
+                (Some(address), _) => i64::from(*address),
+                (None, Some(value)) => value.floor() as i64,
+                (None, None) => {
+                    return Err(self.error(
+                        &fixup.token,
+                        OctoErrorKind::UnknownName(fixup.token.text.clone()),
+                    ));
+                }
+            };
+
+            let offset = fixup.address - usize::from(PROGRAM_START);
+            match fixup.kind {
+                FixupKind::Address => {
+                    let target = self.fit(target, 12, &fixup.token)?;
+                    let opcode = u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]]);
+                    self.rom[offset..offset + 2].copy_from_slice(&(opcode | target).to_be_bytes());
+                }
+                FixupKind::Word => {
+                    let target = self.fit(target, 16, &fixup.token)?;
+                    self.rom[offset..offset + 2].copy_from_slice(&target.to_be_bytes());
+                }
+                FixupKind::UnpackHigh(nibble) => {
+                    let [high, _] = self.fit(target, 12, &fixup.token)?.to_be_bytes();
+                    self.rom[offset + 1] = (nibble << 4) | high;
+                }
+                FixupKind::UnpackLow => {
+                    let [_, low] = self.fit(target, 12, &fixup.token)?.to_be_bytes();
+                    self.rom[offset + 1] = low;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile the whole program
+    fn run(mut self) -> Result<Assembly> {
+        // jump to `main`, removed if it comes first
+        let start = Token {
+            text: "main".to_owned(),
+            line: 1,
+            column: 1,
+        };
+        self.emit(0x10, &start)?;
+        self.emit(0x00, &start)?;
+        self.fixups.push(Fixup {
+            address: usize::from(PROGRAM_START),
+            kind: FixupKind::Address,
+            token: start.clone(),
+        });
+        self.main_jump = true;
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.statement(token)?;
+        }
+
+        if let Some((_, opening)) = self.blocks.last() {
+            return Err(self.error(opening, OctoErrorKind::Unclosed(opening.text.clone())));
+        }
+        if !self.labels.contains_key("main") {
+            return Err(self.error(&start, OctoErrorKind::MissingMain));
+        }
+        self.resolve()?;
+
+        let mut symbols = std::mem::take(&mut self.symbols);
+        for (name, address) in self.labels {
+            symbols.add_label(name, address);
+        }
+        Ok(Assembly::new(self.rom, symbols))
+    }
+}
+
+/// Compile Octo source, `file` names it in errors and in the symbol map
+pub fn compile(file: &str, source: &str) -> std::result::Result<Assembly, OctoError> {
+    Compiler::new(file, tokenize(source)).run()
+}
+
+/// Compile the Octo source file at `path`
+pub fn compile_file(path: &Path) -> std::result::Result<Assembly, OctoError> {
+    let file = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .into_owned();
+    let source = fs::read_to_string(path).map_err(|e| OctoError {
+        file: file.clone(),
+        line: 0,
+        column: 0,
+        kind: OctoErrorKind::Read(e.to_string()),
+    })?;
+    compile(&file, &source)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use chip8_core::Chip8;
+
+    use super::*;
+
+    fn words(opcodes: &[u16]) -> Vec<u8> {
+        opcodes
+            .iter()
+            .flat_map(|opcode| opcode.to_be_bytes())
+            .collect()
+    }
+
+    fn error(source: &str) -> (usize, usize, OctoErrorKind) {
+        let error = compile("game.8o", source).unwrap_err();
+        (error.line, error.column, error.kind)
+    }
+
+    #[test]
+    fn test_statements() {
+        let source = "\
+: main
+  clear
+  v0 := 0x12  v1 := v0  v2 += 3  v2 -= 1  v3 =- v2
+  v4 |= v5  v4 &= v5  v4 ^= v5  v6 >>= v7  v6 <<= v7
+  v8 := random 0x0F  v9 := delay  vA := key  delay := v1  buzzer := v1
+  i := hex v2  i := bighex v2  i += v3  bcd v4  save v5  load v5
+  save v1 - v3  load v1 - v3  saveflags v6  loadflags v6  sprite v1 v2 5
+  jump0 0x300  native 0x123
+  hires lores scroll-down 4 scroll-up 2 scroll-right scroll-left
+  plane 3  audio  pitch := v1
+  i := long data  i := data  sub  jump main  exit
+: sub
+  return
+: data
+  0xFF 0b1010 -1 # sprite
+";
+        let assembly = compile("game.8o", source).unwrap();
+
+        let mut expected = words(&[
+            0x00E0, 0x6012, 0x8100, 0x7203, 0x72FF, 0x8327, 0x8451, 0x8452, 0x8453, 0x8676, 0x867E,
+            0xC80F, 0xF907, 0xFA0A, 0xF115, 0xF118, 0xF229, 0xF230, 0xF31E, 0xF433, 0xF555, 0xF565,
+            0x5132, 0x5133, 0xF675, 0xF685, 0xD125, 0xB300, 0x0123, 0x00FF, 0x00FE, 0x00C4, 0x00D2,
+            0x00FB, 0x00FC, 0xF301, 0xF002, 0xF13A, 0xF000, 0x025A, 0xA25A, 0x2258, 0x1200, 0x00FD,
+            0x00EE,
+        ]);
+        expected.extend([0xFF, 0x0A, 0xFF]);
+        assert_eq!(assembly.rom(), expected);
+
+        let symbols = assembly.symbols();
+        assert_eq!(symbols.label_address("main"), Some(0x200));
+        assert_eq!(symbols.label_address("data"), Some(0x25A));
+        assert_eq!(symbols.source_line(0x200).unwrap().line, 2);
+        assert_eq!(symbols.source_line(0x258).unwrap().line, 13);
+        assert_eq!(symbols.source_line(0x25A), None);
+    }
+
+    #[test]
+    fn test_main_jump() {
+        let assembly = compile("game.8o", ": data 1 2\n: main clear").unwrap();
+        assert_eq!(assembly.rom(), [0x12, 0x04, 0x01, 0x02, 0x00, 0xE0]);
+
+        assert_eq!(error("clear"), (1, 1, OctoErrorKind::MissingMain));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let source = "\
+: main
+  if v0 == 1 then v1 := 2
+  if v0 != v1 begin
+    v2 := 1
+  else
+    v2 := 2
+  end
+  loop
+    v3 += 1
+    while v3 < 5
+  again
+  v4 := 1
+  loop again
+";
+        let assembly = compile("game.8o", source).unwrap();
+
+        assert_eq!(
+            assembly.rom(),
+            words(&[
+                0x4001, 0x6102, // if then
+                0x9010, 0x120C, 0x6201, 0x120E, 0x6202, // if else end
+                0x7301, 0x6F05, 0x8F37, 0x3F00, 0x121A, 0x120E, // loop while again
+                0x6401, 0x121C,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_definitions() {
+        let source = "\
+:const SPEED 3
+:calc DOUBLE { SPEED * 2 + 1 }
+:alias x v5
+:macro twice reg { reg += SPEED reg += SPEED }
+: main
+  x := DOUBLE
+  twice x
+  twice v1
+  :next target v2 := 0
+  i := target
+  :unpack 0xA target
+  :byte { HERE - 0x200 }
+  :pointer main
+  :org 0x300
+  0x3C 0x42
+";
+        let assembly = compile("game.8o", source).unwrap();
+
+        let rom = assembly.rom();
+        assert_eq!(
+            rom[..0x12],
+            words(&[
+                0x6509, 0x7503, 0x7503, 0x7103, 0x7103, 0x6200, 0xA20B, 0x60A2, 0x610B
+            ])
+        );
+        assert_eq!(rom[0x12..0x15], [0x12, 0x02, 0x00]);
+        assert_eq!(rom[0x100..], [0x3C, 0x42]);
+        assert_eq!(assembly.symbols().label_address("target"), Some(0x20B));
+        assert_eq!(assembly.symbols().source_line(0x202).unwrap().line, 7);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error(": main\n  v0 := 256"),
+            (
+                2,
+                9,
+                OctoErrorKind::OutOfRange {
+                    value: 256,
+                    bits: 8
+                }
+            )
+        );
+        assert_eq!(
+            error(": main\n  jump nowhere"),
+            (2, 8, OctoErrorKind::UnknownName("nowhere".to_owned()))
+        );
+        assert_eq!(
+            error(": main\n  if v0 == 1 begin\n  clear"),
+            (2, 3, OctoErrorKind::Unclosed("if".to_owned()))
+        );
+        assert_eq!(
+            error(": main\n  end"),
+            (2, 3, OctoErrorKind::Unmatched("end".to_owned()))
+        );
+        assert_eq!(
+            error(": main\n: main"),
+            (2, 3, OctoErrorKind::DuplicateName("main".to_owned()))
+        );
+        assert_eq!(
+            error(": main\n  v0 +="),
+            (
+                2,
+                8,
+                OctoErrorKind::Expected {
+                    expected: "number".to_owned(),
+                    found: "end of file".to_owned()
+                }
+            )
+        );
+        assert_eq!(
+            error(":macro forever { forever }\n: main forever"),
+            (2, 8, OctoErrorKind::ExpansionLimit)
+        );
+        assert!(
+            error(": main\n  sprite v0 i 5")
+                .2
+                .to_string()
+                .starts_with("expected register, found `i`")
+        );
+    }
+
+    #[test]
+    fn test_runs_on_the_machine() {
+        let source = "\
+: main
+  v0 := 3  v1 := 5  v2 := 0
+  if v0 > v1 then v2 += 2
+  if v1 >= 5 then v2 += 4
+  if v0 <= v0 then v2 += 16
+  if v1 >= 3 then v2 += 32
+  if v0 <= v1 then v2 += 1
+  if v1 < v0 then v2 += 64
+  v3 := 0
+  loop
+    v3 += 1
+    while v3 != 10
+  again
+  if v3 == 10 begin v4 := 1 else v4 := 2 end
+  loop again
+";
+        let assembly = compile("game.8o", source).unwrap();
+        let mut chip8 = Chip8::new();
+        chip8.load_program(assembly.rom()).unwrap();
+        for _ in 0..200 {
+            chip8.step().unwrap();
+        }
+
+        let registers = chip8.cpu().registers();
+        assert_eq!(registers[2], 1 + 4 + 16 + 32);
+        assert_eq!(registers[3], 10);
+        assert_eq!(registers[4], 1);
+    }
+}
@@ -0,0 +1,626 @@
+//! Emulation timing
+//!
+//! The timers run at 60 Hz and the cpu runs a fixed number of instructions per 60 Hz frame,
+//! [`DEFAULT_INSTRUCTIONS_PER_FRAME`] unless configured otherwise. With
+//! [`Timing::CosmacVip`] every instruction costs what it took the original interpreter instead,
+//! see [`vip_cycles`]. [`VirtualClock`] counts the emulated cycles and frames, so runs are
+//! reproducible regardless of the host speed.
+//! [`Scheduler`] owns one and decides from a [`TimeSource`] how many frames are due: real time
+//! frontends use the [`WallClock`], tests a [`ManualClock`]. The scheduler can run faster or slower
+//! than real time, for turbo and slow motion
+
+use std::time::{Duration, Instant};
+
+use crate::{Chip8, Chip8Error, Instruction, types::Index};
+
+/// The time interval for 60hz (timers for chip8 operate on 60hz)
+pub const TIMER_INTERVAL: Duration = Duration::from_micros(16667);
+
+/// Instructions run per 60 Hz frame by default, about the 500 Hz of the original interpreters
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 8;
+
+/// Frames run at once at most to catch up after a stall, the rest are dropped
+pub const DEFAULT_MAX_CATCH_UP: u32 = 6;
+
+/// Slowest speed of a [`Scheduler`], relative to real time
+pub const MIN_SPEED: f64 = 1.0 / 64.0;
+
+/// Fastest speed of a [`Scheduler`], relative to real time
+pub const MAX_SPEED: f64 = 64.0;
+
+/// Machine cycles of the COSMAC VIP per 60 Hz frame, its 1802 ran 8 clocks per machine cycle
+/// at 1.76 MHz
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/// Machine cycles of a frame spent on the video DMA and the display interrupt routine
+pub const VIP_DISPLAY_CYCLES: u32 = 1128;
+
+/// Machine cycles the interpreter takes to fetch and decode an instruction
+pub const VIP_FETCH_CYCLES: u32 = 40;
+
+/// How long instructions take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes the same time, a fixed number of them runs per frame
+    #[default]
+    Fixed,
+    /// Instructions take as long as on the COSMAC VIP and draws wait for the next frame
+    CosmacVip,
+}
+
+/// Approximate machine cycles the COSMAC VIP interpreter takes for `instruction`, fetch included
+///
+/// `registers` are V0..=VF before the instruction runs: skips taken, BCD digits and the sprite
+/// alignment change the cost. Instructions the VIP doesn't have cost as much as `6XNN`. Draws
+/// also wait for the display interrupt, [`VirtualClock::step`] ends the frame after them
+pub fn vip_cycles(instruction: &Instruction, registers: &[u8; 16]) -> u32 {
+    let v = |x: Index| registers[x.into_inner() as usize];
+    let skip = |taken: bool| if taken { 4 } else { 0 };
+
+    let execute = match *instruction {
+        Instruction::ClearDisplay => 24,
+        Instruction::Return
+        | Instruction::Goto { .. }
+        | Instruction::CallSubroutine { .. }
+        | Instruction::GotoPlusV0 { .. } => 23,
+        Instruction::EqConst { x, value } => 10 + skip(v(x) == value),
+        Instruction::NeqConst { x, value } => 10 + skip(v(x) != value),
+        Instruction::EqReg { x, y } => 16 + skip(v(x) == v(y)),
+        Instruction::NeqReg { x, y } => 16 + skip(v(x) != v(y)),
+        Instruction::AssignConst { .. } => 6,
+        Instruction::AddAssignConst { .. }
+        | Instruction::GetDelayTimer { .. }
+        | Instruction::AwaitKeyPress { .. }
+        | Instruction::SetDelayTimer { .. }
+        | Instruction::SetSoundTimer { .. } => 10,
+        Instruction::AssignReg { .. }
+        | Instruction::OrReg { .. }
+        | Instruction::AndReg { .. }
+        | Instruction::XorReg { .. }
+        | Instruction::AddAssignReg { .. }
+        | Instruction::SubAssignReg { .. }
+        | Instruction::RShift { .. }
+        | Instruction::SubAssignRegInverse { .. }
+        | Instruction::LShift { .. } => 44,
+        Instruction::SetI { .. } => 12,
+        Instruction::Rand { .. } => 36,
+        Instruction::DrawSprite { x, height, .. } => {
+            let row = if v(x).is_multiple_of(8) { 20 } else { 34 };
+            26 + row * u32::from(height.into_inner())
+        }
+        Instruction::KeyPressedSkip { .. } | Instruction::KeyReleasedSkip { .. } => 16,
+        Instruction::AddAssignAddress { .. } => 19,
+        Instruction::SetSpriteAddr { .. } => 20,
+        Instruction::SetBCD { x } => {
+            let vx = v(x);
+            20 + 16 * u32::from(vx / 100 + vx / 10 % 10 + vx % 10)
+        }
+        Instruction::DumpRegisters { x } | Instruction::LoadRegisters { x } => {
+            14 + 8 * (u32::from(x.into_inner()) + 1)
+        }
+        _ => 6,
+    };
+    VIP_FETCH_CYCLES + execute
+}
+
+/// Clock advanced one cpu cycle at a time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualClock {
+    /// Cpu cycles run so far
+    cycles: u64,
+    /// Timer ticks (frames) so far
+    frames: u64,
+    /// Time spent in the current frame: instructions in fixed timing, machine cycles in VIP
+    /// timing
+    frame_cycles: u32,
+    /// Cycles in a frame
+    instructions_per_frame: u32,
+    /// How long instructions take
+    timing: Timing,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::with_instructions_per_frame(DEFAULT_INSTRUCTIONS_PER_FRAME)
+    }
+}
+
+impl VirtualClock {
+    /// Create a clock at time zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a clock at time zero running `instructions_per_frame` cycles per frame, at least 1
+    pub fn with_instructions_per_frame(instructions_per_frame: u32) -> Self {
+        Self {
+            cycles: 0,
+            frames: 0,
+            frame_cycles: 0,
+            instructions_per_frame: instructions_per_frame.max(1),
+            timing: Timing::Fixed,
+        }
+    }
+
+    /// Advance by one cpu cycle, returning the number of timer ticks that became due
+    ///
+    /// That is 1 for the last cycle of a frame and 0 otherwise. In VIP timing the cycle costs a
+    /// single machine cycle, use [`step`](Self::step) to charge the real cost
+    pub fn advance(&mut self) -> u64 {
+        self.charge(1, false)
+    }
+
+    /// Run one instruction of `chip8` and advance by its cost, returning the number of timer
+    /// ticks that became due
+    ///
+    /// The caller ticks the timers, like for [`advance`](Self::advance)
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<u64, Chip8Error> {
+        let cost = match self.timing {
+            Timing::Fixed => None,
+            Timing::CosmacVip => {
+                // an opcode that doesn't decode is left to the fault policy of the machine
+                match chip8.instruction_at(chip8.cpu().program_counter()) {
+                    Ok(instruction) => {
+                        let vblank = matches!(instruction, Instruction::DrawSprite { .. });
+                        Some((vip_cycles(&instruction, chip8.cpu().registers()), vblank))
+                    }
+                    Err(_) => Some((VIP_FETCH_CYCLES, false)),
+                }
+            }
+        };
+
+        chip8.step()?;
+        Ok(match cost {
+            None => self.advance(),
+            Some((cycles, vblank)) => self.charge(cycles, vblank),
+        })
+    }
+
+    /// Count an instruction taking `cost`, waiting for the end of the frame if `vblank`
+    fn charge(&mut self, cost: u32, vblank: bool) -> u64 {
+        let budget = self.frame_budget();
+        self.cycles += 1;
+        self.frame_cycles += cost;
+        if vblank {
+            self.frame_cycles = self.frame_cycles.next_multiple_of(budget);
+        }
+
+        let ticks = self.frame_cycles / budget;
+        self.frame_cycles %= budget;
+        self.frames += u64::from(ticks);
+        u64::from(ticks)
+    }
+
+    /// Time available in a frame, in the unit of `frame_cycles`
+    fn frame_budget(&self) -> u32 {
+        match self.timing {
+            Timing::Fixed => self.instructions_per_frame,
+            Timing::CosmacVip => VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES,
+        }
+    }
+
+    /// Cpu cycles run so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Timer ticks so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Cycles in a frame
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    /// Change the cycles in a frame, at least 1. The current frame ends with the next cycle if
+    /// it already ran that many
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.instructions_per_frame = instructions_per_frame.max(1);
+        if self.timing == Timing::Fixed {
+            self.frame_cycles = self.frame_cycles.min(self.instructions_per_frame - 1);
+        }
+    }
+
+    /// How long instructions take
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Change how long instructions take, the current frame starts over
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.frame_cycles = 0;
+    }
+
+    /// Emulated time since the start, in whole frames
+    pub fn elapsed(&self) -> Duration {
+        TIMER_INTERVAL * self.frames as u32
+    }
+}
+
+/// Where a [`Scheduler`] reads the time from
+pub trait TimeSource {
+    /// Time since an arbitrary fixed point, never going backwards
+    fn now(&mut self) -> Duration;
+}
+
+impl<T: TimeSource + ?Sized> TimeSource for Box<T> {
+    fn now(&mut self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// Real time, measured from the creation of the clock
+#[derive(Debug, Clone, Copy)]
+pub struct WallClock {
+    /// Time zero
+    start: Instant,
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WallClock {
+    /// Start measuring now
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl TimeSource for WallClock {
+    fn now(&mut self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Time that only moves when told to, for tests and offline runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ManualClock {
+    /// Current time
+    now: Duration,
+}
+
+impl ManualClock {
+    /// Create a clock at time zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the time forward
+    pub fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+impl TimeSource for ManualClock {
+    fn now(&mut self) -> Duration {
+        self.now
+    }
+}
+
+/// Paces the emulation against a [`TimeSource`]
+///
+/// Owns the emulated [`VirtualClock`]: every instruction is run with [`step`](Self::step),
+/// which ticks the timers once at the end of each frame.
+/// [`frames_due`](Self::frames_due) says how many frames to run to keep up with the time
+/// source. After a stall at most [`max_catch_up`](Self::max_catch_up) frames are run at once
+/// and the rest are dropped, so a suspended laptop doesn't fast forward the game.
+///
+/// With a [`speed`](Self::speed) other than 1 the frames are due that many times as often,
+/// the catch up limit grows along so turbo doesn't drop frames.
+#[derive(Debug, Clone)]
+pub struct Scheduler<T = WallClock> {
+    /// Emulated cycles and frames
+    clock: VirtualClock,
+    /// Real time
+    time: T,
+    /// Real time of the last look at the time source
+    last_now: Duration,
+    /// Real time scaled by the speed, the frames are due on this time line
+    scaled_now: Duration,
+    /// Scaled time the next frame is due at
+    next_frame: Duration,
+    /// Scaled time passed per real time
+    speed: f64,
+    /// Frames run at once at most
+    max_catch_up: u32,
+    /// Frames dropped while catching up
+    dropped_frames: u64,
+}
+
+impl<T: TimeSource> Scheduler<T> {
+    /// Create a scheduler whose first frame is due right away
+    pub fn new(mut time: T, instructions_per_frame: u32) -> Self {
+        let now = time.now();
+        Self {
+            clock: VirtualClock::with_instructions_per_frame(instructions_per_frame),
+            time,
+            last_now: now,
+            scaled_now: now,
+            next_frame: now,
+            speed: 1.0,
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            dropped_frames: 0,
+        }
+    }
+
+    /// Emulated cycles and frames
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    /// Instructions run per frame
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.clock.instructions_per_frame()
+    }
+
+    /// Change the instructions run per frame, at least 1
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.clock
+            .set_instructions_per_frame(instructions_per_frame);
+    }
+
+    /// How long instructions take
+    pub fn timing(&self) -> Timing {
+        self.clock.timing()
+    }
+
+    /// Change how long instructions take
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.clock.set_timing(timing);
+    }
+
+    /// Frames run at once at most to catch up
+    pub fn max_catch_up(&self) -> u32 {
+        self.max_catch_up
+    }
+
+    /// Change the frames run at once at most, at least 1
+    pub fn set_max_catch_up(&mut self, frames: u32) {
+        self.max_catch_up = frames.max(1);
+    }
+
+    /// Scaled time passed per real time, 1 runs in real time
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Run `speed` times as fast as real time, within [`MIN_SPEED`] and [`MAX_SPEED`]
+    ///
+    /// The time passed so far counts at the old speed
+    pub fn set_speed(&mut self, speed: f64) {
+        self.update();
+        self.speed = if speed.is_nan() {
+            1.0
+        } else {
+            speed.clamp(MIN_SPEED, MAX_SPEED)
+        };
+    }
+
+    /// Get the time source, e.g. to advance a [`ManualClock`]
+    pub fn time_mut(&mut self) -> &mut T {
+        &mut self.time
+    }
+
+    /// Frames dropped so far because the emulation fell too far behind
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames
+    }
+
+    /// Number of frames to run now, counting them as run
+    pub fn frames_due(&mut self) -> u32 {
+        let now = self.update();
+        if now < self.next_frame {
+            return 0;
+        }
+
+        let behind = (now - self.next_frame).as_micros() / TIMER_INTERVAL.as_micros() + 1;
+        let behind = u32::try_from(behind).unwrap_or(u32::MAX);
+        let due = behind.min(self.max_catch_up * self.speed.ceil() as u32);
+        self.dropped_frames += u64::from(behind - due);
+        self.next_frame += TIMER_INTERVAL * behind;
+        due
+    }
+
+    /// Real time left until the next frame is due, zero if it is due already
+    pub fn until_next_frame(&mut self) -> Duration {
+        let now = self.update();
+        self.next_frame.saturating_sub(now).div_f64(self.speed)
+    }
+
+    /// Forget about the frames due so far, the next one is due one interval from now
+    ///
+    /// For when the emulation was stopped on purpose, e.g. paused or at a breakpoint
+    pub fn resync(&mut self) {
+        self.next_frame = self.update() + TIMER_INTERVAL;
+    }
+
+    /// Run one instruction of `chip8`, ticking its timers if it ended the frame
+    ///
+    /// Returns whether the frame ended
+    pub fn step(&mut self, chip8: &mut Chip8) -> Result<bool, Chip8Error> {
+        let ticks = self.clock.step(chip8)?;
+        for _ in 0..ticks {
+            chip8.tick_timers();
+        }
+        Ok(ticks > 0)
+    }
+
+    /// Move the scaled time forward by the real time passed since the last call, returning it
+    fn update(&mut self) -> Duration {
+        let now = self.time.now();
+        self.scaled_now += now.saturating_sub(self.last_now).mul_f64(self.speed);
+        self.last_now = now;
+        self.scaled_now
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_follow_cycles() {
+        let mut clock = VirtualClock::new();
+        let ticks: u64 = (0..480).map(|_| clock.advance()).sum();
+
+        assert_eq!(clock.cycles(), 480);
+        assert_eq!(ticks, 60);
+        assert_eq!(clock.frames(), 60);
+        assert_eq!(clock.elapsed(), TIMER_INTERVAL * 60);
+    }
+
+    #[test]
+    fn test_first_tick() {
+        let mut clock = VirtualClock::with_instructions_per_frame(3);
+        assert_eq!(clock.advance(), 0);
+        assert_eq!(clock.advance(), 0);
+        assert_eq!(clock.advance(), 1);
+
+        // shrinking the frame ends the current one on the next cycle
+        clock.advance();
+        clock.advance();
+        clock.set_instructions_per_frame(1);
+        assert_eq!(clock.advance(), 1);
+        assert_eq!(clock.frames(), 2);
+    }
+
+    #[test]
+    fn test_scheduler_catch_up() {
+        let mut scheduler = Scheduler::new(ManualClock::new(), 4);
+        assert_eq!(scheduler.frames_due(), 1);
+        assert_eq!(scheduler.frames_due(), 0);
+        assert_eq!(scheduler.until_next_frame(), TIMER_INTERVAL);
+
+        // late events don't lose frames
+        scheduler
+            .time_mut()
+            .advance(TIMER_INTERVAL * 2 + TIMER_INTERVAL / 2);
+        assert_eq!(scheduler.frames_due(), 2);
+        assert_eq!(scheduler.until_next_frame(), TIMER_INTERVAL / 2);
+        scheduler.time_mut().advance(TIMER_INTERVAL / 2);
+        assert_eq!(scheduler.frames_due(), 1);
+
+        // a long stall only catches up a few frames
+        scheduler.time_mut().advance(TIMER_INTERVAL * 100);
+        assert_eq!(scheduler.frames_due(), DEFAULT_MAX_CATCH_UP);
+        assert_eq!(
+            scheduler.dropped_frames(),
+            u64::from(100 - DEFAULT_MAX_CATCH_UP)
+        );
+        assert_eq!(scheduler.frames_due(), 0);
+
+        scheduler.time_mut().advance(TIMER_INTERVAL * 3);
+        scheduler.resync();
+        assert_eq!(scheduler.frames_due(), 0);
+        assert_eq!(scheduler.until_next_frame(), TIMER_INTERVAL);
+    }
+
+    #[test]
+    fn test_scheduler_speed() {
+        let mut scheduler = Scheduler::new(ManualClock::new(), 8);
+        assert_eq!(scheduler.frames_due(), 1);
+
+        scheduler.set_speed(4.0);
+        scheduler.time_mut().advance(TIMER_INTERVAL * 2);
+        assert_eq!(scheduler.frames_due(), 8);
+        assert_eq!(scheduler.until_next_frame(), TIMER_INTERVAL / 4);
+
+        // a stall catches up more frames at a higher speed
+        scheduler.time_mut().advance(TIMER_INTERVAL * 10);
+        assert_eq!(scheduler.frames_due(), DEFAULT_MAX_CATCH_UP * 4);
+
+        scheduler.set_speed(0.5);
+        scheduler.time_mut().advance(TIMER_INTERVAL);
+        assert_eq!(scheduler.frames_due(), 0);
+        scheduler.time_mut().advance(TIMER_INTERVAL);
+        assert_eq!(scheduler.frames_due(), 1);
+
+        scheduler.set_speed(1000.0);
+        assert_eq!(scheduler.speed(), MAX_SPEED);
+    }
+
+    #[test]
+    fn test_scheduler_ticks_timers_once_per_frame() {
+        let mut chip8 = Chip8::new();
+        // V0 = 10, DT = V0, loop
+        chip8
+            .load_program(&[0x60, 0x0A, 0xF0, 0x15, 0x12, 0x04])
+            .unwrap();
+        let mut scheduler = Scheduler::new(ManualClock::new(), 3);
+
+        let ends: Vec<bool> = (0..7)
+            .map(|_| scheduler.step(&mut chip8).unwrap())
+            .collect();
+        assert_eq!(ends, [false, false, true, false, false, true, false]);
+        assert_eq!(chip8.cpu().delay_timer(), 8);
+        assert_eq!(scheduler.clock().frames(), 2);
+    }
+
+    #[test]
+    fn test_vip_cycles() {
+        let mut registers = [0; 16];
+        registers[1] = 255;
+        registers[2] = 3;
+        let cost = |instruction| vip_cycles(&instruction, &registers) - VIP_FETCH_CYCLES;
+        let index = |x| Index::try_new(x).unwrap();
+
+        assert_eq!(cost(Instruction::SetBCD { x: index(1) }), 20 + 16 * 12);
+        assert_eq!(
+            cost(Instruction::LoadRegisters { x: index(15) }),
+            14 + 8 * 16
+        );
+        assert_eq!(
+            cost(Instruction::EqConst {
+                x: index(2),
+                value: 3
+            }),
+            14
+        );
+        assert_eq!(
+            cost(Instruction::NeqConst {
+                x: index(2),
+                value: 3
+            }),
+            10
+        );
+        assert_eq!(cost(Instruction::HighRes), 6);
+    }
+
+    #[test]
+    fn test_vip_timing() {
+        let mut chip8 = Chip8::new();
+        // V0 = 1, DRW V0, V0, 1, JP 0x200
+        chip8
+            .load_program(&[0x60, 0x01, 0xD0, 0x01, 0x12, 0x00])
+            .unwrap();
+        let mut clock = VirtualClock::new();
+        clock.set_timing(Timing::CosmacVip);
+
+        // every draw waits for the end of the frame
+        let ticks: Vec<u64> = (0..6).map(|_| clock.step(&mut chip8).unwrap()).collect();
+        assert_eq!(ticks, [0, 1, 0, 0, 1, 0]);
+        assert_eq!(clock.cycles(), 6);
+
+        // without draws the frame holds as many instructions as fit
+        let mut chip8 = Chip8::new();
+        // V0 = 1, JP 0x200
+        chip8.load_program(&[0x60, 0x01, 0x12, 0x00]).unwrap();
+        let mut clock = VirtualClock::new();
+        clock.set_timing(Timing::CosmacVip);
+        while clock.frames() == 0 {
+            clock.step(&mut chip8).unwrap();
+        }
+        let pair = 2 * VIP_FETCH_CYCLES + 6 + 23;
+        let budget = VIP_CYCLES_PER_FRAME - VIP_DISPLAY_CYCLES;
+        assert_eq!(clock.cycles(), u64::from(budget.div_ceil(pair) * 2 - 1));
+    }
+}
@@ -25,6 +25,26 @@ pub enum Instruction {
     ClearDisplay,
     /// 00EE, returns from the function
     Return,
+    /// 00CN, scroll the display down by N rows (SUPER-CHIP)
+    ScrollDown {
+        /// number of rows
+        rows: u8,
+    },
+    /// 00DN, scroll the display up by N rows (XO-CHIP)
+    ScrollUp {
+        /// number of rows
+        rows: u8,
+    },
+    /// 00FB, scroll the display right by 4 pixels (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC, scroll the display left by 4 pixels (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD, exit the interpreter (SUPER-CHIP)
+    Exit,
+    /// 00FE, switch to 64x32 low resolution mode (SUPER-CHIP)
+    LowRes,
+    /// 00FF, switch to 128x64 high resolution mode (SUPER-CHIP)
+    HighRes,
     /// 1NNN, jump to address NNN
     Goto {
         /// address to jump to
@@ -56,6 +76,24 @@ pub enum Instruction {
         /// register index for VY
         y: Index,
     },
+    /// 5XY2, save VX..=VY to memory starting at I, I is unchanged (XO-CHIP)
+    ///
+    /// X can be bigger than Y, then the registers are saved in reverse order
+    SaveRange {
+        /// register index for VX
+        x: Index,
+        /// register index for VY
+        y: Index,
+    },
+    /// 5XY3, load VX..=VY from memory starting at I, I is unchanged (XO-CHIP)
+    ///
+    /// X can be bigger than Y, then the registers are loaded in reverse order
+    LoadRange {
+        /// register index for VX
+        x: Index,
+        /// register index for VY
+        y: Index,
+    },
     /// 6XNN, set VX to NN
     AssignConst {
         /// register index for VX
@@ -158,6 +196,8 @@ pub enum Instruction {
         value: u8,
     },
     /// DXYN, draw sprite at VX, VY with height being N
+    ///
+    /// N = 0 draws a 16x16 sprite (SUPER-CHIP)
     DrawSprite {
         /// register index for VX
         x: Index,
@@ -176,6 +216,18 @@ pub enum Instruction {
         /// register index for VX
         x: Index,
     },
+    /// F000 NNNN, set I to the 16-bit address in the next word (XO-CHIP)
+    LongSetI {
+        /// address to set I to
+        address: Address,
+    },
+    /// FN01, select the bitplanes affected by drawing, clearing and scrolling (XO-CHIP)
+    SelectPlanes {
+        /// bitmask of the planes
+        planes: u8,
+    },
+    /// F002, load 16 bytes starting at I into the audio pattern buffer (XO-CHIP)
+    LoadAudioPattern,
     /// FX07, set VX to delay timer value
     GetDelayTimer {
         /// register index for VX
@@ -206,11 +258,21 @@ pub enum Instruction {
         /// register index for VX
         x: Index,
     },
+    /// FX30, I = big_sprite_addr\[VX\] (SUPER-CHIP)
+    SetBigSpriteAddr {
+        /// register index for VX
+        x: Index,
+    },
     /// FX33, store BCD of VX to I
     SetBCD {
         /// register index for VX
         x: Index,
     },
+    /// FX3A, set the audio pattern playback pitch to VX (XO-CHIP)
+    SetPitch {
+        /// register index for VX
+        x: Index,
+    },
     /// FX55, dump register V0..=VX in memory starting at I
     DumpRegisters {
         /// register index for VX
@@ -221,6 +283,16 @@ pub enum Instruction {
         /// register index for VX
         x: Index,
     },
+    /// FX75, save V0..=VX to the RPL user flags (SUPER-CHIP)
+    SaveFlags {
+        /// register index for VX
+        x: Index,
+    },
+    /// FX85, load V0..=VX from the RPL user flags (SUPER-CHIP)
+    LoadFlags {
+        /// register index for VX
+        x: Index,
+    },
 }
 
 /// Enum for all possible decode errors
@@ -229,6 +301,54 @@ pub enum DecodeError {
     #[error("Command {0:#04X} is incorrect")]
     /// The command's bytes don't correspond to any correct instruction
     NoSuchInstruction(u16),
+    #[error("Command {0:#04X} needs a second word")]
+    /// The command is the first half of a two-word instruction, use [`Instruction::decode`]
+    MissingOperand(u16),
+}
+
+impl Instruction {
+    /// Check if the opcode is followed by a 16-bit operand word (XO-CHIP `F000 NNNN`)
+    pub fn has_operand(opcode: u16) -> bool {
+        opcode == 0xF000
+    }
+
+    /// Decode an instruction from an opcode and the word following it
+    ///
+    /// The second word is only used if [`Instruction::has_operand`] is true for the opcode
+    pub fn decode(opcode: u16, operand: u16) -> Result<Self, DecodeError> {
+        if Self::has_operand(opcode) {
+            Ok(Instruction::LongSetI {
+                address: Address::new(operand),
+            })
+        } else {
+            Self::try_from(opcode)
+        }
+    }
+
+    /// Word following the opcode, the 16-bit address of `F000 NNNN`
+    pub fn operand(&self) -> Option<u16> {
+        match self {
+            Instruction::LongSetI { address } => Some(address.into_inner()),
+            _ => None,
+        }
+    }
+
+    /// Big endian bytes of the instruction, the opcode followed by the operand if there is one
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = u16::from(*self).to_be_bytes().to_vec();
+        if let Some(operand) = self.operand() {
+            bytes.extend(operand.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Size of the encoded instruction in bytes
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LongSetI { .. } => 4,
+            _ => 2,
+        }
+    }
 }
 
 #[cfg_attr(coverage_nightly, coverage(off))]
@@ -238,41 +358,57 @@ impl Display for Instruction {
             f,
             "{}",
             match self {
-                Instruction::CallMachineCode { address } => format!("SYS {address:#03X}"),
+                Instruction::CallMachineCode { address } => format!("SYS {address:#05X}"),
                 Instruction::Return => String::from("RET"),
                 Instruction::ClearDisplay => String::from("CLS"),
-                Instruction::Goto { address } => format!("JP {address:#03X}"),
-                Instruction::CallSubroutine { address } => format!("CALL {address:#03X}"),
-                Instruction::EqConst { x, value } => format!("SE V{x:X}, {value:#02X}"),
-                Instruction::NeqConst { x, value } => format!("SNE V{x:X},{value:#02X}"),
-                Instruction::EqReg { x, y } => format!("SE V{x:X}, VV{y:X}"),
-                Instruction::AssignConst { x, value } => format!("LD V{x:X}, {value:#02X}"),
-                Instruction::AddAssignConst { x, value } => format!("ADD V{x:X}, {value:#02X}"),
+                Instruction::ScrollDown { rows } => format!("SCD {rows}"),
+                Instruction::ScrollUp { rows } => format!("SCU {rows}"),
+                Instruction::ScrollRight => String::from("SCR"),
+                Instruction::ScrollLeft => String::from("SCL"),
+                Instruction::Exit => String::from("EXIT"),
+                Instruction::LowRes => String::from("LOW"),
+                Instruction::HighRes => String::from("HIGH"),
+                Instruction::Goto { address } => format!("JP {address:#05X}"),
+                Instruction::CallSubroutine { address } => format!("CALL {address:#05X}"),
+                Instruction::EqConst { x, value } => format!("SE V{x:X}, {value:#04X}"),
+                Instruction::NeqConst { x, value } => format!("SNE V{x:X}, {value:#04X}"),
+                Instruction::EqReg { x, y } => format!("SE V{x:X}, V{y:X}"),
+                Instruction::SaveRange { x, y } => format!("SAVE V{x:X}, V{y:X}"),
+                Instruction::LoadRange { x, y } => format!("LOAD V{x:X}, V{y:X}"),
+                Instruction::AssignConst { x, value } => format!("LD V{x:X}, {value:#04X}"),
+                Instruction::AddAssignConst { x, value } => format!("ADD V{x:X}, {value:#04X}"),
                 Instruction::AssignReg { x, y } => format!("LD V{x:X}, V{y:X}"),
                 Instruction::OrReg { x, y } => format!("OR V{x:X}, V{y:X}"),
                 Instruction::AndReg { x, y } => format!("AND V{x:X}, V{y:X}"),
                 Instruction::XorReg { x, y } => format!("XOR V{x:X}, V{y:X}"),
                 Instruction::AddAssignReg { x, y } => format!("ADD V{x:X}, V{y:X}"),
                 Instruction::SubAssignReg { x, y } => format!("SUB V{x:X}, V{y:X}"),
-                Instruction::RShift { x, y: _y } => format!("SHR V{x:X}"),
+                Instruction::RShift { x, y } => format!("SHR V{x:X}, V{y:X}"),
                 Instruction::SubAssignRegInverse { x, y } => format!("SUBN V{x:X}, V{y:X}"),
-                Instruction::LShift { x, y: _y } => format!("SHL V{x:X}"),
+                Instruction::LShift { x, y } => format!("SHL V{x:X}, V{y:X}"),
                 Instruction::NeqReg { x, y } => format!("SNE V{x:X}, V{y:X}"),
-                Instruction::SetI { address } => format!("LD I, {address:#03X}"),
-                Instruction::GotoPlusV0 { address } => format!("JP V0, {address:#03X}"),
-                Instruction::Rand { x, value } => format!("RND V{x:X}, {value:#02X}"),
+                Instruction::SetI { address } => format!("LD I, {address:#05X}"),
+                Instruction::GotoPlusV0 { address } => format!("JP V0, {address:#05X}"),
+                Instruction::Rand { x, value } => format!("RND V{x:X}, {value:#04X}"),
                 Instruction::DrawSprite { x, y, height } => format!("DRW V{x:X}, V{y:X}, {height}"),
                 Instruction::KeyPressedSkip { x } => format!("SKP V{x:X}"),
                 Instruction::KeyReleasedSkip { x } => format!("SKNP V{x:X}"),
+                Instruction::LongSetI { address } => format!("LD I, LONG {address:#06X}"),
+                Instruction::SelectPlanes { planes } => format!("PLANE {planes}"),
+                Instruction::LoadAudioPattern => String::from("AUDIO"),
                 Instruction::GetDelayTimer { x } => format!("LD V{x:X}, DT"),
                 Instruction::AwaitKeyPress { x } => format!("LD V{x:X}, K"),
                 Instruction::SetDelayTimer { x } => format!("LD DT, V{x:X}"),
                 Instruction::SetSoundTimer { x } => format!("LD ST, V{x:X}"),
                 Instruction::AddAssignAddress { x } => format!("ADD I, V{x:X}"),
                 Instruction::SetSpriteAddr { x } => format!("LD F, V{x:X}"),
+                Instruction::SetBigSpriteAddr { x } => format!("LD HF, V{x:X}"),
                 Instruction::SetBCD { x } => format!("LD B, V{x:X}"),
+                Instruction::SetPitch { x } => format!("LD PITCH, V{x:X}"),
                 Instruction::DumpRegisters { x } => format!("LD [I], V{x:X}"),
                 Instruction::LoadRegisters { x } => format!("LD V{x:X}, [I]"),
+                Instruction::SaveFlags { x } => format!("LD R, V{x:X}"),
+                Instruction::LoadFlags { x } => format!("LD V{x:X}, R"),
             }
         )
     }
@@ -292,12 +428,21 @@ impl TryFrom<u16> for Instruction {
         let instruction = match parts {
             [0, 0, 0xE, 0xE] => Instruction::Return,
             [0, 0, 0xE, 0] => Instruction::ClearDisplay,
+            [0, 0, 0xC, n] => Instruction::ScrollDown { rows: n },
+            [0, 0, 0xD, n] => Instruction::ScrollUp { rows: n },
+            [0, 0, 0xF, 0xB] => Instruction::ScrollRight,
+            [0, 0, 0xF, 0xC] => Instruction::ScrollLeft,
+            [0, 0, 0xF, 0xD] => Instruction::Exit,
+            [0, 0, 0xF, 0xE] => Instruction::LowRes,
+            [0, 0, 0xF, 0xF] => Instruction::HighRes,
             [0, n1, n2, n3] => op_addr!(CallMachineCode, n1, n2, n3),
             [1, n1, n2, n3] => op_addr!(Goto, n1, n2, n3),
             [2, n1, n2, n3] => op_addr!(CallSubroutine, n1, n2, n3),
             [3, x, n1, n2] => op_regconst!(EqConst, x, n1, n2),
             [4, x, n1, n2] => op_regconst!(NeqConst, x, n1, n2),
             [5, x, y, 0] => op_reg2!(EqReg, x, y),
+            [5, x, y, 2] => op_reg2!(SaveRange, x, y),
+            [5, x, y, 3] => op_reg2!(LoadRange, x, y),
             [6, x, n1, n2] => op_regconst!(AssignConst, x, n1, n2),
             [7, x, n1, n2] => op_regconst!(AddAssignConst, x, n1, n2),
             [8, x, y, 0] => op_reg2!(AssignReg, x, y),
@@ -316,15 +461,22 @@ impl TryFrom<u16> for Instruction {
             [0xD, x, y, n] => op_reg3!(DrawSprite, x, y, n),
             [0xE, x, 9, 0xE] => op_reg1!(KeyPressedSkip, x),
             [0xE, x, 0xA, 1] => op_reg1!(KeyReleasedSkip, x),
+            [0xF, 0, 0, 0] => return Err(DecodeError::MissingOperand(value)),
+            [0xF, n, 0, 1] => Instruction::SelectPlanes { planes: n },
+            [0xF, 0, 0, 2] => Instruction::LoadAudioPattern,
             [0xF, x, 0, 7] => op_reg1!(GetDelayTimer, x),
             [0xF, x, 0, 0xA] => op_reg1!(AwaitKeyPress, x),
             [0xF, x, 1, 5] => op_reg1!(SetDelayTimer, x),
             [0xF, x, 1, 8] => op_reg1!(SetSoundTimer, x),
             [0xF, x, 1, 0xE] => op_reg1!(AddAssignAddress, x),
             [0xF, x, 2, 9] => op_reg1!(SetSpriteAddr, x),
+            [0xF, x, 3, 0] => op_reg1!(SetBigSpriteAddr, x),
             [0xF, x, 3, 3] => op_reg1!(SetBCD, x),
+            [0xF, x, 3, 0xA] => op_reg1!(SetPitch, x),
             [0xF, x, 5, 5] => op_reg1!(DumpRegisters, x),
             [0xF, x, 6, 5] => op_reg1!(LoadRegisters, x),
+            [0xF, x, 7, 5] => op_reg1!(SaveFlags, x),
+            [0xF, x, 8, 5] => op_reg1!(LoadFlags, x),
             _ => {
                 return Err(DecodeError::NoSuchInstruction(value));
             }
@@ -333,3 +485,73 @@ impl TryFrom<u16> for Instruction {
         Ok(instruction)
     }
 }
+
+/// Encode into the opcode, the inverse of the [`TryFrom<u16>`] decoding
+///
+/// `F000 NNNN` encodes to `F000`, its address is the [`Instruction::operand`].
+/// Fields wider than their nibbles are truncated
+impl From<Instruction> for u16 {
+    fn from(instruction: Instruction) -> Self {
+        let x = |x: Index| u16::from(x.into_inner()) << 8;
+        let xy = |op: u16, vx: Index, vy: Index, n: u16| {
+            (op << 12) | x(vx) | (u16::from(vy.into_inner()) << 4) | n
+        };
+        let xnn = |op: u16, vx: Index, value: u8| (op << 12) | x(vx) | u16::from(value);
+        let nnn = |op: u16, address: Address| (op << 12) | (address.into_inner() & 0xFFF);
+        let nibble = |value: u8| u16::from(value & 0xF);
+
+        match instruction {
+            Instruction::CallMachineCode { address } => nnn(0, address),
+            Instruction::ClearDisplay => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown { rows } => 0x00C0 | nibble(rows),
+            Instruction::ScrollUp { rows } => 0x00D0 | nibble(rows),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Goto { address } => nnn(1, address),
+            Instruction::CallSubroutine { address } => nnn(2, address),
+            Instruction::EqConst { x, value } => xnn(3, x, value),
+            Instruction::NeqConst { x, value } => xnn(4, x, value),
+            Instruction::EqReg { x, y } => xy(5, x, y, 0),
+            Instruction::SaveRange { x, y } => xy(5, x, y, 2),
+            Instruction::LoadRange { x, y } => xy(5, x, y, 3),
+            Instruction::AssignConst { x, value } => xnn(6, x, value),
+            Instruction::AddAssignConst { x, value } => xnn(7, x, value),
+            Instruction::AssignReg { x, y } => xy(8, x, y, 0),
+            Instruction::OrReg { x, y } => xy(8, x, y, 1),
+            Instruction::AndReg { x, y } => xy(8, x, y, 2),
+            Instruction::XorReg { x, y } => xy(8, x, y, 3),
+            Instruction::AddAssignReg { x, y } => xy(8, x, y, 4),
+            Instruction::SubAssignReg { x, y } => xy(8, x, y, 5),
+            Instruction::RShift { x, y } => xy(8, x, y, 6),
+            Instruction::SubAssignRegInverse { x, y } => xy(8, x, y, 7),
+            Instruction::LShift { x, y } => xy(8, x, y, 0xE),
+            Instruction::NeqReg { x, y } => xy(9, x, y, 0),
+            Instruction::SetI { address } => nnn(0xA, address),
+            Instruction::GotoPlusV0 { address } => nnn(0xB, address),
+            Instruction::Rand { x, value } => xnn(0xC, x, value),
+            Instruction::DrawSprite { x, y, height } => xy(0xD, x, y, nibble(height.into_inner())),
+            Instruction::KeyPressedSkip { x: vx } => 0xE09E | x(vx),
+            Instruction::KeyReleasedSkip { x: vx } => 0xE0A1 | x(vx),
+            Instruction::LongSetI { .. } => 0xF000,
+            Instruction::SelectPlanes { planes } => 0xF001 | (nibble(planes) << 8),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::GetDelayTimer { x: vx } => 0xF007 | x(vx),
+            Instruction::AwaitKeyPress { x: vx } => 0xF00A | x(vx),
+            Instruction::SetDelayTimer { x: vx } => 0xF015 | x(vx),
+            Instruction::SetSoundTimer { x: vx } => 0xF018 | x(vx),
+            Instruction::AddAssignAddress { x: vx } => 0xF01E | x(vx),
+            Instruction::SetSpriteAddr { x: vx } => 0xF029 | x(vx),
+            Instruction::SetBigSpriteAddr { x: vx } => 0xF030 | x(vx),
+            Instruction::SetBCD { x: vx } => 0xF033 | x(vx),
+            Instruction::SetPitch { x: vx } => 0xF03A | x(vx),
+            Instruction::DumpRegisters { x: vx } => 0xF055 | x(vx),
+            Instruction::LoadRegisters { x: vx } => 0xF065 | x(vx),
+            Instruction::SaveFlags { x: vx } => 0xF075 | x(vx),
+            Instruction::LoadFlags { x: vx } => 0xF085 | x(vx),
+        }
+    }
+}
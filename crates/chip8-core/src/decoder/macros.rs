@@ -10,7 +10,7 @@
 /// where the second nibble (`X`) specifies a single register index.
 ///
 /// # Example
-/// ```
+/// ```ignore
 /// op_reg1!(Cls, x_nibble);
 /// ```
 macro_rules! op_reg1 {
@@ -30,7 +30,7 @@ macro_rules! op_reg1 {
 /// (`X` and `Y`) are register indices.
 ///
 /// # Example
-/// ```
+/// ```ignore
 /// op_reg2!(Add, x_nibble, y_nibble);
 /// ```
 macro_rules! op_reg2 {
@@ -52,7 +52,7 @@ macro_rules! op_reg2 {
 /// and `Z` represents a small immediate (e.g., sprite height).
 ///
 /// # Example
-/// ```
+/// ```ignore
 /// op_reg3!(Draw, x_nibble, y_nibble, height_nibble);
 /// ```
 macro_rules! op_reg3 {
@@ -76,7 +76,7 @@ macro_rules! op_reg3 {
 /// represent a 12-bit address.
 ///
 /// # Example
-/// ```
+/// ```ignore
 /// op_addr!(Jump, n1, n2, n3);
 /// ```
 macro_rules! op_addr {
@@ -93,7 +93,7 @@ macro_rules! op_addr {
 /// the last two nibbles form an 8-bit constant.
 ///
 /// # Example
-/// ```
+/// ```ignore
 /// op_regconst!(LoadConst, x_nibble, n1, n2);
 /// ```
 macro_rules! op_regconst {
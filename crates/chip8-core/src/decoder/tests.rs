@@ -31,6 +31,60 @@ fn test_return_correct() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_encode_every_opcode() {
+    for opcode in 0..=u16::MAX {
+        if let Ok(instruction) = Instruction::try_from(opcode) {
+            assert_eq!(u16::from(instruction), opcode, "{instruction}");
+        }
+    }
+}
+
+#[test_case(0x4A12, "SNE VA, 0x12" ; "skip not equal const")]
+#[test_case(0x5120, "SE V1, V2" ; "skip equal registers")]
+#[test_case(0x8346, "SHR V3, V4" ; "shift right")]
+#[test_case(0x834E, "SHL V3, V4" ; "shift left")]
+#[test_case(0x22A4, "CALL 0x2A4" ; "call")]
+#[test_case(0xD125, "DRW V1, V2, 5" ; "draw")]
+fn test_display(opcode: u16, expected: &str) {
+    assert_eq!(Instruction::try_from(opcode).unwrap().to_string(), expected);
+}
+
+#[test_case(0x00FB, Instruction::ScrollRight ; "scroll right")]
+#[test_case(0x00FC, Instruction::ScrollLeft ; "scroll left")]
+#[test_case(0x00FD, Instruction::Exit ; "exit")]
+#[test_case(0x00FE, Instruction::LowRes ; "low resolution")]
+#[test_case(0x00FF, Instruction::HighRes ; "high resolution")]
+fn test_superchip_display_control(opcode: u16, expected: Instruction) {
+    assert_eq!(Instruction::try_from(opcode).unwrap(), expected);
+}
+
+#[test_case(0xF001, Instruction::SelectPlanes { planes: 0 } ; "no planes")]
+#[test_case(0xF301, Instruction::SelectPlanes { planes: 3 } ; "both planes")]
+#[test_case(0xF002, Instruction::LoadAudioPattern ; "audio pattern")]
+fn test_xochip_no_registers(opcode: u16, expected: Instruction) {
+    assert_eq!(Instruction::try_from(opcode).unwrap(), expected);
+}
+
+#[test]
+fn test_long_set_i() {
+    assert!(matches!(
+        Instruction::try_from(0xF000),
+        Err(DecodeError::MissingOperand(0xF000))
+    ));
+
+    let instruction = Instruction::decode(0xF000, 0xBEEF).unwrap();
+    assert!(matches!(
+        instruction,
+        Instruction::LongSetI { address } if address.into_inner() == 0xBEEF
+    ));
+    assert_eq!(instruction.size(), 4);
+
+    let instruction = Instruction::decode(0x00E0, 0xF000).unwrap();
+    assert_eq!(instruction, Instruction::ClearDisplay);
+    assert_eq!(instruction.size(), 2);
+}
+
 #[test_case(0xFFFF ; "incorrect opcode 1")]
 #[test_case(0x8FAB ; "incorrect opcode 2")]
 #[test_case(0xE01B ; "incorrect opcode 3")]
@@ -45,6 +99,8 @@ proptest! {
     fn test_call_machine_code(n1 in HEX, n2 in HEX, n3 in HEX) {
         prop_assume!([n1, n2, n3] != [0, 0xE, 0xE]);
         prop_assume!([n1, n2, n3] != [0, 0xE, 0]);
+        prop_assume!(!(n1 == 0 && (n2 == 0xC || n2 == 0xD)));
+        prop_assume!(!(n1 == 0 && n2 == 0xF && n3 >= 0xB));
 
         let code = from_nibbles([0, n1, n2, n3]);
         let correct_address = (n1 << 8) | (n2 << 4) | n3 ;
@@ -552,4 +608,88 @@ proptest! {
 
         prop_assert!(ok, "code: {:#04X}, result: {:?}", code, result);
     }
+
+    #[test]
+    fn test_scroll_down(n in HEX) {
+        let code = from_nibbles([0, 0, 0xC, n]);
+        let result = Instruction::try_from(code).unwrap();
+
+        prop_assert_eq!(result, Instruction::ScrollDown { rows: n as u8 });
+    }
+
+    #[test]
+    fn test_load_big_sprite(x in HEX) {
+        let code = from_nibbles([0xF, x, 3, 0]);
+        let result = Instruction::try_from(code).unwrap();
+        let correct_x = unsafe {Index::new_unchecked(x as u8)};
+
+        prop_assert_eq!(result, Instruction::SetBigSpriteAddr { x: correct_x });
+    }
+
+    #[test]
+    fn test_save_flags(x in HEX) {
+        let code = from_nibbles([0xF, x, 7, 5]);
+        let result = Instruction::try_from(code).unwrap();
+        let correct_x = unsafe {Index::new_unchecked(x as u8)};
+
+        prop_assert_eq!(result, Instruction::SaveFlags { x: correct_x });
+    }
+
+    #[test]
+    fn test_load_flags(x in HEX) {
+        let code = from_nibbles([0xF, x, 8, 5]);
+        let result = Instruction::try_from(code).unwrap();
+        let correct_x = unsafe {Index::new_unchecked(x as u8)};
+
+        prop_assert_eq!(result, Instruction::LoadFlags { x: correct_x });
+    }
+
+    #[test]
+    fn test_scroll_up(n in HEX) {
+        let code = from_nibbles([0, 0, 0xD, n]);
+        let result = Instruction::try_from(code).unwrap();
+
+        prop_assert_eq!(result, Instruction::ScrollUp { rows: n as u8 });
+    }
+
+    #[test]
+    fn test_save_range(x in HEX, y in HEX) {
+        let code = from_nibbles([5, x, y, 2]);
+        let result = Instruction::try_from(code).unwrap();
+        let correct_x = unsafe {Index::new_unchecked(x as u8)};
+        let correct_y = unsafe {Index::new_unchecked(y as u8)};
+
+        prop_assert_eq!(result, Instruction::SaveRange { x: correct_x, y: correct_y });
+    }
+
+    #[test]
+    fn test_load_range(x in HEX, y in HEX) {
+        let code = from_nibbles([5, x, y, 3]);
+        let result = Instruction::try_from(code).unwrap();
+        let correct_x = unsafe {Index::new_unchecked(x as u8)};
+        let correct_y = unsafe {Index::new_unchecked(y as u8)};
+
+        prop_assert_eq!(result, Instruction::LoadRange { x: correct_x, y: correct_y });
+    }
+
+    #[test]
+    fn test_set_pitch(x in HEX) {
+        let code = from_nibbles([0xF, x, 3, 0xA]);
+        let result = Instruction::try_from(code).unwrap();
+        let correct_x = unsafe {Index::new_unchecked(x as u8)};
+
+        prop_assert_eq!(result, Instruction::SetPitch { x: correct_x });
+    }
+
+    #[test]
+    fn test_encode_round_trip(opcode in any::<u16>(), operand in any::<u16>()) {
+        if let Ok(instruction) = Instruction::decode(opcode, operand) {
+            prop_assert_eq!(u16::from(instruction), opcode);
+            prop_assert_eq!(Instruction::decode(opcode, instruction.operand().unwrap_or(operand)).unwrap(), instruction);
+
+            let bytes = instruction.encode();
+            prop_assert_eq!(bytes.len(), instruction.size() as usize);
+            prop_assert_eq!(&bytes[..2], &opcode.to_be_bytes());
+        }
+    }
 }
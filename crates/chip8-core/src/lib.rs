@@ -0,0 +1,29 @@
+//! Chip8 emulation core
+//!
+//! Emulates CHIP-8, SUPER-CHIP 1.1 and XO-CHIP without depending on any windowing or audio
+//! library. Frontends drive a [`Chip8`] with [`Chip8::step`] and [`Chip8::tick_timers`],
+//! forward key presses with [`Chip8::set_key_state`] and read the screen back with
+//! [`Chip8::planes_snapshot`]. [`Chip8Builder`] sets up machines with other fonts, memory layouts
+//! or a fixed seed, [`machine::inspect`] reads registers, stack, memory, keys and display without
+//! touching the machine. [`symbols::SymbolMap`] maps rom addresses back to assembler
+//! labels and source lines for debuggers, [`trace::Tracer`] logs every executed instruction.
+//!
+//! Optional pieces behind cargo features:
+//! - `rewind` (default): [`machine::rewind::RewindBuffer`], a bounded history of save states
+
+#![warn(missing_docs)]
+#![warn(clippy::missing_docs_in_private_items)]
+#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+
+pub mod clock;
+pub mod decoder;
+pub mod machine;
+pub mod symbols;
+pub mod trace;
+pub mod types;
+
+pub use decoder::instruction::{DecodeError, Instruction};
+pub use machine::{
+    Chip8, Chip8Error, ExecResult, builder::Chip8Builder, fault::FaultPolicy, platform::Platform,
+    quirks::Quirks, state::StateError,
+};
@@ -0,0 +1,109 @@
+//! XO-CHIP audio implementation
+//!
+//! Instead of a fixed buzzer XO-CHIP plays a 128-bit pattern in a loop while the sound timer
+//! is active, at a rate controlled by the pitch register
+
+use tklog::debug;
+
+use crate::machine::state::{StateError, StateReader, StateWriter};
+
+/// Pitch at which the pattern is played at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Audio pattern buffer and pitch register
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Audio {
+    /// 1-bit samples, most significant bit first, `None` until `F002` is executed
+    pattern: Option<[u8; 16]>,
+    /// Pitch register
+    pitch: u8,
+}
+
+impl Audio {
+    /// Create an audio unit with no pattern loaded
+    pub fn new() -> Self {
+        Self {
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+        }
+    }
+
+    /// Replace the pattern buffer
+    pub fn set_pattern(&mut self, pattern: [u8; 16]) {
+        debug!(format!("Audio pattern was set to {pattern:02X?}"));
+        self.pattern = Some(pattern);
+    }
+
+    /// Get the pattern buffer, if the program has loaded one
+    pub fn pattern(&self) -> Option<&[u8; 16]> {
+        self.pattern.as_ref()
+    }
+
+    /// Set the pitch register
+    pub fn set_pitch(&mut self, pitch: u8) {
+        debug!("Audio pitch was set to ", pitch);
+        self.pitch = pitch;
+    }
+
+    /// Get the pitch register
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Append the audio unit to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.pattern.is_some());
+        if let Some(pattern) = &self.pattern {
+            writer.bytes(pattern);
+        }
+        writer.u8(self.pitch);
+    }
+
+    /// Read the audio unit from a save state
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let pattern = if reader.bool("audio pattern flag")? {
+            Some(reader.array()?)
+        } else {
+            None
+        };
+        let pitch = reader.u8()?;
+
+        Ok(Self { pattern, pitch })
+    }
+
+    /// Rate at which the pattern bits are played, in bits per second
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2_f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let audio = Audio::new();
+        assert!(audio.pattern().is_none());
+        assert_eq!(audio.playback_rate(), 4000.0);
+    }
+
+    #[test]
+    fn test_pitch() {
+        let mut audio = Audio::new();
+
+        audio.set_pitch(DEFAULT_PITCH + 48);
+        assert_eq!(audio.playback_rate(), 8000.0);
+
+        audio.set_pitch(DEFAULT_PITCH - 48);
+        assert_eq!(audio.playback_rate(), 2000.0);
+    }
+}
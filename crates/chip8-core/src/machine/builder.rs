@@ -0,0 +1,317 @@
+//! Fully configured machines
+//!
+//! [`Chip8::new`] and friends build the machine every platform had: fonts at 0x000, programs at
+//! 0x200, a 16 entry stack and a random seed. [`Chip8Builder`] changes any of that, and with a
+//! seed two machines built from the same settings run the same program identically.
+
+use rand::{RngCore, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    machine::{
+        Chip8,
+        cpu::Cpu,
+        fault::FaultPolicy,
+        memory::{BIG_DIGIT_SPRITES, BigFont, DIGIT_SPRITES, FONTS_SIZE, Font, Memory},
+        platform::Platform,
+        quirks::Quirks,
+        rng::SplitMix64,
+        state::{StateError, StateReader, StateWriter},
+    },
+    types::Index,
+};
+
+/// Largest memory, every address has to fit in 16 bits
+pub const MAX_MEMORY_SIZE: usize = 1 << 16;
+
+/// Deepest stack, the depth is stored in a byte
+pub const MAX_STACK_DEPTH: usize = u8::MAX as usize;
+
+/// Enum for all the settings a machine can't be built with
+#[derive(Debug, Error, PartialEq, Eq)]
+#[must_use]
+pub enum BuildError {
+    #[error("Memory size {0:#X} is out of range, it has to be at most 0x10000 bytes")]
+    /// The memory is empty or has addresses that don't fit in 16 bits
+    MemorySize(usize),
+    #[error("Load address {0:#05X} is outside of memory")]
+    /// Programs would be loaded past the end of memory
+    LoadAddress(u16),
+    #[error("Fonts at {0:#05X} don't fit below the load address")]
+    /// The fonts would overlap the program, or the memory past the end
+    FontAddress(u16),
+    #[error("Stack depth {0} is out of range, it has to be between 1 and 255")]
+    /// The stack can't hold a single return address, or its depth doesn't fit in a byte
+    StackDepth(usize),
+}
+
+/// Where things are in memory and how deep the stack is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Memory size in bytes
+    pub memory_size: usize,
+    /// Address of the small font, the big one follows right after it
+    pub font_address: u16,
+    /// Address programs are loaded at and start from, memory below it can't be written
+    pub load_address: u16,
+    /// Return addresses the stack holds
+    pub stack_depth: usize,
+}
+
+impl Layout {
+    /// Usual layout for memory of the given size: fonts at 0x000, programs at 0x200 and a
+    /// 16 entry stack
+    pub fn with_memory_size(memory_size: usize) -> Self {
+        Self {
+            memory_size,
+            font_address: 0,
+            load_address: 0x200,
+            stack_depth: 16,
+        }
+    }
+
+    /// Check that the fonts and the program fit in memory without overlapping
+    pub fn validate(&self) -> Result<(), BuildError> {
+        if self.memory_size == 0 || self.memory_size > MAX_MEMORY_SIZE {
+            return Err(BuildError::MemorySize(self.memory_size));
+        }
+        if self.load_address as usize >= self.memory_size {
+            return Err(BuildError::LoadAddress(self.load_address));
+        }
+        if self.font_address as usize + FONTS_SIZE > self.load_address as usize {
+            return Err(BuildError::FontAddress(self.font_address));
+        }
+        if !(1..=MAX_STACK_DEPTH).contains(&self.stack_depth) {
+            return Err(BuildError::StackDepth(self.stack_depth));
+        }
+        Ok(())
+    }
+
+    /// Append the layout to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.memory_size as u32);
+        writer.u16(self.font_address);
+        writer.u16(self.load_address);
+        writer.u8(self.stack_depth as u8);
+    }
+
+    /// Read a valid layout from a save state
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let layout = Self {
+            memory_size: reader.u32()? as usize,
+            font_address: reader.u16()?,
+            load_address: reader.u16()?,
+            stack_depth: reader.u8()? as usize,
+        };
+        layout
+            .validate()
+            .map_err(|_| StateError::InvalidValue("layout"))?;
+        Ok(layout)
+    }
+}
+
+/// Initial contents of the registers or the memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// Every byte holds the value
+    Value(u8),
+    /// Random bytes, reproducible with [`Chip8Builder::seed`]. They don't change what `CXNN`
+    /// draws
+    Random,
+}
+
+impl Default for Fill {
+    fn default() -> Self {
+        Self::Value(0)
+    }
+}
+
+impl Fill {
+    /// Fill the bytes
+    fn apply(self, bytes: &mut [u8], random: &mut SplitMix64) {
+        match self {
+            Self::Value(value) => bytes.fill(value),
+            Self::Random => random.fill_bytes(bytes),
+        }
+    }
+}
+
+/// Builder of a [`Chip8`] with every setting under control
+///
+/// ```ignore
+/// let mut chip8 = Chip8Builder::new()
+///     .platform(Platform::SuperChip)
+///     .seed(42)
+///     .load_address(0x600)
+///     .build()?;
+/// chip8.load_program(&rom)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Chip8Builder {
+    /// Emulated platform
+    platform: Platform,
+    /// Interpretation of the ambiguous instructions
+    quirks: Quirks,
+    /// What happens when the program faults
+    fault_policy: FaultPolicy,
+    /// Seed of the random number generator, drawn from the thread generator if `None`
+    seed: Option<u64>,
+    /// 4x5 hex digits
+    font: Font,
+    /// 8x10 hex digits
+    big_font: BigFont,
+    /// Memory size, the platform's if `None`
+    memory_size: Option<usize>,
+    /// Address of the fonts
+    font_address: u16,
+    /// Address programs are loaded at
+    load_address: u16,
+    /// Return addresses the stack holds
+    stack_depth: usize,
+    /// Initial V0..=VF
+    registers: Fill,
+    /// Initial memory outside of the fonts
+    memory: Fill,
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        let layout = Layout::with_memory_size(Platform::default().memory_size());
+        Self {
+            platform: Platform::default(),
+            quirks: Quirks::default(),
+            fault_policy: FaultPolicy::default(),
+            seed: None,
+            font: DIGIT_SPRITES,
+            big_font: BIG_DIGIT_SPRITES,
+            memory_size: None,
+            font_address: layout.font_address,
+            load_address: layout.load_address,
+            stack_depth: layout.stack_depth,
+            registers: Fill::default(),
+            memory: Fill::default(),
+        }
+    }
+}
+
+impl Chip8Builder {
+    /// Builder of the same machine as [`Chip8::new`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emulate the platform, its memory size is used unless [`memory_size`](Self::memory_size)
+    /// is set
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Interpret the ambiguous instructions with the quirks
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Handle faults with the policy
+    pub fn fault_policy(mut self, policy: FaultPolicy) -> Self {
+        self.fault_policy = policy;
+        self
+    }
+
+    /// Seed the random number generator, making `CXNN` and random fills reproducible
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Replace the 4x5 hex digits `FX29` points at
+    pub fn font(mut self, font: Font) -> Self {
+        self.font = font;
+        self
+    }
+
+    /// Replace the 8x10 hex digits `FX30` points at
+    pub fn big_font(mut self, big_font: BigFont) -> Self {
+        self.big_font = big_font;
+        self
+    }
+
+    /// Put the fonts at the address, they have to fit below the load address
+    pub fn font_address(mut self, address: u16) -> Self {
+        self.font_address = address;
+        self
+    }
+
+    /// Load programs at the address and start them there, e.g. 0x600 for the ETI 660
+    pub fn load_address(mut self, address: u16) -> Self {
+        self.load_address = address;
+        self
+    }
+
+    /// Let the stack hold `depth` return addresses, e.g. 12 like the COSMAC VIP
+    pub fn stack_depth(mut self, depth: usize) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    /// Give the machine `size` bytes of memory instead of the platform's
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+
+    /// Start V0..=VF with the fill instead of zeros
+    pub fn register_fill(mut self, fill: Fill) -> Self {
+        self.registers = fill;
+        self
+    }
+
+    /// Start the memory outside of the fonts with the fill instead of zeros
+    pub fn memory_fill(mut self, fill: Fill) -> Self {
+        self.memory = fill;
+        self
+    }
+
+    /// Layout the machine will have
+    pub fn layout(&self) -> Layout {
+        Layout {
+            memory_size: self
+                .memory_size
+                .unwrap_or_else(|| self.platform.memory_size()),
+            font_address: self.font_address,
+            load_address: self.load_address,
+            stack_depth: self.stack_depth,
+        }
+    }
+
+    /// Build the machine, ready for [`Chip8::load_program`]
+    pub fn build(self) -> Result<Chip8, BuildError> {
+        let layout = self.layout();
+        layout.validate()?;
+
+        let mut cpu = Cpu::with_layout(&layout);
+        if let Some(seed) = self.seed {
+            cpu.seed_random(seed);
+        }
+        // the fills draw from a generator of their own, so they don't shift what CXNN draws
+        let mut random = SplitMix64::from_rng(&mut cpu.random_engine.clone());
+
+        let mut registers = [0; 16];
+        self.registers.apply(&mut registers, &mut random);
+        for (index, value) in (0..).zip(registers) {
+            *cpu.vx(Index::try_new(index).unwrap()) = value;
+        }
+
+        let mut data = vec![0; layout.memory_size];
+        self.memory.apply(&mut data, &mut random);
+        let memory = Memory::with_layout(data, &layout, &self.font, &self.big_font);
+
+        let mut chip8 = Chip8::with_platform(self.platform, self.quirks);
+        chip8.cpu = cpu;
+        chip8.memory = memory;
+        chip8.layout = layout;
+        chip8.set_fault_policy(self.fault_policy);
+        Ok(chip8)
+    }
+}
@@ -0,0 +1,586 @@
+//! Chip8 cpu implementation
+
+use crate::{
+    machine::{
+        builder::Layout,
+        rng::SplitMix64,
+        state::{StateError, StateReader, StateWriter},
+    },
+    types::Index,
+};
+
+use rand::{Rng, SeedableRng, rng};
+use thiserror::Error;
+
+use tklog::{debug, error};
+
+/// Store register and stack state\
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cpu {
+    /// General purpose registers, V0 through VF, 8 bit each
+    general: [u8; 16],
+    /// Address register
+    address: u16,
+    /// Delay timer
+    delay_timer: u8,
+    /// Sound timer
+    sound_timer: u8,
+    /// Program counter
+    program_counter: u16,
+    /// Return addresses, the most recent one last
+    stack: Vec<u16>,
+    /// Return addresses the stack holds
+    stack_depth: usize,
+    /// RPL user flags (SUPER-CHIP), survive the register file being overwritten
+    rpl_flags: [u8; 16],
+    /// Size of the memory PC and I have to point into
+    memory_size: usize,
+    /// PC and I wrap around past the end of memory instead of being an error
+    wrap: bool,
+    /// Random engine for reproducible randomness
+    pub(crate) random_engine: SplitMix64,
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu::new()
+    }
+}
+
+/// Enum of possible CPU errors
+#[derive(Debug, Error)]
+#[must_use]
+pub enum CpuError {
+    #[error("Stack limit was reached")]
+    /// Stack is limited to 16 entries, unless the machine was built with another depth
+    StackLimitReached,
+    #[error("Stack is empty")]
+    /// Stack is empty, but pop was executed
+    StackEmpty,
+    #[error("Address out of range")]
+    /// Address has to point inside the memory (2^12 = 4096 bytes, 2^16 on XO-CHIP)
+    AddressOutOfRange,
+    #[error("Program counter out of range")]
+    /// Program counter has to point inside the memory (2^12 = 4096 bytes, 2^16 on XO-CHIP)
+    PCOutOfRange,
+}
+
+impl Cpu {
+    /// Create a new CPU for 4 KiB of memory
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_memory_size(1 << 12)
+    }
+
+    /// Create a new CPU for memory of the given size
+    #[must_use]
+    pub fn with_memory_size(memory_size: usize) -> Self {
+        Self::with_layout(&Layout::with_memory_size(memory_size))
+    }
+
+    /// Create a new CPU starting at the load address of the layout
+    #[must_use]
+    pub(crate) fn with_layout(layout: &Layout) -> Self {
+        Self {
+            general: [0; 16],
+            address: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            program_counter: layout.load_address,
+            stack: Vec::with_capacity(layout.stack_depth),
+            stack_depth: layout.stack_depth,
+            rpl_flags: [0; 16],
+            memory_size: layout.memory_size,
+            wrap: false,
+            random_engine: SplitMix64::from_rng(&mut rng()),
+        }
+    }
+
+    /// Get all the general purpose registers, V0 to VF
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.general
+    }
+
+    /// Get a general purpose register without changing it, see [`Cpu::vx`] for writing
+    pub fn register(&self, x: Index) -> u8 {
+        self.general[x.into_inner() as usize]
+    }
+
+    /// Get the RPL user flags saved by `FX75`
+    pub fn rpl_flags(&self) -> &[u8; 16] {
+        &self.rpl_flags
+    }
+
+    /// Get the number of addresses on the stack
+    pub fn stack_pointer(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Get the return addresses on the stack, the most recent one last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Reseed the random number generator used by `CXNN`
+    pub fn seed_random(&mut self, seed: u64) {
+        self.random_engine = SplitMix64::seed_from_u64(seed);
+    }
+
+    /// Let PC and I wrap around past the end of memory
+    pub(crate) fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Address reduced modulo the memory size if addresses wrap
+    fn wrapped(&self, address: usize) -> usize {
+        if self.wrap {
+            address % self.memory_size
+        } else {
+            address
+        }
+    }
+
+    /// Get program counter
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Advance program counter
+    ///
+    /// Returns an error if the program counter doesn't point into memory
+    pub fn advance_program_counter(&mut self, step: u16) -> Result<(), CpuError> {
+        let new_pc = self.wrapped(self.program_counter as usize + step as usize);
+        if new_pc >= self.memory_size {
+            error!("Program counter out of range!");
+            Err(CpuError::PCOutOfRange)
+        } else {
+            self.program_counter = new_pc as u16;
+            debug!(format!(
+                "Program counter was advanced by {} to {}",
+                step, self.program_counter
+            ));
+            Ok(())
+        }
+    }
+
+    /// Set program counter
+    ///
+    /// Returns an error if the program counter doesn't point into memory
+    pub fn set_program_counter(&mut self, new_pc: u16) -> Result<(), CpuError> {
+        let new_pc = self.wrapped(new_pc as usize) as u16;
+        if new_pc as usize >= self.memory_size {
+            error!("Program counter out of range!");
+            Err(CpuError::PCOutOfRange)
+        } else {
+            self.program_counter = new_pc;
+            debug!("Program counter was set to ", new_pc);
+            Ok(())
+        }
+    }
+
+    /// Get address register (I)
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    /// Set a new address register value (I)
+    ///
+    /// Returns an error if the address doesn't point into memory
+    pub fn set_address(&mut self, value: u16) -> Result<(), CpuError> {
+        let value = self.wrapped(value as usize) as u16;
+        if value as usize >= self.memory_size {
+            error!("Address is too big for the memory!");
+            Err(CpuError::AddressOutOfRange)
+        } else {
+            self.address = value;
+            debug!("Address was set to ", value);
+            Ok(())
+        }
+    }
+
+    /// Increment address register by given step
+    ///
+    /// Returns an error if the address doesn't point into memory
+    pub fn advance_address(&mut self, step: u16) -> Result<(), CpuError> {
+        let address = self.wrapped(step as usize + self.address as usize);
+        if address >= self.memory_size {
+            error!("Address is too big for the memory!");
+            Err(CpuError::AddressOutOfRange)
+        } else {
+            self.address = address as u16;
+            debug!(format!(
+                "Address register was advanced by {} to {}",
+                step, self.address
+            ));
+            Ok(())
+        }
+    }
+
+    /// Push an addres onto stack, updating stack pointer accordingly
+    ///
+    /// Returns an error if the stack limit is reached
+    pub fn stack_push(&mut self, address: u16) -> Result<(), CpuError> {
+        if self.stack.len() == self.stack_depth {
+            error!("Stack limit of ", self.stack_depth, " was reached!");
+            return Err(CpuError::StackLimitReached);
+        }
+
+        self.stack.push(address);
+        debug!("Put value ", address, " on top of the stack");
+
+        Ok(())
+    }
+
+    /// Pop an address from the stack, returning it
+    ///
+    /// Returns an error if was called on an empty stack
+    pub fn stack_pop(&mut self) -> Result<u16, CpuError> {
+        let Some(result) = self.stack.pop() else {
+            error!("Pop was called on empty stack!");
+            return Err(CpuError::StackEmpty);
+        };
+
+        debug!("Removed value ", result, " from the stack");
+
+        Ok(result)
+    }
+
+    /// Helper to get the VX register, reduced boilerplate
+    pub fn vx(&mut self, x: Index) -> &mut u8 {
+        &mut self.general[x.into_inner() as usize]
+    }
+
+    /// Store V0..=VX into the RPL user flags
+    pub fn save_flags(&mut self, x: Index) {
+        let count = x.into_inner() as usize + 1;
+        self.rpl_flags[..count].copy_from_slice(&self.general[..count]);
+        debug!("Saved ", count, " registers to RPL flags");
+    }
+
+    /// Load V0..=VX from the RPL user flags
+    pub fn load_flags(&mut self, x: Index) {
+        let count = x.into_inner() as usize + 1;
+        self.general[..count].copy_from_slice(&self.rpl_flags[..count]);
+        debug!("Loaded ", count, " registers from RPL flags");
+    }
+
+    /// Get delay timer value
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Get sound timer value
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Set delay timer
+    pub fn set_delay_timer(&mut self, value: u8) {
+        debug!("Delay timer was set to ", value);
+        self.delay_timer = value;
+    }
+
+    /// Set sound timer
+    pub fn set_sound_timer(&mut self, value: u8) {
+        debug!("Sound timer was set to ", value);
+        self.sound_timer = value;
+    }
+
+    /// Get randomness
+    pub fn random(&mut self) -> u8 {
+        self.random_engine.random_range(0x0..=0xFF)
+    }
+
+    /// Append the cpu to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.bytes(&self.general);
+        writer.u16(self.address);
+        writer.u16(self.program_counter);
+        writer.u8(self.stack.len() as u8);
+        for entry in &self.stack {
+            writer.u16(*entry);
+        }
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        writer.bytes(&self.rpl_flags);
+        writer.u64(self.random_engine.state());
+    }
+
+    /// Read the cpu from a save state for the layout
+    pub(crate) fn read_state(
+        reader: &mut StateReader,
+        layout: &Layout,
+    ) -> Result<Self, StateError> {
+        let memory_size = layout.memory_size;
+        let general = reader.array()?;
+        let address = reader.u16()?;
+        let program_counter = reader.u16()?;
+        let stack_pointer = reader.u8()? as usize;
+        if stack_pointer > layout.stack_depth {
+            return Err(StateError::InvalidValue("stack pointer"));
+        }
+        let mut stack = Vec::with_capacity(layout.stack_depth);
+        for _ in 0..stack_pointer {
+            stack.push(reader.u16()?);
+        }
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let rpl_flags = reader.array()?;
+        let random_engine = SplitMix64::from_state(reader.u64()?);
+
+        if address as usize >= memory_size {
+            return Err(StateError::InvalidValue("address register"));
+        }
+        if program_counter as usize >= memory_size {
+            return Err(StateError::InvalidValue("program counter"));
+        }
+
+        Ok(Self {
+            general,
+            address,
+            delay_timer,
+            sound_timer,
+            program_counter,
+            stack,
+            stack_depth: layout.stack_depth,
+            rpl_flags,
+            memory_size,
+            wrap: false,
+            random_engine,
+        })
+    }
+
+    /// Tick timers down by one if possible
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+            debug!("Delay timer ticked down to ", self.delay_timer);
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+            debug!("Sound timer ticked down to ", self.sound_timer);
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+    use crate::types::Index;
+
+    #[test]
+    fn test_new() {
+        let cpu = Cpu::new();
+        assert_eq!(cpu.program_counter(), 0x200);
+        assert_eq!(cpu.address(), 0);
+        assert_eq!(cpu.delay_timer(), 0);
+        assert_eq!(cpu.stack_pointer(), 0);
+        assert!(cpu.stack().is_empty());
+    }
+
+    mod program_counter {
+        use crate::machine::cpu::{Cpu, CpuError};
+
+        #[test]
+        fn test_advance() {
+            let mut cpu = Cpu::new();
+            assert!(cpu.advance_program_counter(0x100).is_ok());
+            assert_eq!(cpu.program_counter(), 0x300);
+        }
+
+        #[test]
+        fn test_set() {
+            let mut cpu = Cpu::new();
+
+            assert!(cpu.set_program_counter(0x100).is_ok());
+            assert_eq!(cpu.program_counter(), 0x100);
+        }
+
+        #[test]
+        fn test_out_of_range() {
+            let mut cpu = Cpu::new();
+            assert!(matches!(
+                cpu.set_program_counter(0x1000),
+                Err(CpuError::PCOutOfRange)
+            ));
+        }
+    }
+
+    mod address {
+        use crate::machine::cpu::{Cpu, CpuError};
+
+        #[test]
+        fn test_big_memory() {
+            let mut cpu = Cpu::with_memory_size(1 << 16);
+            assert!(cpu.set_address(0xFFFF).is_ok());
+            assert!(matches!(
+                cpu.advance_address(1),
+                Err(CpuError::AddressOutOfRange)
+            ));
+        }
+
+        #[test]
+        fn test_set() {
+            let mut cpu = Cpu::new();
+            assert!(cpu.set_address(0x100).is_ok());
+            assert_eq!(cpu.address(), 0x100);
+        }
+
+        #[test]
+        fn test_advance() {
+            let mut cpu = Cpu::new();
+            assert!(cpu.advance_address(0x100).is_ok());
+            assert_eq!(cpu.address(), 0x100);
+        }
+
+        #[test]
+        fn test_out_of_range() {
+            let mut cpu = Cpu::new();
+            assert!(matches!(
+                cpu.set_address(0x1000),
+                Err(CpuError::AddressOutOfRange)
+            ));
+        }
+    }
+
+    mod stack {
+        use crate::machine::{
+            builder::Layout,
+            cpu::{Cpu, CpuError},
+        };
+
+        #[test]
+        fn test_correct_opeation() {
+            let mut cpu = Cpu::new();
+
+            assert!(cpu.stack_push(0x100).is_ok());
+            assert_eq!(cpu.stack_pointer(), 1);
+
+            assert!(cpu.stack_push(0x200).is_ok());
+            assert_eq!(cpu.stack_pointer(), 2);
+        }
+
+        #[test]
+        fn test_overflow() {
+            let mut cpu = Cpu::new();
+
+            for i in 0..16 {
+                assert!(cpu.stack_push(i as u16).is_ok());
+            }
+            assert!(matches!(
+                cpu.stack_push(0x100),
+                Err(CpuError::StackLimitReached)
+            ));
+        }
+
+        #[test]
+        fn test_configured_depth() {
+            let mut cpu = Cpu::with_layout(&Layout {
+                stack_depth: 12,
+                ..Layout::with_memory_size(1 << 12)
+            });
+
+            for i in 0..12 {
+                assert!(cpu.stack_push(i as u16).is_ok());
+            }
+            assert!(matches!(
+                cpu.stack_push(0x100),
+                Err(CpuError::StackLimitReached)
+            ));
+            assert_eq!(cpu.stack_pointer(), 12);
+        }
+
+        #[test]
+        fn test_empty_error() {
+            let mut cpu = Cpu::new();
+            assert!(matches!(cpu.stack_pop(), Err(CpuError::StackEmpty)));
+        }
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut cpu = Cpu::new();
+        let index = Index::try_new(0).unwrap();
+        *cpu.vx(index) = 0x10;
+        assert_eq!(cpu.general[0], 0x10);
+    }
+
+    #[test]
+    fn test_rpl_flags() {
+        let mut cpu = Cpu::new();
+        for i in 0..16 {
+            *cpu.vx(Index::try_new(i).unwrap()) = i + 1;
+        }
+
+        cpu.save_flags(Index::try_new(3).unwrap());
+        cpu.general = [0; 16];
+        cpu.load_flags(Index::try_new(7).unwrap());
+
+        assert_eq!(cpu.general[..8], [1, 2, 3, 4, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_timers() {
+        let mut cpu = Cpu::new();
+        cpu.set_delay_timer(0x10);
+        assert_eq!(cpu.delay_timer(), 0x10);
+
+        cpu.set_sound_timer(0x20);
+        assert_eq!(cpu.sound_timer, 0x20);
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut cpu = Cpu::new();
+        *cpu.vx(Index::try_new(3).unwrap()) = 0x33;
+        cpu.set_address(0x123).unwrap();
+        cpu.stack_push(0x456).unwrap();
+        cpu.set_delay_timer(7);
+        cpu.random();
+
+        let mut writer = StateWriter::new();
+        cpu.write_state(&mut writer);
+        let state = writer.finish();
+
+        let mut reader = StateReader::new(&state).unwrap();
+        let mut restored =
+            Cpu::read_state(&mut reader, &Layout::with_memory_size(1 << 12)).unwrap();
+        reader.finish().unwrap();
+
+        assert_eq!(restored, cpu);
+        assert_eq!(restored.random(), cpu.random());
+    }
+
+    #[test]
+    fn test_state_invalid_pc() {
+        let mut cpu = Cpu::with_memory_size(1 << 16);
+        cpu.set_program_counter(0x2000).unwrap();
+
+        let mut writer = StateWriter::new();
+        cpu.write_state(&mut writer);
+        let state = writer.finish();
+
+        let mut reader = StateReader::new(&state).unwrap();
+        assert_eq!(
+            Cpu::read_state(&mut reader, &Layout::with_memory_size(1 << 12)),
+            Err(StateError::InvalidValue("program counter"))
+        );
+    }
+
+    #[test]
+    fn test_reproducible_randomness() {
+        let mut cpu1 = Cpu::new();
+        cpu1.random_engine = SplitMix64::seed_from_u64(42);
+
+        let values = (0..10).map(|_| cpu1.random()).collect::<Vec<_>>();
+
+        let mut cpu2 = Cpu::new();
+        cpu2.random_engine = SplitMix64::seed_from_u64(42);
+
+        let values2 = (0..10).map(|_| cpu2.random()).collect::<Vec<_>>();
+
+        assert_eq!(values, values2);
+    }
+}
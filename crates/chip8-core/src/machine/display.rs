@@ -0,0 +1,572 @@
+//! Chip8 display implementation
+
+use thiserror::Error;
+use tklog::{debug, error, trace};
+
+use crate::machine::state::{StateError, StateReader, StateWriter};
+
+/// Width of the display in high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+/// Height of the display in high resolution mode
+pub const HIRES_HEIGHT: usize = 64;
+/// Width of the display in low resolution mode
+pub const LORES_WIDTH: usize = 64;
+/// Height of the display in low resolution mode
+pub const LORES_HEIGHT: usize = 32;
+
+/// Pixel buffer big enough for the high resolution mode
+///
+/// In low resolution mode only the top-left 64x32 corner is used
+pub type Framebuffer = [[bool; HIRES_WIDTH]; HIRES_HEIGHT];
+
+/// Number of bitplanes (XO-CHIP), other platforms only ever use the first one
+pub const PLANE_COUNT: usize = 2;
+
+/// Screen of up to [`PLANE_COUNT`] monochrome bitplanes,
+/// 64x32 in low resolution and 128x64 in high resolution (SUPER-CHIP)
+pub struct Display {
+    /// 2D arrays of pixels, one per plane
+    /// true = on
+    /// false = off
+    planes: [Framebuffer; PLANE_COUNT],
+    /// Bitmask of the planes affected by drawing, clearing and scrolling
+    selected_planes: u8,
+    /// Whether the high resolution mode is on
+    hires: bool,
+}
+
+/// Enum for all possible display errors
+#[derive(Debug, Error)]
+#[must_use]
+pub enum DisplayError {
+    #[error("Sprite height exceeds 32 in height")]
+    /// The height of chip8 display is 32, so no sprite can be taller than it
+    SpriteTooBig,
+}
+
+impl Display {
+    /// Create an empty display in low resolution mode with the first plane selected
+    pub fn new() -> Self {
+        Self {
+            planes: [[[false; HIRES_WIDTH]; HIRES_HEIGHT]; PLANE_COUNT],
+            selected_planes: 0b01,
+            hires: false,
+        }
+    }
+
+    /// Clear the selected planes
+    pub fn clear(&mut self) {
+        debug!("The screen was cleared");
+        for plane in self.selected_planes_mut() {
+            *plane = [[false; HIRES_WIDTH]; HIRES_HEIGHT];
+        }
+    }
+
+    /// Current resolution as (width, height)
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (LORES_WIDTH, LORES_HEIGHT)
+        }
+    }
+
+    /// Whether the high resolution mode is on
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switch between low and high resolution, clearing all planes
+    pub fn set_hires(&mut self, hires: bool) {
+        debug!("High resolution mode set to ", hires);
+        self.hires = hires;
+        self.planes = [[[false; HIRES_WIDTH]; HIRES_HEIGHT]; PLANE_COUNT];
+    }
+
+    /// Select the planes affected by drawing, clearing and scrolling
+    ///
+    /// Bits above [`PLANE_COUNT`] are ignored
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & ((1 << PLANE_COUNT) - 1);
+        debug!("Selected planes ", self.selected_planes);
+    }
+
+    /// Bitmask of the selected planes
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// Number of the selected planes
+    pub fn selected_plane_count(&self) -> usize {
+        self.selected_planes.count_ones() as usize
+    }
+
+    /// Iterator over the selected planes
+    fn selected_planes_mut(&mut self) -> impl Iterator<Item = &mut Framebuffer> {
+        let mask = self.selected_planes;
+        self.planes
+            .iter_mut()
+            .enumerate()
+            .filter(move |(i, _)| mask & (1 << i) != 0)
+            .map(|(_, plane)| plane)
+    }
+
+    /// Scroll the selected planes down by given number of rows, new rows are blank
+    pub fn scroll_down(&mut self, rows: usize) {
+        let (width, height) = self.resolution();
+        for plane in self.selected_planes_mut() {
+            for y in (0..height).rev() {
+                let source = if y >= rows {
+                    plane[y - rows]
+                } else {
+                    [false; HIRES_WIDTH]
+                };
+                plane[y][..width].copy_from_slice(&source[..width]);
+            }
+        }
+        trace!("Scrolled the screen down by ", rows);
+    }
+
+    /// Scroll the selected planes up by given number of rows, new rows are blank
+    pub fn scroll_up(&mut self, rows: usize) {
+        let (width, height) = self.resolution();
+        for plane in self.selected_planes_mut() {
+            for y in 0..height {
+                let source = if y + rows < height {
+                    plane[y + rows]
+                } else {
+                    [false; HIRES_WIDTH]
+                };
+                plane[y][..width].copy_from_slice(&source[..width]);
+            }
+        }
+        trace!("Scrolled the screen up by ", rows);
+    }
+
+    /// Scroll the selected planes right by given number of columns, new columns are blank
+    pub fn scroll_right(&mut self, columns: usize) {
+        let (width, height) = self.resolution();
+        for plane in self.selected_planes_mut() {
+            for row in plane.iter_mut().take(height) {
+                for x in (0..width).rev() {
+                    row[x] = x >= columns && row[x - columns];
+                }
+            }
+        }
+        trace!("Scrolled the screen right by ", columns);
+    }
+
+    /// Scroll the selected planes left by given number of columns, new columns are blank
+    pub fn scroll_left(&mut self, columns: usize) {
+        let (width, height) = self.resolution();
+        for plane in self.selected_planes_mut() {
+            for row in plane.iter_mut().take(height) {
+                for x in 0..width {
+                    row[x] = x + columns < width && row[x + columns];
+                }
+            }
+        }
+        trace!("Scrolled the screen left by ", columns);
+    }
+
+    /// Draw the 8 pixels wide sprite starting at (vx, vy) on the selected planes
+    ///
+    /// The sprite holds the rows for every selected plane, one plane after another
+    ///
+    /// The starting position always wraps around the screen, the parts of the sprite
+    /// that cross the edge either wrap too (`wrap = true`) or are clipped
+    ///
+    /// Returns true if any pixels were turned off by drawing this sprite
+    pub fn draw_sprite(
+        &mut self,
+        sprite: &[u8],
+        vx: u8,
+        vy: u8,
+        wrap: bool,
+    ) -> Result<bool, DisplayError> {
+        let plane_count = self.selected_plane_count().max(1);
+        if sprite.len() / plane_count > 32 {
+            error!(
+                "DRW was called on sprite of length > 32, namely ",
+                sprite.len() / plane_count
+            );
+            return Err(DisplayError::SpriteTooBig);
+        }
+
+        Ok(self.draw_planes(sprite, 1, vx, vy, wrap))
+    }
+
+    /// Draw the 16x16 sprite (SUPER-CHIP `DXY0`) starting at (vx, vy) on the selected planes
+    ///
+    /// The sprite is 32 bytes long per selected plane, two bytes per row.
+    /// Wrapping works the same as in [`Display::draw_sprite`]
+    ///
+    /// Returns true if any pixels were turned off by drawing this sprite
+    pub fn draw_wide_sprite(&mut self, sprite: &[u8], vx: u8, vy: u8, wrap: bool) -> bool {
+        self.draw_planes(sprite, 2, vx, vy, wrap)
+    }
+
+    /// Split the sprite data between the selected planes and draw each part
+    fn draw_planes(
+        &mut self,
+        sprite: &[u8],
+        bytes_per_row: usize,
+        vx: u8,
+        vy: u8,
+        wrap: bool,
+    ) -> bool {
+        let plane_count = self.selected_plane_count();
+        if plane_count == 0 {
+            return false;
+        }
+
+        let (width, height) = self.resolution();
+        let plane_len = sprite.len() / plane_count;
+        let mut collision = false;
+
+        for (plane, data) in self
+            .selected_planes_mut()
+            .zip(sprite.chunks_exact(plane_len.max(1)))
+        {
+            let rows = data.chunks_exact(bytes_per_row).map(|row| {
+                row.iter()
+                    .fold(0_u16, |acc, &byte| (acc << 8) | byte as u16)
+            });
+            collision |= Self::draw_rows(
+                plane,
+                (width, height),
+                rows,
+                bytes_per_row * 8,
+                vx,
+                vy,
+                wrap,
+            );
+        }
+        collision
+    }
+
+    /// XOR the rows of the sprite onto the plane, each row holds `width` pixels in its lowest bits
+    fn draw_rows(
+        plane: &mut Framebuffer,
+        (screen_width, screen_height): (usize, usize),
+        rows: impl Iterator<Item = u16>,
+        width: usize,
+        vx: u8,
+        vy: u8,
+        wrap: bool,
+    ) -> bool {
+        let mut collision = false;
+        let start_x = vx as usize % screen_width;
+        let start_y = vy as usize % screen_height;
+
+        for (idx, word) in rows.enumerate() {
+            if !wrap && start_y + idx >= screen_height {
+                break;
+            }
+
+            for i in 0..width {
+                if !wrap && start_x + i >= screen_width {
+                    break;
+                }
+
+                let pixel = (word >> (width - 1 - i)) & 0x1;
+                let pos_x = (start_x + i) % screen_width;
+                let pos_y = (start_y + idx) % screen_height;
+
+                let new_pixel = plane[pos_y][pos_x] ^ (pixel == 1);
+                if plane[pos_y][pos_x] && !new_pixel {
+                    collision = true;
+                    trace!("Found collision on x ", pos_x, ", y ", pos_y);
+                }
+
+                if plane[pos_y][pos_x] != new_pixel {
+                    trace!("Pixel at x ", pos_x, ", y ", pos_y, " changed it state");
+                }
+
+                plane[pos_y][pos_x] = new_pixel;
+            }
+        }
+        collision
+    }
+
+    /// Append the display to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.hires);
+        writer.u8(self.selected_planes);
+        for plane in &self.planes {
+            for row in plane {
+                for chunk in row.chunks_exact(8) {
+                    let byte = chunk
+                        .iter()
+                        .fold(0_u8, |acc, &pixel| (acc << 1) | pixel as u8);
+                    writer.u8(byte);
+                }
+            }
+        }
+    }
+
+    /// Read the display from a save state
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let hires = reader.bool("high resolution flag")?;
+        let selected_planes = reader.u8()?;
+        if selected_planes >= 1 << PLANE_COUNT {
+            return Err(StateError::InvalidValue("selected planes"));
+        }
+
+        let mut planes = [[[false; HIRES_WIDTH]; HIRES_HEIGHT]; PLANE_COUNT];
+        for plane in planes.iter_mut() {
+            for row in plane.iter_mut() {
+                let packed = reader.bytes(HIRES_WIDTH / 8)?;
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    *pixel = (packed[x / 8] >> (7 - x % 8)) & 1 == 1;
+                }
+            }
+        }
+
+        Ok(Self {
+            planes,
+            selected_planes,
+            hires,
+        })
+    }
+
+    /// Get current state of the first plane
+    ///
+    /// Only the part given by [`Display::resolution`] is meaningful
+    pub fn state(&self) -> &Framebuffer {
+        &self.planes[0]
+    }
+
+    /// Get current state of all planes
+    ///
+    /// Only the part given by [`Display::resolution`] is meaningful
+    pub fn planes(&self) -> &[Framebuffer; PLANE_COUNT] {
+        &self.planes
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use ndarray::{ArrayView2, s};
+
+    use super::*;
+
+    #[test]
+    fn test_simple_reactangle() {
+        let mut display = Display::new();
+        let sprite = [0b11110000, 0b10010000, 0b11110000, 0b10010000, 0b11110000];
+
+        let collisions = display.draw_sprite(&sprite, 0, 0, true).unwrap();
+        let display_state = display.state();
+        let result = ArrayView2::from(display_state);
+
+        let expected = ndarray::array![
+            [true, true, true, true],
+            [true, false, false, true],
+            [true, true, true, true],
+            [true, false, false, true],
+            [true, true, true, true],
+        ];
+
+        assert!(!collisions);
+        assert!(result.slice(s![0..5, 0..4]).eq(&expected));
+    }
+
+    #[test]
+    fn test_clear_display() {
+        let mut display = Display::new();
+        let sprite = [0b11110000, 0b10010000, 0b11110000, 0b10010000, 0b11110000];
+
+        let collisions = display.draw_sprite(&sprite, 0, 0, true).unwrap();
+        display.clear();
+        let display_state = display.state();
+
+        assert!(!collisions);
+        assert_eq!(display_state, &[[false; HIRES_WIDTH]; HIRES_HEIGHT]);
+    }
+
+    #[test]
+    fn test_rectangle_intersection() {
+        let mut display = Display::new();
+
+        // sprite 5 in width, 4 in height, drawn from 0, 0
+        // (0, 0) -> (4, 3)
+        let sprite1 = [0b11111000, 0b11111000, 0b11111000, 0b11111000];
+        // sprite 6 in width, 3 in height, drawn from 2, 2
+        // (2, 2) -> (7, 4)
+        let sprite2 = [0b11111100, 0b11111100, 0b11111100];
+
+        let collisions1 = display.draw_sprite(&sprite1, 0, 0, true).unwrap();
+        let collisions2 = display.draw_sprite(&sprite2, 2, 2, true).unwrap();
+        let display_state = display.state();
+        let result = ArrayView2::from(display_state);
+
+        let expected = ndarray::array![
+            [true, true, true, true, true, false, false, false],
+            [true, true, true, true, true, false, false, false],
+            [true, true, false, false, false, true, true, true],
+            [true, true, false, false, false, true, true, true],
+            [false, false, true, true, true, true, true, true],
+        ];
+
+        dbg!(result.slice(s![0..5, 0..8]));
+
+        assert!(!collisions1);
+        assert!(collisions2);
+        assert!(result.slice(s![0..5, 0..8]).eq(&expected));
+    }
+
+    #[test]
+    fn test_wraparound() {
+        let mut display = Display::new();
+        let sprite = [0b10000000];
+        display.draw_sprite(&sprite, 63, 31, true).unwrap();
+        assert!(display.state()[31][63]);
+    }
+
+    #[test]
+    fn test_wraparound_crossing_edge() {
+        let mut display = Display::new();
+        let sprite = [0b11000000, 0b11000000];
+        display.draw_sprite(&sprite, 63, 31, true).unwrap();
+
+        assert!(display.state()[31][63]);
+        assert!(display.state()[31][0]);
+        assert!(display.state()[0][63]);
+        assert!(display.state()[0][0]);
+    }
+
+    #[test]
+    fn test_clipping() {
+        let mut display = Display::new();
+        let sprite = [0b11000000, 0b11000000];
+        display.draw_sprite(&sprite, 63, 31, false).unwrap();
+
+        assert!(display.state()[31][63]);
+        assert!(!display.state()[31][0]);
+        assert!(!display.state()[0][63]);
+        assert!(!display.state()[0][0]);
+    }
+
+    #[test]
+    fn test_clipping_wraps_start_position() {
+        let mut display = Display::new();
+        let sprite = [0b10000000];
+        display.draw_sprite(&sprite, 64 + 3, 32 + 2, false).unwrap();
+
+        assert!(display.state()[2][3]);
+    }
+
+    #[test]
+    fn test_hires_wraparound() {
+        let mut display = Display::new();
+        display.set_hires(true);
+        let sprite = [0b11000000];
+        display.draw_sprite(&sprite, 127, 63, true).unwrap();
+
+        assert_eq!(display.resolution(), (128, 64));
+        assert!(display.state()[63][127]);
+        assert!(display.state()[63][0]);
+    }
+
+    #[test]
+    fn test_wide_sprite() {
+        let mut display = Display::new();
+        display.set_hires(true);
+        let mut sprite = [0; 32];
+        sprite[0] = 0x80;
+        sprite[31] = 0x01;
+
+        let collision = display.draw_wide_sprite(&sprite, 10, 20, false);
+
+        assert!(!collision);
+        assert!(display.state()[20][10]);
+        assert!(display.state()[35][25]);
+        assert!(display.draw_wide_sprite(&sprite, 10, 20, false));
+        assert!(!display.state()[20][10]);
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0b10000000], 4, 4, true).unwrap();
+
+        display.scroll_down(3);
+        assert!(display.state()[7][4]);
+        assert!(!display.state()[4][4]);
+
+        display.scroll_right(4);
+        assert!(display.state()[7][8]);
+
+        display.scroll_left(4);
+        display.scroll_left(4);
+        assert!(display.state()[7][0]);
+
+        display.scroll_left(4);
+        assert_eq!(display.state(), &[[false; HIRES_WIDTH]; HIRES_HEIGHT]);
+    }
+
+    #[test]
+    fn test_scroll_stays_in_lores_area() {
+        let mut display = Display::new();
+        display.draw_sprite(&[0b10000000], 63, 31, true).unwrap();
+
+        display.scroll_down(1);
+        display.scroll_right(4);
+
+        assert_eq!(display.state(), &[[false; HIRES_WIDTH]; HIRES_HEIGHT]);
+    }
+
+    #[test]
+    fn test_planes() {
+        let mut display = Display::new();
+        display.select_planes(0b11);
+        assert_eq!(display.selected_plane_count(), 2);
+
+        // first plane gets the top row, second one the bottom row
+        let sprite = [0b10000000, 0b00000000, 0b00000000, 0b10000000];
+        display.draw_sprite(&sprite, 0, 0, true).unwrap();
+
+        assert!(display.planes()[0][0][0]);
+        assert!(!display.planes()[0][1][0]);
+        assert!(!display.planes()[1][0][0]);
+        assert!(display.planes()[1][1][0]);
+
+        display.select_planes(0b10);
+        display.scroll_up(1);
+        assert!(display.planes()[1][0][0]);
+        assert!(display.planes()[0][0][0]);
+
+        display.clear();
+        assert!(display.planes()[0][0][0]);
+        assert_eq!(display.planes()[1], [[false; HIRES_WIDTH]; HIRES_HEIGHT]);
+    }
+
+    #[test]
+    fn test_no_planes_selected() {
+        let mut display = Display::new();
+        display.select_planes(0);
+
+        assert!(!display.draw_sprite(&[0xFF], 0, 0, true).unwrap());
+        assert_eq!(display.state(), &[[false; HIRES_WIDTH]; HIRES_HEIGHT]);
+    }
+
+    #[test]
+    fn test_big_sprite_error() {
+        let mut display = Display::new();
+
+        let sprite = [0; 33];
+        assert!(matches!(
+            display.draw_sprite(&sprite, 0, 0, true),
+            Err(DisplayError::SpriteTooBig)
+        ));
+    }
+}
@@ -0,0 +1,444 @@
+//! Chip8 faults
+//!
+//! A fault is an error caused by the running program rather than by the emulator: an opcode
+//! that doesn't decode, an instruction the platform lacks, a stack over- or underflow or an
+//! access out of range. [`FaultPolicy`] picks what [`Chip8::step`](crate::Chip8::step) does
+//! about each [`FaultClass`], a [`Fault`] is what it reports, along with the machine state
+//! and the last instructions that led to it.
+//!
+//! A machine halted by a fault can be saved as a crash dump with
+//! [`Chip8::crash_dump`](crate::Chip8::crash_dump) and restored for inspection with
+//! [`Chip8::load_crash_dump`](crate::Chip8::load_crash_dump). A crash dump has the header of a
+//! [save state](super::state) with the magic bytes `C8CD` and the version
+//! [`CRASH_DUMP_VERSION`], followed by the payload:
+//!
+//! 1. Fault
+//!    - action, 1 byte: 0 = halt, 1 = nop, 2 = wrap, 3 = break
+//!    - PC, opcode, 2 bytes each
+//!    - decoded flag, 1 byte, followed by the operand of `F000 NNNN` (0 otherwise), 2 bytes
+//!    - cycle, 8 bytes
+//!    - V0..=VF, 16 bytes, then I, 2 bytes
+//!    - stack depth, 1 byte, followed by the return addresses, 2 bytes each
+//!    - history length, 2 bytes, followed by the records in the binary
+//!      [trace](crate::trace) form
+//!    - error, 1 byte for the kind followed by 2 bytes for its value (0 if it has none), see
+//!      `write_error`
+//! 2. Machine: length (4 bytes), then a complete save state
+//!
+//! The class isn't stored, it follows from the error.
+//! Bumping the version is required for any change to the layout above.
+
+use std::{error::Error, fmt, fmt::Write as _};
+
+use crate::{
+    Instruction,
+    decoder::instruction::DecodeError,
+    machine::{
+        Chip8Error,
+        builder::Layout,
+        cpu::CpuError,
+        display::DisplayError,
+        keypad::KeypadError,
+        memory::MemoryError,
+        platform::Platform,
+        quirks::Quirks,
+        state::{StateError, StateReader, StateWriter},
+    },
+    trace::{RECORD_SIZE, TraceRecord},
+};
+
+/// Magic bytes every crash dump starts with
+pub const CRASH_DUMP_MAGIC: [u8; 4] = *b"C8CD";
+
+/// Version of the crash dump format written by this build
+pub const CRASH_DUMP_VERSION: u16 = 1;
+
+/// Number of executed instructions a [`Fault`] reports
+pub const FAULT_HISTORY: usize = 16;
+
+/// Kind of fault, each one has its own [`FaultAction`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultClass {
+    /// The opcode doesn't decode to any instruction
+    InvalidOpcode,
+    /// The instruction exists, but not on the emulated platform, or it calls machine code
+    Unsupported,
+    /// Call with a full stack or return with an empty one
+    Stack,
+    /// PC, I, a memory access, a key or a font digit out of range, or a write to the
+    /// reserved area below 0x200
+    OutOfRange,
+}
+
+impl FaultClass {
+    /// Class of the error, `None` if it isn't caused by the program, e.g. a trace write error
+    pub fn of(error: &Chip8Error) -> Option<Self> {
+        match error {
+            Chip8Error::DecodeError(_) => Some(Self::InvalidOpcode),
+            Chip8Error::UnsupportedInstruction => Some(Self::Unsupported),
+            Chip8Error::CpuError(CpuError::StackLimitReached | CpuError::StackEmpty) => {
+                Some(Self::Stack)
+            }
+            Chip8Error::CpuError(CpuError::AddressOutOfRange | CpuError::PCOutOfRange)
+            | Chip8Error::MemoryError(_)
+            | Chip8Error::KeypadError(_)
+            | Chip8Error::DisplayError(_) => Some(Self::OutOfRange),
+            Chip8Error::StateError(_) | Chip8Error::TraceError(_) | Chip8Error::Fault(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for FaultClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidOpcode => "invalid opcode",
+            Self::Unsupported => "unsupported instruction",
+            Self::Stack => "stack fault",
+            Self::OutOfRange => "out of range access",
+        })
+    }
+}
+
+/// What happens on a fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Stop the machine for good, [`Chip8::halted`](crate::Chip8::halted) tells why.
+    /// Loading a save state or rewinding brings it back
+    Halt,
+    /// Skip the faulting instruction, its effects until the fault are kept.
+    /// Halts if the instruction can't be skipped, e.g. at the end of memory
+    Nop,
+    /// PC, I and memory accesses wrap around modulo the memory size, so they never fault.
+    /// Out of range faults wrapping can't fix (the reserved area, keys, font digits) and faults
+    /// of the other classes halt
+    Wrap,
+    /// Report the fault and leave the machine at the faulting instruction, for a debugger
+    /// to inspect. Stepping again runs into the same fault
+    Break,
+}
+
+impl fmt::Display for FaultAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Halt => "halt",
+            Self::Nop => "nop",
+            Self::Wrap => "wrap",
+            Self::Break => "break",
+        })
+    }
+}
+
+/// Action for every fault class
+///
+/// Single classes can be overridden with struct update syntax:
+///
+/// ```ignore
+/// let policy = FaultPolicy {
+///     out_of_range: FaultAction::Wrap,
+///     ..FaultPolicy::HALT
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultPolicy {
+    /// Opcodes that don't decode
+    pub invalid_opcode: FaultAction,
+    /// Instructions the platform lacks
+    pub unsupported: FaultAction,
+    /// Stack over- and underflows
+    pub stack: FaultAction,
+    /// Accesses out of range
+    pub out_of_range: FaultAction,
+}
+
+impl FaultPolicy {
+    /// Every fault halts the machine
+    pub const HALT: FaultPolicy = FaultPolicy::all(FaultAction::Halt);
+
+    /// Every fault breaks
+    pub const BREAK: FaultPolicy = FaultPolicy::all(FaultAction::Break);
+
+    /// The same action for every class
+    pub const fn all(action: FaultAction) -> Self {
+        Self {
+            invalid_opcode: action,
+            unsupported: action,
+            stack: action,
+            out_of_range: action,
+        }
+    }
+
+    /// Action for the class
+    pub fn action(&self, class: FaultClass) -> FaultAction {
+        match class {
+            FaultClass::InvalidOpcode => self.invalid_opcode,
+            FaultClass::Unsupported => self.unsupported,
+            FaultClass::Stack => self.stack,
+            FaultClass::OutOfRange => self.out_of_range,
+        }
+    }
+
+    /// Whether PC, I and memory accesses wrap around
+    pub(crate) fn wraps_addresses(&self) -> bool {
+        self.out_of_range == FaultAction::Wrap
+    }
+}
+
+/// Behavior of this emulator before the policy was configurable, the error is returned
+/// and the machine stays where it was
+impl Default for FaultPolicy {
+    fn default() -> Self {
+        Self::BREAK
+    }
+}
+
+/// Fault reported by [`Chip8::step`](crate::Chip8::step)
+#[derive(Debug)]
+pub struct Fault {
+    /// Kind of fault
+    pub class: FaultClass,
+    /// Action the policy had for it
+    pub action: FaultAction,
+    /// Address of the faulting instruction
+    pub pc: u16,
+    /// Word at PC, 0 if PC is out of memory
+    pub opcode: u16,
+    /// Decoded instruction at PC, `None` if it doesn't decode
+    pub instruction: Option<Instruction>,
+    /// Instructions the machine executed before this one
+    pub cycle: u64,
+    /// V0..=VF when the fault was raised, the instruction may have changed some already
+    pub registers: [u8; 16],
+    /// I register
+    pub i: u16,
+    /// Return addresses on the stack, the most recent one last
+    pub stack: Vec<u16>,
+    /// Last executed instructions, oldest first, at most [`FAULT_HISTORY`]. Cycles count from
+    /// the creation of the machine
+    pub history: Vec<TraceRecord>,
+    /// What went wrong
+    pub error: Chip8Error,
+}
+
+impl Fault {
+    /// The fault followed by the machine state and the instructions leading to it, one item
+    /// per line
+    pub fn report(&self) -> String {
+        let mut out = format!("{self}\n");
+
+        let _ = write!(out, "Opcode:    {:04X}", self.opcode);
+        match self.instruction {
+            Some(instruction) => {
+                if let Some(operand) = instruction.operand() {
+                    let _ = write!(out, "{operand:04X}");
+                }
+                let _ = writeln!(out, " {instruction}");
+            }
+            None => out.push_str(" ???\n"),
+        }
+        let _ = writeln!(out, "Cycle:     {}", self.cycle);
+
+        out.push_str("Registers:");
+        for (index, register) in self.registers.iter().enumerate() {
+            let _ = write!(out, " V{index:X}={register:02X}");
+        }
+        let _ = writeln!(out, " I={:04X}", self.i);
+
+        out.push_str("Stack:    ");
+        if self.stack.is_empty() {
+            out.push_str(" empty");
+        }
+        for address in &self.stack {
+            let _ = write!(out, " {address:#05X}");
+        }
+        out.push('\n');
+
+        if !self.history.is_empty() {
+            out.push_str("Last instructions:\n");
+            for record in &self.history {
+                let _ = writeln!(out, "{record}");
+            }
+        }
+        out
+    }
+
+    /// Crash dump of the fault and the machine it halted, `state` being its save state
+    pub(crate) fn crash_dump(&self, state: &[u8]) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.u8(match self.action {
+            FaultAction::Halt => 0,
+            FaultAction::Nop => 1,
+            FaultAction::Wrap => 2,
+            FaultAction::Break => 3,
+        });
+        writer.u16(self.pc);
+        writer.u16(self.opcode);
+        writer.bool(self.instruction.is_some());
+        writer.u16(
+            self.instruction
+                .and_then(|instruction| instruction.operand())
+                .unwrap_or(0),
+        );
+        writer.u64(self.cycle);
+        writer.bytes(&self.registers);
+        writer.u16(self.i);
+        writer.u8(self.stack.len() as u8);
+        for address in &self.stack {
+            writer.u16(*address);
+        }
+        writer.u16(self.history.len() as u16);
+        for record in &self.history {
+            writer.bytes(&record.to_bytes());
+        }
+        write_error(&mut writer, &self.error);
+
+        writer.u32(state.len() as u32);
+        writer.bytes(state);
+
+        writer.finish_as(CRASH_DUMP_MAGIC, CRASH_DUMP_VERSION)
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the innermost error is the one saying what actually happened
+        let mut cause: &dyn Error = &self.error;
+        while let Some(source) = cause.source() {
+            cause = source;
+        }
+        write!(
+            f,
+            "{} at {:#05X}: {cause} (policy: {})",
+            self.class, self.pc, self.action
+        )
+    }
+}
+
+/// The display already names the cause, so it isn't a source as well
+impl Error for Fault {}
+
+/// Read a crash dump back into the fault and the save state of the machine
+pub fn read_crash_dump(dump: &[u8]) -> Result<(Fault, Vec<u8>), StateError> {
+    let mut reader = StateReader::with_header(dump, CRASH_DUMP_MAGIC, CRASH_DUMP_VERSION)?;
+
+    let action = match reader.u8()? {
+        0 => FaultAction::Halt,
+        1 => FaultAction::Nop,
+        2 => FaultAction::Wrap,
+        3 => FaultAction::Break,
+        _ => return Err(StateError::InvalidValue("fault action")),
+    };
+    let pc = reader.u16()?;
+    let opcode = reader.u16()?;
+    let decoded = reader.bool("decoded flag")?;
+    let operand = reader.u16()?;
+    let instruction = if !decoded {
+        None
+    } else if Instruction::has_operand(opcode) {
+        Some(Instruction::decode(opcode, operand))
+    } else {
+        Some(Instruction::try_from(opcode))
+    }
+    .transpose()
+    .map_err(|_| StateError::InvalidValue("instruction"))?;
+    let cycle = reader.u64()?;
+    let registers = reader.array()?;
+    let i = reader.u16()?;
+    let depth = reader.u8()?;
+    let stack: Vec<u16> = (0..depth).map(|_| reader.u16()).collect::<Result<_, _>>()?;
+    let length = reader.u16()?;
+    let history = (0..length)
+        .map(|_| Ok(TraceRecord::from_bytes(&reader.array::<RECORD_SIZE>()?)))
+        .collect::<Result<_, StateError>>()?;
+    let error = read_error(&mut reader)?;
+    let class = FaultClass::of(&error).ok_or(StateError::InvalidValue("fault error"))?;
+
+    let state_length = reader.u32()? as usize;
+    let state = reader.bytes(state_length)?.to_vec();
+    reader.finish()?;
+    if stack.len() > state_layout(&state)?.stack_depth {
+        return Err(StateError::InvalidValue("stack depth"));
+    }
+
+    let fault = Fault {
+        class,
+        action,
+        pc,
+        opcode,
+        instruction,
+        cycle,
+        registers,
+        i,
+        stack,
+        history,
+        error,
+    };
+    Ok((fault, state))
+}
+
+/// Layout of the machine in a save state, read without restoring the rest of it
+fn state_layout(state: &[u8]) -> Result<Layout, StateError> {
+    let mut reader = StateReader::new(state)?;
+    Platform::read_state(&mut reader)?;
+    Quirks::read_state(&mut reader)?;
+    reader.bool("exited flag")?;
+    Layout::read_state(&mut reader)
+}
+
+/// Append the error of a fault as a kind byte and a value
+///
+/// | Kind | Error                                            |
+/// |------|--------------------------------------------------|
+/// | 0    | no such instruction, value is the opcode         |
+/// | 1    | missing operand, value is the opcode             |
+/// | 2    | unsupported instruction                          |
+/// | 3    | stack limit reached                              |
+/// | 4    | stack empty                                      |
+/// | 5    | address out of range                             |
+/// | 6    | program counter out of range                     |
+/// | 7    | reserved memory access                           |
+/// | 8    | sprite index out of range                        |
+/// | 9    | memory access out of range, value is the address |
+/// | 10   | no such key                                      |
+/// | 11   | sprite too big                                   |
+/// | 255  | not caused by the program, can't be read back    |
+fn write_error(writer: &mut StateWriter, error: &Chip8Error) {
+    let (kind, value) = match error {
+        Chip8Error::DecodeError(DecodeError::NoSuchInstruction(opcode)) => (0, *opcode),
+        Chip8Error::DecodeError(DecodeError::MissingOperand(opcode)) => (1, *opcode),
+        Chip8Error::UnsupportedInstruction => (2, 0),
+        Chip8Error::CpuError(CpuError::StackLimitReached) => (3, 0),
+        Chip8Error::CpuError(CpuError::StackEmpty) => (4, 0),
+        Chip8Error::CpuError(CpuError::AddressOutOfRange) => (5, 0),
+        Chip8Error::CpuError(CpuError::PCOutOfRange) => (6, 0),
+        Chip8Error::MemoryError(MemoryError::PermissionDenied) => (7, 0),
+        Chip8Error::MemoryError(MemoryError::IncorrectSprite) => (8, 0),
+        Chip8Error::MemoryError(MemoryError::OutOfRange(address)) => (9, *address),
+        Chip8Error::KeypadError(KeypadError::NoSuchKey) => (10, 0),
+        Chip8Error::DisplayError(DisplayError::SpriteTooBig) => (11, 0),
+        Chip8Error::StateError(_) | Chip8Error::TraceError(_) | Chip8Error::Fault(_) => (255, 0),
+    };
+    writer.u8(kind);
+    writer.u16(value);
+}
+
+/// Read an error written by [`write_error`]
+fn read_error(reader: &mut StateReader) -> Result<Chip8Error, StateError> {
+    let kind = reader.u8()?;
+    let value = reader.u16()?;
+    Ok(match kind {
+        0 => DecodeError::NoSuchInstruction(value).into(),
+        1 => DecodeError::MissingOperand(value).into(),
+        2 => Chip8Error::UnsupportedInstruction,
+        3 => CpuError::StackLimitReached.into(),
+        4 => CpuError::StackEmpty.into(),
+        5 => CpuError::AddressOutOfRange.into(),
+        6 => CpuError::PCOutOfRange.into(),
+        7 => MemoryError::PermissionDenied.into(),
+        8 => MemoryError::IncorrectSprite.into(),
+        9 => MemoryError::OutOfRange(value).into(),
+        10 => KeypadError::NoSuchKey.into(),
+        11 => DisplayError::SpriteTooBig.into(),
+        _ => return Err(StateError::InvalidValue("fault error")),
+    })
+}
@@ -0,0 +1,143 @@
+//! Read-only views of a running machine
+//!
+//! Debuggers, overlays and bots look at a [`Chip8`] through these without changing anything:
+//! in particular, reading the display here doesn't consume the redraw flag that
+//! [`Chip8::planes_snapshot`] clears.
+
+use std::ops::{Bound, RangeBounds};
+
+use crate::{
+    Instruction,
+    machine::{
+        Chip8, Chip8Error,
+        display::{Display, Framebuffer},
+        keypad::Keypad,
+    },
+};
+
+/// Copy of the register file and the timers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    /// V0..=VF
+    pub v: [u8; 16],
+    /// I register
+    pub i: u16,
+    /// Program counter
+    pub pc: u16,
+    /// Delay timer
+    pub dt: u8,
+    /// Sound timer
+    pub st: u8,
+    /// SUPER-CHIP RPL user flags
+    pub rpl_flags: [u8; 16],
+}
+
+/// Return addresses on the stack and how many it holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackView<'a> {
+    /// Return addresses, the most recent one last
+    addresses: &'a [u16],
+    /// Return addresses the stack holds
+    depth: usize,
+}
+
+impl<'a> StackView<'a> {
+    /// Return addresses, the most recent one last
+    pub fn addresses(&self) -> &'a [u16] {
+        self.addresses
+    }
+
+    /// Address the next return goes to
+    pub fn top(&self) -> Option<u16> {
+        self.addresses.last().copied()
+    }
+
+    /// Number of addresses on the stack, the stack pointer
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Whether a return would fault
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Return addresses the stack holds
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Whether a call would fault
+    pub fn is_full(&self) -> bool {
+        self.addresses.len() == self.depth
+    }
+}
+
+impl Chip8 {
+    /// Snapshot of V0..=VF, I, PC, the timers and the RPL flags
+    pub fn registers(&self) -> Registers {
+        Registers {
+            v: *self.cpu.registers(),
+            i: self.cpu.address(),
+            pc: self.cpu.program_counter(),
+            dt: self.cpu.delay_timer(),
+            st: self.cpu.sound_timer(),
+            rpl_flags: *self.cpu.rpl_flags(),
+        }
+    }
+
+    /// The call stack with its depth
+    pub fn stack(&self) -> StackView<'_> {
+        StackView {
+            addresses: self.cpu.stack(),
+            depth: self.layout.stack_depth,
+        }
+    }
+
+    /// Bytes of memory in the address range, `None` if it reaches past the end of memory
+    pub fn memory_slice(&self, range: impl RangeBounds<u16>) -> Option<&[u8]> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start as usize,
+            Bound::Excluded(&start) => start as usize + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end as usize + 1,
+            Bound::Excluded(&end) => end as usize,
+            Bound::Unbounded => self.memory.size(),
+        };
+        self.memory.bytes().get(start..end)
+    }
+
+    /// Decoded instruction the next step runs
+    pub fn current_instruction(&self) -> Result<Instruction, Chip8Error> {
+        self.instruction_at(self.cpu.program_counter())
+    }
+
+    /// Get the keypad, e.g. to see which keys the machine sees pressed
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    /// Pressed state of keys 0..=F
+    pub fn keys(&self) -> [bool; 16] {
+        self.keypad.keys()
+    }
+
+    /// Get the display: planes, resolution and selected planes
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// First bitplane, regardless of whether it changed since the last snapshot.
+    /// See [`Chip8::planes`] for all of them
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.display.state()
+    }
+
+    /// Whether the display changed since the last [`Chip8::display_snapshot`] or
+    /// [`Chip8::planes_snapshot`], without clearing it
+    pub fn needs_redraw(&self) -> bool {
+        self.dirty_flag
+    }
+}
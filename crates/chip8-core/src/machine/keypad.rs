@@ -3,6 +3,8 @@
 use thiserror::Error;
 use tklog::{error, trace};
 
+use crate::machine::state::{StateError, StateReader, StateWriter};
+
 /// Hex keypad (0-F)
 pub struct Keypad {
     /// Keypad state
@@ -56,6 +58,29 @@ impl Keypad {
         Ok(self.state[key as usize])
     }
 
+    /// Pressed state of every key, indexed by key
+    pub fn keys(&self) -> [bool; 16] {
+        self.state
+    }
+
+    /// Append the keypad to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        let mask = self
+            .state
+            .iter()
+            .enumerate()
+            .fold(0_u16, |acc, (i, &pressed)| acc | ((pressed as u16) << i));
+        writer.u16(mask);
+    }
+
+    /// Read the keypad from a save state
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        let mask = reader.u16()?;
+        Ok(Self {
+            state: std::array::from_fn(|i| mask & (1 << i) != 0),
+        })
+    }
+
     /// Returns the first key that is pressed right now
     pub fn any_pressed(&self) -> Option<u8> {
         trace!(format!("Current state: {:?}", self.state));
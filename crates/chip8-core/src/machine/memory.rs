@@ -0,0 +1,428 @@
+//! Chip8 memory implementation
+
+use thiserror::Error;
+
+use crate::machine::{
+    builder::Layout,
+    state::{StateError, StateReader, StateWriter},
+};
+
+/// Chip8 ram struct
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    /// Bytes in memory, 4 KiB on most platforms and 64 KiB on XO-CHIP
+    data: Vec<u8>,
+    /// Address of the small font, the big one follows it
+    font_address: u16,
+    /// Writes below this address are rejected, it is where programs are loaded
+    load_address: u16,
+    /// Addresses past the end wrap around instead of being an error
+    wrap: bool,
+}
+
+use tklog::{error, trace};
+
+/// 4x5 sprites for the hex digits 0..=F
+pub type Font = [[u8; 5]; 16];
+
+/// 8x10 sprites for the hex digits 0..=F
+pub type BigFont = [[u8; 10]; 16];
+
+/// Bytes both fonts take in memory
+pub const FONTS_SIZE: usize = size_of::<Font>() + size_of::<BigFont>();
+
+/// Predefined sprites for all hex digits
+pub const DIGIT_SPRITES: Font = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // ZERO
+    [0x20, 0x60, 0x20, 0x20, 0x70], // ONE
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0], // TWO
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0], // THREE
+    [0x90, 0x90, 0xF0, 0x10, 0x10], // FOUR
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // FIVE
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0], // SIX
+    [0xF0, 0x10, 0x20, 0x40, 0x40], // SEVEN
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0], // EIGHT
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0], // NINE
+    [0xF0, 0x90, 0xF0, 0x90, 0x90], // A
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0], // B
+    [0xF0, 0x80, 0x80, 0x80, 0xF0], // C
+    [0xE0, 0x90, 0x90, 0x90, 0xE0], // D
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0], // E
+    [0xF0, 0x80, 0xF0, 0x80, 0x80], // F
+];
+
+/// Offset of the big digit sprites from the font address, right after the small ones
+const BIG_DIGIT_SPRITES_START: usize = size_of::<Font>();
+
+/// Predefined 8x10 sprites for all hex digits (SUPER-CHIP, A-F are from XO-CHIP)
+pub const BIG_DIGIT_SPRITES: BigFont = [
+    [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C], // ZERO
+    [0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C], // ONE
+    [0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF], // TWO
+    [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C], // THREE
+    [0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06], // FOUR
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C], // FIVE
+    [0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C], // SIX
+    [0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60], // SEVEN
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C], // EIGHT
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C], // NINE
+    [0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3], // A
+    [0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC], // B
+    [0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C], // C
+    [0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC], // D
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF], // E
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0], // F
+];
+
+/// Enum with variants encoding all memory-related errors
+#[derive(Debug, Error)]
+#[must_use]
+pub enum MemoryError {
+    #[error("Reserved memory access")]
+    /// Addresses below the load address (0x200 unless built otherwise) are reserved and can't
+    /// be overwritten
+    PermissionDenied,
+    #[error("Sprite index out of range")]
+    /// There are exactly 16 sprites (0..=F), accesing other indices is erroneous
+    IncorrectSprite,
+    #[error("Memory access out of range: {0:#X}")]
+    /// Access of memory beyond the available addresses
+    OutOfRange(u16),
+}
+
+impl Memory {
+    /// Create a 4 KiB ram filled with digit sprites (0x000 - 0x1FF)
+    pub fn new() -> Self {
+        Self::with_size(1 << 12)
+    }
+
+    /// Create a ram of given size filled with digit sprites (0x000 - 0x1FF)
+    pub fn with_size(size: usize) -> Self {
+        Self::with_layout(
+            vec![0; size],
+            &Layout::with_memory_size(size),
+            &DIGIT_SPRITES,
+            &BIG_DIGIT_SPRITES,
+        )
+    }
+
+    /// Create a ram holding `data`, with the fonts written over it at the font address of
+    /// the layout. `data` has to be as long as the memory of the layout
+    pub(crate) fn with_layout(
+        mut data: Vec<u8>,
+        layout: &Layout,
+        font: &Font,
+        big_font: &BigFont,
+    ) -> Self {
+        let start = layout.font_address as usize;
+
+        for (number, constant) in font.iter().enumerate() {
+            for (i, byte) in constant.iter().enumerate() {
+                data[start + number * 5 + i] = *byte;
+            }
+        }
+
+        for (number, constant) in big_font.iter().enumerate() {
+            let start = start + BIG_DIGIT_SPRITES_START + number * 10;
+            data[start..start + 10].copy_from_slice(constant);
+        }
+
+        Self {
+            data,
+            font_address: layout.font_address,
+            load_address: layout.load_address,
+            wrap: false,
+        }
+    }
+
+    /// Fetch sprite address from reserved memory
+    ///
+    /// Returns error if the sprite asked is not in 0..=F
+    pub fn read_sprite_address(&self, digit: u8) -> Result<u16, MemoryError> {
+        if digit > 0xF {
+            error!(
+                "Incorrect digit sprite asked, should be <= 0xF, was ",
+                digit
+            );
+            Err(MemoryError::IncorrectSprite)
+        } else {
+            let address = self.font_address + digit as u16 * 5;
+            trace!("Found digit ", digit, " sprite at ", address);
+            Ok(address)
+        }
+    }
+
+    /// Fetch big (8x10) sprite address from reserved memory
+    ///
+    /// Returns error if the sprite asked is not in 0..=F
+    pub fn read_big_sprite_address(&self, digit: u8) -> Result<u16, MemoryError> {
+        if digit > 0xF {
+            error!(
+                "Incorrect big digit sprite asked, should be <= 0xF, was ",
+                digit
+            );
+            Err(MemoryError::IncorrectSprite)
+        } else {
+            let address =
+                self.font_address + (BIG_DIGIT_SPRITES_START + digit as usize * 10) as u16;
+            trace!("Found big digit ", digit, " sprite at ", address);
+            Ok(address)
+        }
+    }
+
+    /// Size of the memory in bytes
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// All bytes in memory
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Let addresses past the end wrap around to the start
+    pub(crate) fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Address reduced modulo the memory size if addresses wrap
+    fn wrapped(&self, addr: usize) -> usize {
+        if self.wrap {
+            addr % self.data.len()
+        } else {
+            addr
+        }
+    }
+
+    /// Fetch a byte from address
+    pub fn read_byte(&self, addr: u16) -> Result<u8, MemoryError> {
+        let addr = self.wrapped(addr as usize) as u16;
+        if addr as usize >= self.data.len() {
+            error!(format!(
+                "Got incorrect address, should be below {}, was {addr}",
+                self.data.len()
+            ));
+            return Err(MemoryError::OutOfRange(addr));
+        }
+        trace!(
+            "Read byte ",
+            self.data[addr as usize], " from address ", addr
+        );
+        Ok(self.data[addr as usize])
+    }
+
+    /// Fetch a 2-byte word from address
+    pub fn read_word(&self, addr: u16) -> Result<u16, MemoryError> {
+        let next = self.wrapped(addr as usize + 1);
+        if next >= self.data.len() {
+            error!(format!(
+                "Got incorrect address, should be below {}, was {addr}",
+                self.data.len() - 1
+            ));
+            return Err(MemoryError::OutOfRange(addr));
+        }
+        let hi = self.data[self.wrapped(addr as usize)] as u16;
+        let lo = self.data[next] as u16;
+        trace!("Read word ", (hi << 8) | lo, " from address ", addr);
+        Ok((hi << 8) | lo)
+    }
+
+    /// Append the memory to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.u32(self.data.len() as u32);
+        writer.bytes(&self.data);
+    }
+
+    /// Read memory of the layout from a save state
+    pub(crate) fn read_state(
+        reader: &mut StateReader,
+        layout: &Layout,
+    ) -> Result<Self, StateError> {
+        let size = reader.u32()? as usize;
+        if size != layout.memory_size {
+            return Err(StateError::InvalidValue("memory size"));
+        }
+
+        Ok(Self {
+            data: reader.bytes(size)?.to_vec(),
+            font_address: layout.font_address,
+            load_address: layout.load_address,
+            wrap: false,
+        })
+    }
+
+    /// Load bytes into ram starting from given address
+    ///
+    /// The reserved area below the load address can't be written. If addresses wrap, the
+    /// bytes past the end would land in it, so that is still an error
+    pub fn load(&mut self, start: u16, bytes: &[u8]) -> Result<(), MemoryError> {
+        let start = self.wrapped(start as usize) as u16;
+        let wraps_around = start as usize + bytes.len() > self.data.len();
+        if start < self.load_address || (self.wrap && wraps_around) {
+            error!(format!(
+                "Requested reserved memory, addresses should be at least {}, got {start}",
+                self.load_address
+            ));
+            return Err(MemoryError::PermissionDenied);
+        }
+
+        if wraps_around {
+            let end = start.wrapping_add(bytes.len() as u16);
+            error!(format!(
+                "Invalid memory request, address should be below {}, got {end}",
+                self.data.len()
+            ));
+            return Err(MemoryError::OutOfRange(end));
+        }
+
+        self.data[start as usize..start as usize + bytes.len()].copy_from_slice(bytes);
+        trace!(format!(
+            "Wrote {bytes:?} to memory from {start} to {}",
+            start as usize + bytes.len()
+        ));
+        Ok(())
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn test_incorrect_sprite_index() {
+        let memory = Memory::new();
+
+        assert!(matches!(
+            memory.read_sprite_address(0xF + 2),
+            Err(MemoryError::IncorrectSprite)
+        ));
+    }
+
+    #[test_case(0x0 ; "lowest sprite")]
+    #[test_case(0xF ; "highest sprite")]
+    #[test_case(0x8 ; "random sprite")]
+    fn test_correct_sprite_indexes(index: u8) {
+        let memory = Memory::new();
+
+        // 5 rows for each digit
+        assert_eq!(memory.read_sprite_address(index).unwrap(), index as u16 * 5);
+    }
+
+    #[test_case(0x0 ; "lowest sprite")]
+    #[test_case(0xF ; "highest sprite")]
+    fn test_correct_big_sprite_indexes(index: u8) {
+        let memory = Memory::new();
+        let address = memory.read_big_sprite_address(index).unwrap();
+
+        assert_eq!(address, 0x50 + index as u16 * 10);
+        for (i, byte) in BIG_DIGIT_SPRITES[index as usize].iter().enumerate() {
+            assert_eq!(memory.read_byte(address + i as u16).unwrap(), *byte);
+        }
+    }
+
+    #[test]
+    fn test_incorrect_big_sprite_index() {
+        let memory = Memory::new();
+
+        assert!(matches!(
+            memory.read_big_sprite_address(0x10),
+            Err(MemoryError::IncorrectSprite)
+        ));
+    }
+
+    #[test]
+    fn test_read_byte_out_of_range() {
+        let memory = Memory::new();
+
+        assert!(matches!(
+            memory.read_byte(1 << 12),
+            Err(MemoryError::OutOfRange(_))
+        ));
+    }
+
+    #[test_case(0x200 ; "lowest address")]
+    #[test_case(0xFFF ; "highest address")]
+    #[test_case(0xB42 ; "random address")]
+    fn test_read_byte_correct(addr: u16) {
+        let memory = Memory::new();
+
+        assert_eq!(memory.read_byte(addr).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_read_word_out_of_range() {
+        let memory = Memory::new();
+
+        assert!(matches!(
+            memory.read_word(1 << 12),
+            Err(MemoryError::OutOfRange(_))
+        ));
+
+        // edge case when the hi in word is in memory, but lo is outside of it
+        assert!(matches!(
+            memory.read_word((1 << 12) - 1),
+            Err(MemoryError::OutOfRange(_))
+        ));
+    }
+
+    #[test_case(0x200 ; "lowest address")]
+    #[test_case(0xFFE ; "highest address")] // 0xFFF - 1 because we read 2 bytes at a time
+    #[test_case(0xB42 ; "random address")]
+    fn test_read_word_correct(addr: u16) {
+        let memory = Memory::new();
+
+        assert_eq!(memory.read_word(addr).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_load_permission_denied() {
+        let mut memory = Memory::new();
+        assert!(matches!(
+            memory.load(0x100, &[0xAA]),
+            Err(MemoryError::PermissionDenied)
+        ));
+    }
+
+    #[test]
+    fn test_load_out_of_range() {
+        let mut memory = Memory::new();
+        assert!(matches!(
+            memory.load(0xFFF, &[0xAA, 0xBB]),
+            Err(MemoryError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_xochip_size() {
+        let mut memory = Memory::with_size(1 << 16);
+        assert_eq!(memory.size(), 0x10000);
+
+        memory.load(0xFFFE, &[0xAB, 0xCD]).unwrap();
+        assert_eq!(memory.read_word(0xFFFE).unwrap(), 0xABCD);
+        assert!(matches!(
+            memory.read_word(0xFFFF),
+            Err(MemoryError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            memory.load(0xFFFF, &[0xAB, 0xCD]),
+            Err(MemoryError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_write_cycle() {
+        let mut memory = Memory::new();
+        memory.load(0x200, &[0xAB, 0xCD]).unwrap();
+        assert_eq!(memory.read_byte(0x200).unwrap(), 0xAB);
+        assert_eq!(memory.read_word(0x200).unwrap(), 0xABCD);
+    }
+}
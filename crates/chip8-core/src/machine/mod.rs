@@ -0,0 +1,889 @@
+//! Chip8 machine
+//!
+//! Contains all components of chip8: registers, stack, display, timers and sounds
+
+use std::{collections::VecDeque, sync::Arc};
+
+use thiserror::Error;
+use tklog::{debug, warn};
+
+use crate::{
+    decoder::instruction::{DecodeError, Instruction},
+    machine::{
+        audio::Audio,
+        builder::Layout,
+        cpu::{Cpu, CpuError},
+        display::{Display, DisplayError, Framebuffer, PLANE_COUNT},
+        fault::{FAULT_HISTORY, Fault, FaultAction, FaultClass, FaultPolicy},
+        keypad::{Keypad, KeypadError},
+        memory::{Memory, MemoryError},
+        platform::Platform,
+        quirks::{MemoryIncrement, Quirks},
+        state::{StateError, StateReader, StateWriter},
+    },
+    trace::{TraceRecord, Tracer},
+    types::Index,
+};
+
+pub mod audio;
+pub mod builder;
+pub mod cpu;
+pub mod display;
+pub mod fault;
+pub mod inspect;
+pub mod keypad;
+pub mod memory;
+pub mod platform;
+pub mod quirks;
+#[cfg(feature = "rewind")]
+pub mod rewind;
+pub mod rng;
+pub mod state;
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests;
+
+/// Enum of program counter statuses after command execution
+#[derive(Debug)]
+pub enum ExecResult {
+    /// PC should advance to next word
+    Advance,
+    /// Last instruction jumped, no need to move PC
+    Jumped,
+    /// Waiting on something, no need to move PC
+    Wait,
+    /// Skip the next instruction, moves PC past it (by 4, or 6 for XO-CHIP `F000 NNNN`)
+    Skip,
+    /// The program asked to exit, no need to move PC
+    Exit,
+}
+
+/// Full Chip-8 machine
+pub struct Chip8 {
+    /// Chip8 cpu
+    cpu: Cpu,
+    /// Chip8 memory
+    memory: Memory,
+    /// Chip8 display
+    display: Display,
+    /// Chip8 keypad
+    keypad: Keypad,
+    /// XO-CHIP audio pattern and pitch
+    audio: Audio,
+    /// Flag that shows if any redraw is actually needed
+    dirty_flag: bool,
+    /// Interpretation of the ambiguous instructions
+    quirks: Quirks,
+    /// Emulated platform
+    platform: Platform,
+    /// Memory size, where the fonts and the program are and how deep the stack is
+    layout: Layout,
+    /// Set once the program executed `00FD`, no more instructions are run after that
+    exited: bool,
+    /// What to do when the program faults, kept across save state loads
+    fault_policy: FaultPolicy,
+    /// Fault that halted the machine, no more instructions are run after that
+    halted: Option<Arc<Fault>>,
+    /// Instructions executed since the machine was created, kept across save state loads
+    cycles: u64,
+    /// Last instructions executed, oldest first, reported with a fault
+    history: VecDeque<TraceRecord>,
+    /// Records the executed instructions, kept across save state loads
+    tracer: Option<Tracer>,
+}
+
+/// Enum of all possible errors with chip8 instance
+#[derive(Debug, Error)]
+pub enum Chip8Error {
+    #[error(transparent)]
+    /// Cpu error
+    CpuError(#[from] CpuError),
+    #[error(transparent)]
+    /// Memory error
+    MemoryError(#[from] MemoryError),
+    #[error(transparent)]
+    /// Display error
+    DisplayError(#[from] DisplayError),
+    #[error(transparent)]
+    /// Keypad error
+    KeypadError(#[from] KeypadError),
+    #[error(transparent)]
+    /// Instruction decoding error
+    DecodeError(#[from] DecodeError),
+    #[error("Unsupported instruction")]
+    /// Unsupported instruction called (assembly subroutines, or an extension the platform lacks)
+    UnsupportedInstruction,
+    #[error(transparent)]
+    /// Save state could not be restored
+    StateError(#[from] StateError),
+    #[error("Trace error: {0}")]
+    /// The execution trace could not be written
+    TraceError(#[from] std::io::Error),
+    #[error("{0}")]
+    /// The program faulted, the [`FaultPolicy`] decided what happened to the machine
+    Fault(Arc<Fault>),
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8 {
+    /// VF register is used a lot here, so it is a predefined constant
+    const VF: Index = unsafe { Index::new_unchecked(0xF) };
+
+    /// Create a new Chip8
+    pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+
+    /// Create a new Chip8 with the given quirks
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Self::with_platform(Platform::default(), quirks)
+    }
+
+    /// Create a new Chip8 emulating the given platform
+    ///
+    /// See [`builder::Chip8Builder`] for more settings
+    pub fn with_platform(platform: Platform, quirks: Quirks) -> Self {
+        let layout = Layout::with_memory_size(platform.memory_size());
+        Self {
+            cpu: Cpu::with_layout(&layout),
+            memory: Memory::with_size(layout.memory_size),
+            display: Display::new(),
+            keypad: Keypad::new(),
+            audio: Audio::new(),
+            dirty_flag: false,
+            quirks,
+            platform,
+            layout,
+            exited: false,
+            fault_policy: FaultPolicy::default(),
+            halted: None,
+            cycles: 0,
+            history: VecDeque::with_capacity(FAULT_HISTORY),
+            tracer: None,
+        }
+    }
+
+    /// Get the emulated platform
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Get the active quirks
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Get the memory size, the font and load addresses and the stack depth
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Change the quirks, takes effect from the next executed instruction
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Get the fault policy
+    pub fn fault_policy(&self) -> FaultPolicy {
+        self.fault_policy
+    }
+
+    /// Change what happens when the program faults, takes effect from the next executed
+    /// instruction
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.fault_policy = policy;
+        self.cpu.set_wrap(policy.wraps_addresses());
+        self.memory.set_wrap(policy.wraps_addresses());
+    }
+
+    /// Fault that halted the machine, `None` while it runs
+    pub fn halted(&self) -> Option<&Fault> {
+        self.halted.as_deref()
+    }
+
+    /// Crash dump of the halted machine, `None` while it runs. See [`fault::read_crash_dump`]
+    /// for the format
+    pub fn crash_dump(&self) -> Option<Vec<u8>> {
+        let fault = self.halted.as_deref()?;
+        Some(fault.crash_dump(&self.save_state()))
+    }
+
+    /// Restore the machine a crash dump was taken from, halted on its fault
+    ///
+    /// The fault policy and the tracer are kept, like with [`Chip8::load_state`].
+    /// On error the machine is left untouched
+    pub fn load_crash_dump(&mut self, dump: &[u8]) -> Result<(), Chip8Error> {
+        let (fault, state) = fault::read_crash_dump(dump)?;
+        self.load_state(&state)?;
+        self.cycles = fault.cycle;
+        self.history = fault.history.iter().copied().collect();
+        self.halted = Some(Arc::new(fault));
+
+        Ok(())
+    }
+
+    /// Load the program for execution, starting at the load address (0x200 unless built
+    /// otherwise)
+    pub fn load_program(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        self.memory.load(self.layout.load_address, program)?;
+        self.cpu.set_program_counter(self.layout.load_address)?;
+
+        Ok(())
+    }
+
+    /// Serialize the whole machine, see [`state`] for the format
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        self.platform.write_state(&mut writer);
+        self.quirks.write_state(&mut writer);
+        writer.bool(self.exited);
+        self.layout.write_state(&mut writer);
+        self.cpu.write_state(&mut writer);
+        self.memory.write_state(&mut writer);
+        self.display.write_state(&mut writer);
+        self.keypad.write_state(&mut writer);
+        self.audio.write_state(&mut writer);
+
+        writer.finish()
+    }
+
+    /// Restore the machine from a save state
+    ///
+    /// The platform, quirks and layout are restored as well, a halted machine runs again.
+    /// On error the machine is left untouched
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Chip8Error> {
+        let mut reader = StateReader::new(state)?;
+
+        let platform = Platform::read_state(&mut reader)?;
+        let quirks = Quirks::read_state(&mut reader)?;
+        let exited = reader.bool("exited flag")?;
+        let layout = Layout::read_state(&mut reader)?;
+        let cpu = Cpu::read_state(&mut reader, &layout)?;
+        let memory = Memory::read_state(&mut reader, &layout)?;
+        let display = Display::read_state(&mut reader)?;
+        let keypad = Keypad::read_state(&mut reader)?;
+        let audio = Audio::read_state(&mut reader)?;
+        reader.finish()?;
+
+        *self = Self {
+            cpu,
+            memory,
+            display,
+            keypad,
+            audio,
+            dirty_flag: true,
+            quirks,
+            platform,
+            layout,
+            exited,
+            fault_policy: self.fault_policy,
+            halted: None,
+            cycles: self.cycles,
+            history: VecDeque::with_capacity(FAULT_HISTORY),
+            tracer: self.tracer.take(),
+        };
+        self.set_fault_policy(self.fault_policy);
+
+        Ok(())
+    }
+
+    /// Run one fetch-decode-execute cycle
+    ///
+    /// Faults are handled according to the [`FaultPolicy`], they come back as
+    /// [`Chip8Error::Fault`] unless the instruction is skipped.
+    /// Does nothing once the program has exited or the machine halted
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        if self.exited || self.halted.is_some() {
+            return Ok(());
+        }
+
+        let pc = self.cpu.program_counter();
+        match self.run_instruction(pc) {
+            Ok(()) => Ok(()),
+            Err(error) => self.fault(pc, error),
+        }
+    }
+
+    /// Fetch, decode and execute the instruction at `pc`, moving PC past it
+    fn run_instruction(&mut self, pc: u16) -> Result<(), Chip8Error> {
+        let instruction = self.fetch(pc)?;
+        let record = TraceRecord::new(self.cycles, &instruction, &self.cpu);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(&instruction, &self.cpu)?;
+        }
+
+        let exec_result = self.execute(instruction)?;
+        match exec_result {
+            ExecResult::Advance => self.cpu.advance_program_counter(instruction.size())?,
+            ExecResult::Jumped => {}
+            ExecResult::Wait => {}
+            ExecResult::Skip => {
+                let skipped = self.fetch_size(pc.wrapping_add(instruction.size()))?;
+                self.cpu
+                    .advance_program_counter(instruction.size() + skipped)?
+            }
+            ExecResult::Exit => {}
+        };
+
+        debug!(format!("ExecResult = {:?}", exec_result));
+
+        if self.history.len() == FAULT_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(record);
+        self.cycles += 1;
+
+        Ok(())
+    }
+
+    /// Apply the fault policy to an error of the instruction at `pc`
+    ///
+    /// Errors that aren't caused by the program are returned as they are
+    fn fault(&mut self, pc: u16, error: Chip8Error) -> Result<(), Chip8Error> {
+        let Some(class) = FaultClass::of(&error) else {
+            return Err(error);
+        };
+        let action = self.fault_policy.action(class);
+        let instruction = self.fetch(pc).ok();
+        let fault = Arc::new(Fault {
+            class,
+            action,
+            pc,
+            opcode: self.memory.read_word(pc).unwrap_or_default(),
+            instruction,
+            cycle: self.cycles,
+            registers: *self.cpu.registers(),
+            i: self.cpu.address(),
+            stack: self.cpu.stack().to_vec(),
+            history: self.history.iter().copied().collect(),
+            error,
+        });
+
+        match action {
+            FaultAction::Break => return Err(Chip8Error::Fault(fault)),
+            FaultAction::Nop => {
+                let size = self.fetch_size(pc).unwrap_or(2);
+                if self.cpu.advance_program_counter(size).is_ok() {
+                    warn!(format!("Skipped the faulting instruction: {fault}"));
+                    return Ok(());
+                }
+            }
+            FaultAction::Halt | FaultAction::Wrap => {}
+        }
+
+        self.halted = Some(Arc::clone(&fault));
+        Err(Chip8Error::Fault(fault))
+    }
+
+    /// Read and decode the instruction at the address
+    fn fetch(&self, pc: u16) -> Result<Instruction, Chip8Error> {
+        let opcode = self.memory.read_word(pc)?;
+        debug!(format!("PC={:#03x}, opcode={:#04x}", pc, opcode));
+
+        if Instruction::has_operand(opcode) {
+            let operand = self.memory.read_word(pc.wrapping_add(2))?;
+            Ok(Instruction::decode(opcode, operand)?)
+        } else {
+            Ok(Instruction::try_from(opcode)?)
+        }
+    }
+
+    /// Record every executed instruction with `tracer`, replacing the previous one
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Detach the tracer, e.g. to flush it
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Decode the instruction at the address without executing it
+    pub fn instruction_at(&self, address: u16) -> Result<Instruction, Chip8Error> {
+        self.fetch(address)
+    }
+
+    /// Size of the instruction at the address, without decoding it
+    fn fetch_size(&self, pc: u16) -> Result<u16, Chip8Error> {
+        let opcode = self.memory.read_word(pc)?;
+        Ok(if Instruction::has_operand(opcode) {
+            4
+        } else {
+            2
+        })
+    }
+
+    /// Execute an instruction
+    fn execute(&mut self, instruction: Instruction) -> Result<ExecResult, Chip8Error> {
+        if !self.platform.supports(&instruction) {
+            return Err(Chip8Error::UnsupportedInstruction);
+        }
+
+        let result = match instruction {
+            Instruction::CallMachineCode { address: _address } => {
+                return Err(Chip8Error::UnsupportedInstruction);
+            }
+            Instruction::ClearDisplay => {
+                self.display.clear();
+                self.dirty_flag = true;
+                ExecResult::Advance
+            }
+            Instruction::ScrollDown { rows } => {
+                self.display.scroll_down(rows as usize);
+                self.dirty_flag = true;
+                ExecResult::Advance
+            }
+            Instruction::ScrollUp { rows } => {
+                self.display.scroll_up(rows as usize);
+                self.dirty_flag = true;
+                ExecResult::Advance
+            }
+            Instruction::ScrollRight => {
+                self.display.scroll_right(4);
+                self.dirty_flag = true;
+                ExecResult::Advance
+            }
+            Instruction::ScrollLeft => {
+                self.display.scroll_left(4);
+                self.dirty_flag = true;
+                ExecResult::Advance
+            }
+            Instruction::Exit => {
+                self.exited = true;
+                ExecResult::Exit
+            }
+            Instruction::LowRes => {
+                self.display.set_hires(false);
+                self.dirty_flag = true;
+                ExecResult::Advance
+            }
+            Instruction::HighRes => {
+                self.display.set_hires(true);
+                self.dirty_flag = true;
+                ExecResult::Advance
+            }
+            Instruction::Return => {
+                let new_pc = self.cpu.stack_pop()?;
+                self.cpu.set_program_counter(new_pc)?;
+                ExecResult::Jumped
+            }
+            Instruction::Goto { address } => {
+                self.cpu.set_program_counter(address.into_inner())?;
+                ExecResult::Jumped
+            }
+            Instruction::CallSubroutine { address } => {
+                self.cpu
+                    .stack_push(self.cpu.program_counter().wrapping_add(2))?;
+                self.cpu.set_program_counter(address.into_inner())?;
+                ExecResult::Jumped
+            }
+            Instruction::EqConst { x, value } => {
+                if *self.cpu.vx(x) == value {
+                    ExecResult::Skip
+                } else {
+                    ExecResult::Advance
+                }
+            }
+            Instruction::NeqConst { x, value } => {
+                if *self.cpu.vx(x) != value {
+                    ExecResult::Skip
+                } else {
+                    ExecResult::Advance
+                }
+            }
+            Instruction::EqReg { x, y } => {
+                let vx = *self.cpu.vx(x);
+                let vy = *self.cpu.vx(y);
+                if vx == vy {
+                    ExecResult::Skip
+                } else {
+                    ExecResult::Advance
+                }
+            }
+            Instruction::SaveRange { x, y } => {
+                for (offset, i) in Self::register_range(x, y).enumerate() {
+                    let vi = *self.cpu.vx(i);
+                    self.memory
+                        .load(self.cpu.address().wrapping_add(offset as u16), &[vi])?;
+                }
+                ExecResult::Advance
+            }
+            Instruction::LoadRange { x, y } => {
+                for (offset, i) in Self::register_range(x, y).enumerate() {
+                    *self.cpu.vx(i) = self
+                        .memory
+                        .read_byte(self.cpu.address().wrapping_add(offset as u16))?;
+                }
+                ExecResult::Advance
+            }
+            Instruction::AssignConst { x, value } => {
+                *self.cpu.vx(x) = value;
+                ExecResult::Advance
+            }
+            Instruction::AddAssignConst { x, value } => {
+                // 7XNN has no carry flag, it wraps silently
+                let vx = self.cpu.vx(x);
+                *vx = vx.wrapping_add(value);
+                ExecResult::Advance
+            }
+            Instruction::AssignReg { x, y } => {
+                let vy = *self.cpu.vx(y);
+                *self.cpu.vx(x) = vy;
+                ExecResult::Advance
+            }
+            Instruction::OrReg { x, y } => {
+                let vy = *self.cpu.vx(y);
+                *self.cpu.vx(x) |= vy;
+                self.maybe_reset_vf();
+                ExecResult::Advance
+            }
+            Instruction::AndReg { x, y } => {
+                let vy = *self.cpu.vx(y);
+                *self.cpu.vx(x) &= vy;
+                self.maybe_reset_vf();
+                ExecResult::Advance
+            }
+            Instruction::XorReg { x, y } => {
+                let vy = *self.cpu.vx(y);
+                *self.cpu.vx(x) ^= vy;
+                self.maybe_reset_vf();
+                ExecResult::Advance
+            }
+            Instruction::AddAssignReg { x, y } => {
+                let (sum, carry) = self.cpu.vx(x).overflowing_add(*self.cpu.vx(y));
+                // the flag is written last, so it wins when X is F
+                *self.cpu.vx(x) = sum;
+                *self.cpu.vx(Chip8::VF) = carry as u8;
+                ExecResult::Advance
+            }
+            Instruction::SubAssignReg { x, y } => {
+                let (difference, borrow) = self.cpu.vx(x).overflowing_sub(*self.cpu.vx(y));
+                *self.cpu.vx(x) = difference;
+                *self.cpu.vx(Chip8::VF) = !borrow as u8;
+                ExecResult::Advance
+            }
+            Instruction::RShift { x, y } => {
+                let source = *self.cpu.vx(self.shift_source(x, y));
+                *self.cpu.vx(x) = source >> 1;
+                *self.cpu.vx(Chip8::VF) = source & 1;
+                ExecResult::Advance
+            }
+            Instruction::SubAssignRegInverse { x, y } => {
+                let (difference, borrow) = self.cpu.vx(y).overflowing_sub(*self.cpu.vx(x));
+                *self.cpu.vx(x) = difference;
+                *self.cpu.vx(Chip8::VF) = !borrow as u8;
+                ExecResult::Advance
+            }
+            Instruction::LShift { x, y } => {
+                let source = *self.cpu.vx(self.shift_source(x, y));
+                *self.cpu.vx(x) = source << 1;
+                *self.cpu.vx(Chip8::VF) = (source >> 7) & 1;
+                ExecResult::Advance
+            }
+            Instruction::NeqReg { x, y } => {
+                let vx = *self.cpu.vx(x);
+                let vy = *self.cpu.vx(y);
+                if vx != vy {
+                    ExecResult::Skip
+                } else {
+                    ExecResult::Advance
+                }
+            }
+            Instruction::SetI { address } => {
+                self.cpu.set_address(address.into_inner())?;
+                ExecResult::Advance
+            }
+            Instruction::GotoPlusV0 { address } => {
+                let offset_register = if self.quirks.jump_uses_vx {
+                    Index::try_new(((address.into_inner() >> 8) & 0xF) as u8).unwrap()
+                } else {
+                    Index::try_new(0x0_u8).unwrap()
+                };
+                let vx = *self.cpu.vx(offset_register);
+                self.cpu
+                    .set_program_counter(address.into_inner() + vx as u16)?;
+                ExecResult::Jumped
+            }
+            Instruction::Rand { x, value } => {
+                let rand = self.cpu.random();
+                *self.cpu.vx(x) = rand & value;
+                ExecResult::Advance
+            }
+            Instruction::DrawSprite { x, y, height } => {
+                let vx = *self.cpu.vx(x);
+                let vy = *self.cpu.vx(y);
+                let wrap = self.quirks.wrap_sprites;
+                let planes = self.display.selected_plane_count();
+
+                let collision = if height.into_inner() == 0 && self.platform.has_wide_sprites() {
+                    let mut sprite = vec![0_u8; 32 * planes];
+                    self.read_sprite(&mut sprite)?;
+                    self.display.draw_wide_sprite(&sprite, vx, vy, wrap)
+                } else {
+                    let mut sprite = vec![0_u8; height.into_inner() as usize * planes];
+                    self.read_sprite(&mut sprite)?;
+                    self.display.draw_sprite(&sprite, vx, vy, wrap)?
+                };
+
+                *self.cpu.vx(Chip8::VF) = collision as u8;
+                self.dirty_flag = true;
+
+                ExecResult::Advance
+            }
+            Instruction::KeyPressedSkip { x } => {
+                let vx = *self.cpu.vx(x);
+                let pressed = self.keypad.is_pressed(vx)?;
+                if pressed {
+                    ExecResult::Skip
+                } else {
+                    ExecResult::Advance
+                }
+            }
+            Instruction::KeyReleasedSkip { x } => {
+                let vx = *self.cpu.vx(x);
+
+                if !self.keypad.is_pressed(vx)? {
+                    ExecResult::Skip
+                } else {
+                    ExecResult::Advance
+                }
+            }
+            Instruction::LongSetI { address } => {
+                self.cpu.set_address(address.into_inner())?;
+                ExecResult::Advance
+            }
+            Instruction::SelectPlanes { planes } => {
+                self.display.select_planes(planes);
+                ExecResult::Advance
+            }
+            Instruction::LoadAudioPattern => {
+                let mut pattern = [0_u8; 16];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self
+                        .memory
+                        .read_byte(self.cpu.address().wrapping_add(i as u16))?;
+                }
+                self.audio.set_pattern(pattern);
+                ExecResult::Advance
+            }
+            Instruction::SetPitch { x } => {
+                let vx = *self.cpu.vx(x);
+                self.audio.set_pitch(vx);
+                ExecResult::Advance
+            }
+            Instruction::GetDelayTimer { x } => {
+                *self.cpu.vx(x) = self.cpu.delay_timer();
+                ExecResult::Advance
+            }
+            Instruction::AwaitKeyPress { x } => {
+                if let Some(k) = self.keypad.any_pressed() {
+                    *self.cpu.vx(x) = k;
+                    ExecResult::Advance
+                } else {
+                    ExecResult::Wait
+                }
+            }
+            Instruction::SetDelayTimer { x } => {
+                let vx = *self.cpu.vx(x);
+                self.cpu.set_delay_timer(vx);
+                ExecResult::Advance
+            }
+            Instruction::SetSoundTimer { x } => {
+                let vx = *self.cpu.vx(x);
+                self.cpu.set_sound_timer(vx);
+                ExecResult::Advance
+            }
+            Instruction::AddAssignAddress { x } => {
+                let vx = *self.cpu.vx(x);
+
+                self.cpu.advance_address(vx as u16)?;
+                ExecResult::Advance
+            }
+            Instruction::SetSpriteAddr { x } => {
+                let digit = *self.cpu.vx(x);
+
+                // & 0x0F guarantees that the sprite index is in required bounds (0..=F)
+                let sprite_addr = self.memory.read_sprite_address(digit)?;
+                self.cpu.set_address(sprite_addr)?;
+
+                ExecResult::Advance
+            }
+            Instruction::SetBigSpriteAddr { x } => {
+                let digit = *self.cpu.vx(x);
+
+                let sprite_addr = self.memory.read_big_sprite_address(digit)?;
+                self.cpu.set_address(sprite_addr)?;
+
+                ExecResult::Advance
+            }
+            Instruction::SetBCD { x } => {
+                let vx = *self.cpu.vx(x);
+
+                let hundreds = vx / 100;
+                let tens = (vx / 10) % 10;
+                let ones = vx % 10;
+
+                self.memory
+                    .load(self.cpu.address(), &[hundreds, tens, ones])?;
+                ExecResult::Advance
+            }
+            Instruction::DumpRegisters { x } => {
+                for i in 0..=x.into_inner() {
+                    self.memory.load(
+                        self.cpu.address().wrapping_add(i as u16),
+                        &[*self.cpu.vx(Index::try_new(i).unwrap())],
+                    )?;
+                }
+                self.advance_address_after_transfer(x)?;
+                ExecResult::Advance
+            }
+            Instruction::LoadRegisters { x } => {
+                for i in 0..=x.into_inner() {
+                    *self.cpu.vx(Index::try_new(i).unwrap()) = self
+                        .memory
+                        .read_byte(self.cpu.address().wrapping_add(i as u16))?;
+                }
+                self.advance_address_after_transfer(x)?;
+                ExecResult::Advance
+            }
+            Instruction::SaveFlags { x } => {
+                self.cpu.save_flags(x);
+                ExecResult::Advance
+            }
+            Instruction::LoadFlags { x } => {
+                self.cpu.load_flags(x);
+                ExecResult::Advance
+            }
+        };
+
+        Ok(result)
+    }
+
+    /// Fill the sprite buffer with bytes starting at I
+    fn read_sprite(&self, sprite: &mut [u8]) -> Result<(), Chip8Error> {
+        for (i, byte) in sprite.iter_mut().enumerate() {
+            *byte = self
+                .memory
+                .read_byte(self.cpu.address().wrapping_add(i as u16))?;
+        }
+        Ok(())
+    }
+
+    /// Registers X..=Y in order, going down if X > Y
+    pub(crate) fn register_range(x: Index, y: Index) -> impl Iterator<Item = Index> {
+        let (x, y) = (x.into_inner(), y.into_inner());
+        (0..=x.abs_diff(y)).map(move |i| {
+            let register = if x <= y { x + i } else { x - i };
+            Index::try_new(register).unwrap()
+        })
+    }
+
+    /// Register that 8XY6/8XYE read from, depending on the shift quirk
+    fn shift_source(&self, x: Index, y: Index) -> Index {
+        if self.quirks.shift_uses_vy { y } else { x }
+    }
+
+    /// Reset VF after a logic instruction if the quirk asks for it
+    fn maybe_reset_vf(&mut self) {
+        if self.quirks.logic_resets_vf {
+            *self.cpu.vx(Chip8::VF) = 0;
+        }
+    }
+
+    /// Move I after FX55/FX65 according to the memory increment quirk
+    fn advance_address_after_transfer(&mut self, x: Index) -> Result<(), Chip8Error> {
+        let step = match self.quirks.memory_increment {
+            MemoryIncrement::None => return Ok(()),
+            MemoryIncrement::ByX => x.into_inner() as u16,
+            MemoryIncrement::ByXPlusOne => x.into_inner() as u16 + 1,
+        };
+        self.cpu.advance_address(step)?;
+        Ok(())
+    }
+
+    /// Get a snapshot of current display state to render
+    ///
+    /// Only the part given by [`Chip8::resolution`] is meaningful
+    pub fn display_snapshot(&mut self) -> Option<&Framebuffer> {
+        if self.dirty_flag {
+            self.dirty_flag = false;
+            Some(self.display.state())
+        } else {
+            None
+        }
+    }
+
+    /// Get a snapshot of all bitplanes to render, see [`Chip8::display_snapshot`]
+    pub fn planes_snapshot(&mut self) -> Option<&[Framebuffer; PLANE_COUNT]> {
+        if self.dirty_flag {
+            self.dirty_flag = false;
+            Some(self.display.planes())
+        } else {
+            None
+        }
+    }
+
+    /// Get all bitplanes, regardless of whether they changed since the last snapshot
+    pub fn planes(&self) -> &[Framebuffer; PLANE_COUNT] {
+        self.display.planes()
+    }
+
+    /// Get the cpu registers and timers
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    /// Get the memory, fonts included
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Overwrite memory starting at the address, the reserved area below the load address is
+    /// rejected
+    pub fn write_memory(&mut self, start: u16, bytes: &[u8]) -> Result<(), Chip8Error> {
+        self.memory.load(start, bytes)?;
+        Ok(())
+    }
+
+    /// Reseed the random number generator, making `CXNN` reproducible
+    pub fn seed_random(&mut self, seed: u64) {
+        self.cpu.seed_random(seed);
+    }
+
+    /// Get the XO-CHIP audio pattern and pitch
+    pub fn audio(&self) -> &Audio {
+        &self.audio
+    }
+
+    /// Current display resolution as (width, height)
+    pub fn resolution(&self) -> (usize, usize) {
+        self.display.resolution()
+    }
+
+    /// Check if the program has exited with `00FD`
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Tick timers by one if possible
+    pub fn tick_timers(&mut self) {
+        self.cpu.tick_timers()
+    }
+
+    /// Check if the sound should be played
+    pub fn is_sound_playing(&self) -> bool {
+        self.cpu.sound_timer() > 0
+    }
+
+    /// Forward the keypresses to the keypad
+    pub fn set_key_state(&mut self, key: u8, state: bool) -> Result<(), Chip8Error> {
+        self.keypad.set_key_state(key, state).map_err(|x| x.into())
+    }
+}
@@ -0,0 +1,78 @@
+//! Chip8 platforms
+//!
+//! The platform decides which instruction set extensions are available and how much memory
+//! the machine has. Ambiguous instruction semantics are configured separately with [`Quirks`](super::quirks::Quirks).
+
+use crate::{
+    decoder::instruction::Instruction,
+    machine::state::{StateError, StateReader, StateWriter},
+};
+
+/// Chip8 variant the machine emulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// Original CHIP-8 instruction set, 4 KiB of memory
+    Chip8,
+    /// CHIP-8 with the SUPER-CHIP 1.1 extensions, 4 KiB of memory
+    #[default]
+    SuperChip,
+    /// SUPER-CHIP with the XO-CHIP extensions, 64 KiB of memory
+    XoChip,
+}
+
+impl Platform {
+    /// Size of the addressable memory in bytes
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 1 << 12,
+            Platform::XoChip => 1 << 16,
+        }
+    }
+
+    /// Check if `DXY0` draws a 16x16 sprite, on the original CHIP-8 it draws nothing
+    pub fn has_wide_sprites(self) -> bool {
+        self != Platform::Chip8
+    }
+
+    /// Check if the instruction exists on this platform
+    pub fn supports(self, instruction: &Instruction) -> bool {
+        match instruction {
+            Instruction::ScrollDown { .. }
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::SetBigSpriteAddr { .. }
+            | Instruction::SaveFlags { .. }
+            | Instruction::LoadFlags { .. } => self != Platform::Chip8,
+            Instruction::ScrollUp { .. }
+            | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LongSetI { .. }
+            | Instruction::SelectPlanes { .. }
+            | Instruction::LoadAudioPattern
+            | Instruction::SetPitch { .. } => self == Platform::XoChip,
+            _ => true,
+        }
+    }
+
+    /// Append the platform to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.u8(match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        });
+    }
+
+    /// Read the platform from a save state
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        match reader.u8()? {
+            0 => Ok(Platform::Chip8),
+            1 => Ok(Platform::SuperChip),
+            2 => Ok(Platform::XoChip),
+            _ => Err(StateError::InvalidValue("platform")),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Chip8 quirks
+//!
+//! Interpreters never agreed on the exact semantics of a handful of opcodes,
+//! so ROMs written for one of them may misbehave on another. [`Quirks`] picks
+//! one interpretation per contested behavior.
+
+use crate::machine::state::{StateError, StateReader, StateWriter};
+
+/// How `FX55`/`FX65` change the address register after the transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    /// I is left untouched
+    None,
+    /// I is advanced by X
+    ByX,
+    /// I is advanced by X + 1, pointing right after the last register
+    ByXPlusOne,
+}
+
+/// Set of toggles for the ambiguous chip8 instructions
+///
+/// Presets are provided for the common platforms, single flags can be
+/// overridden with struct update syntax:
+///
+/// ```ignore
+/// let quirks = Quirks {
+///     wrap_sprites: true,
+///     ..Quirks::COSMAC_VIP
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift VY and store the result in VX,
+    /// instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// What `FX55`/`FX65` do to I
+    pub memory_increment: MemoryIncrement,
+    /// `BNNN` behaves like `BXNN`, jumping to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to 0
+    pub logic_resets_vf: bool,
+    /// Sprites crossing the screen edge wrap around instead of being clipped
+    pub wrap_sprites: bool,
+}
+
+impl Quirks {
+    /// Original COSMAC VIP interpreter
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        memory_increment: MemoryIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        wrap_sprites: false,
+    };
+
+    /// CHIP-48 for the HP-48 calculators
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        memory_increment: MemoryIncrement::ByX,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        wrap_sprites: false,
+    };
+
+    /// SUPER-CHIP 1.1
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        memory_increment: MemoryIncrement::None,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        wrap_sprites: false,
+    };
+
+    /// XO-CHIP, as implemented by Octo
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        memory_increment: MemoryIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        wrap_sprites: true,
+    };
+
+    /// Append the quirks to a save state
+    pub(crate) fn write_state(&self, writer: &mut StateWriter) {
+        writer.bool(self.shift_uses_vy);
+        writer.u8(match self.memory_increment {
+            MemoryIncrement::None => 0,
+            MemoryIncrement::ByX => 1,
+            MemoryIncrement::ByXPlusOne => 2,
+        });
+        writer.bool(self.jump_uses_vx);
+        writer.bool(self.logic_resets_vf);
+        writer.bool(self.wrap_sprites);
+    }
+
+    /// Read the quirks from a save state
+    pub(crate) fn read_state(reader: &mut StateReader) -> Result<Self, StateError> {
+        Ok(Self {
+            shift_uses_vy: reader.bool("shift quirk")?,
+            memory_increment: match reader.u8()? {
+                0 => MemoryIncrement::None,
+                1 => MemoryIncrement::ByX,
+                2 => MemoryIncrement::ByXPlusOne,
+                _ => return Err(StateError::InvalidValue("memory increment quirk")),
+            },
+            jump_uses_vx: reader.bool("jump quirk")?,
+            logic_resets_vf: reader.bool("logic quirk")?,
+            wrap_sprites: reader.bool("wrap quirk")?,
+        })
+    }
+}
+
+/// Behavior of this emulator before quirks were configurable
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift_uses_vy: false,
+            memory_increment: MemoryIncrement::None,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            wrap_sprites: true,
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Rewind buffer
+//!
+//! Keeps a bounded history of save states. Only the newest snapshot is kept in full,
+//! every older one is stored as a delta against its successor: the two states are XORed
+//! and the runs of zero bytes are dropped, so a frame that touched a few bytes of memory
+//! and a handful of pixels costs a few dozen bytes instead of the whole state.
+//!
+//! A delta is a sequence of chunks, each one being
+//! `skip: varint, length: varint, bytes: [u8; length]`, preceded by the length of the
+//! older state as a varint. Bytes past the end of the newer state are XORed against zero.
+
+use std::collections::VecDeque;
+
+use crate::machine::{Chip8, Chip8Error};
+
+/// Ring buffer of machine snapshots with a memory budget
+///
+/// Once the budget is exceeded the oldest snapshots are dropped first
+#[derive(Debug, Clone)]
+pub struct RewindBuffer {
+    /// Most recent snapshot, stored in full
+    newest: Option<Vec<u8>>,
+    /// Deltas leading from every snapshot to the one before it, oldest first
+    deltas: VecDeque<Vec<u8>>,
+    /// Bytes used by the newest snapshot and all the deltas
+    used: usize,
+    /// Maximum number of bytes to use
+    budget: usize,
+}
+
+impl RewindBuffer {
+    /// Create an empty buffer that keeps at most `budget` bytes of snapshots
+    pub fn new(budget: usize) -> Self {
+        Self {
+            newest: None,
+            deltas: VecDeque::new(),
+            used: 0,
+            budget,
+        }
+    }
+
+    /// Snapshot the machine
+    pub fn push(&mut self, chip8: &Chip8) {
+        self.push_state(chip8.save_state());
+    }
+
+    /// Add a save state as the newest snapshot
+    ///
+    /// A state that doesn't fit the budget on its own is not stored and clears the buffer
+    pub fn push_state(&mut self, state: Vec<u8>) {
+        if state.len() > self.budget {
+            self.clear();
+            return;
+        }
+
+        if let Some(previous) = self.newest.take() {
+            let delta = encode_delta(&state, &previous);
+            self.used = self.used - previous.len() + delta.len();
+            self.deltas.push_back(delta);
+        }
+
+        self.used += state.len();
+        self.newest = Some(state);
+
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Remove the newest snapshot and return it
+    pub fn pop_state(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.used -= newest.len();
+
+        if let Some(delta) = self.deltas.pop_back() {
+            let previous = decode_delta(&newest, &delta);
+            self.used = self.used - delta.len() + previous.len();
+            self.newest = Some(previous);
+        }
+
+        Some(newest)
+    }
+
+    /// Restore the machine to the newest snapshot and remove it from the buffer
+    ///
+    /// Returns `false` if there was nothing to rewind to
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> Result<bool, Chip8Error> {
+        match self.pop_state() {
+            Some(state) => {
+                chip8.load_state(&state)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Drop all the snapshots
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+
+    /// Number of stored snapshots
+    pub fn len(&self) -> usize {
+        self.newest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    /// Check if there are no snapshots
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Bytes currently taken by the snapshots
+    pub fn memory_usage(&self) -> usize {
+        self.used
+    }
+
+    /// Maximum number of bytes the snapshots may take
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+}
+
+/// Encode the difference turning `newer` into `older`
+fn encode_delta(newer: &[u8], older: &[u8]) -> Vec<u8> {
+    let xored: Vec<u8> = older
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| byte ^ newer.get(i).copied().unwrap_or(0))
+        .collect();
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, older.len());
+
+    let mut position = 0;
+    while position < xored.len() {
+        let skip = xored[position..]
+            .iter()
+            .take_while(|&&byte| byte == 0)
+            .count();
+        if position + skip == xored.len() {
+            break;
+        }
+        position += skip;
+
+        let length = xored[position..]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .count();
+        write_varint(&mut delta, skip);
+        write_varint(&mut delta, length);
+        delta.extend_from_slice(&xored[position..position + length]);
+        position += length;
+    }
+
+    delta.shrink_to_fit();
+    delta
+}
+
+/// Apply a delta produced by [`encode_delta`] to `newer`, recovering the older state
+fn decode_delta(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut cursor = delta;
+    let length = read_varint(&mut cursor);
+
+    let mut older: Vec<u8> = (0..length)
+        .map(|i| newer.get(i).copied().unwrap_or(0))
+        .collect();
+
+    let mut position = 0;
+    while !cursor.is_empty() {
+        position += read_varint(&mut cursor);
+        let run = read_varint(&mut cursor);
+        let (bytes, rest) = cursor.split_at(run);
+        for (target, byte) in older[position..position + run].iter_mut().zip(bytes) {
+            *target ^= byte;
+        }
+        position += run;
+        cursor = rest;
+    }
+
+    older
+}
+
+/// Append an unsigned LEB128 number
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Read an unsigned LEB128 number, advancing the slice past it
+fn read_varint(input: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = input[0];
+        *input = &input[1..];
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_push_pop_order() {
+        let mut buffer = RewindBuffer::new(1 << 10);
+        buffer.push_state(vec![1, 2, 3]);
+        buffer.push_state(vec![1, 5, 3]);
+        buffer.push_state(vec![7, 5, 3, 9]);
+
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.pop_state(), Some(vec![7, 5, 3, 9]));
+        assert_eq!(buffer.pop_state(), Some(vec![1, 5, 3]));
+        assert_eq!(buffer.pop_state(), Some(vec![1, 2, 3]));
+        assert_eq!(buffer.pop_state(), None);
+        assert_eq!(buffer.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_budget_drops_oldest() {
+        let mut buffer = RewindBuffer::new(40);
+        for i in 0..10_u8 {
+            buffer.push_state(vec![i; 16]);
+            assert!(buffer.memory_usage() <= buffer.budget());
+        }
+
+        assert!(buffer.len() < 10);
+        let mut last = None;
+        while let Some(state) = buffer.pop_state() {
+            last = Some(state);
+        }
+        assert_ne!(last, Some(vec![0; 16]));
+    }
+
+    #[test]
+    fn test_oversized_state_clears() {
+        let mut buffer = RewindBuffer::new(4);
+        buffer.push_state(vec![1; 4]);
+        buffer.push_state(vec![1; 5]);
+
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_small_change_is_small() {
+        let older = vec![0xAA; 4096];
+        let mut newer = older.clone();
+        newer[100] = 0;
+        newer[3000] = 1;
+
+        let delta = encode_delta(&newer, &older);
+        assert!(delta.len() < 16);
+        assert_eq!(decode_delta(&newer, &delta), older);
+    }
+
+    #[test]
+    fn test_rewind_machine() {
+        let mut chip8 = Chip8::new();
+        // V0 += 1 in a loop
+        chip8.load_program(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        let mut buffer = RewindBuffer::new(1 << 20);
+        buffer.push(&chip8);
+        let initial = chip8.save_state();
+        for _ in 0..10 {
+            chip8.step().unwrap();
+            buffer.push(&chip8);
+        }
+
+        for _ in 0..10 {
+            assert!(buffer.rewind(&mut chip8).unwrap());
+        }
+        assert!(buffer.rewind(&mut chip8).unwrap());
+        assert_eq!(chip8.save_state(), initial);
+        assert!(!buffer.rewind(&mut chip8).unwrap());
+    }
+
+    proptest! {
+        #[test]
+        fn test_delta_roundtrip(
+            newer in proptest::collection::vec(any::<u8>(), 0..64),
+            older in proptest::collection::vec(any::<u8>(), 0..64),
+        ) {
+            let delta = encode_delta(&newer, &older);
+            prop_assert_eq!(decode_delta(&newer, &delta), older);
+        }
+
+        #[test]
+        fn test_varint_roundtrip(value in any::<usize>()) {
+            let mut out = Vec::new();
+            write_varint(&mut out, value);
+            let mut input = &out[..];
+            prop_assert_eq!(read_varint(&mut input), value);
+            prop_assert!(input.is_empty());
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Random number generator for `CXNN`
+//!
+//! Unlike the generators from `rand`, its whole state is a single public `u64`,
+//! so it can be stored in save states and restored bit for bit
+
+use rand::{RngCore, SeedableRng, rand_core::impls};
+
+/// SplitMix64 generator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitMix64 {
+    /// Generator state, advanced on every draw
+    state: u64,
+}
+
+impl SplitMix64 {
+    /// Restore a generator from a state previously returned by [`SplitMix64::state`]
+    pub fn from_state(state: u64) -> Self {
+        Self { state }
+    }
+
+    /// Current generator state
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+}
+
+impl RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+impl SeedableRng for SplitMix64 {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::from_state(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(state: u64) -> Self {
+        Self::from_state(state)
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_sequence() {
+        // reference values of SplitMix64 seeded with 0
+        let mut rng = SplitMix64::seed_from_u64(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut rng = SplitMix64::seed_from_u64(42);
+        rng.next_u64();
+
+        let mut restored = SplitMix64::from_state(rng.state());
+        assert_eq!(rng.next_u64(), restored.next_u64());
+    }
+}
@@ -0,0 +1,327 @@
+//! Chip8 save states
+//!
+//! A save state is a little-endian binary blob with a fixed header followed by the payload:
+//!
+//! | Offset | Size | Content                                               |
+//! |--------|------|-------------------------------------------------------|
+//! | 0      | 4    | Magic bytes `C8SS`                                    |
+//! | 4      | 2    | Format version, currently [`STATE_VERSION`]           |
+//! | 6      | 4    | Payload length in bytes                               |
+//! | 10     | 4    | CRC-32 (IEEE) of the payload                          |
+//! | 14     | ...  | Payload                                               |
+//!
+//! The payload consists of the following sections, in order:
+//!
+//! 1. Machine configuration
+//!    - platform, 1 byte: 0 = CHIP-8, 1 = SUPER-CHIP, 2 = XO-CHIP
+//!    - quirks, 5 bytes: shift uses VY, memory increment (0 = none, 1 = X, 2 = X + 1),
+//!      jump uses VX, logic resets VF, wrap sprites
+//!    - exited flag, 1 byte
+//!    - layout: memory size, 4 bytes, font address, load address, 2 bytes each, stack depth,
+//!      1 byte
+//! 2. Cpu
+//!    - V0..=VF, 16 bytes
+//!    - I, PC, 2 bytes each
+//!    - stack pointer, 1 byte, followed by the return addresses on the stack, 2 bytes each
+//!    - delay and sound timers, 1 byte each
+//!    - RPL user flags, 16 bytes
+//!    - random generator state, 8 bytes
+//! 3. Memory: length (4 bytes), then the bytes themselves. The length has to match the layout
+//! 4. Display
+//!    - high resolution flag, 1 byte
+//!    - selected planes bitmask, 1 byte
+//!    - for every plane 64 rows of 128 pixels, each row packed into 16 bytes, leftmost pixel
+//!      in the most significant bit
+//! 5. Keypad: 2 bytes, bit N set if key N is pressed
+//! 6. Audio
+//!    - pattern present flag, 1 byte, followed by the 16 pattern bytes if set
+//!    - pitch, 1 byte
+//!
+//! Booleans are stored as 0 or 1, any other value is rejected.
+//! Bumping the version is required for any change to the layout above.
+
+use thiserror::Error;
+
+/// Magic bytes every save state starts with
+pub const STATE_MAGIC: [u8; 4] = *b"C8SS";
+
+/// Version of the save state format written by this build
+pub const STATE_VERSION: u16 = 2;
+
+/// Size of the header preceding the payload
+const HEADER_SIZE: usize = 14;
+
+/// Enum for all possible save state errors
+#[derive(Debug, Error, PartialEq, Eq)]
+#[must_use]
+pub enum StateError {
+    #[error("Not a save state")]
+    /// The data doesn't start with the magic bytes
+    BadMagic,
+    #[error("Save state version {0} is not supported, expected {STATE_VERSION}")]
+    /// The data was written by an incompatible version of the emulator
+    UnsupportedVersion(u16),
+    #[error("Save state is truncated")]
+    /// The data ends before all the fields were read
+    Truncated,
+    #[error("Save state has {0} trailing bytes")]
+    /// The data is longer than the fields it should contain
+    TrailingBytes(usize),
+    #[error("Save state checksum mismatch")]
+    /// The payload doesn't match its checksum, the file is corrupt
+    ChecksumMismatch,
+    #[error("Invalid value in save state: {0}")]
+    /// A field holds a value that is impossible for the machine
+    InvalidValue(&'static str),
+}
+
+/// Builder of the save state payload
+#[derive(Debug, Default)]
+pub(crate) struct StateWriter {
+    /// Payload written so far
+    payload: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Create an empty writer
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a byte
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.payload.push(value);
+    }
+
+    /// Append a boolean as 0 or 1
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    /// Append a little-endian u16
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Append a little-endian u32
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Append a little-endian u64
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Append raw bytes
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.payload.extend_from_slice(bytes);
+    }
+
+    /// Prepend the header and return the finished save state
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.finish_as(STATE_MAGIC, STATE_VERSION)
+    }
+
+    /// Prepend a header with other magic bytes and version, for files sharing the layout
+    pub(crate) fn finish_as(self, magic: [u8; 4], version: u16) -> Vec<u8> {
+        let mut state = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        state.extend_from_slice(&magic);
+        state.extend_from_slice(&version.to_le_bytes());
+        state.extend_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        state.extend_from_slice(&crc32(&self.payload).to_le_bytes());
+        state.extend_from_slice(&self.payload);
+        state
+    }
+}
+
+/// Cursor over a validated save state payload
+#[derive(Debug)]
+pub(crate) struct StateReader<'a> {
+    /// Part of the payload that is not read yet
+    rest: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Check the header and the checksum, returning a reader over the payload
+    pub(crate) fn new(state: &'a [u8]) -> Result<Self, StateError> {
+        Self::with_header(state, STATE_MAGIC, STATE_VERSION)
+    }
+
+    /// Check a header written by [`StateWriter::finish_as`] and the checksum
+    pub(crate) fn with_header(
+        state: &'a [u8],
+        magic: [u8; 4],
+        expected_version: u16,
+    ) -> Result<Self, StateError> {
+        if state.len() < 4 || state[..4] != magic {
+            return Err(StateError::BadMagic);
+        }
+        if state.len() < HEADER_SIZE {
+            return Err(StateError::Truncated);
+        }
+
+        let version = u16::from_le_bytes([state[4], state[5]]);
+        if version != expected_version {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let length = u32::from_le_bytes(state[6..10].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(state[10..14].try_into().unwrap());
+        let payload = &state[HEADER_SIZE..];
+
+        if payload.len() < length {
+            return Err(StateError::Truncated);
+        }
+        if payload.len() > length {
+            return Err(StateError::TrailingBytes(payload.len() - length));
+        }
+        if crc32(payload) != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        Ok(Self { rest: payload })
+    }
+
+    /// Read a byte
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Read a boolean stored as 0 or 1
+    pub(crate) fn bool(&mut self, field: &'static str) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidValue(field)),
+        }
+    }
+
+    /// Read a little-endian u16
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    /// Read a little-endian u32
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    /// Read a little-endian u64
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// Read a fixed size array of bytes
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    /// Read a slice of raw bytes
+    pub(crate) fn bytes(&mut self, count: usize) -> Result<&'a [u8], StateError> {
+        if self.rest.len() < count {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.rest.split_at(count);
+        self.rest = rest;
+        Ok(bytes)
+    }
+
+    /// Make sure the whole payload was consumed
+    pub(crate) fn finish(self) -> Result<(), StateError> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::TrailingBytes(self.rest.len()))
+        }
+    }
+}
+
+/// CRC-32 with the IEEE polynomial, as used by zip and png
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut writer = StateWriter::new();
+        writer.u8(0xAB);
+        writer.bool(true);
+        writer.u16(0x1234);
+        writer.u32(0xDEAD_BEEF);
+        writer.u64(u64::MAX);
+        writer.bytes(&[1, 2, 3]);
+        let state = writer.finish();
+
+        let mut reader = StateReader::new(&state).unwrap();
+        assert_eq!(reader.u8().unwrap(), 0xAB);
+        assert!(reader.bool("flag").unwrap());
+        assert_eq!(reader.u16().unwrap(), 0x1234);
+        assert_eq!(reader.u32().unwrap(), 0xDEAD_BEEF);
+        assert_eq!(reader.u64().unwrap(), u64::MAX);
+        assert_eq!(reader.array::<3>().unwrap(), [1, 2, 3]);
+        assert!(matches!(reader.u8(), Err(StateError::Truncated)));
+    }
+
+    #[test]
+    fn test_corrupt_header() {
+        let mut writer = StateWriter::new();
+        writer.u8(1);
+        let state = writer.finish();
+
+        assert_eq!(StateReader::new(b"NOPE").unwrap_err(), StateError::BadMagic);
+        assert_eq!(
+            StateReader::new(&state[..10]).unwrap_err(),
+            StateError::Truncated
+        );
+
+        let mut wrong_version = state.clone();
+        wrong_version[4] = 0xFF;
+        assert_eq!(
+            StateReader::new(&wrong_version).unwrap_err(),
+            StateError::UnsupportedVersion(0xFF)
+        );
+
+        let mut flipped = state.clone();
+        *flipped.last_mut().unwrap() ^= 0xFF;
+        assert_eq!(
+            StateReader::new(&flipped).unwrap_err(),
+            StateError::ChecksumMismatch
+        );
+
+        let mut longer = state.clone();
+        longer.push(0);
+        assert_eq!(
+            StateReader::new(&longer).unwrap_err(),
+            StateError::TrailingBytes(1)
+        );
+    }
+
+    #[test]
+    fn test_invalid_bool() {
+        let mut writer = StateWriter::new();
+        writer.u8(2);
+        let state = writer.finish();
+
+        let mut reader = StateReader::new(&state).unwrap();
+        assert_eq!(reader.bool("flag"), Err(StateError::InvalidValue("flag")));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::machine::{
+    builder::{BuildError, Chip8Builder, Fill, Layout},
+    cpu::CpuError,
+    memory::{DIGIT_SPRITES, FONTS_SIZE, Font, MemoryError},
+    platform::Platform,
+};
+
+use super::*;
+
+/// Random numbers into V0..=V3
+const RANDOM: [u8; 8] = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF];
+
+/// Registers after running the first `steps` instructions of `program`
+fn run(mut chip8: Chip8, program: &[u8], steps: usize) -> [u8; 16] {
+    chip8.load_program(program).unwrap();
+    for _ in 0..steps {
+        chip8.step().unwrap();
+    }
+    *chip8.cpu.registers()
+}
+
+#[test]
+fn test_default_is_the_usual_machine() {
+    let chip8 = Chip8Builder::new().seed(1).build().unwrap();
+    let mut usual = Chip8::new();
+    usual.seed_random(1);
+
+    assert_eq!(chip8.save_state(), usual.save_state());
+    assert_eq!(chip8.layout(), Layout::with_memory_size(1 << 12));
+}
+
+#[test]
+fn test_seed_is_reproducible() {
+    let build = |seed| Chip8Builder::new().seed(seed).build().unwrap();
+
+    assert_eq!(run(build(7), &RANDOM, 4), run(build(7), &RANDOM, 4));
+    assert_ne!(run(build(7), &RANDOM, 4), run(build(8), &RANDOM, 4));
+}
+
+#[test]
+fn test_platform_and_memory_size() {
+    let chip8 = Chip8Builder::new()
+        .platform(Platform::XoChip)
+        .build()
+        .unwrap();
+    assert_eq!(chip8.memory.size(), 1 << 16);
+
+    let mut chip8 = Chip8Builder::new().memory_size(0x2000).build().unwrap();
+    assert_eq!(chip8.memory.size(), 0x2000);
+    chip8.write_memory(0x1FFF, &[0xAB]).unwrap();
+    assert!(chip8.write_memory(0x2000, &[0xAB]).is_err());
+}
+
+#[test]
+fn test_load_address() {
+    let mut chip8 = Chip8Builder::new().load_address(0x600).build().unwrap();
+    assert_eq!(chip8.cpu.program_counter(), 0x600);
+
+    chip8.load_program(&[0x60, 0x05]).unwrap();
+    chip8.step().unwrap();
+    assert_eq!(*chip8.cpu.vx(V0), 5);
+    assert_eq!(chip8.cpu.program_counter(), 0x602);
+    assert!(matches!(
+        chip8.write_memory(0x5FF, &[0]),
+        Err(Chip8Error::MemoryError(MemoryError::PermissionDenied))
+    ));
+}
+
+#[test]
+fn test_font_and_font_address() {
+    let mut font: Font = DIGIT_SPRITES;
+    font[7] = [0xFF; 5];
+    // V0 = 7, I = sprite of V0
+    let mut chip8 = Chip8Builder::new()
+        .font(font)
+        .font_address(0x100)
+        .build()
+        .unwrap();
+    chip8.load_program(&[0x60, 0x07, 0xF0, 0x29]).unwrap();
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+
+    assert_eq!(chip8.cpu.address(), 0x100 + 7 * 5);
+    assert_eq!(chip8.memory.read_byte(0x100 + 7 * 5).unwrap(), 0xFF);
+    assert_eq!(chip8.memory.read_byte(0x000).unwrap(), 0x00);
+}
+
+#[test]
+fn test_stack_depth() {
+    // calls itself forever
+    let mut chip8 = Chip8Builder::new().stack_depth(2).build().unwrap();
+    chip8.load_program(&[0x22, 0x00]).unwrap();
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+
+    let Err(Chip8Error::Fault(fault)) = chip8.step() else {
+        panic!("no fault");
+    };
+    assert!(matches!(
+        fault.error,
+        Chip8Error::CpuError(CpuError::StackLimitReached)
+    ));
+}
+
+#[test]
+fn test_fills() {
+    let chip8 = Chip8Builder::new()
+        .register_fill(Fill::Value(0xAA))
+        .memory_fill(Fill::Value(0x55))
+        .build()
+        .unwrap();
+    assert_eq!(*chip8.cpu.registers(), [0xAA; 16]);
+    assert_eq!(chip8.memory.read_byte(0x000).unwrap(), DIGIT_SPRITES[0][0]);
+    assert_eq!(chip8.memory.read_byte(FONTS_SIZE as u16).unwrap(), 0x55);
+    assert_eq!(chip8.memory.read_byte(0xFFF).unwrap(), 0x55);
+
+    // random fills follow the seed, without changing the random numbers of the program
+    let build = |fill| {
+        Chip8Builder::new()
+            .seed(3)
+            .register_fill(fill)
+            .memory_fill(fill)
+            .build()
+            .unwrap()
+    };
+    assert_eq!(
+        build(Fill::Random).memory.bytes(),
+        build(Fill::Random).memory.bytes()
+    );
+    assert_ne!(*build(Fill::Random).cpu.registers(), [0; 16]);
+    assert_eq!(
+        run(build(Fill::Random), &RANDOM, 4)[..4],
+        run(build(Fill::default()), &RANDOM, 4)[..4]
+    );
+}
+
+#[test]
+fn test_layout_survives_save_states() {
+    let mut chip8 = Chip8Builder::new()
+        .memory_size(0x1000)
+        .font_address(0x300)
+        .load_address(0x400)
+        .stack_depth(4)
+        .build()
+        .unwrap();
+    chip8.load_program(&[0x60, 0x01]).unwrap();
+    let state = chip8.save_state();
+
+    let mut restored = Chip8::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.layout(), chip8.layout());
+    assert_eq!(restored.cpu.program_counter(), 0x400);
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn test_invalid_settings() {
+    let error = |builder: Chip8Builder| builder.build().err().unwrap();
+
+    assert_eq!(
+        error(Chip8Builder::new().memory_size(0x10001)),
+        BuildError::MemorySize(0x10001)
+    );
+    assert_eq!(
+        error(Chip8Builder::new().load_address(0x1000)),
+        BuildError::LoadAddress(0x1000)
+    );
+    assert_eq!(
+        error(Chip8Builder::new().font_address(0x1A0)),
+        BuildError::FontAddress(0x1A0)
+    );
+    assert_eq!(
+        error(Chip8Builder::new().stack_depth(0)),
+        BuildError::StackDepth(0)
+    );
+}
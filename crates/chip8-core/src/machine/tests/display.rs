@@ -73,7 +73,7 @@ fn test_clear_display(ctx: &mut Context) {
 
     let display_state = ctx.chip8.display_snapshot().unwrap();
 
-    assert_eq!(display_state, &[[false; 64]; 32]);
+    assert_eq!(display_state, &[[false; 128]; 64]);
 }
 
 #[test_context(Context)]
@@ -0,0 +1,257 @@
+use crate::machine::{
+    builder::Chip8Builder,
+    cpu::CpuError,
+    fault::{FAULT_HISTORY, FaultAction, FaultClass, FaultPolicy},
+};
+
+use super::*;
+
+const V1: Index = unsafe { Index::new_unchecked(0x1) };
+
+/// Machine running `program` with the policy
+fn machine(program: &[u8], policy: FaultPolicy) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.set_fault_policy(policy);
+    chip8.load_program(program).unwrap();
+    chip8
+}
+
+/// Fault the error is, panics if it isn't one
+fn fault(error: Chip8Error) -> Arc<Fault> {
+    match error {
+        Chip8Error::Fault(fault) => fault,
+        error => panic!("not a fault: {error}"),
+    }
+}
+
+#[test]
+fn test_break_stays_at_the_instruction() {
+    // RET with an empty stack
+    let mut chip8 = machine(&[0x00, 0xEE], FaultPolicy::default());
+
+    for _ in 0..2 {
+        let fault = fault(chip8.step().unwrap_err());
+        assert_eq!(fault.class, FaultClass::Stack);
+        assert_eq!(fault.action, FaultAction::Break);
+        assert_eq!(fault.pc, 0x200);
+        assert!(matches!(
+            fault.error,
+            Chip8Error::CpuError(CpuError::StackEmpty)
+        ));
+    }
+    assert_eq!(chip8.cpu.program_counter(), 0x200);
+    assert!(chip8.halted().is_none());
+}
+
+#[test]
+fn test_halt() {
+    let mut chip8 = machine(&[0x60, 0x01, 0xFF, 0xFF], FaultPolicy::HALT);
+    chip8.step().unwrap();
+    let state = chip8.save_state();
+
+    let fault = fault(chip8.step().unwrap_err());
+    assert_eq!(fault.class, FaultClass::InvalidOpcode);
+    assert_eq!(
+        fault.to_string(),
+        "invalid opcode at 0x202: Command 0xFFFF is incorrect (policy: halt)"
+    );
+    assert_eq!(chip8.halted().unwrap().pc, 0x202);
+
+    // a halted machine doesn't run any more, but a save state brings it back
+    chip8.step().unwrap();
+    assert_eq!(chip8.cpu.program_counter(), 0x202);
+    chip8.load_state(&state).unwrap();
+    assert!(chip8.halted().is_none());
+    assert!(chip8.step().is_err());
+}
+
+#[test]
+fn test_nop_skips_the_instruction() {
+    let policy = FaultPolicy {
+        invalid_opcode: FaultAction::Nop,
+        ..FaultPolicy::HALT
+    };
+    let mut chip8 = machine(&[0xFF, 0xFF, 0x60, 0x05], policy);
+
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+    assert_eq!(*chip8.cpu.vx(V0), 5);
+    assert!(chip8.halted().is_none());
+}
+
+#[test]
+fn test_nop_halts_at_the_end_of_memory() {
+    let mut chip8 = machine(&[0x1F, 0xFE], FaultPolicy::all(FaultAction::Nop));
+    chip8.step().unwrap();
+    chip8.memory.load(0xFFE, &[0xFF, 0xFF]).unwrap();
+
+    let fault = fault(chip8.step().unwrap_err());
+    assert_eq!(fault.action, FaultAction::Nop);
+    assert!(chip8.halted().is_some());
+}
+
+#[test]
+fn test_wrap_addresses() {
+    let policy = FaultPolicy {
+        out_of_range: FaultAction::Wrap,
+        ..FaultPolicy::HALT
+    };
+    // I = FFF, load V0 and V1 from FFF and 000, then I += 2
+    let mut chip8 = machine(&[0xAF, 0xFF, 0xF1, 0x65, 0x60, 0x02, 0xF0, 0x1E], policy);
+    chip8.memory.load(0xFFF, &[0x42]).unwrap();
+
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(*chip8.cpu.vx(V0), 0x02);
+    assert_eq!(*chip8.cpu.vx(V1), 0xF0);
+    assert_eq!(chip8.cpu.address(), 0x001);
+
+    // the font can't be overwritten through the wrap
+    chip8.cpu.set_address(0xFFF).unwrap();
+    chip8.memory.load(0x208, &[0xF0, 0x33]).unwrap();
+    let fault = fault(chip8.step().unwrap_err());
+    assert_eq!(fault.class, FaultClass::OutOfRange);
+    assert!(chip8.halted().is_some());
+}
+
+#[test]
+fn test_wrap_is_kept_across_save_states() {
+    let policy = FaultPolicy::all(FaultAction::Wrap);
+    let mut chip8 = machine(&[0x1F, 0xFE], policy);
+    chip8.step().unwrap();
+    chip8.memory.load(0xFFE, &[0x60, 0x07]).unwrap();
+    let state = chip8.save_state();
+    chip8.load_state(&state).unwrap();
+
+    chip8.step().unwrap();
+    assert_eq!(chip8.cpu.program_counter(), 0x000);
+}
+
+#[test]
+fn test_fault_context() {
+    // V0 = 1, CALL 0x206, then V1 = 2 and an invalid opcode in the subroutine
+    let mut chip8 = machine(
+        &[0x60, 0x01, 0x22, 0x06, 0x00, 0x00, 0x61, 0x02, 0xFF, 0xFF],
+        FaultPolicy::HALT,
+    );
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+
+    let fault = fault(chip8.step().unwrap_err());
+    assert_eq!(fault.opcode, 0xFFFF);
+    assert_eq!(fault.instruction, None);
+    assert_eq!(fault.cycle, 3);
+    assert_eq!(fault.registers[..2], [1, 2]);
+    assert_eq!(fault.stack, [0x204]);
+    let history: Vec<u16> = fault.history.iter().map(|record| record.pc).collect();
+    assert_eq!(history, [0x200, 0x202, 0x206]);
+    assert_eq!(
+        fault.report().lines().take(5).collect::<Vec<_>>(),
+        [
+            "invalid opcode at 0x208: Command 0xFFFF is incorrect (policy: halt)",
+            "Opcode:    FFFF ???",
+            "Cycle:     3",
+            "Registers: V0=01 V1=02 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00 V8=00 V9=00 VA=00 \
+             VB=00 VC=00 VD=00 VE=00 VF=00 I=0000",
+            "Stack:     0x204",
+        ]
+    );
+    assert_eq!(fault.report().lines().count(), 9);
+}
+
+#[test]
+fn test_history_is_bounded() {
+    // jump to itself
+    let mut chip8 = machine(&[0x12, 0x00], FaultPolicy::HALT);
+    for _ in 0..100 {
+        chip8.step().unwrap();
+    }
+    chip8.memory.load(0x200, &[0x00, 0xEE]).unwrap();
+
+    let fault = fault(chip8.step().unwrap_err());
+    assert_eq!(fault.cycle, 100);
+    assert_eq!(fault.history.len(), FAULT_HISTORY);
+    assert_eq!(fault.history[0].cycle, 100 - FAULT_HISTORY as u64);
+    assert_eq!(fault.instruction, Some(Instruction::Return));
+}
+
+#[test]
+fn test_crash_dump_roundtrip() {
+    let mut chip8 = machine(
+        &[0x60, 0x01, 0x22, 0x06, 0x00, 0x00, 0x00, 0xEE],
+        FaultPolicy::HALT,
+    );
+    assert!(chip8.crash_dump().is_none());
+    for _ in 0..3 {
+        chip8.step().unwrap();
+    }
+    chip8.step().unwrap_err();
+    let dump = chip8.crash_dump().unwrap();
+
+    let mut restored = Chip8::new();
+    restored.load_crash_dump(&dump).unwrap();
+    let fault = restored.halted().unwrap();
+    let original = chip8.halted().unwrap();
+    assert_eq!(fault.to_string(), original.to_string());
+    assert_eq!(fault.report(), original.report());
+    assert_eq!(fault.instruction, original.instruction);
+    assert_eq!(restored.save_state(), chip8.save_state());
+
+    // still halted, and another dump of it is the same
+    restored.step().unwrap();
+    assert_eq!(restored.crash_dump().unwrap(), dump);
+}
+
+#[test]
+fn test_deep_stack_crash_dump() {
+    let mut chip8 = Chip8Builder::new()
+        .stack_depth(20)
+        .fault_policy(FaultPolicy::HALT)
+        .build()
+        .unwrap();
+    chip8.load_program(&[0x22, 0x00]).unwrap();
+    for _ in 0..20 {
+        chip8.step().unwrap();
+    }
+    let fault = fault(chip8.step().unwrap_err());
+    assert_eq!(fault.class, FaultClass::Stack);
+    assert_eq!(fault.stack.len(), 20);
+    let dump = chip8.crash_dump().unwrap();
+
+    let mut restored = Chip8::new();
+    restored.load_crash_dump(&dump).unwrap();
+    assert_eq!(restored.halted().unwrap().stack, fault.stack);
+    assert_eq!(restored.stack().depth(), 20);
+    assert_eq!(restored.save_state(), chip8.save_state());
+}
+
+#[test]
+fn test_corrupt_crash_dump() {
+    let mut chip8 = machine(&[0x00, 0xEE], FaultPolicy::HALT);
+    chip8.step().unwrap_err();
+    let dump = chip8.crash_dump().unwrap();
+
+    let mut restored = Chip8::new();
+    assert!(matches!(
+        restored.load_crash_dump(&chip8.save_state()),
+        Err(Chip8Error::StateError(StateError::BadMagic))
+    ));
+    let mut flipped = dump.clone();
+    *flipped.last_mut().unwrap() ^= 0xFF;
+    assert!(restored.load_crash_dump(&flipped).is_err());
+    assert!(restored.halted().is_none());
+}
+
+#[test]
+fn test_errors_show_their_cause() {
+    assert_eq!(
+        Chip8Error::from(CpuError::StackEmpty).to_string(),
+        "Stack is empty"
+    );
+    assert_eq!(
+        Chip8Error::from(std::io::Error::other("disk full")).to_string(),
+        "Trace error: disk full"
+    );
+}
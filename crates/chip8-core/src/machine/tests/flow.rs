@@ -4,7 +4,7 @@ use super::*;
 fn test_goto_correct() {
     let mut chip8 = Chip8::new();
     let instr = Instruction::Goto {
-        address: Address::try_new(0x304).unwrap(),
+        address: Address::new(0x304),
     };
 
     assert!(matches!(chip8.execute(instr), Ok(ExecResult::Jumped)));
@@ -15,7 +15,7 @@ fn test_goto_correct() {
 fn test_call_function_correct() {
     let mut chip8 = Chip8::new();
     let instr = Instruction::CallSubroutine {
-        address: Address::try_new(0x47A).unwrap(),
+        address: Address::new(0x47A),
     };
 
     assert!(matches!(chip8.execute(instr), Ok(ExecResult::Jumped)));
@@ -27,7 +27,7 @@ fn test_call_function_correct() {
 fn test_call_and_return_from_function() {
     let mut chip8 = Chip8::new();
     let instr = Instruction::CallSubroutine {
-        address: Address::try_new(0x47A).unwrap(),
+        address: Address::new(0x47A),
     };
 
     assert!(matches!(chip8.execute(instr), Ok(ExecResult::Jumped)));
@@ -39,6 +39,20 @@ fn test_call_and_return_from_function() {
     assert_eq!(chip8.cpu.program_counter(), 0x202);
 }
 
+#[test]
+fn test_call_and_return_stack() {
+    let mut chip8 = Chip8::new();
+    let instr = Instruction::CallSubroutine {
+        address: Address::new(0x47A),
+    };
+
+    chip8.execute(instr).unwrap();
+    assert_eq!(chip8.cpu.stack(), &[0x202]);
+
+    chip8.execute(Instruction::Return).unwrap();
+    assert!(chip8.cpu.stack().is_empty());
+}
+
 #[test]
 fn test_return_on_empty_stack() {
     let mut chip8 = Chip8::new();
@@ -54,7 +68,7 @@ fn test_return_on_empty_stack() {
 fn test_goto_plus_v0() {
     let mut chip8 = Chip8::new();
     let instr = Instruction::GotoPlusV0 {
-        address: Address::try_new(0xAB0).unwrap(),
+        address: Address::new(0xAB0),
     };
 
     *chip8.cpu.vx(Index::try_new(0).unwrap()) = 0x1D;
@@ -0,0 +1,100 @@
+use crate::machine::{builder::Chip8Builder, inspect::Registers};
+
+use super::*;
+
+/// V0 = 0x12, I = 0x300, DT = V0, CALL 0x20C, then draw the font sprite for 0 at (0, 0)
+const PROGRAM: [u8; 16] = [
+    0x60, 0x12, 0xA3, 0x00, 0xF0, 0x15, 0x22, 0x0C, 0x00, 0x00, 0x00, 0x00, 0xA0, 0x00, 0xD1, 0x15,
+];
+
+fn machine() -> Chip8 {
+    let mut chip8 = Chip8Builder::new().stack_depth(12).build().unwrap();
+    chip8.load_program(&PROGRAM).unwrap();
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    chip8
+}
+
+#[test]
+fn test_registers() {
+    let chip8 = machine();
+    let mut v = [0; 16];
+    v[0] = 0x12;
+
+    assert_eq!(
+        chip8.registers(),
+        Registers {
+            v,
+            i: 0x300,
+            pc: 0x20C,
+            dt: 0x12,
+            st: 0,
+            rpl_flags: [0; 16],
+        }
+    );
+    assert_eq!(chip8.cpu().register(V0), 0x12);
+}
+
+#[test]
+fn test_stack() {
+    let chip8 = machine();
+    let stack = chip8.stack();
+
+    assert_eq!(stack.addresses(), [0x208]);
+    assert_eq!(stack.top(), Some(0x208));
+    assert_eq!((stack.len(), stack.depth()), (1, 12));
+    assert!(!stack.is_empty() && !stack.is_full());
+}
+
+#[test]
+fn test_memory_slice() {
+    let chip8 = machine();
+
+    assert_eq!(chip8.memory_slice(0x200..0x202), Some(&PROGRAM[..2]));
+    assert_eq!(chip8.memory_slice(0x20E..=0x20F), Some(&PROGRAM[14..]));
+    assert_eq!(chip8.memory_slice(0xFFE..).map(<[u8]>::len), Some(2));
+    assert_eq!(chip8.memory_slice(..).map(<[u8]>::len), Some(1 << 12));
+    assert_eq!(chip8.memory_slice(0xFFF..0x1001), None);
+}
+
+#[test]
+fn test_current_instruction() {
+    let mut chip8 = machine();
+    assert_eq!(
+        chip8.current_instruction().unwrap(),
+        Instruction::try_from(0xA000).unwrap()
+    );
+
+    // looking doesn't run anything
+    assert_eq!(chip8.registers().pc, 0x20C);
+    chip8.step().unwrap();
+    assert_eq!(chip8.registers().i, 0x000);
+}
+
+#[test]
+fn test_keys() {
+    let mut chip8 = machine();
+    chip8.set_key_state(0x3, true).unwrap();
+
+    let keys = chip8.keys();
+    assert!(keys[0x3]);
+    assert_eq!(keys.iter().filter(|&&pressed| pressed).count(), 1);
+    assert!(chip8.keypad().is_pressed(0x3).unwrap());
+}
+
+#[test]
+fn test_display_keeps_the_redraw_flag() {
+    let mut chip8 = machine();
+    chip8.step().unwrap();
+    chip8.step().unwrap();
+
+    assert!(chip8.needs_redraw());
+    assert!(chip8.framebuffer()[0][..4].iter().all(|&pixel| pixel));
+    assert_eq!(chip8.display().resolution(), (64, 32));
+    assert!(chip8.needs_redraw());
+
+    assert!(chip8.planes_snapshot().is_some());
+    assert!(!chip8.needs_redraw());
+    assert!(chip8.framebuffer()[0][0]);
+}
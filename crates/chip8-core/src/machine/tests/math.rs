@@ -0,0 +1,121 @@
+use super::*;
+
+struct Context {
+    chip8: Chip8,
+    index_x: Index,
+    index_y: Index,
+}
+const VX: u8 = 0x74;
+const VY: u8 = 0x3F;
+
+impl TestContext for Context {
+    fn setup() -> Self {
+        let index_x = Index::try_new(0x4).unwrap();
+        let index_y = Index::try_new(0xD).unwrap();
+        let mut ctx = Self {
+            chip8: Chip8::new(),
+            index_x,
+            index_y,
+        };
+
+        *ctx.chip8.cpu.vx(index_x) = VX;
+        *ctx.chip8.cpu.vx(index_y) = VY;
+
+        ctx
+    }
+}
+
+#[test_context(Context)]
+#[test]
+fn test_add_assign_reg(ctx: &mut Context) {
+    let instr = Instruction::AddAssignReg {
+        x: ctx.index_x,
+        y: ctx.index_y,
+    };
+
+    assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*ctx.chip8.cpu.vx(ctx.index_x), VX + VY);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_sub_assign_reg(ctx: &mut Context) {
+    let instr = Instruction::SubAssignReg {
+        x: ctx.index_x,
+        y: ctx.index_y,
+    };
+
+    assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*ctx.chip8.cpu.vx(ctx.index_x), VX - VY);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_sub_assign_reg_inverse(ctx: &mut Context) {
+    *ctx.chip8.cpu.vx(ctx.index_x) = VY;
+    *ctx.chip8.cpu.vx(ctx.index_y) = VX;
+
+    let instr = Instruction::SubAssignRegInverse {
+        x: ctx.index_x,
+        y: ctx.index_y,
+    };
+
+    assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*ctx.chip8.cpu.vx(ctx.index_x), VX - VY);
+}
+
+#[test]
+fn test_flag_written_after_result() {
+    let mut chip8 = Chip8::new();
+
+    // VF - VF borrows nothing, the flag replaces the 0 result
+    *chip8.cpu.vx(VF) = 0x10;
+    let instr = Instruction::SubAssignReg { x: VF, y: VF };
+    chip8.execute(instr).unwrap();
+    assert_eq!(*chip8.cpu.vx(VF), 1);
+
+    // VF + V0 carries, the flag replaces the wrapped sum
+    *chip8.cpu.vx(VF) = 0xF0;
+    *chip8.cpu.vx(V0) = 0x20;
+    let instr = Instruction::AddAssignReg { x: VF, y: V0 };
+    chip8.execute(instr).unwrap();
+    assert_eq!(*chip8.cpu.vx(VF), 1);
+
+    // V0 - VF borrows
+    *chip8.cpu.vx(V0) = 0;
+    let instr = Instruction::SubAssignRegInverse { x: VF, y: V0 };
+    chip8.execute(instr).unwrap();
+    assert_eq!(*chip8.cpu.vx(VF), 0);
+}
+
+proptest! {
+    #[test]
+    fn test_arithmetic_wraps(vx in any::<u8>(), vy in any::<u8>()) {
+        let x = Index::try_new(0x4).unwrap();
+        let y = Index::try_new(0xD).unwrap();
+        let run = |instr: Instruction| {
+            let mut chip8 = Chip8::new();
+            *chip8.cpu.vx(x) = vx;
+            *chip8.cpu.vx(y) = vy;
+            chip8.execute(instr).unwrap();
+            (*chip8.cpu.vx(x), *chip8.cpu.vx(VF))
+        };
+
+        prop_assert_eq!(
+            run(Instruction::AddAssignConst { x, value: vy }),
+            (vx.wrapping_add(vy), 0)
+        );
+        prop_assert_eq!(
+            run(Instruction::AddAssignReg { x, y }),
+            (vx.wrapping_add(vy), (vx as u16 + vy as u16 > 0xFF) as u8)
+        );
+        prop_assert_eq!(
+            run(Instruction::SubAssignReg { x, y }),
+            (vx.wrapping_sub(vy), (vx >= vy) as u8)
+        );
+        prop_assert_eq!(
+            run(Instruction::SubAssignRegInverse { x, y }),
+            (vy.wrapping_sub(vx), (vy >= vx) as u8)
+        );
+    }
+}
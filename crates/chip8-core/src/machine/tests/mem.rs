@@ -5,7 +5,7 @@ const V7: Index = unsafe { Index::new_unchecked(7) };
 #[test]
 fn test_set_address() {
     let mut chip8 = Chip8::new();
-    let addr = Address::try_new(0x3FB).unwrap();
+    let addr = Address::new(0x3FB);
 
     let instr = Instruction::SetI { address: addr };
 
@@ -73,3 +73,27 @@ fn test_load_registers() {
         assert_eq!(*chip8.cpu.vx(Index::try_new(i).unwrap()), i * 2);
     }
 }
+
+#[test]
+fn test_write_memory_and_instruction_at() {
+    let mut chip8 = Chip8::new();
+
+    chip8
+        .write_memory(0x300, &[0x22, 0x40, 0x50, 0x01])
+        .unwrap();
+    assert_eq!(&chip8.memory().bytes()[0x300..0x302], &[0x22, 0x40]);
+    assert_eq!(
+        chip8.instruction_at(0x300).unwrap(),
+        Instruction::CallSubroutine {
+            address: Address::new(0x240)
+        }
+    );
+    assert!(matches!(
+        chip8.instruction_at(0x302),
+        Err(Chip8Error::DecodeError(_))
+    ));
+    assert!(matches!(
+        chip8.write_memory(0x100, &[0]),
+        Err(Chip8Error::MemoryError(MemoryError::PermissionDenied))
+    ));
+}
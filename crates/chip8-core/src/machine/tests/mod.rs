@@ -1,18 +1,28 @@
-use rand::{Rng, SeedableRng, rngs::SmallRng};
+use rand::{Rng, SeedableRng};
 
-use crate::{machine::*, types::Address};
+use crate::{
+    machine::{rng::SplitMix64, *},
+    types::Address,
+};
 use proptest::prelude::*;
 
 use test_context::{TestContext, test_context};
 
 mod bitop;
+mod builder;
 mod cond;
 mod display;
+mod fault;
 mod flow;
+mod inspect;
 mod keypad;
 mod math;
 mod mem;
+mod quirks;
+mod state;
+mod superchip;
 mod timers;
+mod xochip;
 
 const V0: Index = unsafe { Index::new_unchecked(0x0) };
 const VF: Index = unsafe { Index::new_unchecked(0xF) };
@@ -33,7 +43,7 @@ impl TestContext for Context {
 #[test]
 fn test_unsupported_instruction_error(ctx: &mut Context) {
     let instr = Instruction::CallMachineCode {
-        address: Address::try_new(0xAB0).unwrap(),
+        address: Address::new(0xAB0),
     };
 
     assert!(matches!(
@@ -80,9 +90,9 @@ fn test_assign_reg(ctx: &mut Context) {
 #[test_context(Context)]
 #[test]
 fn test_random(ctx: &mut Context) {
-    ctx.chip8.cpu.random_engine = SmallRng::seed_from_u64(42);
+    ctx.chip8.cpu.random_engine = SplitMix64::seed_from_u64(42);
 
-    let mut rng = SmallRng::seed_from_u64(42);
+    let mut rng = SplitMix64::seed_from_u64(42);
 
     let instr = Instruction::Rand { x: V0, value: 0xEA };
     assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
@@ -110,4 +120,29 @@ proptest! {
         assert_eq!(chip8.memory.read_byte(0x401).unwrap(), tens);
         assert_eq!(chip8.memory.read_byte(0x402).unwrap(), ones);
     }
+
+    #[test]
+    fn test_random_programs_never_panic(
+        program in prop::collection::vec(any::<u8>(), 0..=0x400),
+        platform in prop_oneof![
+            Just(Platform::Chip8),
+            Just(Platform::SuperChip),
+            Just(Platform::XoChip),
+        ],
+        keys in any::<u16>(),
+    ) {
+        let mut chip8 = Chip8::with_platform(platform, Quirks::default());
+        chip8.load_program(&program).unwrap();
+        for key in 0..16 {
+            chip8.set_key_state(key, keys & (1 << key) != 0).unwrap();
+        }
+
+        // errors are fine, panics are not
+        for _ in 0..1000 {
+            if chip8.step().is_err() {
+                break;
+            }
+            chip8.tick_timers();
+        }
+    }
 }
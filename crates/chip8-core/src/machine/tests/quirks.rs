@@ -0,0 +1,127 @@
+use super::*;
+use crate::{
+    machine::quirks::{MemoryIncrement, Quirks},
+    types::SpriteHeight,
+};
+
+const V1: Index = unsafe { Index::new_unchecked(0x1) };
+const V2: Index = unsafe { Index::new_unchecked(0x2) };
+
+#[test]
+fn test_shift_uses_vy() {
+    let mut chip8 = Chip8::with_quirks(Quirks {
+        shift_uses_vy: true,
+        ..Quirks::default()
+    });
+    *chip8.cpu.vx(V1) = 0x00;
+    *chip8.cpu.vx(V2) = 0x81;
+
+    let instr = Instruction::RShift { x: V1, y: V2 };
+    assert!(matches!(chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*chip8.cpu.vx(V1), 0x40);
+    assert_eq!(*chip8.cpu.vx(VF), 1);
+
+    let instr = Instruction::LShift { x: V1, y: V2 };
+    assert!(matches!(chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*chip8.cpu.vx(V1), 0x02);
+    assert_eq!(*chip8.cpu.vx(VF), 1);
+}
+
+#[test]
+fn test_shift_ignores_vy() {
+    let mut chip8 = Chip8::with_quirks(Quirks::SUPER_CHIP);
+    *chip8.cpu.vx(V1) = 0x02;
+    *chip8.cpu.vx(V2) = 0x81;
+
+    let instr = Instruction::RShift { x: V1, y: V2 };
+    assert!(matches!(chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*chip8.cpu.vx(V1), 0x01);
+    assert_eq!(*chip8.cpu.vx(VF), 0);
+}
+
+#[test]
+fn test_memory_increment() {
+    let cases = [
+        (MemoryIncrement::None, 0x300),
+        (MemoryIncrement::ByX, 0x302),
+        (MemoryIncrement::ByXPlusOne, 0x303),
+    ];
+
+    for (memory_increment, expected) in cases {
+        let mut chip8 = Chip8::with_quirks(Quirks {
+            memory_increment,
+            ..Quirks::default()
+        });
+
+        chip8.cpu.set_address(0x300).unwrap();
+        chip8.execute(Instruction::DumpRegisters { x: V2 }).unwrap();
+        assert_eq!(chip8.cpu.address(), expected);
+
+        chip8.cpu.set_address(0x300).unwrap();
+        chip8.execute(Instruction::LoadRegisters { x: V2 }).unwrap();
+        assert_eq!(chip8.cpu.address(), expected);
+    }
+}
+
+#[test]
+fn test_jump_uses_vx() {
+    let mut chip8 = Chip8::with_quirks(Quirks::CHIP_48);
+    *chip8.cpu.vx(V0) = 0x10;
+    *chip8.cpu.vx(V2) = 0x04;
+
+    let instr = Instruction::GotoPlusV0 {
+        address: Address::new(0x2A0),
+    };
+
+    assert!(matches!(chip8.execute(instr), Ok(ExecResult::Jumped)));
+    assert_eq!(chip8.cpu.program_counter(), 0x2A4);
+}
+
+#[test]
+fn test_logic_resets_vf() {
+    let instructions = [
+        Instruction::OrReg { x: V1, y: V2 },
+        Instruction::AndReg { x: V1, y: V2 },
+        Instruction::XorReg { x: V1, y: V2 },
+    ];
+
+    for instr in instructions {
+        let mut chip8 = Chip8::with_quirks(Quirks::COSMAC_VIP);
+        *chip8.cpu.vx(VF) = 0xAA;
+        assert!(matches!(chip8.execute(instr), Ok(ExecResult::Advance)));
+        assert_eq!(*chip8.cpu.vx(VF), 0);
+
+        let mut chip8 = Chip8::with_quirks(Quirks::SUPER_CHIP);
+        *chip8.cpu.vx(VF) = 0xAA;
+        assert!(matches!(chip8.execute(instr), Ok(ExecResult::Advance)));
+        assert_eq!(*chip8.cpu.vx(VF), 0xAA);
+    }
+}
+
+#[test]
+fn test_sprite_clipping() {
+    let mut chip8 = Chip8::with_quirks(Quirks::COSMAC_VIP);
+    chip8.memory.load(0x300, &[0xFF]).unwrap();
+    chip8.cpu.set_address(0x300).unwrap();
+    *chip8.cpu.vx(V1) = 60;
+
+    let instr = Instruction::DrawSprite {
+        x: V1,
+        y: V0,
+        height: SpriteHeight::try_new(1).unwrap(),
+    };
+    chip8.execute(instr).unwrap();
+
+    let display_state = chip8.display_snapshot().unwrap();
+    assert!(display_state[0][63]);
+    assert!(!display_state[0][0]);
+}
+
+#[test]
+fn test_set_quirks() {
+    let mut chip8 = Chip8::new();
+    assert_eq!(chip8.quirks(), Quirks::default());
+
+    chip8.set_quirks(Quirks::XO_CHIP);
+    assert_eq!(chip8.quirks(), Quirks::XO_CHIP);
+}
@@ -0,0 +1,78 @@
+use crate::machine::{platform::Platform, quirks::Quirks, state::StateError};
+
+use super::*;
+
+const V1: Index = unsafe { Index::new_unchecked(0x1) };
+
+struct Context {
+    chip8: Chip8,
+}
+
+impl TestContext for Context {
+    fn setup() -> Self {
+        let mut chip8 = Chip8::with_platform(Platform::XoChip, Quirks::XO_CHIP);
+        // random number into V0, then draw the font sprite for 0 on both planes
+        chip8
+            .load_program(&[0xC0, 0xFF, 0xF3, 0x01, 0xA0, 0x00, 0xD1, 0x15, 0x12, 0x08])
+            .unwrap();
+        chip8.set_key_state(0xA, true).unwrap();
+        Self { chip8 }
+    }
+}
+
+#[test_context(Context)]
+#[test]
+fn test_roundtrip(ctx: &mut Context) {
+    ctx.chip8.step().unwrap();
+    ctx.chip8.step().unwrap();
+    let state = ctx.chip8.save_state();
+
+    let mut restored = Chip8::new();
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(restored.save_state(), state);
+    assert_eq!(restored.platform(), Platform::XoChip);
+    assert_eq!(restored.quirks(), Quirks::XO_CHIP);
+    assert!(restored.planes_snapshot().is_some());
+}
+
+#[test_context(Context)]
+#[test]
+fn test_restored_machine_runs_identically(ctx: &mut Context) {
+    let state = ctx.chip8.save_state();
+    let mut restored = Chip8::new();
+    restored.load_state(&state).unwrap();
+
+    for _ in 0..4 {
+        ctx.chip8.step().unwrap();
+        restored.step().unwrap();
+    }
+
+    assert_eq!(*restored.cpu.vx(V0), *ctx.chip8.cpu.vx(V0));
+    assert_eq!(*restored.cpu.vx(V1), *ctx.chip8.cpu.vx(V1));
+    assert_eq!(restored.save_state(), ctx.chip8.save_state());
+}
+
+#[test_context(Context)]
+#[test]
+fn test_corrupt_state_leaves_machine_untouched(ctx: &mut Context) {
+    let mut state = ctx.chip8.save_state();
+    *state.last_mut().unwrap() ^= 0xFF;
+
+    let mut chip8 = Chip8::new();
+    let before = chip8.save_state();
+    assert!(matches!(
+        chip8.load_state(&state),
+        Err(Chip8Error::StateError(StateError::ChecksumMismatch))
+    ));
+    assert_eq!(chip8.save_state(), before);
+
+    assert!(matches!(
+        chip8.load_state(&state[..20]),
+        Err(Chip8Error::StateError(StateError::Truncated))
+    ));
+    assert!(matches!(
+        chip8.load_state(&[]),
+        Err(Chip8Error::StateError(StateError::BadMagic))
+    ));
+}
@@ -0,0 +1,115 @@
+use crate::types::SpriteHeight;
+
+use super::*;
+
+const V1: Index = unsafe { Index::new_unchecked(0x1) };
+
+#[test_context(Context)]
+#[test]
+fn test_resolution_switch(ctx: &mut Context) {
+    assert_eq!(ctx.chip8.resolution(), (64, 32));
+
+    assert!(matches!(
+        ctx.chip8.execute(Instruction::HighRes),
+        Ok(ExecResult::Advance)
+    ));
+    assert_eq!(ctx.chip8.resolution(), (128, 64));
+    assert!(ctx.chip8.dirty_flag);
+
+    assert!(matches!(
+        ctx.chip8.execute(Instruction::LowRes),
+        Ok(ExecResult::Advance)
+    ));
+    assert_eq!(ctx.chip8.resolution(), (64, 32));
+}
+
+#[test_context(Context)]
+#[test]
+fn test_draw_wide_sprite(ctx: &mut Context) {
+    ctx.chip8.execute(Instruction::HighRes).unwrap();
+    ctx.chip8.memory.load(0x300, &[0xFF; 32]).unwrap();
+    ctx.chip8.cpu.set_address(0x300).unwrap();
+    *ctx.chip8.cpu.vx(V0) = 100;
+    *ctx.chip8.cpu.vx(V1) = 40;
+
+    let instr = Instruction::DrawSprite {
+        x: V0,
+        y: V1,
+        height: SpriteHeight::try_new(0).unwrap(),
+    };
+    assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*ctx.chip8.cpu.vx(VF), 0);
+
+    let display_state = ctx.chip8.display_snapshot().unwrap();
+    assert!(display_state[40][100]);
+    assert!(display_state[55][115]);
+    assert!(!display_state[56][116]);
+
+    ctx.chip8.execute(instr).unwrap();
+    assert_eq!(*ctx.chip8.cpu.vx(VF), 1);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_scroll_instructions(ctx: &mut Context) {
+    ctx.chip8.memory.load(0x300, &[0x80]).unwrap();
+    ctx.chip8.cpu.set_address(0x300).unwrap();
+    *ctx.chip8.cpu.vx(V0) = 10;
+
+    let instr = Instruction::DrawSprite {
+        x: V0,
+        y: V0,
+        height: SpriteHeight::try_new(1).unwrap(),
+    };
+    ctx.chip8.execute(instr).unwrap();
+
+    ctx.chip8
+        .execute(Instruction::ScrollDown { rows: 5 })
+        .unwrap();
+    ctx.chip8.execute(Instruction::ScrollRight).unwrap();
+    assert!(ctx.chip8.display_snapshot().unwrap()[15][14]);
+
+    ctx.chip8.execute(Instruction::ScrollLeft).unwrap();
+    assert!(ctx.chip8.display_snapshot().unwrap()[15][10]);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_big_sprite_address(ctx: &mut Context) {
+    *ctx.chip8.cpu.vx(V0) = 9;
+
+    let instr = Instruction::SetBigSpriteAddr { x: V0 };
+
+    assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(ctx.chip8.cpu.address(), 0x50 + 9 * 10);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_rpl_flags(ctx: &mut Context) {
+    *ctx.chip8.cpu.vx(V0) = 0x12;
+    *ctx.chip8.cpu.vx(V1) = 0x34;
+
+    ctx.chip8.execute(Instruction::SaveFlags { x: V1 }).unwrap();
+    *ctx.chip8.cpu.vx(V0) = 0;
+    *ctx.chip8.cpu.vx(V1) = 0;
+    ctx.chip8.execute(Instruction::LoadFlags { x: V1 }).unwrap();
+
+    assert_eq!(*ctx.chip8.cpu.vx(V0), 0x12);
+    assert_eq!(*ctx.chip8.cpu.vx(V1), 0x34);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_exit_stops_execution(ctx: &mut Context) {
+    // 00FD, then 6042 which must never run
+    ctx.chip8.load_program(&[0x00, 0xFD, 0x60, 0x42]).unwrap();
+
+    ctx.chip8.step().unwrap();
+    assert!(ctx.chip8.has_exited());
+    assert_eq!(ctx.chip8.cpu.program_counter(), 0x200);
+
+    ctx.chip8.step().unwrap();
+    assert_eq!(ctx.chip8.cpu.program_counter(), 0x200);
+    assert_eq!(*ctx.chip8.cpu.vx(V0), 0);
+}
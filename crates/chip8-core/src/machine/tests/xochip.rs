@@ -0,0 +1,150 @@
+use crate::{
+    machine::{platform::Platform, quirks::Quirks},
+    types::SpriteHeight,
+};
+
+use super::*;
+
+const V1: Index = unsafe { Index::new_unchecked(0x1) };
+const V2: Index = unsafe { Index::new_unchecked(0x2) };
+
+struct Context {
+    chip8: Chip8,
+}
+
+impl TestContext for Context {
+    fn setup() -> Self {
+        Self {
+            chip8: Chip8::with_platform(Platform::XoChip, Quirks::XO_CHIP),
+        }
+    }
+}
+
+#[test]
+fn test_platform_gating() {
+    let mut chip8 = Chip8::with_platform(Platform::Chip8, Quirks::COSMAC_VIP);
+    assert!(matches!(
+        chip8.execute(Instruction::HighRes),
+        Err(Chip8Error::UnsupportedInstruction)
+    ));
+
+    let mut chip8 = Chip8::with_platform(Platform::SuperChip, Quirks::SUPER_CHIP);
+    assert!(matches!(
+        chip8.execute(Instruction::HighRes),
+        Ok(ExecResult::Advance)
+    ));
+    assert!(matches!(
+        chip8.execute(Instruction::SelectPlanes { planes: 3 }),
+        Err(Chip8Error::UnsupportedInstruction)
+    ));
+}
+
+#[test]
+fn test_chip8_draws_nothing_for_zero_height() {
+    let mut chip8 = Chip8::with_platform(Platform::Chip8, Quirks::COSMAC_VIP);
+    chip8.memory.load(0x300, &[0xFF; 32]).unwrap();
+    chip8.cpu.set_address(0x300).unwrap();
+
+    let instr = Instruction::DrawSprite {
+        x: V0,
+        y: V0,
+        height: SpriteHeight::try_new(0).unwrap(),
+    };
+    chip8.execute(instr).unwrap();
+
+    assert_eq!(chip8.display_snapshot().unwrap(), &[[false; 128]; 64]);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_memory_size(ctx: &mut Context) {
+    assert_eq!(ctx.chip8.memory.size(), 1 << 16);
+
+    let program = vec![0; 0x8000];
+    assert!(ctx.chip8.load_program(&program).is_ok());
+}
+
+#[test_context(Context)]
+#[test]
+fn test_long_set_i(ctx: &mut Context) {
+    ctx.chip8
+        .load_program(&[0xF0, 0x00, 0xBE, 0xEF, 0x00, 0xE0])
+        .unwrap();
+
+    ctx.chip8.step().unwrap();
+    assert_eq!(ctx.chip8.cpu.address(), 0xBEEF);
+    assert_eq!(ctx.chip8.cpu.program_counter(), 0x204);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_skip_over_long_instruction(ctx: &mut Context) {
+    // 3000 skips when V0 == 0, the next instruction is 4 bytes long
+    ctx.chip8
+        .load_program(&[0x30, 0x00, 0xF0, 0x00, 0xBE, 0xEF, 0x00, 0xE0])
+        .unwrap();
+
+    ctx.chip8.step().unwrap();
+    assert_eq!(ctx.chip8.cpu.program_counter(), 0x206);
+    assert_eq!(ctx.chip8.cpu.address(), 0);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_save_load_range(ctx: &mut Context) {
+    *ctx.chip8.cpu.vx(V0) = 0x10;
+    *ctx.chip8.cpu.vx(V1) = 0x11;
+    *ctx.chip8.cpu.vx(V2) = 0x12;
+    ctx.chip8.cpu.set_address(0x400).unwrap();
+
+    let instr = Instruction::SaveRange { x: V2, y: V0 };
+    assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(ctx.chip8.cpu.address(), 0x400);
+    assert_eq!(ctx.chip8.memory.read_byte(0x400).unwrap(), 0x12);
+    assert_eq!(ctx.chip8.memory.read_byte(0x402).unwrap(), 0x10);
+
+    let instr = Instruction::LoadRange { x: V0, y: V2 };
+    assert!(matches!(ctx.chip8.execute(instr), Ok(ExecResult::Advance)));
+    assert_eq!(*ctx.chip8.cpu.vx(V0), 0x12);
+    assert_eq!(*ctx.chip8.cpu.vx(V1), 0x11);
+    assert_eq!(*ctx.chip8.cpu.vx(V2), 0x10);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_draw_on_both_planes(ctx: &mut Context) {
+    ctx.chip8
+        .execute(Instruction::SelectPlanes { planes: 3 })
+        .unwrap();
+    ctx.chip8.memory.load(0x300, &[0x80, 0x40]).unwrap();
+    ctx.chip8.cpu.set_address(0x300).unwrap();
+
+    let instr = Instruction::DrawSprite {
+        x: V0,
+        y: V0,
+        height: SpriteHeight::try_new(1).unwrap(),
+    };
+    ctx.chip8.execute(instr).unwrap();
+
+    let planes = ctx.chip8.planes_snapshot().unwrap();
+    assert!(planes[0][0][0]);
+    assert!(!planes[0][0][1]);
+    assert!(!planes[1][0][0]);
+    assert!(planes[1][0][1]);
+}
+
+#[test_context(Context)]
+#[test]
+fn test_audio(ctx: &mut Context) {
+    let pattern: Vec<u8> = (0..16).collect();
+    ctx.chip8.memory.load(0x300, &pattern).unwrap();
+    ctx.chip8.cpu.set_address(0x300).unwrap();
+    *ctx.chip8.cpu.vx(V1) = 112;
+
+    ctx.chip8.execute(Instruction::LoadAudioPattern).unwrap();
+    ctx.chip8.execute(Instruction::SetPitch { x: V1 }).unwrap();
+
+    assert_eq!(ctx.chip8.audio().pattern().unwrap()[..], pattern[..]);
+    assert_eq!(ctx.chip8.audio().pitch(), 112);
+    assert_eq!(ctx.chip8.audio().playback_rate(), 8000.0);
+}
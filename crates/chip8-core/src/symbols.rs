@@ -0,0 +1,263 @@
+//! Symbol and line maps
+//!
+//! A [`SymbolMap`] ties rom addresses back to the source they were assembled from: label names
+//! and the file and line of every instruction. Debuggers use it to show names instead of raw
+//! addresses and to set breakpoints on source lines.
+//!
+//! The text form is one entry per line, `;` starts a comment and addresses are hexadecimal:
+//!
+//! ```text
+//! ; labels
+//! label 0x0200 start
+//! label 0x0206 draw
+//! ; instructions, ADDRESS LINE FILE
+//! line 0x0200 3 game.asm
+//! line 0x0202 4 game.asm
+//! ```
+//!
+//! The file is the rest of the line, so it can contain spaces.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+/// Source position of an instruction
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceLine {
+    /// Source file, as given to the assembler
+    pub file: String,
+    /// Line in the file, starting at 1
+    pub line: u32,
+}
+
+/// Enum for all possible symbol map parsing errors
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SymbolMapError {
+    #[error("line {0}: unknown entry `{1}`")]
+    /// The line starts with something else than `label` or `line`
+    UnknownEntry(usize, String),
+    #[error("line {0}: missing field")]
+    /// The entry ends before all of its fields
+    MissingField(usize),
+    #[error("line {0}: invalid number `{1}`")]
+    /// An address or line number doesn't parse
+    InvalidNumber(usize, String),
+}
+
+/// Labels and source lines of a rom
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SymbolMap {
+    /// Address of every label
+    labels: BTreeMap<String, u16>,
+    /// Source position of every instruction
+    lines: BTreeMap<u16, SourceLine>,
+}
+
+impl SymbolMap {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name `address`, replacing an earlier label with the same name
+    pub fn add_label(&mut self, name: impl Into<String>, address: u16) {
+        self.labels.insert(name.into(), address);
+    }
+
+    /// Record that the instruction at `address` comes from `line` of `file`
+    pub fn add_line(&mut self, address: u16, file: impl Into<String>, line: u32) {
+        self.lines.insert(
+            address,
+            SourceLine {
+                file: file.into(),
+                line,
+            },
+        );
+    }
+
+    /// All labels, sorted by name
+    pub fn labels(&self) -> impl Iterator<Item = (&str, u16)> + '_ {
+        self.labels
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address))
+    }
+
+    /// Address of a label
+    pub fn label_address(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).copied()
+    }
+
+    /// Label at exactly `address`, the first by name if there are several
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.labels()
+            .find(|(_, at)| *at == address)
+            .map(|(name, _)| name)
+    }
+
+    /// Closest label at or before `address`, e.g. the subroutine it is part of
+    pub fn enclosing_label(&self, address: u16) -> Option<(&str, u16)> {
+        self.labels()
+            .filter(|(_, at)| *at <= address)
+            .max_by_key(|(_, at)| *at)
+    }
+
+    /// Source position of the instruction at `address`
+    pub fn source_line(&self, address: u16) -> Option<&SourceLine> {
+        self.lines.get(&address)
+    }
+
+    /// All instructions with a source position, sorted by address
+    pub fn lines(&self) -> impl Iterator<Item = (u16, &SourceLine)> + '_ {
+        self.lines.iter().map(|(address, line)| (*address, line))
+    }
+
+    /// Distinct source files, sorted
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = self.lines.values().map(|line| line.file.as_str()).collect();
+        files.sort_unstable();
+        files.dedup();
+        files
+    }
+
+    /// First instruction of `file` on `line` or the closest line after it with code
+    ///
+    /// Returns the address and the line it was found on, like debuggers moving a breakpoint
+    /// set on a comment to the next instruction
+    pub fn line_address(&self, file: &str, line: u32) -> Option<(u16, u32)> {
+        self.lines
+            .iter()
+            .filter(|(_, source)| source.file == file && source.line >= line)
+            .min_by_key(|(address, source)| (source.line, **address))
+            .map(|(address, source)| (*address, source.line))
+    }
+}
+
+/// Parse a hexadecimal address with an optional `0x` prefix
+fn parse_hex(s: &str, line: usize) -> Result<u16, SymbolMapError> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| SymbolMapError::InvalidNumber(line, s.to_owned()))
+}
+
+impl FromStr for SymbolMap {
+    type Err = SymbolMapError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::new();
+        for (index, text) in s.lines().enumerate() {
+            let number = index + 1;
+            let text = text.split(';').next().unwrap_or_default().trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            let mut fields = text.splitn(4, char::is_whitespace);
+            let kind = fields.next().unwrap_or_default();
+            let mut field = || {
+                fields
+                    .next()
+                    .map(str::trim)
+                    .filter(|field| !field.is_empty())
+                    .ok_or(SymbolMapError::MissingField(number))
+            };
+            match kind {
+                "label" => {
+                    let address = parse_hex(field()?, number)?;
+                    map.add_label(field()?, address);
+                }
+                "line" => {
+                    let address = parse_hex(field()?, number)?;
+                    let line = field()?;
+                    let line = line
+                        .parse()
+                        .map_err(|_| SymbolMapError::InvalidNumber(number, line.to_owned()))?;
+                    map.add_line(address, field()?, line);
+                }
+                other => return Err(SymbolMapError::UnknownEntry(number, other.to_owned())),
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl Display for SymbolMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, address) in self.labels() {
+            writeln!(f, "label {address:#06X} {name}")?;
+        }
+        for (address, source) in self.lines() {
+            writeln!(f, "line {address:#06X} {} {}", source.line, source.file)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    const MAP: &str = "\
+; labels
+label 0x0200 start
+label 206 draw ; subroutine
+line 0x0200 3 src/my game.asm
+line 0x0202 4 src/my game.asm
+line 0x0206 8 src/my game.asm
+line 0x0208 9 src/my game.asm
+";
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let map: SymbolMap = MAP.parse().unwrap();
+
+        assert_eq!(map.label_address("draw"), Some(0x206));
+        assert_eq!(map.label_at(0x200), Some("start"));
+        assert_eq!(map.label_at(0x202), None);
+        assert_eq!(map.enclosing_label(0x208), Some(("draw", 0x206)));
+        assert_eq!(map.enclosing_label(0x100), None);
+        assert_eq!(
+            map.source_line(0x202),
+            Some(&SourceLine {
+                file: "src/my game.asm".to_owned(),
+                line: 4
+            })
+        );
+        assert_eq!(map.files(), ["src/my game.asm"]);
+        assert_eq!(map.line_address("src/my game.asm", 4), Some((0x202, 4)));
+        assert_eq!(map.line_address("src/my game.asm", 5), Some((0x206, 8)));
+        assert_eq!(map.line_address("src/my game.asm", 10), None);
+        assert_eq!(map.line_address("other.asm", 1), None);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let map: SymbolMap = MAP.parse().unwrap();
+        let text = map.to_string();
+
+        assert!(text.starts_with("label 0x0206 draw\nlabel 0x0200 start\nline 0x0200 3"));
+        assert_eq!(text.parse::<SymbolMap>(), Ok(map));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "label 0x200 start\nlabel 0x202".parse::<SymbolMap>(),
+            Err(SymbolMapError::MissingField(2))
+        );
+        assert_eq!(
+            "line 0x200 x a.asm".parse::<SymbolMap>(),
+            Err(SymbolMapError::InvalidNumber(1, "x".to_owned()))
+        );
+        assert_eq!(
+            "\nsym 0x200".parse::<SymbolMap>(),
+            Err(SymbolMapError::UnknownEntry(2, "sym".to_owned()))
+        );
+    }
+}
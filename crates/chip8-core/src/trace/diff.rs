@@ -0,0 +1,733 @@
+//! First divergence between two runs
+//!
+//! [`lockstep`] runs two machines side by side, e.g. with different quirks, and compares them
+//! before every instruction: the [`TraceRecord`] fields, the whole memory, the bitplanes and
+//! whether they stopped. [`compare_traces`] does the same for two recorded traces, which only
+//! hold the record fields, e.g. one of this emulator and one of a reference converted to the
+//! text form.
+//!
+//! Both report the first cycle whose state differs, so the instruction that caused it is the
+//! one right before. The [`Divergence`] keeps the records around it, with the memory writes
+//! of every instruction:
+//!
+//! ```text
+//! First divergence at cycle 2
+//!   V0: 00 != 01
+//!   VF: 00 != 01
+//! left:
+//!           1 0202 8016     SHR V0, V1         V=00 03 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I=0000 SP=0 DT=00 ST=00
+//! >         2 0204 A300     LD I, 0x300        V=00 03 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I=0000 SP=0 DT=00 ST=00
+//!           3 0206 F033     LD B, V0           V=00 03 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I=0300 SP=0 DT=00 ST=00 [0300=00 0301=00 0302=00]
+//! right:
+//! ...
+//! ```
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    iter,
+};
+
+use crate::{
+    Chip8,
+    clock::VirtualClock,
+    trace::{TraceError, TraceRecord},
+};
+
+/// One thing two runs disagree on, left value first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// Cycle counters of the records, traces filtered differently
+    Cycle {
+        /// Left cycle
+        left: u64,
+        /// Right cycle
+        right: u64,
+    },
+    /// Program counter
+    Pc {
+        /// Left PC
+        left: u16,
+        /// Right PC
+        right: u16,
+    },
+    /// Instruction at the PC, with the operand of `F000 NNNN`
+    Opcode {
+        /// Left opcode and operand
+        left: (u16, Option<u16>),
+        /// Right opcode and operand
+        right: (u16, Option<u16>),
+    },
+    /// General purpose register
+    Register {
+        /// 0 to F
+        index: u8,
+        /// Left value
+        left: u8,
+        /// Right value
+        right: u8,
+    },
+    /// I register
+    I {
+        /// Left I
+        left: u16,
+        /// Right I
+        right: u16,
+    },
+    /// Stack pointer
+    Sp {
+        /// Left stack pointer
+        left: u8,
+        /// Right stack pointer
+        right: u8,
+    },
+    /// Delay timer
+    Dt {
+        /// Left delay timer
+        left: u8,
+        /// Right delay timer
+        right: u8,
+    },
+    /// Sound timer
+    St {
+        /// Left sound timer
+        left: u8,
+        /// Right sound timer
+        right: u8,
+    },
+    /// Memory, only compared in lockstep
+    Memory {
+        /// Lowest differing address
+        address: u16,
+        /// Left byte there
+        left: u8,
+        /// Right byte there
+        right: u8,
+        /// Differing bytes in total
+        count: usize,
+    },
+    /// Bitplanes, only compared in lockstep
+    Framebuffer {
+        /// First differing pixel as `(x, y)`, row by row
+        pixel: (usize, usize),
+        /// Plane of that pixel
+        plane: usize,
+        /// Differing pixels over all planes
+        count: usize,
+    },
+    /// Screen resolution, only compared in lockstep
+    Resolution {
+        /// Left `(width, height)`
+        left: (usize, usize),
+        /// Right `(width, height)`
+        right: (usize, usize),
+    },
+    /// One side stopped and the other didn't, or for another reason
+    Stopped {
+        /// Why the left side stopped, `None` if it is still running
+        left: Option<String>,
+        /// Why the right side stopped, `None` if it is still running
+        right: Option<String>,
+    },
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Opcode as printed in the traces
+        fn opcode((opcode, operand): (u16, Option<u16>)) -> String {
+            match operand {
+                Some(operand) => format!("{opcode:04X}{operand:04X}"),
+                None => format!("{opcode:04X}"),
+            }
+        }
+        /// Stop reason, or that the side is still running
+        fn stopped(reason: &Option<String>) -> &str {
+            reason.as_deref().unwrap_or("running")
+        }
+
+        match self {
+            Self::Cycle { left, right } => write!(f, "cycle: {left} != {right}"),
+            Self::Pc { left, right } => write!(f, "PC: {left:04X} != {right:04X}"),
+            Self::Opcode { left, right } => {
+                write!(f, "opcode: {} != {}", opcode(*left), opcode(*right))
+            }
+            Self::Register { index, left, right } => {
+                write!(f, "V{index:X}: {left:02X} != {right:02X}")
+            }
+            Self::I { left, right } => write!(f, "I: {left:04X} != {right:04X}"),
+            Self::Sp { left, right } => write!(f, "SP: {left:X} != {right:X}"),
+            Self::Dt { left, right } => write!(f, "DT: {left:02X} != {right:02X}"),
+            Self::St { left, right } => write!(f, "ST: {left:02X} != {right:02X}"),
+            Self::Memory {
+                address,
+                left,
+                right,
+                count,
+            } => write!(
+                f,
+                "memory at {address:04X}: {left:02X} != {right:02X}, {count} bytes differ"
+            ),
+            Self::Framebuffer {
+                pixel: (x, y),
+                plane,
+                count,
+            } => write!(
+                f,
+                "pixel ({x}, {y}) on plane {plane} differs, {count} pixels differ"
+            ),
+            Self::Resolution { left, right } => write!(
+                f,
+                "resolution: {}x{} != {}x{}",
+                left.0, left.1, right.0, right.1
+            ),
+            Self::Stopped { left, right } => {
+                write!(f, "stopped: {} != {}", stopped(left), stopped(right))
+            }
+        }
+    }
+}
+
+/// Where two runs part ways
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// First cycle whose state differs
+    pub cycle: u64,
+    /// Everything that differs on that cycle
+    pub differences: Vec<Difference>,
+    /// Left records around the divergence
+    pub left: Vec<TraceRecord>,
+    /// Right records around the divergence
+    pub right: Vec<TraceRecord>,
+    /// Index of the diverging record in both lists, past the end for a side that stopped
+    pub at: usize,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "First divergence at cycle {}", self.cycle)?;
+        for difference in &self.differences {
+            writeln!(f, "  {difference}")?;
+        }
+        for (side, records) in [("left", &self.left), ("right", &self.right)] {
+            writeln!(f, "{side}:")?;
+            for (index, record) in records.iter().enumerate() {
+                let marker = if index == self.at { '>' } else { ' ' };
+                write!(f, "{marker}{record}")?;
+                let writes = record.memory_writes();
+                if !writes.is_empty() {
+                    let writes: Vec<String> = writes
+                        .iter()
+                        .map(|(address, value)| format!("{address:04X}={value:02X}"))
+                        .collect();
+                    write!(f, " [{}]", writes.join(" "))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Differences between the fields of two records, the cycle included
+pub fn record_differences(left: &TraceRecord, right: &TraceRecord) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    if left.cycle != right.cycle {
+        differences.push(Difference::Cycle {
+            left: left.cycle,
+            right: right.cycle,
+        });
+    }
+    if left.pc != right.pc {
+        differences.push(Difference::Pc {
+            left: left.pc,
+            right: right.pc,
+        });
+    }
+    if (left.opcode, left.operand) != (right.opcode, right.operand) {
+        differences.push(Difference::Opcode {
+            left: (left.opcode, left.operand),
+            right: (right.opcode, right.operand),
+        });
+    }
+    for (index, (&l, &r)) in (0..).zip(left.registers.iter().zip(&right.registers)) {
+        if l != r {
+            differences.push(Difference::Register {
+                index,
+                left: l,
+                right: r,
+            });
+        }
+    }
+    if left.i != right.i {
+        differences.push(Difference::I {
+            left: left.i,
+            right: right.i,
+        });
+    }
+    if left.sp != right.sp {
+        differences.push(Difference::Sp {
+            left: left.sp,
+            right: right.sp,
+        });
+    }
+    if left.dt != right.dt {
+        differences.push(Difference::Dt {
+            left: left.dt,
+            right: right.dt,
+        });
+    }
+    if left.st != right.st {
+        differences.push(Difference::St {
+            left: left.st,
+            right: right.st,
+        });
+    }
+    differences
+}
+
+/// Compare two traces record by record
+///
+/// `context` records are kept before and after the divergence. Reading errors are returned as
+/// they come, a trace ending before the other is a [`Difference::Stopped`]
+pub fn compare_traces(
+    left: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+    right: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+    context: usize,
+) -> Result<Option<Divergence>, TraceError> {
+    let (mut left, mut right) = (left.into_iter(), right.into_iter());
+    let mut history = VecDeque::new();
+
+    loop {
+        let (l, r) = (left.next().transpose()?, right.next().transpose()?);
+        let differences = match (&l, &r) {
+            (None, None) => return Ok(None),
+            (Some(l), Some(r)) => record_differences(l, r),
+            _ => vec![Difference::Stopped {
+                left: l.is_none().then(|| String::from("end of trace")),
+                right: r.is_none().then(|| String::from("end of trace")),
+            }],
+        };
+
+        if differences.is_empty() {
+            history.extend(l.zip(r));
+            while history.len() > context {
+                history.pop_front();
+            }
+            continue;
+        }
+
+        let cycle = l.or(r).map_or(0, |record| record.cycle);
+        let at = history.len();
+        let (before_left, before_right): (Vec<_>, Vec<_>) = history.into_iter().unzip();
+        let after = |first: Option<TraceRecord>, rest: &mut dyn Iterator<Item = _>| {
+            first
+                .map(Ok)
+                .into_iter()
+                .chain(rest.take(context))
+                .collect::<Result<Vec<_>, TraceError>>()
+        };
+        let left = [before_left, after(l, &mut left)?].concat();
+        let right = [before_right, after(r, &mut right)?].concat();
+
+        return Ok(Some(Divergence {
+            cycle,
+            differences,
+            left,
+            right,
+            at,
+        }));
+    }
+}
+
+/// One of the machines run in lockstep
+struct Side<'a> {
+    /// The machine
+    chip8: &'a mut Chip8,
+    /// Why it stopped, `None` while it runs
+    stopped: Option<String>,
+}
+
+impl Side<'_> {
+    /// Run one instruction, the timers follow `clock` after it advanced
+    fn step(&mut self, ticks: u64) {
+        if self.stopped.is_some() {
+            return;
+        }
+        match self.chip8.step() {
+            Ok(()) if self.chip8.has_exited() => self.stopped = Some(String::from("exited")),
+            Ok(()) => {
+                for _ in 0..ticks {
+                    self.chip8.tick_timers();
+                }
+            }
+            Err(e) => self.stopped = Some(e.to_string()),
+        }
+    }
+
+    /// Records of the next `count` instructions, fewer if the machine stops
+    fn records(&mut self, cycle: u64, count: usize, mut clock: VirtualClock) -> Vec<TraceRecord> {
+        let mut records = Vec::new();
+        for cycle in (cycle..).take(count) {
+            if self.stopped.is_some() {
+                break;
+            }
+            records.push(TraceRecord::capture(cycle, self.chip8));
+            let ticks = clock.advance();
+            self.step(ticks);
+        }
+        records
+    }
+}
+
+/// Differences in the memory, the screen and the run state of two machines
+fn machine_differences(left: &Side<'_>, right: &Side<'_>) -> Vec<Difference> {
+    let mut differences = Vec::new();
+
+    let (l, r) = (left.chip8.memory().bytes(), right.chip8.memory().bytes());
+    let mut bytes = iter::zip(l, r).enumerate().filter(|(_, (l, r))| l != r);
+    if let Some((address, (&l, &r))) = bytes.next() {
+        differences.push(Difference::Memory {
+            address: address as u16,
+            left: l,
+            right: r,
+            count: bytes.count() + 1,
+        });
+    }
+
+    let (l, r) = (left.chip8.resolution(), right.chip8.resolution());
+    if l != r {
+        differences.push(Difference::Resolution { left: l, right: r });
+    }
+
+    let mut pixels = iter::zip(left.chip8.planes(), right.chip8.planes())
+        .enumerate()
+        .flat_map(|(plane, (l, r))| {
+            iter::zip(l, r).enumerate().flat_map(move |(y, (l, r))| {
+                iter::zip(l, r)
+                    .enumerate()
+                    .filter(|(_, (l, r))| l != r)
+                    .map(move |(x, _)| (plane, x, y))
+            })
+        });
+    if let Some((plane, x, y)) = pixels.next() {
+        differences.push(Difference::Framebuffer {
+            pixel: (x, y),
+            plane,
+            count: pixels.count() + 1,
+        });
+    }
+
+    if left.stopped != right.stopped {
+        differences.push(Difference::Stopped {
+            left: left.stopped.clone(),
+            right: right.stopped.clone(),
+        });
+    }
+
+    differences
+}
+
+/// Run two machines side by side for up to `cycles` instructions
+///
+/// The timers tick on a shared [`VirtualClock`]. Errors stop a machine instead of the run, they
+/// only count as a divergence if the other one keeps going or stops another way. `None` if
+/// the machines agree until both stop or the cycles run out
+pub fn lockstep(
+    left: &mut Chip8,
+    right: &mut Chip8,
+    cycles: u64,
+    context: usize,
+) -> Option<Divergence> {
+    let mut left = Side {
+        chip8: left,
+        stopped: None,
+    };
+    let mut right = Side {
+        chip8: right,
+        stopped: None,
+    };
+    let mut clock = VirtualClock::new();
+    let mut history = VecDeque::new();
+
+    for cycle in 0..cycles {
+        let running = |side: &Side<'_>| {
+            side.stopped
+                .is_none()
+                .then(|| TraceRecord::capture(cycle, side.chip8))
+        };
+        let records = (running(&left), running(&right));
+        let mut differences = match records {
+            (Some(l), Some(r)) => record_differences(&l, &r),
+            _ => Vec::new(),
+        };
+        differences.extend(machine_differences(&left, &right));
+
+        if !differences.is_empty() {
+            let at = history.len();
+            let (mut before_left, mut before_right): (Vec<_>, Vec<_>) = history.into_iter().unzip();
+            // the records after the divergence don't run past the cycles either
+            let remaining = usize::try_from(cycles - cycle).unwrap_or(usize::MAX);
+            let count = context.saturating_add(1).min(remaining);
+            before_left.extend(left.records(cycle, count, clock.clone()));
+            before_right.extend(right.records(cycle, count, clock));
+            return Some(Divergence {
+                cycle,
+                differences,
+                left: before_left,
+                right: before_right,
+                at,
+            });
+        }
+
+        let (Some(l), Some(r)) = records else {
+            // both stopped the same way
+            return None;
+        };
+        history.push_back((l, r));
+        while history.len() > context {
+            history.pop_front();
+        }
+
+        let ticks = clock.advance();
+        left.step(ticks);
+        right.step(ticks);
+    }
+
+    None
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+    use crate::{
+        Platform, Quirks,
+        trace::{TraceFilter, TraceFormat, Tracer, read_trace},
+    };
+
+    const PROGRAM: [u8; 12] = [
+        0x61, 0x03, // 200: LD V1, 0x03
+        0x80, 0x16, // 202: SHR V0, V1
+        0xA3, 0x00, // 204: LD I, 0x300
+        0xF0, 0x33, // 206: LD B, V0
+        0x00, 0xE0, // 208: CLS
+        0x12, 0x0A, // 20A: JP 0x20A
+    ];
+
+    fn machine(quirks: Quirks) -> Chip8 {
+        let mut chip8 = Chip8::with_platform(Platform::SuperChip, quirks);
+        chip8.load_program(&PROGRAM).unwrap();
+        chip8.seed_random(0);
+        chip8
+    }
+
+    #[test]
+    fn test_lockstep_quirks() {
+        let shift_vy = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        let divergence = lockstep(
+            &mut machine(Quirks::default()),
+            &mut machine(shift_vy),
+            100,
+            1,
+        )
+        .unwrap();
+
+        // SHR V0 of 0 vs SHR of V1 = 3, the right side gets V0 = 1 and VF = 1
+        assert_eq!(divergence.cycle, 2);
+        assert_eq!(
+            divergence.differences,
+            [
+                Difference::Register {
+                    index: 0,
+                    left: 0,
+                    right: 1
+                },
+                Difference::Register {
+                    index: 0xF,
+                    left: 0,
+                    right: 1
+                },
+            ]
+        );
+        assert_eq!(divergence.at, 1);
+        assert_eq!(divergence.left.len(), 3);
+        assert_eq!(divergence.left[1].pc, 0x204);
+        assert_eq!(
+            divergence.right[2].memory_writes(),
+            [(0x300, 0), (0x301, 0), (0x302, 1)]
+        );
+
+        let text = divergence.to_string();
+        assert!(
+            text.starts_with(
+                "First divergence at cycle 2\n  V0: 00 != 01\n  VF: 00 != 01\nleft:\n"
+            )
+        );
+        assert!(text.contains("\n>         2 0204 A300 "));
+        assert!(text.ends_with("[0300=00 0301=00 0302=01]\n"));
+    }
+
+    #[test]
+    fn test_lockstep_memory_and_stop() {
+        let mut left = machine(Quirks::default());
+        let mut right = machine(Quirks::default());
+        assert_eq!(lockstep(&mut left, &mut right, 100, 3), None);
+
+        let mut left = machine(Quirks::default());
+        let mut right = machine(Quirks::default());
+        right.write_memory(0x300, &[0xFF, 0xFF]).unwrap();
+        let divergence = lockstep(&mut left, &mut right, 100, 3).unwrap();
+        assert_eq!(divergence.cycle, 0);
+        assert_eq!(
+            divergence.differences,
+            [Difference::Memory {
+                address: 0x300,
+                left: 0,
+                right: 0xFF,
+                count: 2
+            }]
+        );
+        assert_eq!((divergence.at, divergence.left.len()), (0, 4));
+
+        // LD B, V0 at 0xFFF only fits in the XO-CHIP memory
+        let program = [0xAF, 0xFF, 0xF0, 0x33, 0x12, 0x04];
+        let mut left = Chip8::with_platform(Platform::Chip8, Quirks::default());
+        left.load_program(&program).unwrap();
+        let mut right = Chip8::with_platform(Platform::XoChip, Quirks::default());
+        right.load_program(&program).unwrap();
+        let divergence = lockstep(&mut left, &mut right, 100, 3).unwrap();
+        assert_eq!(divergence.cycle, 2);
+        assert!(divergence.differences.contains(&Difference::Stopped {
+            left: Some(String::from(
+                "out of range access at 0x202: Memory access out of range: 0x1002 (policy: break)"
+            )),
+            right: None
+        }));
+        assert_eq!(divergence.left.len(), 2);
+        assert_eq!(divergence.right.len(), 6);
+    }
+
+    #[test]
+    fn test_lockstep_context() {
+        let shift_vy = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        let divergence = lockstep(
+            &mut machine(Quirks::default()),
+            &mut machine(shift_vy),
+            100,
+            0,
+        )
+        .unwrap();
+        assert_eq!((divergence.at, divergence.left.len()), (0, 1));
+        assert_eq!(divergence.left[0].cycle, 2);
+
+        let divergence = lockstep(
+            &mut machine(Quirks::default()),
+            &mut machine(shift_vy),
+            100,
+            usize::MAX,
+        )
+        .unwrap();
+        assert_eq!((divergence.at, divergence.left.len()), (2, 100));
+    }
+
+    fn trace(quirks: Quirks, format: TraceFormat, steps: usize) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!(
+            "chip8-diff-{}-{format:?}-{}.trace",
+            std::process::id(),
+            quirks.shift_uses_vy
+        ));
+        let mut chip8 = machine(quirks);
+        chip8.set_tracer(
+            Tracer::new(
+                std::fs::File::create(&path).unwrap(),
+                format,
+                TraceFilter::default(),
+            )
+            .unwrap(),
+        );
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+        drop(chip8);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bytes
+    }
+
+    fn records(bytes: Vec<u8>) -> crate::trace::TraceRecords {
+        read_trace(std::io::Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn test_compare_traces() {
+        let shift_vy = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        let text = trace(Quirks::default(), TraceFormat::Text, 6);
+        let binary = trace(Quirks::default(), TraceFormat::Binary, 6);
+        let other = trace(shift_vy, TraceFormat::Binary, 6);
+
+        // the same run in both forms
+        assert_eq!(
+            compare_traces(records(text.clone()), records(binary.clone()), 2).unwrap(),
+            None
+        );
+
+        let divergence = compare_traces(records(text.clone()), records(other), 2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.cycle, 2);
+        assert_eq!(divergence.differences.len(), 2);
+        assert_eq!((divergence.at, divergence.left.len()), (2, 5));
+
+        let short = trace(Quirks::default(), TraceFormat::Text, 4);
+        let divergence = compare_traces(records(short), records(binary), 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(divergence.cycle, 4);
+        assert_eq!(
+            divergence.differences,
+            [Difference::Stopped {
+                left: Some(String::from("end of trace")),
+                right: None
+            }]
+        );
+        assert_eq!(divergence.left.len(), 1);
+        assert_eq!(divergence.right.len(), 3);
+
+        let mut broken = text;
+        broken.extend_from_slice(b"garbage\n");
+        assert!(matches!(
+            compare_traces(records(broken.clone()), records(broken), 1),
+            Err(TraceError::InvalidLine(7))
+        ));
+    }
+
+    #[test]
+    fn test_compare_traces_context() {
+        let shift_vy = Quirks {
+            shift_uses_vy: true,
+            ..Quirks::default()
+        };
+        let text = trace(Quirks::default(), TraceFormat::Text, 6);
+        let other = trace(shift_vy, TraceFormat::Binary, 6);
+
+        let divergence = compare_traces(records(text.clone()), records(other.clone()), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!((divergence.at, divergence.left.len()), (0, 1));
+        assert_eq!(divergence.left[0].cycle, 2);
+
+        let divergence = compare_traces(records(text), records(other), usize::MAX)
+            .unwrap()
+            .unwrap();
+        assert_eq!((divergence.at, divergence.left.len()), (2, 6));
+    }
+}
@@ -0,0 +1,559 @@
+//! Instruction execution traces
+//!
+//! A [`Tracer`] attached with [`Chip8::set_tracer`](crate::Chip8::set_tracer) records every
+//! executed instruction with the machine state it ran in, before its effects. Cycles count the
+//! instructions executed since the tracer was attached.
+//!
+//! The text form has one line per instruction with fixed columns, so two traces can be compared
+//! with any diff tool:
+//!
+//! ```text
+//!          0 0200 6A12     LD VA, 0x12        V=00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I=0000 SP=0 DT=00 ST=00
+//!          1 0202 F0001234 LD I, LONG 0x1234  V=00 00 00 00 00 00 00 00 00 00 12 00 00 00 00 00 I=0000 SP=0 DT=00 ST=00
+//! ```
+//!
+//! The binary form is little-endian, a header followed by fixed-size records:
+//!
+//! | Offset | Size | Content                                    |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 4    | Magic bytes `C8TR`                         |
+//! | 4      | 2    | Format version, currently [`TRACE_VERSION`] |
+//!
+//! | Offset | Size | Record content                             |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 8    | Cycle                                      |
+//! | 8      | 2    | PC                                         |
+//! | 10     | 2    | Opcode                                     |
+//! | 12     | 2    | Operand of `F000 NNNN`, 0 otherwise        |
+//! | 14     | 16   | V0..=VF                                    |
+//! | 30     | 2    | I                                          |
+//! | 32     | 1    | Stack pointer                              |
+//! | 33     | 1    | Delay timer                                |
+//! | 34     | 1    | Sound timer                                |
+//!
+//! Bumping the version is required for any change to the layout above.
+//!
+//! [`read_trace`] reads both forms back, [`diff`] finds where two traces or two machines part
+//! ways.
+
+use std::{
+    fmt::{self, Display},
+    fs::File,
+    io::{self, BufRead, BufWriter, Read, Write},
+    ops::{Range, RangeInclusive},
+    path::PathBuf,
+};
+
+use thiserror::Error;
+
+use crate::{Chip8, Instruction, machine::cpu::Cpu};
+
+pub mod diff;
+
+/// Magic bytes every binary trace starts with
+pub const TRACE_MAGIC: [u8; 4] = *b"C8TR";
+
+/// Version of the binary trace format written by this build
+pub const TRACE_VERSION: u16 = 1;
+
+/// Size of a binary trace record
+pub const RECORD_SIZE: usize = 35;
+
+/// Enum for all possible trace reading errors
+#[derive(Debug, Error)]
+pub enum TraceError {
+    #[error("Trace io error: {0}")]
+    /// The trace can't be read
+    Io(#[from] io::Error),
+    #[error("Not a binary trace")]
+    /// The data doesn't start with the magic bytes
+    BadMagic,
+    #[error("Trace version {0} is not supported, expected {TRACE_VERSION}")]
+    /// The trace was written by an incompatible version of the emulator
+    UnsupportedVersion(u16),
+    #[error("Trace ends in the middle of a record")]
+    /// The last record is cut short
+    Truncated,
+    #[error("Line {0} is not a trace record")]
+    /// A line of a text trace doesn't have the fields of [`TraceRecord`]'s text form
+    InvalidLine(usize),
+}
+
+/// How traces are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    /// One line per instruction
+    #[default]
+    Text,
+    /// Fixed-size records, see the module docs
+    Binary,
+}
+
+/// Which instructions are recorded
+///
+/// Empty lists match everything, otherwise the PC has to be in one of the address ranges and
+/// the cycle in one of the windows
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// PC ranges to record
+    pub addresses: Vec<RangeInclusive<u16>>,
+    /// Cycle windows to record
+    pub cycles: Vec<Range<u64>>,
+}
+
+impl TraceFilter {
+    /// Whether the instruction at `pc` executed on `cycle` is recorded
+    pub fn matches(&self, cycle: u64, pc: u16) -> bool {
+        (self.addresses.is_empty() || self.addresses.iter().any(|range| range.contains(&pc)))
+            && (self.cycles.is_empty() || self.cycles.iter().any(|window| window.contains(&cycle)))
+    }
+}
+
+/// One executed instruction and the state it ran in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Instructions executed before this one
+    pub cycle: u64,
+    /// Address of the instruction
+    pub pc: u16,
+    /// Opcode
+    pub opcode: u16,
+    /// Second word of `F000 NNNN`
+    pub operand: Option<u16>,
+    /// V0..=VF
+    pub registers: [u8; 16],
+    /// I register
+    pub i: u16,
+    /// Stack pointer
+    pub sp: u8,
+    /// Delay timer
+    pub dt: u8,
+    /// Sound timer
+    pub st: u8,
+}
+
+impl TraceRecord {
+    /// Record of `instruction` about to run on `cpu`
+    pub fn new(cycle: u64, instruction: &Instruction, cpu: &Cpu) -> Self {
+        Self {
+            cycle,
+            pc: cpu.program_counter(),
+            opcode: u16::from(*instruction),
+            operand: instruction.operand(),
+            registers: *cpu.registers(),
+            i: cpu.address(),
+            sp: cpu.stack_pointer() as u8,
+            dt: cpu.delay_timer(),
+            st: cpu.sound_timer(),
+        }
+    }
+
+    /// Record of the instruction `chip8` is about to run, even if it can't be decoded
+    pub fn capture(cycle: u64, chip8: &Chip8) -> Self {
+        let cpu = chip8.cpu();
+        let pc = cpu.program_counter();
+        let opcode = chip8.memory().read_word(pc).unwrap_or_default();
+        Self {
+            cycle,
+            pc,
+            opcode,
+            operand: Instruction::has_operand(opcode)
+                .then(|| chip8.memory().read_word(pc.wrapping_add(2)).ok())
+                .flatten(),
+            registers: *cpu.registers(),
+            i: cpu.address(),
+            sp: cpu.stack_pointer() as u8,
+            dt: cpu.delay_timer(),
+            st: cpu.sound_timer(),
+        }
+    }
+
+    /// Decoded instruction
+    pub fn instruction(&self) -> Option<Instruction> {
+        match self.operand {
+            Some(operand) => Instruction::decode(self.opcode, operand).ok(),
+            None => Instruction::try_from(self.opcode).ok(),
+        }
+    }
+
+    /// Bytes the instruction stores in memory as `(address, value)`, worked out from the
+    /// registers it ran with
+    pub fn memory_writes(&self) -> Vec<(u16, u8)> {
+        let register = |index: crate::types::Index| self.registers[usize::from(index.into_inner())];
+        let values: Vec<u8> = match self.instruction() {
+            Some(Instruction::SetBCD { x }) => {
+                let value = register(x);
+                vec![value / 100, value / 10 % 10, value % 10]
+            }
+            Some(Instruction::DumpRegisters { x }) => {
+                self.registers[..=usize::from(x.into_inner())].to_vec()
+            }
+            Some(Instruction::SaveRange { x, y }) => {
+                Chip8::register_range(x, y).map(register).collect()
+            }
+            _ => Vec::new(),
+        };
+        (0..)
+            .zip(values)
+            .map(|(offset, value)| (self.i.wrapping_add(offset), value))
+            .collect()
+    }
+
+    /// Parse a line of the text form, the mnemonic is skipped
+    pub fn parse_line(line: &str) -> Option<Self> {
+        let hex =
+            |token: &str, prefix: &str| u16::from_str_radix(token.strip_prefix(prefix)?, 16).ok();
+        let mut tokens = line.split_whitespace();
+        let cycle = tokens.next()?.parse().ok()?;
+        let pc = hex(tokens.next()?, "")?;
+        let word = tokens.next()?;
+        let opcode = hex(word.get(..4)?, "")?;
+        let operand = match word.len() {
+            4 => None,
+            8 => Some(hex(&word[4..], "")?),
+            _ => return None,
+        };
+
+        let mut tokens = tokens.skip_while(|token| !token.starts_with("V="));
+        let mut registers = [0; 16];
+        registers[0] = hex(tokens.next()?, "V=")? as u8;
+        for register in &mut registers[1..] {
+            *register = u8::from_str_radix(tokens.next()?, 16).ok()?;
+        }
+        let i = hex(tokens.next()?, "I=")?;
+        let sp = hex(tokens.next()?, "SP=")? as u8;
+        let dt = hex(tokens.next()?, "DT=")? as u8;
+        let st = hex(tokens.next()?, "ST=")? as u8;
+
+        Some(Self {
+            cycle,
+            pc,
+            opcode,
+            operand,
+            registers,
+            i,
+            sp,
+            dt,
+            st,
+        })
+    }
+
+    /// Binary form, see the module docs
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[0..8].copy_from_slice(&self.cycle.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.pc.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.opcode.to_le_bytes());
+        bytes[12..14].copy_from_slice(&self.operand.unwrap_or(0).to_le_bytes());
+        bytes[14..30].copy_from_slice(&self.registers);
+        bytes[30..32].copy_from_slice(&self.i.to_le_bytes());
+        bytes[32] = self.sp;
+        bytes[33] = self.dt;
+        bytes[34] = self.st;
+        bytes
+    }
+
+    /// Parse the binary form
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Self {
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let opcode = word(10);
+        Self {
+            cycle: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            pc: word(8),
+            opcode,
+            operand: Instruction::has_operand(opcode).then(|| word(12)),
+            registers: bytes[14..30].try_into().unwrap(),
+            i: word(30),
+            sp: bytes[32],
+            dt: bytes[33],
+            st: bytes[34],
+        }
+    }
+}
+
+impl Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let opcode = match self.operand {
+            Some(operand) => format!("{:04X}{operand:04X}", self.opcode),
+            None => format!("{:04X}", self.opcode),
+        };
+        let mnemonic = self.instruction().map_or_else(
+            || String::from("???"),
+            |instruction| instruction.to_string(),
+        );
+        write!(
+            f,
+            "{:>10} {:04X} {opcode:<8} {mnemonic:<18} V=",
+            self.cycle, self.pc
+        )?;
+        for (index, register) in self.registers.iter().enumerate() {
+            let separator = if index == 0 { "" } else { " " };
+            write!(f, "{separator}{register:02X}")?;
+        }
+        write!(
+            f,
+            " I={:04X} SP={:X} DT={:02X} ST={:02X}",
+            self.i, self.sp, self.dt, self.st
+        )
+    }
+}
+
+/// Where and how to write a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceOptions {
+    /// File to write, truncated first
+    pub path: PathBuf,
+    /// Text or binary
+    pub format: TraceFormat,
+    /// Which instructions are recorded
+    pub filter: TraceFilter,
+}
+
+impl TraceOptions {
+    /// Create the file and a tracer writing to it
+    pub fn open(&self) -> io::Result<Tracer> {
+        let file = File::create(&self.path)?;
+        Tracer::new(BufWriter::new(file), self.format, self.filter.clone())
+    }
+}
+
+/// Writes a trace of the instructions a [`Chip8`](crate::Chip8) executes
+pub struct Tracer {
+    /// Destination of the trace
+    writer: Box<dyn Write + Send>,
+    /// Text or binary
+    format: TraceFormat,
+    /// Which instructions are recorded
+    filter: TraceFilter,
+    /// Instructions executed since the tracer was attached
+    cycle: u64,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .field("filter", &self.filter)
+            .field("cycle", &self.cycle)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    /// Create a tracer writing to `writer`, the binary header is written right away
+    pub fn new(
+        writer: impl Write + Send + 'static,
+        format: TraceFormat,
+        filter: TraceFilter,
+    ) -> io::Result<Self> {
+        let mut writer: Box<dyn Write + Send> = Box::new(writer);
+        if format == TraceFormat::Binary {
+            writer.write_all(&TRACE_MAGIC)?;
+            writer.write_all(&TRACE_VERSION.to_le_bytes())?;
+        }
+        Ok(Self {
+            writer,
+            format,
+            filter,
+            cycle: 0,
+        })
+    }
+
+    /// Instructions executed since the tracer was attached
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Flush the buffered records
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Record `instruction` about to run on `cpu` if the filter matches
+    pub(crate) fn record(&mut self, instruction: &Instruction, cpu: &Cpu) -> io::Result<()> {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if !self.filter.matches(cycle, cpu.program_counter()) {
+            return Ok(());
+        }
+
+        let record = TraceRecord::new(cycle, instruction, cpu);
+        match self.format {
+            TraceFormat::Text => writeln!(self.writer, "{record}"),
+            TraceFormat::Binary => self.writer.write_all(&record.to_bytes()),
+        }
+    }
+}
+
+/// Reads the records of a binary trace
+#[derive(Debug)]
+pub struct TraceReader<R> {
+    /// Source of the trace, after the header
+    reader: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Check the header of a binary trace
+    pub fn new(mut reader: R) -> Result<Self, TraceError> {
+        let mut header = [0; 6];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => TraceError::BadMagic,
+            _ => TraceError::Io(e),
+        })?;
+        if header[..4] != TRACE_MAGIC {
+            return Err(TraceError::BadMagic);
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != TRACE_VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+        Ok(Self { reader })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bytes = [0; RECORD_SIZE];
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            match self.reader.read(&mut bytes[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(TraceError::Truncated)),
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        Some(Ok(TraceRecord::from_bytes(&bytes)))
+    }
+}
+
+/// Records of a trace in either form, told apart by the magic bytes
+pub type TraceRecords = Box<dyn Iterator<Item = Result<TraceRecord, TraceError>>>;
+
+/// Read a binary or text trace
+pub fn read_trace(mut reader: impl BufRead + 'static) -> Result<TraceRecords, TraceError> {
+    if reader.fill_buf()?.starts_with(&TRACE_MAGIC) {
+        return Ok(Box::new(TraceReader::new(reader)?));
+    }
+
+    Ok(Box::new(
+        reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|(index, line)| {
+                TraceRecord::parse_line(&line?).ok_or(TraceError::InvalidLine(index + 1))
+            }),
+    ))
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::Chip8;
+
+    /// Writer whose bytes stay readable after the tracer is dropped
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const PROGRAM: [u8; 10] = [
+        0x6A, 0x12, // 200: LD VA, 0x12
+        0xF0, 0x00, 0x12, 0x34, // 202: LD I, LONG 0x1234
+        0x7A, 0x01, // 206: ADD VA, 0x01
+        0x12, 0x06, // 208: JP 0x206
+    ];
+
+    fn trace(format: TraceFormat, filter: TraceFilter, steps: usize) -> Vec<u8> {
+        let output = Shared::default();
+        let mut chip8 = Chip8::with_platform(crate::Platform::XoChip, crate::Quirks::default());
+        chip8.load_program(&PROGRAM).unwrap();
+        chip8.set_tracer(Tracer::new(output.clone(), format, filter).unwrap());
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+        assert_eq!(chip8.take_tracer().unwrap().cycle(), steps as u64);
+        output.0.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn test_text() {
+        let text = String::from_utf8(trace(TraceFormat::Text, TraceFilter::default(), 3)).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(
+            lines,
+            [
+                "         0 0200 6A12     LD VA, 0x12        V=00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I=0000 SP=0 DT=00 ST=00",
+                "         1 0202 F0001234 LD I, LONG 0x1234  V=00 00 00 00 00 00 00 00 00 00 12 00 00 00 00 00 I=0000 SP=0 DT=00 ST=00",
+                "         2 0206 7A01     ADD VA, 0x01       V=00 00 00 00 00 00 00 00 00 00 12 00 00 00 00 00 I=1234 SP=0 DT=00 ST=00",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = TraceFilter {
+            addresses: vec![0x206..=0x207],
+            cycles: vec![0..6, 8..10],
+        };
+        let text = String::from_utf8(trace(TraceFormat::Text, filter, 12)).unwrap();
+        let cycles: Vec<u64> = text
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+            .collect();
+
+        assert_eq!(cycles, [2, 4, 8]);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let bytes = trace(TraceFormat::Binary, TraceFilter::default(), 4);
+        assert_eq!(bytes.len(), 6 + 4 * RECORD_SIZE);
+
+        let records: Vec<TraceRecord> = TraceReader::new(bytes.as_slice())
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[1].operand, Some(0x1234));
+        assert_eq!(records[2].i, 0x1234);
+        assert_eq!(records[3].registers[0xA], 0x13);
+        assert_eq!(
+            records[1].instruction().unwrap().to_string(),
+            "LD I, LONG 0x1234"
+        );
+
+        assert!(matches!(
+            TraceReader::new(&bytes[..3]),
+            Err(TraceError::BadMagic)
+        ));
+        let mut truncated = TraceReader::new(&bytes[..6 + RECORD_SIZE + 1]).unwrap();
+        assert!(truncated.next().unwrap().is_ok());
+        assert!(matches!(truncated.next(), Some(Err(TraceError::Truncated))));
+    }
+
+    #[test]
+    fn test_io_error_shows_its_cause() {
+        assert_eq!(
+            TraceError::from(io::Error::other("disk full")).to_string(),
+            "Trace io error: disk full"
+        );
+    }
+}
@@ -4,16 +4,15 @@ use std::fmt;
 
 use nutype::nutype;
 
-/// Newtype struct for addresses, they are 12-bit in chip8
-#[nutype(
-    validate(less = 4096),
-    derive(Debug, PartialEq, Eq, Clone, Display, Copy)
-)]
+/// Newtype struct for addresses
+///
+/// They are 12-bit in most chip8 opcodes, XO-CHIP's `F000 NNNN` carries a full 16-bit one
+#[nutype(derive(Debug, PartialEq, Eq, Clone, Display, Copy))]
 pub struct Address(u16);
 
 impl From<[u8; 3]> for Address {
     fn from(arr: [u8; 3]) -> Self {
-        Address::try_new(((arr[0] as u16) << 8) | ((arr[1] as u16) << 4) | (arr[2] as u16)).unwrap()
+        Address::new(((arr[0] as u16) << 8) | ((arr[1] as u16) << 4) | (arr[2] as u16))
     }
 }
 
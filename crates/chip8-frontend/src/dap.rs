@@ -0,0 +1,1162 @@
+//! Debug Adapter Protocol server
+//!
+//! [`DapServer`] lets editors speaking DAP debug a rom over stdio or a localhost TCP port. It
+//! drives a [`Debugger`], so breakpoints and stepping behave like in the command line
+//! debugger:
+//!
+//! - `launch` loads `program` (the rom from the command line by default) and the symbol map
+//!   given as `symbols`, or `<rom>.sym` next to the rom if it exists. `stopOnEntry` stops
+//!   before the first instruction
+//! - breakpoints on source lines need the symbol map, function breakpoints take a label or a
+//!   hex address and instruction breakpoints an address
+//! - `stepIn` runs one instruction, `next` steps over `CALL` and `stepOut` runs until the
+//!   subroutine returns
+//! - the scopes are the registers, the timers, the return addresses on the stack and the
+//!   memory, 16 bytes per variable
+//!
+//! There is a single thread, the machine, and every return address on the stack is a frame.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    iter,
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+};
+
+use anyhow::Context;
+use serde_json::{Value, json};
+use tklog::warn;
+
+use chip8_core::{Chip8, Chip8Error, FaultPolicy, Platform, Quirks, symbols::SymbolMap};
+
+use crate::debugger::{Debugger, RUN_LIMIT, StopReason, parse_address};
+
+/// Id of the only thread
+const THREAD_ID: i64 = 1;
+
+/// Cycles run between looking for requests while the machine is running
+const CHUNK_CYCLES: u64 = 10_000;
+
+/// Variables reference of the registers scope
+const REGISTERS: i64 = 1;
+/// Variables reference of the timers scope
+const TIMERS: i64 = 2;
+/// Variables reference of the stack scope
+const STACK: i64 = 3;
+/// Variables reference of the memory scope
+const MEMORY: i64 = 4;
+
+/// Bytes shown by one memory variable
+const MEMORY_ROW: usize = 16;
+
+/// Response body or the error message of a request
+type Reply = Result<Value, String>;
+
+/// Read one message, `None` at the end of the input
+///
+/// Messages are JSON preceded by a `Content-Length` header and an empty line
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return if length.is_none() {
+                Ok(None)
+            } else {
+                Err(ErrorKind::UnexpectedEof.into())
+            };
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            );
+        }
+    }
+
+    let length = length
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "missing Content-Length header"))?;
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Write one message with its header
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{content}", content.len())?;
+    writer.flush()
+}
+
+/// Format an address the way it is shown to the editor
+fn hex(address: u16) -> String {
+    format!("{address:#06X}")
+}
+
+/// Symbol map with the directory its source paths are relative to
+struct Symbols {
+    /// Labels and lines
+    map: SymbolMap,
+    /// Directory of the map file
+    dir: PathBuf,
+}
+
+impl Symbols {
+    /// Read a symbol map file
+    fn read(path: &Path) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("Reading {}: {e}", path.display()))?;
+        let map = text
+            .parse()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(Self {
+            map,
+            dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        })
+    }
+
+    /// File of the map the editor means with `path`
+    fn file_for(&self, path: &str) -> Option<&str> {
+        let path = Path::new(path);
+        self.map
+            .files()
+            .into_iter()
+            .find(|file| path == self.dir.join(file) || path.ends_with(file))
+    }
+
+    /// Path of a map file for the editor
+    fn path_of(&self, file: &str) -> String {
+        self.dir.join(file).display().to_string()
+    }
+}
+
+/// Settings of the DAP server
+#[derive(Debug, Clone)]
+pub struct DapOptions {
+    /// Rom launched when the launch request doesn't name one
+    pub rom: PathBuf,
+    /// Image of `rom` to launch instead of reading it, e.g. compiled from Octo source
+    pub program: Option<Vec<u8>>,
+    /// Platform to emulate
+    pub platform: Platform,
+    /// Quirks to run with
+    pub quirks: Quirks,
+    /// What happens when the program faults
+    pub fault_policy: FaultPolicy,
+    /// Symbol map used when the launch request doesn't name one, instead of `<rom>.sym`
+    pub symbols: Option<PathBuf>,
+    /// Symbols of `program`, with the source files next to `rom`. Used in place of
+    /// `<rom>.sym` when `symbols` isn't set
+    pub symbol_map: Option<SymbolMap>,
+    /// Serve on this localhost port instead of stdio
+    pub port: Option<u16>,
+}
+
+/// Debug adapter answering requests from an editor
+pub struct DapServer<W> {
+    /// Where responses and events go
+    writer: W,
+    /// Sequence number of the last message sent
+    seq: i64,
+    /// Settings, for the launch request
+    options: DapOptions,
+    /// Machine, once launched
+    debugger: Option<Debugger>,
+    /// Symbol map of the launched rom
+    symbols: Option<Symbols>,
+    /// Requested lines of each source file, kept to be resolved again after launching
+    source_breakpoints: BTreeMap<String, Vec<u32>>,
+    /// Requested function breakpoints, labels or addresses
+    function_breakpoints: Vec<String>,
+    /// Requested instruction breakpoints
+    instruction_breakpoints: Vec<u16>,
+    /// Events to send after the response of the current request
+    events: Vec<Value>,
+    /// Stop before the first instruction once configured
+    stop_on_entry: bool,
+    /// The machine is continuing
+    running: bool,
+    /// The last chunk of a continue ran out of cycles, PC was not checked for a breakpoint yet
+    chunked: bool,
+    /// The editor disconnected
+    finished: bool,
+}
+
+impl<W: Write> DapServer<W> {
+    /// Create a server that hasn't launched anything yet
+    pub fn new(options: DapOptions, writer: W) -> Self {
+        Self {
+            writer,
+            seq: 0,
+            options,
+            debugger: None,
+            symbols: None,
+            source_breakpoints: BTreeMap::new(),
+            function_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            events: Vec::new(),
+            stop_on_entry: false,
+            running: false,
+            chunked: false,
+            finished: false,
+        }
+    }
+
+    /// The machine is continuing, [`DapServer::resume`] should be called until it stops
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// The editor disconnected or terminated the session
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Answer a request, followed by the events it caused
+    pub fn handle(&mut self, request: &Value) -> io::Result<()> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let reply = self.dispatch(command, &request["arguments"]);
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": reply.is_ok(),
+        });
+        match reply {
+            Ok(Value::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = message.into(),
+        }
+        self.send(response)?;
+        self.flush_events()
+    }
+
+    /// Run the continuing machine for a while, sending the stop event if it stopped
+    pub fn resume(&mut self) -> io::Result<()> {
+        let Some(debugger) = self.debugger.as_mut() else {
+            self.running = false;
+            return Ok(());
+        };
+
+        // Breakpoints are not checked before the first instruction of a run, so the one the
+        // previous chunk ended on is checked here
+        let hit = if self.chunked {
+            debugger.breakpoints().hit(debugger.chip8())
+        } else {
+            None
+        };
+        let result = match hit {
+            Some(reason) => Ok(reason),
+            None => debugger.continue_for(CHUNK_CYCLES),
+        };
+        if matches!(result, Ok(StopReason::Limit)) {
+            self.chunked = true;
+            return Ok(());
+        }
+
+        self.running = false;
+        self.stopped(result);
+        self.flush_events()
+    }
+
+    /// Send a message with the next sequence number
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        write_message(&mut self.writer, &message)
+    }
+
+    /// Send the queued events
+    fn flush_events(&mut self) -> io::Result<()> {
+        for event in std::mem::take(&mut self.events) {
+            self.send(event)?;
+        }
+        Ok(())
+    }
+
+    /// Queue an event
+    fn event(&mut self, event: &str, body: Value) {
+        self.events.push(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    /// Queue the stop event of a run
+    fn stopped(&mut self, result: Result<StopReason, Chip8Error>) {
+        let (reason, text) = match result {
+            Ok(StopReason::Done) => ("step", None),
+            Ok(StopReason::Breakpoint(_) | StopReason::OpcodeBreakpoint(_)) => ("breakpoint", None),
+            Ok(StopReason::Limit) => ("pause", Some(format!("Stopped after {RUN_LIMIT} cycles"))),
+            Ok(StopReason::Exited) => {
+                self.event("exited", json!({ "exitCode": 0 }));
+                self.event("terminated", json!({}));
+                return;
+            }
+            Ok(StopReason::Halted) => (
+                "exception",
+                self.debugger()
+                    .ok()
+                    .and_then(|debugger| debugger.chip8().halted())
+                    .map(|fault| format!("Halted: {fault}")),
+            ),
+            Err(e) => ("exception", Some(e.to_string())),
+        };
+
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = text.into();
+        }
+        self.event("stopped", body);
+    }
+
+    /// Get the launched machine
+    fn debugger(&self) -> Result<&Debugger, String> {
+        self.debugger
+            .as_ref()
+            .ok_or_else(|| String::from("No rom launched"))
+    }
+
+    /// Get the launched machine for running it
+    fn debugger_mut(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger
+            .as_mut()
+            .ok_or_else(|| String::from("No rom launched"))
+    }
+
+    /// Run a request
+    fn dispatch(&mut self, command: &str, args: &Value) -> Reply {
+        match command {
+            "initialize" => {
+                self.event("initialized", json!({}));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsEvaluateForHovers": true,
+                    "supportsTerminateRequest": true,
+                }))
+            }
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setFunctionBreakpoints" => self.set_function_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(Value::Null),
+            "configurationDone" => {
+                self.debugger()?;
+                if self.stop_on_entry {
+                    self.event(
+                        "stopped",
+                        json!({ "reason": "entry", "threadId": THREAD_ID, "allThreadsStopped": true }),
+                    );
+                } else {
+                    self.running = true;
+                    self.chunked = false;
+                }
+                Ok(Value::Null)
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.stack_trace(args),
+            "scopes" => self.scopes(),
+            "variables" => self.variables(args),
+            "evaluate" => self.evaluate(args),
+            "continue" => {
+                self.debugger()?;
+                self.running = true;
+                self.chunked = false;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => {
+                let result = self.debugger_mut()?.step_over();
+                self.stopped(result);
+                Ok(Value::Null)
+            }
+            "stepIn" => {
+                let result = self.debugger_mut()?.step(1);
+                self.stopped(result);
+                Ok(Value::Null)
+            }
+            "stepOut" => {
+                let result = self
+                    .debugger_mut()?
+                    .finish()
+                    .ok_or_else(|| String::from("Not inside a subroutine"))?;
+                self.stopped(result);
+                Ok(Value::Null)
+            }
+            "pause" => {
+                if self.running {
+                    self.running = false;
+                    self.event(
+                        "stopped",
+                        json!({ "reason": "pause", "threadId": THREAD_ID, "allThreadsStopped": true }),
+                    );
+                }
+                Ok(Value::Null)
+            }
+            "disconnect" | "terminate" => {
+                self.running = false;
+                self.finished = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("Unsupported request `{command}`")),
+        }
+    }
+
+    /// Load the rom and its symbol map
+    fn launch(&mut self, args: &Value) -> Reply {
+        let requested = args["program"].as_str().map(PathBuf::from);
+        let rom = requested
+            .clone()
+            .unwrap_or_else(|| self.options.rom.clone());
+        let program = match (&requested, &self.options.program) {
+            (None, Some(program)) => program.clone(),
+            _ => fs::read(&rom).map_err(|e| format!("Reading {}: {e}", rom.display()))?,
+        };
+        let mut chip8 = Chip8::with_platform(self.options.platform, self.options.quirks);
+        chip8.set_fault_policy(self.options.fault_policy);
+        chip8.load_program(&program).map_err(|e| e.to_string())?;
+
+        let symbols = args["symbols"]
+            .as_str()
+            .map(PathBuf::from)
+            .or_else(|| self.options.symbols.clone());
+        self.symbols = match (symbols, &self.options.symbol_map) {
+            (Some(path), _) => Some(Symbols::read(&path)?),
+            (None, Some(map)) if requested.is_none() => Some(Symbols {
+                map: map.clone(),
+                dir: rom.parent().map(Path::to_path_buf).unwrap_or_default(),
+            }),
+            (None, _) => {
+                let path = rom.with_extension("sym");
+                path.exists().then(|| Symbols::read(&path)).transpose()?
+            }
+        };
+
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.debugger = Some(Debugger::new(chip8));
+        self.sync_breakpoints();
+        Ok(Value::Null)
+    }
+
+    /// Address of a source line, or why it can't have a breakpoint
+    fn resolve_line(&self, path: &str, line: u32) -> Result<(u16, u32), &'static str> {
+        let symbols = self.symbols.as_ref().ok_or("No symbol map loaded")?;
+        let file = symbols
+            .file_for(path)
+            .ok_or("Source is not in the symbol map")?;
+        symbols
+            .map
+            .line_address(file, line)
+            .ok_or("No code at or after this line")
+    }
+
+    /// Address of a function breakpoint, a label or a hex address
+    fn resolve_function(&self, name: &str) -> Option<u16> {
+        self.symbols
+            .as_ref()
+            .and_then(|symbols| symbols.map.label_address(name))
+            .or_else(|| parse_address(name).ok())
+    }
+
+    /// Replace the debugger breakpoints with every requested one that resolves
+    fn sync_breakpoints(&mut self) {
+        let lines = self.source_breakpoints.iter().flat_map(|(path, lines)| {
+            lines
+                .iter()
+                .filter_map(|line| self.resolve_line(path, *line).ok())
+                .map(|(address, _)| address)
+        });
+        let functions = self
+            .function_breakpoints
+            .iter()
+            .filter_map(|name| self.resolve_function(name));
+        let addresses: Vec<u16> = lines
+            .chain(functions)
+            .chain(self.instruction_breakpoints.iter().copied())
+            .collect();
+
+        if let Some(debugger) = self.debugger.as_mut() {
+            let breakpoints = debugger.breakpoints_mut();
+            breakpoints.clear();
+            for address in addresses {
+                breakpoints.add(address);
+            }
+        }
+    }
+
+    /// Set the line breakpoints of one source file
+    fn set_breakpoints(&mut self, args: &Value) -> Reply {
+        let path = args["source"]["path"]
+            .as_str()
+            .ok_or_else(|| String::from("Missing source path"))?
+            .to_owned();
+        let lines: Vec<u32> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+            .map(|line| line as u32)
+            .collect();
+
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|line| match self.resolve_line(&path, *line) {
+                Ok((address, line)) => json!({
+                    "verified": true,
+                    "line": line,
+                    "instructionReference": hex(address),
+                }),
+                Err(message) => json!({ "verified": false, "line": line, "message": message }),
+            })
+            .collect();
+
+        self.source_breakpoints.insert(path, lines);
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Replace the function breakpoints
+    fn set_function_breakpoints(&mut self, args: &Value) -> Reply {
+        self.function_breakpoints = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|breakpoint| breakpoint["name"].as_str())
+            .map(str::to_owned)
+            .collect();
+
+        let breakpoints: Vec<Value> = self
+            .function_breakpoints
+            .iter()
+            .map(|name| match self.resolve_function(name) {
+                Some(address) => json!({ "verified": true, "instructionReference": hex(address) }),
+                None => json!({ "verified": false, "message": "Unknown label" }),
+            })
+            .collect();
+
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Replace the instruction breakpoints
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Reply {
+        let mut breakpoints = Vec::new();
+        self.instruction_breakpoints.clear();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let address = breakpoint["instructionReference"]
+                .as_str()
+                .and_then(|reference| parse_address(reference).ok())
+                .map(|address| {
+                    address.wrapping_add_signed(breakpoint["offset"].as_i64().unwrap_or(0) as i16)
+                });
+            breakpoints.push(match address {
+                Some(address) => {
+                    self.instruction_breakpoints.push(address);
+                    json!({ "verified": true, "instructionReference": hex(address) })
+                }
+                None => json!({ "verified": false, "message": "Invalid instruction reference" }),
+            });
+        }
+
+        self.sync_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Describe the frame of the instruction at `address`
+    fn frame(&self, id: usize, address: u16) -> Value {
+        let symbols = self.symbols.as_ref();
+        let name = symbols
+            .and_then(|symbols| symbols.map.enclosing_label(address))
+            .map_or_else(|| hex(address), |(label, _)| label.to_owned());
+
+        let mut frame = json!({
+            "id": id,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": hex(address),
+        });
+        if let Some(symbols) = symbols
+            && let Some(source) = symbols.map.source_line(address)
+        {
+            let name = Path::new(&source.file)
+                .file_name()
+                .map_or_else(|| source.file.clone(), |name| name.to_string_lossy().into());
+            frame["source"] = json!({ "name": name, "path": symbols.path_of(&source.file) });
+            frame["line"] = source.line.into();
+            frame["column"] = 1.into();
+        }
+        frame
+    }
+
+    /// PC, then the call of every subroutine on the stack, innermost first
+    fn stack_trace(&self, args: &Value) -> Reply {
+        let cpu = self.debugger()?.chip8().cpu();
+        // return addresses point after the 2 byte CALL
+        let addresses = iter::once(cpu.program_counter())
+            .chain(cpu.stack().iter().rev().map(|ret| ret.wrapping_sub(2)));
+        let frames: Vec<Value> = addresses
+            .enumerate()
+            .map(|(id, address)| self.frame(id, address))
+            .collect();
+
+        let start = args["startFrame"].as_u64().unwrap_or(0) as usize;
+        let levels = match args["levels"].as_u64() {
+            Some(levels) if levels > 0 => levels as usize,
+            _ => frames.len(),
+        };
+        Ok(json!({
+            "stackFrames": frames.iter().skip(start).take(levels).collect::<Vec<_>>(),
+            "totalFrames": frames.len(),
+        }))
+    }
+
+    /// The same scopes for every frame, the machine has no locals
+    fn scopes(&self) -> Reply {
+        let rows = self
+            .debugger()?
+            .chip8()
+            .memory()
+            .size()
+            .div_ceil(MEMORY_ROW);
+        Ok(json!({ "scopes": [
+            { "name": "Registers", "presentationHint": "registers", "variablesReference": REGISTERS, "expensive": false },
+            { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+            { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            { "name": "Memory", "variablesReference": MEMORY, "indexedVariables": rows, "expensive": true },
+        ]}))
+    }
+
+    /// Variables of a scope, the memory scope is paged with `start` and `count`
+    fn variables(&self, args: &Value) -> Reply {
+        let debugger = self.debugger()?;
+        let chip8 = debugger.chip8();
+        let cpu = chip8.cpu();
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+
+        let variables: Vec<Value> = match args["variablesReference"].as_i64() {
+            Some(REGISTERS) => {
+                let mut variables: Vec<Value> = cpu
+                    .registers()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| variable(format!("V{i:X}"), format!("{value:#04X}")))
+                    .collect();
+                let mut address = variable("I".into(), hex(cpu.address()));
+                address["memoryReference"] = hex(cpu.address()).into();
+                variables.push(address);
+                variables.push(variable("PC".into(), hex(cpu.program_counter())));
+                variables.push(variable("SP".into(), cpu.stack_pointer().to_string()));
+                variables
+            }
+            Some(TIMERS) => vec![
+                variable("DT".into(), cpu.delay_timer().to_string()),
+                variable("ST".into(), cpu.sound_timer().to_string()),
+                variable("cycles".into(), debugger.clock().cycles().to_string()),
+                variable("frames".into(), debugger.clock().frames().to_string()),
+            ],
+            Some(STACK) => cpu
+                .stack()
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(depth, address)| {
+                    let label = self
+                        .symbols
+                        .as_ref()
+                        .and_then(|symbols| symbols.map.enclosing_label(*address));
+                    let value = match label {
+                        Some((label, _)) => format!("{} ({label})", hex(*address)),
+                        None => hex(*address),
+                    };
+                    variable(format!("#{depth}"), value)
+                })
+                .collect(),
+            Some(MEMORY) => {
+                let bytes = chip8.memory().bytes();
+                let start = args["start"].as_u64().unwrap_or(0) as usize;
+                let count = args["count"].as_u64().unwrap_or(u64::MAX) as usize;
+                bytes
+                    .chunks(MEMORY_ROW)
+                    .enumerate()
+                    .skip(start)
+                    .take(count)
+                    .map(|(row, chunk)| {
+                        let address = hex((row * MEMORY_ROW) as u16);
+                        let value = chunk
+                            .iter()
+                            .map(|byte| format!("{byte:02X}"))
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        let mut row = variable(address.clone(), value);
+                        row["memoryReference"] = address.into();
+                        row
+                    })
+                    .collect()
+            }
+            _ => return Err(String::from("Unknown variables reference")),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Value of a register name or a label, for hovers and the debug console
+    fn evaluate(&self, args: &Value) -> Reply {
+        let cpu = self.debugger()?.chip8().cpu();
+        let expression = args["expression"].as_str().unwrap_or_default().trim();
+        let register = expression
+            .strip_prefix(['v', 'V'])
+            .filter(|digit| digit.len() == 1)
+            .and_then(|digit| usize::from_str_radix(digit, 16).ok());
+
+        let result = match (expression.to_ascii_lowercase().as_str(), register) {
+            (_, Some(x)) => format!("{:#04X}", cpu.registers()[x]),
+            ("i", _) => hex(cpu.address()),
+            ("pc", _) => hex(cpu.program_counter()),
+            ("sp", _) => cpu.stack_pointer().to_string(),
+            ("dt", _) => cpu.delay_timer().to_string(),
+            ("st", _) => cpu.sound_timer().to_string(),
+            _ => self
+                .symbols
+                .as_ref()
+                .and_then(|symbols| symbols.map.label_address(expression))
+                .map(hex)
+                .ok_or_else(|| format!("Unknown register or label `{expression}`"))?,
+        };
+        Ok(json!({ "result": result, "variablesReference": 0 }))
+    }
+}
+
+/// Answer the requests read from `reader` until the editor disconnects or the input ends
+///
+/// Requests are read on their own thread, so a running machine can be paused
+pub fn serve(
+    options: &DapOptions,
+    mut reader: impl BufRead + Send + 'static,
+    writer: impl Write,
+) -> anyhow::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            match read_message(&mut reader) {
+                Ok(Some(message)) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("Invalid DAP message:", e.to_string());
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut server = DapServer::new(options.clone(), writer);
+    while !server.is_finished() {
+        let request = if server.is_running() {
+            match receiver.try_recv() {
+                Ok(request) => Some(request),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            }
+        };
+
+        if let Some(request) = request {
+            server.handle(&request)?;
+        }
+        if server.is_running() {
+            server.resume()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Serve one editor over stdio, or over the localhost port from the options
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn run_dap(options: &DapOptions) -> anyhow::Result<()> {
+    match options.port {
+        Some(port) => {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+                .with_context(|| format!("listening for DAP on port {port}"))?;
+            eprintln!("DAP server listening on {}", listener.local_addr()?);
+            let (stream, _) = listener.accept()?;
+            let reader = BufReader::new(stream.try_clone()?);
+            serve(options, reader, stream)
+        }
+        None => serve(options, BufReader::new(io::stdin()), io::stdout()),
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Calls a subroutine drawing a sprite, then loops forever
+    const PROGRAM: [u8; 12] = [
+        0x22, 0x06, // 200: CALL 206
+        0x60, 0x01, // 202: LD V0, 1
+        0x12, 0x04, // 204: JP 204
+        0x61, 0x02, // 206: LD V1, 2
+        0xD0, 0x15, // 208: DRW V0, V1, 5
+        0x00, 0xEE, // 20A: RET
+    ];
+
+    /// Symbol map of [`PROGRAM`], line 5 is the `draw:` label
+    const SYMBOLS: &str = "\
+label 0x0200 start
+label 0x0206 draw
+line 0x0200 1 game.asm
+line 0x0202 2 game.asm
+line 0x0204 3 game.asm
+line 0x0206 6 game.asm
+line 0x0208 7 game.asm
+line 0x020A 8 game.asm
+";
+
+    /// Write a rom, and its symbol map if given, into a fresh directory
+    fn write_rom(name: &str, program: &[u8], symbols: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chip8-dap-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8");
+        fs::write(&rom, program).unwrap();
+        if let Some(symbols) = symbols {
+            fs::write(dir.join("game.sym"), symbols).unwrap();
+        }
+        rom
+    }
+
+    fn options(rom: PathBuf) -> DapOptions {
+        DapOptions {
+            rom,
+            platform: Platform::SuperChip,
+            quirks: Quirks::default(),
+            fault_policy: FaultPolicy::default(),
+            program: None,
+            symbols: None,
+            symbol_map: None,
+            port: None,
+        }
+    }
+
+    fn read_all(bytes: Vec<u8>) -> Vec<Value> {
+        let mut cursor = Cursor::new(bytes);
+        iter::from_fn(|| read_message(&mut cursor).unwrap()).collect()
+    }
+
+    /// Editor sending requests one after the other
+    struct Script {
+        server: DapServer<Vec<u8>>,
+        seq: i64,
+    }
+
+    impl Script {
+        fn new(options: DapOptions) -> Self {
+            Self {
+                server: DapServer::new(options, Vec::new()),
+                seq: 0,
+            }
+        }
+
+        /// Send a request without running the machine
+        fn send(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            self.server.handle(&request).unwrap();
+            read_all(std::mem::take(&mut self.server.writer))
+        }
+
+        /// Send a request and run the machine until it stops
+        fn request(&mut self, command: &str, arguments: Value) -> Vec<Value> {
+            let mut messages = self.send(command, arguments);
+            while self.server.is_running() {
+                self.server.resume().unwrap();
+            }
+            messages.extend(read_all(std::mem::take(&mut self.server.writer)));
+            messages
+        }
+
+        /// Body of the response to a request that must succeed
+        fn body(&mut self, command: &str, arguments: Value) -> Value {
+            let messages = self.request(command, arguments);
+            assert_eq!(messages[0]["success"], true, "{messages:?}");
+            messages[0]["body"].clone()
+        }
+    }
+
+    fn stop_reason(messages: &[Value]) -> Option<&str> {
+        messages
+            .iter()
+            .find(|message| message["event"] == "stopped")
+            .and_then(|message| message["body"]["reason"].as_str())
+    }
+
+    fn frame_lines(body: &Value) -> Vec<(String, u64)> {
+        body["stackFrames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| {
+                (
+                    frame["name"].as_str().unwrap().to_owned(),
+                    frame["line"].as_u64().unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &json!({ "seq": 1 })).unwrap();
+        assert_eq!(bytes, b"Content-Length: 9\r\n\r\n{\"seq\":1}");
+
+        let mut input = Cursor::new(b"Content-Type: json\r\ncontent-length: 2\r\n\r\n{}".to_vec());
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        let mut input = Cursor::new(b"Content-Type: json\r\n\r\n{}".to_vec());
+        assert!(read_message(&mut input).is_err());
+    }
+
+    #[test]
+    fn test_source_breakpoints_and_stepping() {
+        let rom = write_rom("source", &PROGRAM, Some(SYMBOLS));
+        let source = rom.with_file_name("game.asm").display().to_string();
+        let mut script = Script::new(options(rom));
+
+        let messages = script.request("initialize", json!({ "adapterID": "chip8" }));
+        assert_eq!(
+            messages[0]["body"]["supportsConfigurationDoneRequest"],
+            true
+        );
+        assert_eq!(messages[1]["event"], "initialized");
+
+        script.body("launch", json!({ "stopOnEntry": true }));
+        let body = script.body(
+            "setBreakpoints",
+            json!({ "source": { "path": source }, "breakpoints": [{ "line": 7 }, { "line": 4 }] }),
+        );
+        assert_eq!(
+            body["breakpoints"],
+            json!([
+                { "verified": true, "line": 7, "instructionReference": "0x0208" },
+                { "verified": true, "line": 6, "instructionReference": "0x0206" },
+            ])
+        );
+
+        let messages = script.request("configurationDone", json!({}));
+        assert_eq!(stop_reason(&messages), Some("entry"));
+        let body = script.body("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(frame_lines(&body), [("start".to_owned(), 1)]);
+        assert_eq!(body["stackFrames"][0]["source"]["path"], source);
+
+        let messages = script.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), Some("breakpoint"));
+        let body = script.body("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(
+            frame_lines(&body),
+            [("draw".to_owned(), 6), ("start".to_owned(), 1)]
+        );
+
+        let messages = script.request("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), Some("step"));
+        let messages = script.request("stepOut", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), Some("step"));
+        let body = script.body("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(frame_lines(&body), [("start".to_owned(), 2)]);
+
+        let body = script.body("evaluate", json!({ "expression": "draw" }));
+        assert_eq!(body["result"], "0x0206");
+
+        // the rom ends in an endless loop, it only stops when paused
+        script.body(
+            "setBreakpoints",
+            json!({ "source": { "path": source }, "breakpoints": [] }),
+        );
+        let messages = script.send("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), None);
+        for _ in 0..3 {
+            script.server.resume().unwrap();
+        }
+        assert!(script.server.is_running());
+        let messages = script.request("pause", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), Some("pause"));
+        let body = script.body("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(frame_lines(&body), [("start".to_owned(), 3)]);
+    }
+
+    #[test]
+    fn test_program_in_memory() {
+        // nothing is written next to the source, only the directory is used
+        let rom = write_rom("memory", &[], None).with_file_name("game.8o");
+        let source = rom.with_file_name("game.asm").display().to_string();
+        let mut script = Script::new(DapOptions {
+            program: Some(PROGRAM.to_vec()),
+            symbol_map: Some(SYMBOLS.parse().unwrap()),
+            ..options(rom)
+        });
+
+        script.body("launch", json!({ "stopOnEntry": true }));
+        let body = script.body(
+            "setBreakpoints",
+            json!({ "source": { "path": source }, "breakpoints": [{ "line": 6 }] }),
+        );
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+        script.request("configurationDone", json!({}));
+        let messages = script.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), Some("breakpoint"));
+        let body = script.body("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(body["stackFrames"][0]["source"]["path"], source);
+    }
+
+    #[test]
+    fn test_address_breakpoints_and_variables() {
+        let mut script = Script::new(options(write_rom("address", &PROGRAM, None)));
+        script.request("initialize", json!({}));
+        script.body("launch", json!({}));
+
+        let body = script.body(
+            "setBreakpoints",
+            json!({ "source": { "path": "game.asm" }, "breakpoints": [{ "line": 7 }] }),
+        );
+        assert_eq!(body["breakpoints"][0]["verified"], false);
+        let body = script.body(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x0206", "offset": 2 }] }),
+        );
+        assert_eq!(body["breakpoints"][0]["instructionReference"], "0x0208");
+        let body = script.body(
+            "setFunctionBreakpoints",
+            json!({ "breakpoints": [{ "name": "20A" }, { "name": "draw" }] }),
+        );
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+        assert_eq!(body["breakpoints"][1]["verified"], false);
+
+        let messages = script.request("configurationDone", json!({}));
+        assert_eq!(stop_reason(&messages), Some("breakpoint"));
+        let body = script.body("stackTrace", json!({ "threadId": THREAD_ID }));
+        assert_eq!(
+            frame_lines(&body),
+            [("0x0208".to_owned(), 0), ("0x0200".to_owned(), 0)]
+        );
+
+        let body = script.body("scopes", json!({ "frameId": 0 }));
+        assert_eq!(body["scopes"][3]["indexedVariables"], 256);
+
+        let body = script.body("variables", json!({ "variablesReference": REGISTERS }));
+        assert_eq!(body["variables"][1]["value"], "0x02");
+        assert_eq!(body["variables"][17]["name"], "PC");
+        assert_eq!(body["variables"][17]["value"], "0x0208");
+        let body = script.body("variables", json!({ "variablesReference": TIMERS }));
+        assert_eq!(body["variables"][2]["value"], "2");
+        let body = script.body("variables", json!({ "variablesReference": STACK }));
+        assert_eq!(
+            body["variables"],
+            json!([{ "name": "#0", "value": "0x0202", "variablesReference": 0 }])
+        );
+        let body = script.body(
+            "variables",
+            json!({ "variablesReference": MEMORY, "start": 32, "count": 1 }),
+        );
+        assert_eq!(body["variables"][0]["name"], "0x0200");
+        assert_eq!(
+            body["variables"][0]["value"],
+            "22 06 60 01 12 04 61 02 D0 15 00 EE 00 00 00 00"
+        );
+
+        let body = script.body("evaluate", json!({ "expression": "v1" }));
+        assert_eq!(body["result"], "0x02");
+        let messages = script.request("evaluate", json!({ "expression": "draw" }));
+        assert_eq!(messages[0]["success"], false);
+
+        let messages = script.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), Some("breakpoint"));
+        let messages = script.request("stepIn", json!({ "threadId": THREAD_ID }));
+        assert_eq!(stop_reason(&messages), Some("step"));
+        let messages = script.request("stepOut", json!({ "threadId": THREAD_ID }));
+        assert_eq!(messages[0]["message"], "Not inside a subroutine");
+        let messages = script.request("restartFrame", json!({}));
+        assert_eq!(messages[0]["success"], false);
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut script = Script::new(options(write_rom("exit", &[0x00, 0xFD], None)));
+        script.request("initialize", json!({}));
+        script.body("launch", json!({}));
+
+        let messages = script.request("configurationDone", json!({}));
+        let events: Vec<&Value> = messages.iter().map(|message| &message["event"]).collect();
+        assert_eq!(
+            events,
+            [&Value::Null, &json!("exited"), &json!("terminated")]
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let requests = [
+            json!({ "seq": 1, "type": "request", "command": "initialize", "arguments": {} }),
+            json!({ "seq": 2, "type": "request", "command": "launch", "arguments": { "stopOnEntry": true } }),
+            json!({ "seq": 3, "type": "request", "command": "configurationDone" }),
+            json!({ "seq": 4, "type": "request", "command": "threads" }),
+            json!({ "seq": 5, "type": "request", "command": "disconnect" }),
+        ];
+        let mut input = Vec::new();
+        for request in &requests {
+            write_message(&mut input, request).unwrap();
+        }
+
+        let mut output = Vec::new();
+        let options = options(write_rom("serve", &PROGRAM, None));
+        serve(&options, Cursor::new(input), &mut output).unwrap();
+
+        let messages = read_all(output);
+        let seqs: Vec<u64> = messages
+            .iter()
+            .map(|message| message["seq"].as_u64().unwrap())
+            .collect();
+        assert_eq!(seqs, [1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(stop_reason(&messages), Some("entry"));
+        assert_eq!(messages[5]["body"]["threads"][0]["name"], "CHIP-8");
+        assert_eq!(messages[6]["command"], "disconnect");
+    }
+}
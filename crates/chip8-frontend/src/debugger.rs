@@ -0,0 +1,1172 @@
+//! Interactive debugger
+//!
+//! [`Debugger`] wraps a [`Chip8`] on a [`VirtualClock`] and adds address and opcode
+//! breakpoints, stepping over calls and running to the end of a subroutine. [`repl`] drives it
+//! with gdb-like commands read line by line, so it works in a plain terminal or from a script:
+//!
+//! ```text
+//! (chip8) break 2A4
+//! (chip8) break op DXYN
+//! (chip8) continue
+//! Breakpoint at 0x2A4
+//! => 0x2A4  LD I, 0x2EA
+//! (chip8) regs
+//! ```
+//!
+//! Addresses and bytes are hexadecimal with an optional `0x` prefix, counts are decimal.
+//! An empty line repeats the previous command.
+
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display, Write as _},
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::Context;
+use thiserror::Error;
+
+use chip8_core::{
+    Chip8, Chip8Error, FaultPolicy, Instruction, Platform, Quirks, clock::VirtualClock,
+};
+
+use crate::headless::dump_ascii;
+
+/// Cycles a command runs at most before giving control back, about half an hour of emulated time
+pub const RUN_LIMIT: u64 = 1_000_000;
+
+/// Opcode breakpoint, hex digits must match and the other characters match any nibble
+///
+/// `DXYN` breaks on every draw, `00E0` on clears and `2...` on every call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OpcodePattern {
+    /// Nibbles that have to match
+    value: u16,
+    /// Set for every nibble that has to match
+    mask: u16,
+}
+
+impl OpcodePattern {
+    /// Check if the opcode matches the pattern
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+impl FromStr for OpcodePattern {
+    type Err = CommandError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = s.chars().count() == 4
+            && s.chars()
+                .all(|c| c.is_ascii_hexdigit() || "NXYKnxyk.?".contains(c));
+        if !valid {
+            return Err(CommandError::InvalidPattern(s.to_owned()));
+        }
+
+        let (value, mask) = s.chars().fold((0, 0), |(value, mask), c| {
+            let (nibble, nibble_mask) = c.to_digit(16).map_or((0, 0), |digit| (digit as u16, 0xF));
+            ((value << 4) | nibble, (mask << 4) | nibble_mask)
+        });
+
+        Ok(Self { value, mask })
+    }
+}
+
+impl Display for OpcodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for shift in [12, 8, 4, 0] {
+            if (self.mask >> shift) & 0xF == 0 {
+                write!(f, ".")?;
+            } else {
+                write!(f, "{:X}", (self.value >> shift) & 0xF)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why the machine stopped running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The step, step over or finish completed
+    Done,
+    /// PC reached an address breakpoint
+    Breakpoint(u16),
+    /// The instruction at PC matched an opcode breakpoint
+    OpcodeBreakpoint(OpcodePattern),
+    /// The program exited with `00FD`
+    Exited,
+    /// The machine halted on a fault, see [`Chip8::halted`]
+    Halted,
+    /// [`RUN_LIMIT`] or the requested number of cycles ran out
+    Limit,
+}
+
+/// Address and opcode breakpoints
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+    /// Address breakpoints
+    addresses: BTreeSet<u16>,
+    /// Opcode breakpoints, in the order they were added
+    opcodes: Vec<OpcodePattern>,
+}
+
+impl Breakpoints {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Break when PC reaches the address, returns `false` if there already was a breakpoint
+    pub fn add(&mut self, address: u16) -> bool {
+        self.addresses.insert(address)
+    }
+
+    /// Remove the breakpoint at the address, returns `false` if there was none
+    pub fn remove(&mut self, address: u16) -> bool {
+        self.addresses.remove(&address)
+    }
+
+    /// Check for a breakpoint at the address
+    pub fn contains(&self, address: u16) -> bool {
+        self.addresses.contains(&address)
+    }
+
+    /// Address breakpoints in ascending order
+    pub fn addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.addresses.iter().copied()
+    }
+
+    /// Break before running an instruction matching the pattern, returns `false` if it was
+    /// already set
+    pub fn add_opcode(&mut self, pattern: OpcodePattern) -> bool {
+        if self.opcodes.contains(&pattern) {
+            return false;
+        }
+        self.opcodes.push(pattern);
+        true
+    }
+
+    /// Remove an opcode breakpoint, returns `false` if it was not set
+    pub fn remove_opcode(&mut self, pattern: OpcodePattern) -> bool {
+        let count = self.opcodes.len();
+        self.opcodes.retain(|p| *p != pattern);
+        self.opcodes.len() != count
+    }
+
+    /// Opcode breakpoints in the order they were added
+    pub fn opcodes(&self) -> &[OpcodePattern] {
+        &self.opcodes
+    }
+
+    /// Remove every breakpoint
+    pub fn clear(&mut self) {
+        self.addresses.clear();
+        self.opcodes.clear();
+    }
+
+    /// Breakpoint set on the instruction at PC
+    pub fn hit(&self, chip8: &Chip8) -> Option<StopReason> {
+        let pc = chip8.cpu().program_counter();
+        if self.contains(pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+
+        let opcode = chip8.memory().read_word(pc).ok()?;
+        self.opcodes
+            .iter()
+            .find(|pattern| pattern.matches(opcode))
+            .map(|pattern| StopReason::OpcodeBreakpoint(*pattern))
+    }
+}
+
+/// Chip8 machine with breakpoints
+pub struct Debugger {
+    /// Machine being debugged
+    chip8: Chip8,
+    /// Clock ticking the timers as instructions run
+    clock: VirtualClock,
+    /// Where running stops
+    breakpoints: Breakpoints,
+}
+
+impl Debugger {
+    /// Debug a ready machine, without any breakpoints
+    pub fn new(chip8: Chip8) -> Self {
+        Self {
+            chip8,
+            clock: VirtualClock::new(),
+            breakpoints: Breakpoints::new(),
+        }
+    }
+
+    /// Get the machine
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    /// Get the machine for changes, e.g. pressing keys
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    /// Get the clock
+    pub fn clock(&self) -> &VirtualClock {
+        &self.clock
+    }
+
+    /// Get the breakpoints
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    /// Get the breakpoints for changes
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    /// Run `count` instructions, stopping early at breakpoints after the first one
+    pub fn step(&mut self, count: u64) -> Result<StopReason, Chip8Error> {
+        let mut left = count;
+        self.run_until(count, |_| {
+            left -= 1;
+            left == 0
+        })
+    }
+
+    /// Step, running a whole subroutine if PC is on a `CALL`
+    pub fn step_over(&mut self) -> Result<StopReason, Chip8Error> {
+        let pc = self.chip8.cpu().program_counter();
+        // an opcode that doesn't decode is stepped, so it faults like any other
+        match self.chip8.instruction_at(pc) {
+            Ok(instruction @ Instruction::CallSubroutine { .. }) => {
+                let depth = self.chip8.cpu().stack().len();
+                let next = pc.wrapping_add(instruction.size());
+                self.run_until(RUN_LIMIT, |chip8| {
+                    chip8.cpu().program_counter() == next && chip8.cpu().stack().len() == depth
+                })
+            }
+            _ => self.step(1),
+        }
+    }
+
+    /// Run until the current subroutine returns, `None` if PC is not inside one
+    pub fn finish(&mut self) -> Option<Result<StopReason, Chip8Error>> {
+        let depth = self.chip8.cpu().stack().len();
+        if depth == 0 {
+            return None;
+        }
+        Some(self.run_until(RUN_LIMIT, |chip8| chip8.cpu().stack().len() < depth))
+    }
+
+    /// Run until a breakpoint or the program exits, for at most `limit` cycles
+    pub fn continue_for(&mut self, limit: u64) -> Result<StopReason, Chip8Error> {
+        self.run_until(limit, |_| false)
+    }
+
+    /// Step until `done` returns true after an instruction, a breakpoint is reached or
+    /// `limit` instructions ran
+    ///
+    /// Breakpoints are not checked before the first instruction, so running from a
+    /// breakpoint moves on
+    fn run_until(
+        &mut self,
+        limit: u64,
+        mut done: impl FnMut(&Chip8) -> bool,
+    ) -> Result<StopReason, Chip8Error> {
+        for i in 0..limit {
+            if self.chip8.has_exited() {
+                return Ok(StopReason::Exited);
+            }
+            if self.chip8.halted().is_some() {
+                return Ok(StopReason::Halted);
+            }
+            if i > 0
+                && let Some(reason) = self.breakpoints.hit(&self.chip8)
+            {
+                return Ok(reason);
+            }
+
+            for _ in 0..self.clock.step(&mut self.chip8)? {
+                self.chip8.tick_timers();
+            }
+
+            if done(&self.chip8) {
+                return Ok(if self.chip8.has_exited() {
+                    StopReason::Exited
+                } else {
+                    StopReason::Done
+                });
+            }
+        }
+
+        Ok(StopReason::Limit)
+    }
+}
+
+/// Error in a debugger command
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CommandError {
+    #[error("Unknown command `{0}`, try `help`")]
+    /// The command name is not known
+    UnknownCommand(String),
+    #[error("Missing {0}")]
+    /// A required argument was not given
+    MissingArgument(&'static str),
+    #[error("Invalid number `{0}`")]
+    /// An argument is not a number or out of range
+    InvalidNumber(String),
+    #[error("Invalid opcode pattern `{0}`, expected four hex digits or N/X/Y/K/. wildcards")]
+    /// An opcode pattern is malformed
+    InvalidPattern(String),
+    #[error("Too many arguments")]
+    /// More arguments were given than the command takes
+    TooManyArguments,
+}
+
+/// Parse a hexadecimal address with an optional `0x` prefix
+pub fn parse_address(s: &str) -> Result<u16, CommandError> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| CommandError::InvalidNumber(s.to_owned()))
+}
+
+/// Parse a hexadecimal byte with an optional `0x` prefix
+fn parse_byte(s: &str) -> Result<u8, CommandError> {
+    let value = parse_address(s)?;
+    u8::try_from(value).map_err(|_| CommandError::InvalidNumber(s.to_owned()))
+}
+
+/// Parse a hex key, 0 to F
+fn parse_key(s: &str) -> Result<u8, CommandError> {
+    parse_byte(s)
+        .ok()
+        .filter(|key| *key <= 0xF)
+        .ok_or_else(|| CommandError::InvalidNumber(s.to_owned()))
+}
+
+/// Parse a decimal count
+fn parse_count(s: &str) -> Result<u64, CommandError> {
+    s.parse()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| CommandError::InvalidNumber(s.to_owned()))
+}
+
+/// Debugger command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `break ADDR`, `b`
+    Break(u16),
+    /// `break op PATTERN`
+    BreakOpcode(OpcodePattern),
+    /// `delete ADDR`
+    Delete(u16),
+    /// `delete op PATTERN`
+    DeleteOpcode(OpcodePattern),
+    /// `delete` without arguments
+    DeleteAll,
+    /// `breakpoints`, `info`
+    Breakpoints,
+    /// `step [COUNT]`, `s`
+    Step(u64),
+    /// `next`, `n`
+    Next,
+    /// `finish`, `fin`
+    Finish,
+    /// `continue [CYCLES]`, `c`
+    Continue(u64),
+    /// `regs`, `r`
+    Registers,
+    /// `stack`, `bt`
+    Stack,
+    /// `x ADDR [LEN]`
+    Examine {
+        /// First address
+        start: u16,
+        /// Number of bytes
+        len: u16,
+    },
+    /// `set ADDR BYTE...`
+    Set {
+        /// First address
+        start: u16,
+        /// Bytes to write
+        bytes: Vec<u8>,
+    },
+    /// `disas [ADDR] [COUNT]`, `d`
+    Disassemble {
+        /// First address, around PC if `None`
+        start: Option<u16>,
+        /// Number of instructions
+        count: u64,
+    },
+    /// `screen`
+    Screen,
+    /// `press KEY`
+    Press(u8),
+    /// `release KEY`
+    Release(u8),
+    /// `help`, `h`
+    Help,
+    /// `quit`, `q`
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+
+        let max_args = |count: usize| {
+            if args.len() > count {
+                Err(CommandError::TooManyArguments)
+            } else {
+                Ok(())
+            }
+        };
+        let arg = |i: usize, what: &'static str| {
+            args.get(i)
+                .copied()
+                .ok_or(CommandError::MissingArgument(what))
+        };
+
+        let command = match name {
+            "break" | "b" => match args.first() {
+                Some(&"op") => {
+                    max_args(2)?;
+                    Command::BreakOpcode(arg(1, "opcode pattern")?.parse()?)
+                }
+                _ => {
+                    max_args(1)?;
+                    Command::Break(parse_address(arg(0, "address")?)?)
+                }
+            },
+            "delete" => match args.first() {
+                None => Command::DeleteAll,
+                Some(&"op") => {
+                    max_args(2)?;
+                    Command::DeleteOpcode(arg(1, "opcode pattern")?.parse()?)
+                }
+                Some(address) => {
+                    max_args(1)?;
+                    Command::Delete(parse_address(address)?)
+                }
+            },
+            "breakpoints" | "info" => {
+                max_args(0)?;
+                Command::Breakpoints
+            }
+            "step" | "s" => {
+                max_args(1)?;
+                Command::Step(args.first().map_or(Ok(1), |count| parse_count(count))?)
+            }
+            "next" | "n" => {
+                max_args(0)?;
+                Command::Next
+            }
+            "finish" | "fin" => {
+                max_args(0)?;
+                Command::Finish
+            }
+            "continue" | "c" => {
+                max_args(1)?;
+                Command::Continue(
+                    args.first()
+                        .map_or(Ok(RUN_LIMIT), |count| parse_count(count))?,
+                )
+            }
+            "regs" | "r" => {
+                max_args(0)?;
+                Command::Registers
+            }
+            "stack" | "bt" => {
+                max_args(0)?;
+                Command::Stack
+            }
+            "x" => {
+                max_args(2)?;
+                Command::Examine {
+                    start: parse_address(arg(0, "address")?)?,
+                    len: args.get(1).map_or(Ok(64), |len| {
+                        parse_count(len).and_then(|len| {
+                            u16::try_from(len)
+                                .map_err(|_| CommandError::InvalidNumber(len.to_string()))
+                        })
+                    })?,
+                }
+            }
+            "set" => Command::Set {
+                start: parse_address(arg(0, "address")?)?,
+                bytes: {
+                    arg(1, "bytes")?;
+                    args[1..]
+                        .iter()
+                        .map(|byte| parse_byte(byte))
+                        .collect::<Result<_, _>>()?
+                },
+            },
+            "disas" | "d" => {
+                max_args(2)?;
+                Command::Disassemble {
+                    start: args.first().map(|start| parse_address(start)).transpose()?,
+                    count: args.get(1).map_or(Ok(10), |count| parse_count(count))?,
+                }
+            }
+            "screen" => {
+                max_args(0)?;
+                Command::Screen
+            }
+            "press" => {
+                max_args(1)?;
+                Command::Press(parse_key(arg(0, "key")?)?)
+            }
+            "release" => {
+                max_args(1)?;
+                Command::Release(parse_key(arg(0, "key")?)?)
+            }
+            "help" | "h" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(CommandError::UnknownCommand(name.to_owned())),
+        };
+
+        Ok(command)
+    }
+}
+
+/// Text printed by `help`
+const HELP: &str = "\
+break ADDR | break op PATTERN   break at an address, or on opcodes like DXYN or 2...
+delete [ADDR | op PATTERN]      remove a breakpoint, or all of them
+breakpoints                     list the breakpoints
+step [COUNT]                    run COUNT instructions (s)
+next                            step, running over CALL (n)
+finish                          run until the current subroutine returns (fin)
+continue [CYCLES]               run until a breakpoint (c)
+regs                            registers, I, PC, stack pointer and timers (r)
+stack                           return addresses, innermost first (bt)
+x ADDR [LEN]                    hexdump LEN bytes, 64 by default
+set ADDR BYTE...                write bytes to memory
+disas [ADDR] [COUNT]            disassemble, around PC by default (d)
+screen                          show the framebuffer
+press KEY | release KEY         change the state of a hex key
+quit                            exit (q)
+Addresses and bytes are hex, counts are decimal. An empty line repeats the last command.
+";
+
+/// Describe the registers, I, PC, the stack pointer and the timers
+pub fn format_registers(chip8: &Chip8, clock: &VirtualClock) -> String {
+    let cpu = chip8.cpu();
+    let mut out = String::new();
+
+    // writing into a String never fails
+    for (i, value) in cpu.registers().iter().enumerate() {
+        let _ = write!(out, "V{i:X}={value:02X}");
+        out.push(if i % 8 == 7 { '\n' } else { ' ' });
+    }
+    let _ = writeln!(
+        out,
+        "PC={:04X} I={:04X} SP={} DT={} ST={}",
+        cpu.program_counter(),
+        cpu.address(),
+        cpu.stack_pointer(),
+        cpu.delay_timer(),
+        cpu.sound_timer()
+    );
+    let _ = writeln!(out, "cycles={} frames={}", clock.cycles(), clock.frames());
+
+    out
+}
+
+/// List the return addresses, innermost first
+pub fn format_stack(chip8: &Chip8) -> String {
+    let stack = chip8.cpu().stack();
+    if stack.is_empty() {
+        return String::from("Stack is empty\n");
+    }
+
+    stack
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(depth, address)| format!("#{depth} {address:#05X}\n"))
+        .collect()
+}
+
+/// Hexdump memory, 16 bytes per line with an ASCII column
+///
+/// The dump stops at the end of memory
+pub fn hexdump(memory: &[u8], start: u16, len: u16) -> String {
+    let start = start as usize;
+    let end = (start + len as usize).min(memory.len());
+    let mut out = String::new();
+
+    for line_start in (start..end).step_by(16) {
+        let line = &memory[line_start..(line_start + 16).min(end)];
+        let _ = write!(out, "{line_start:04X}:");
+        for byte in line {
+            let _ = write!(out, " {byte:02X}");
+        }
+        out.push_str(&"   ".repeat(16 - line.len()));
+        out.push_str("  ");
+        out.extend(line.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Disassemble `count` instructions from the address
+///
+/// PC is marked with `=>` and breakpoints with `*`, words that don't decode are shown as data
+pub fn disassemble(debugger: &Debugger, start: u16, count: u64) -> String {
+    let chip8 = debugger.chip8();
+    let pc = chip8.cpu().program_counter();
+    let mut out = String::new();
+    let mut address = start;
+
+    for _ in 0..count {
+        let Ok(opcode) = chip8.memory().read_word(address) else {
+            break;
+        };
+        let marker = match (address == pc, debugger.breakpoints.contains(address)) {
+            (true, _) => "=>",
+            (false, true) => " *",
+            (false, false) => "  ",
+        };
+
+        let (text, size) = match chip8.instruction_at(address) {
+            Ok(instruction) => (instruction.to_string(), instruction.size()),
+            Err(_) => (format!("DW {opcode:#06X}"), 2),
+        };
+        let _ = writeln!(out, "{marker} {address:#05X}  {opcode:04X}  {text}");
+
+        address = match address.checked_add(size) {
+            Some(address) => address,
+            None => break,
+        };
+    }
+
+    out
+}
+
+/// Describe where and why the machine stopped
+fn format_stop(debugger: &Debugger, reason: StopReason) -> String {
+    let pc = debugger.chip8().cpu().program_counter();
+    let header = match reason {
+        StopReason::Done => String::new(),
+        StopReason::Breakpoint(address) => format!("Breakpoint at {address:#05X}\n"),
+        StopReason::OpcodeBreakpoint(pattern) => format!("Opcode breakpoint {pattern}\n"),
+        StopReason::Exited => return String::from("Program exited\n"),
+        StopReason::Halted => match debugger.chip8().halted() {
+            Some(fault) => format!("Halted: {fault}\n"),
+            None => String::new(),
+        },
+        StopReason::Limit => format!("Stopped after {} cycles\n", debugger.clock().cycles()),
+    };
+    header + &disassemble(debugger, pc, 1)
+}
+
+/// Result of running a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Text to print, may be empty
+    Output(String),
+    /// The user asked to quit
+    Quit,
+}
+
+impl Debugger {
+    /// Run a parsed command, machine errors are reported in the output
+    pub fn execute(&mut self, command: &Command) -> Outcome {
+        let output = match command {
+            Command::Break(address) => {
+                if self.breakpoints.add(*address) {
+                    format!("Breakpoint at {address:#05X}\n")
+                } else {
+                    format!("Breakpoint at {address:#05X} already set\n")
+                }
+            }
+            Command::BreakOpcode(pattern) => {
+                if self.breakpoints.add_opcode(*pattern) {
+                    format!("Opcode breakpoint {pattern}\n")
+                } else {
+                    format!("Opcode breakpoint {pattern} already set\n")
+                }
+            }
+            Command::Delete(address) => {
+                if self.breakpoints.remove(*address) {
+                    format!("Deleted breakpoint at {address:#05X}\n")
+                } else {
+                    format!("No breakpoint at {address:#05X}\n")
+                }
+            }
+            Command::DeleteOpcode(pattern) => {
+                if self.breakpoints.remove_opcode(*pattern) {
+                    format!("Deleted opcode breakpoint {pattern}\n")
+                } else {
+                    format!("No opcode breakpoint {pattern}\n")
+                }
+            }
+            Command::DeleteAll => {
+                self.breakpoints.clear();
+                String::from("Deleted all breakpoints\n")
+            }
+            Command::Breakpoints => {
+                let mut out: String = self
+                    .breakpoints
+                    .addresses()
+                    .map(|address| format!("break {address:#05X}\n"))
+                    .collect();
+                for pattern in self.breakpoints.opcodes() {
+                    let _ = writeln!(out, "break op {pattern}");
+                }
+                if out.is_empty() {
+                    out = String::from("No breakpoints\n");
+                }
+                out
+            }
+            Command::Step(count) => {
+                let result = self.step(*count);
+                self.report(result)
+            }
+            Command::Next => {
+                let result = self.step_over();
+                self.report(result)
+            }
+            Command::Finish => match self.finish() {
+                Some(result) => self.report(result),
+                None => String::from("Not inside a subroutine\n"),
+            },
+            Command::Continue(limit) => {
+                let result = self.continue_for(*limit);
+                self.report(result)
+            }
+            Command::Registers => format_registers(&self.chip8, &self.clock),
+            Command::Stack => format_stack(&self.chip8),
+            Command::Examine { start, len } => hexdump(self.chip8.memory().bytes(), *start, *len),
+            Command::Set { start, bytes } => match self.chip8.write_memory(*start, bytes) {
+                Ok(()) => hexdump(self.chip8.memory().bytes(), *start, bytes.len() as u16),
+                Err(e) => format!("Error: {e}\n"),
+            },
+            Command::Disassemble { start, count } => {
+                // a few instructions before PC for context, most instructions are two bytes
+                let start = start.unwrap_or_else(|| {
+                    let pc = self.chip8.cpu().program_counter();
+                    pc.saturating_sub(2 * (*count / 2).min(8) as u16)
+                });
+                disassemble(self, start, *count)
+            }
+            Command::Screen => {
+                let ascii = dump_ascii(self.chip8.planes(), self.chip8.resolution());
+                String::from_utf8_lossy(&ascii).into_owned()
+            }
+            Command::Press(key) | Command::Release(key) => {
+                let pressed = matches!(command, Command::Press(_));
+                match self.chip8.set_key_state(*key, pressed) {
+                    Ok(()) => String::new(),
+                    Err(e) => format!("Error: {e}\n"),
+                }
+            }
+            Command::Help => String::from(HELP),
+            Command::Quit => return Outcome::Quit,
+        };
+
+        Outcome::Output(output)
+    }
+
+    /// Describe the result of running the machine
+    fn report(&self, result: Result<StopReason, Chip8Error>) -> String {
+        match result {
+            Ok(reason) => format_stop(self, reason),
+            Err(Chip8Error::Fault(fault)) => {
+                format!("Fault: {fault}\n") + &disassemble(self, fault.pc, 1)
+            }
+            Err(e) => {
+                let pc = self.chip8.cpu().program_counter();
+                format!("Error: {e} ({e:?})\n") + &disassemble(self, pc, 1)
+            }
+        }
+    }
+}
+
+/// Read commands from the input until it ends or the user quits, printing the results
+///
+/// The prompt is only useful on a terminal, so it is left out when `prompt` is false
+pub fn repl(
+    debugger: &mut Debugger,
+    input: impl BufRead,
+    mut output: impl Write,
+    prompt: bool,
+) -> io::Result<()> {
+    let mut last: Option<Command> = None;
+    let mut lines = input.lines();
+
+    loop {
+        if prompt {
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+        }
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(());
+        };
+
+        let command = if line.trim().is_empty() {
+            match &last {
+                Some(command) => command.clone(),
+                None => continue,
+            }
+        } else {
+            match line.parse::<Command>() {
+                Ok(command) => command,
+                Err(e) => {
+                    writeln!(output, "{e}")?;
+                    continue;
+                }
+            }
+        };
+
+        match debugger.execute(&command) {
+            Outcome::Output(text) => output.write_all(text.as_bytes())?,
+            Outcome::Quit => return Ok(()),
+        }
+        last = Some(command);
+    }
+}
+
+/// Settings of the debugger
+#[derive(Debug, Clone)]
+pub struct DebuggerOptions {
+    /// Path to the rom to debug
+    pub rom: PathBuf,
+    /// Image to run instead of reading `rom`, e.g. compiled from Octo source
+    pub program: Option<Vec<u8>>,
+    /// Platform to emulate
+    pub platform: Platform,
+    /// Quirks to run with
+    pub quirks: Quirks,
+    /// What happens when the program faults
+    pub fault_policy: FaultPolicy,
+    /// Address breakpoints set before the first prompt
+    pub breakpoints: Vec<u16>,
+    /// The rom is a crash dump, the machine is restored from it halted on its fault, with the
+    /// platform and quirks it had
+    pub crash_dump: bool,
+}
+
+/// Load the rom and debug it with commands from stdin
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn run_debugger(options: &DebuggerOptions) -> anyhow::Result<()> {
+    let program = match &options.program {
+        Some(program) => program.clone(),
+        None => std::fs::read(&options.rom)
+            .with_context(|| format!("reading {}", options.rom.display()))?,
+    };
+    let mut chip8 = Chip8::with_platform(options.platform, options.quirks);
+    chip8.set_fault_policy(options.fault_policy);
+    if options.crash_dump {
+        chip8
+            .load_crash_dump(&program)
+            .with_context(|| format!("reading {}", options.rom.display()))?;
+        if let Some(fault) = chip8.halted() {
+            print!("{}", fault.report());
+        }
+    } else {
+        chip8.load_program(&program)?;
+    }
+
+    let mut debugger = Debugger::new(chip8);
+    for address in &options.breakpoints {
+        debugger.breakpoints_mut().add(*address);
+    }
+
+    let stdin = io::stdin();
+    let prompt = io::IsTerminal::is_terminal(&stdin);
+    let pc = debugger.chip8().cpu().program_counter();
+    print!("{}", disassemble(&debugger, pc, 1));
+    repl(&mut debugger, stdin.lock(), io::stdout(), prompt)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use chip8_core::{Chip8Builder, machine::memory::DIGIT_SPRITES};
+
+    use super::*;
+
+    /// Calls a subroutine drawing a sprite, then loops forever
+    const PROGRAM: [u8; 12] = [
+        0x22, 0x06, // 200: CALL 206
+        0x60, 0x01, // 202: LD V0, 1
+        0x12, 0x04, // 204: JP 204
+        0x61, 0x02, // 206: LD V1, 2
+        0xD0, 0x15, // 208: DRW V0, V1, 5
+        0x00, 0xEE, // 20A: RET
+    ];
+
+    fn debugger() -> Debugger {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&PROGRAM).unwrap();
+        Debugger::new(chip8)
+    }
+
+    fn pc(debugger: &Debugger) -> u16 {
+        debugger.chip8().cpu().program_counter()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!("b 2A4".parse(), Ok(Command::Break(0x2A4)));
+        assert_eq!(
+            "break op dxyn".parse(),
+            Ok(Command::BreakOpcode("D...".parse().unwrap()))
+        );
+        assert_eq!("delete".parse(), Ok(Command::DeleteAll));
+        assert_eq!("delete 0x300".parse(), Ok(Command::Delete(0x300)));
+        assert_eq!("s 10".parse(), Ok(Command::Step(10)));
+        assert_eq!("c".parse(), Ok(Command::Continue(RUN_LIMIT)));
+        assert_eq!(
+            "x 200 8".parse(),
+            Ok(Command::Examine {
+                start: 0x200,
+                len: 8
+            })
+        );
+        assert_eq!(
+            "set 300 ff 0x1".parse(),
+            Ok(Command::Set {
+                start: 0x300,
+                bytes: vec![0xFF, 0x01]
+            })
+        );
+        assert_eq!(
+            "d".parse(),
+            Ok(Command::Disassemble {
+                start: None,
+                count: 10
+            })
+        );
+        assert_eq!("press a".parse(), Ok(Command::Press(0xA)));
+
+        assert_eq!(
+            "jump".parse::<Command>(),
+            Err(CommandError::UnknownCommand("jump".to_owned()))
+        );
+        assert_eq!(
+            "break".parse::<Command>(),
+            Err(CommandError::MissingArgument("address"))
+        );
+        assert_eq!(
+            "set 300 100".parse::<Command>(),
+            Err(CommandError::InvalidNumber("100".to_owned()))
+        );
+        assert_eq!(
+            "press 10".parse::<Command>(),
+            Err(CommandError::InvalidNumber("10".to_owned()))
+        );
+        assert_eq!(
+            "step 0".parse::<Command>(),
+            Err(CommandError::InvalidNumber("0".to_owned()))
+        );
+        assert_eq!(
+            "regs now".parse::<Command>(),
+            Err(CommandError::TooManyArguments)
+        );
+    }
+
+    #[test]
+    fn test_opcode_pattern() {
+        let pattern: OpcodePattern = "DXYN".parse().unwrap();
+        assert!(pattern.matches(0xD015));
+        assert!(!pattern.matches(0xC015));
+        assert_eq!(pattern.to_string(), "D...");
+
+        let pattern: OpcodePattern = "00e0".parse().unwrap();
+        assert!(pattern.matches(0x00E0));
+        assert!(!pattern.matches(0x00EE));
+
+        for invalid in ["D0", "D0150", "G000"] {
+            assert_eq!(
+                invalid.parse::<OpcodePattern>(),
+                Err(CommandError::InvalidPattern(invalid.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_step_over_call() {
+        let mut debugger = debugger();
+
+        assert_eq!(debugger.step_over().unwrap(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x202);
+        assert_eq!(debugger.chip8().cpu().registers()[1], 2);
+        assert!(debugger.chip8().cpu().stack().is_empty());
+
+        assert_eq!(debugger.step_over().unwrap(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x204);
+    }
+
+    #[test]
+    fn test_step_over_call_at_the_end_of_memory() {
+        // the first digit of the font is a RET at 0x000, called from the last word of memory
+        let mut font = DIGIT_SPRITES;
+        font[0] = [0x00, 0xEE, 0x00, 0x00, 0x00];
+        let mut chip8 = Chip8Builder::new()
+            .platform(Platform::XoChip)
+            .font(font)
+            .load_address(0xFFFE)
+            .build()
+            .unwrap();
+        chip8.load_program(&[0x20, 0x00]).unwrap();
+        let mut debugger = Debugger::new(chip8);
+
+        assert_eq!(debugger.step_over().unwrap(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x0000);
+        assert!(debugger.chip8().cpu().stack().is_empty());
+    }
+
+    #[test]
+    fn test_step_over_stops_at_breakpoint() {
+        let mut debugger = debugger();
+        debugger.breakpoints_mut().add(0x208);
+
+        assert_eq!(debugger.step_over().unwrap(), StopReason::Breakpoint(0x208));
+        assert_eq!(debugger.chip8().cpu().stack(), &[0x202]);
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut debugger = debugger();
+        assert!(debugger.finish().is_none());
+
+        debugger.step(1).unwrap();
+        assert_eq!(pc(&debugger), 0x206);
+
+        assert_eq!(debugger.finish().unwrap().unwrap(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x202);
+        assert!(debugger.chip8().cpu().stack().is_empty());
+    }
+
+    #[test]
+    fn test_continue() {
+        let mut debugger = debugger();
+        debugger
+            .breakpoints_mut()
+            .add_opcode("DXYN".parse().unwrap());
+
+        assert_eq!(
+            debugger.continue_for(RUN_LIMIT).unwrap(),
+            StopReason::OpcodeBreakpoint("D...".parse().unwrap())
+        );
+        assert_eq!(pc(&debugger), 0x208);
+
+        // moves on from the breakpoint, then spins in the loop
+        assert_eq!(debugger.continue_for(100).unwrap(), StopReason::Limit);
+        assert_eq!(pc(&debugger), 0x204);
+        assert_eq!(debugger.clock().cycles(), 102);
+    }
+
+    #[test]
+    fn test_step_count() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step(3).unwrap(), StopReason::Done);
+        assert_eq!(pc(&debugger), 0x20A);
+
+        debugger.breakpoints_mut().add(0x204);
+        assert_eq!(debugger.step(5).unwrap(), StopReason::Breakpoint(0x204));
+        assert_eq!(debugger.clock().cycles(), 5);
+    }
+
+    #[test]
+    fn test_faults() {
+        let mut chip8 = Chip8::new();
+        chip8.set_fault_policy(FaultPolicy::HALT);
+        chip8.load_program(&[0x60, 0x01, 0xFF, 0xFF]).unwrap();
+        let mut debugger = Debugger::new(chip8);
+        let mut output = Vec::new();
+
+        repl(&mut debugger, "c\nc\ns\n".as_bytes(), &mut output, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Fault: invalid opcode at 0x202: Command 0xFFFF is incorrect (policy: halt)\n\
+             => 0x202  FFFF  DW 0xFFFF\n\
+             Halted: invalid opcode at 0x202: Command 0xFFFF is incorrect (policy: halt)\n\
+             => 0x202  FFFF  DW 0xFFFF\n\
+             Halted: invalid opcode at 0x202: Command 0xFFFF is incorrect (policy: halt)\n\
+             => 0x202  FFFF  DW 0xFFFF\n"
+        );
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let memory: Vec<u8> = (0x30..0x50).collect();
+        assert_eq!(
+            hexdump(&memory, 0x1C, 8),
+            format!("001C: 4C 4D 4E 4F{}  LMNO\n", " ".repeat(36))
+        );
+        assert_eq!(
+            hexdump(&memory, 0x00, 0x11),
+            format!(
+                "0000: 30 31 32 33 34 35 36 37 38 39 3A 3B 3C 3D 3E 3F  0123456789:;<=>?\n\
+                 0010: 40{}  @\n",
+                " ".repeat(45)
+            )
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut debugger = debugger();
+        debugger.breakpoints_mut().add(0x202);
+        debugger
+            .chip8_mut()
+            .write_memory(0x20C, &[0x50, 0x01])
+            .unwrap();
+
+        assert_eq!(
+            disassemble(&debugger, 0x200, 2),
+            "=> 0x200  2206  CALL 0x206\n * 0x202  6001  LD V0, 0x01\n"
+        );
+        assert_eq!(
+            disassemble(&debugger, 0x20A, 2),
+            "   0x20A  00EE  RET\n   0x20C  5001  DW 0x5001\n"
+        );
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut debugger = debugger();
+        let input = "b 208\nc\nfin\n\nstack\nbogus\nset 300 12 34\nq\nregs\n";
+        let mut output = Vec::new();
+
+        repl(&mut debugger, input.as_bytes(), &mut output, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Breakpoint at 0x208\n\
+             Breakpoint at 0x208\n\
+             => 0x208  D015  DRW V0, V1, 5\n\
+             => 0x202  6001  LD V0, 0x01\n\
+             Not inside a subroutine\n\
+             Stack is empty\n\
+             Unknown command `bogus`, try `help`\n\
+             0300: 12 34                                            .4\n"
+        );
+    }
+}
@@ -0,0 +1,973 @@
+//! Frontend-independent emulation loop
+//!
+//! A frontend provides a [`VideoSink`], an [`AudioSink`] and an [`InputSource`],
+//! [`Driver`] runs the machine frame by frame and talks to them. Save state slots and
+//! rewinding are handled here as well, so every frontend gets them for free.
+//! A [`StepHook`] sees every cpu cycle, it is how a remote debugger pauses the machine.
+//! The [`Scheduler`] runs a fixed number of instructions per frame and tells real time
+//! frontends how many frames are due, see [`Driver::frames_due`]. The speed controls (turbo,
+//! slow motion, pause and frame advance) work on top of it, [`SpeedStatus`] describes them.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[cfg(feature = "rewind")]
+use chip8_core::machine::rewind::RewindBuffer;
+use chip8_core::{
+    Chip8, Chip8Error, Platform,
+    clock::{DEFAULT_INSTRUCTIONS_PER_FRAME, Scheduler, TimeSource, Timing, WallClock},
+    machine::{
+        display::{Framebuffer, PLANE_COUNT},
+        fault::{Fault, FaultAction},
+    },
+};
+use tklog::info;
+
+/// Receives the screen contents
+pub trait VideoSink {
+    /// Show the visible `(width, height)` part of the bitplanes
+    ///
+    /// Only called when the picture changed
+    fn present(&mut self, planes: &[Framebuffer; PLANE_COUNT], resolution: (usize, usize));
+}
+
+/// Sound to play until the next update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tone {
+    /// Fixed buzzer, the sound of every platform but XO-CHIP
+    Buzzer,
+    /// XO-CHIP audio pattern
+    Pattern {
+        /// 128 1-bit samples, most significant bit first
+        pattern: [u8; 16],
+        /// Samples played per second
+        rate: f32,
+    },
+}
+
+/// Plays the sound
+pub trait AudioSink {
+    /// Called every frame with the sound to play, `None` for silence
+    fn update(&mut self, tone: Option<Tone>);
+}
+
+/// Event coming from the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// Hex keypad key pressed (true) or released (false)
+    Key {
+        /// Hex key
+        key: u8,
+        /// Pressed (true) or released (false)
+        pressed: bool,
+    },
+    /// Save the machine into a numbered slot
+    SaveSlot(u8),
+    /// Restore the machine from a numbered slot
+    LoadSlot(u8),
+    /// Start (true) or stop (false) going back in time
+    Rewind(bool),
+    /// Start (true) or stop (false) running at the turbo speed
+    Turbo(bool),
+    /// Toggle running at the slow motion speed
+    SlowMotion,
+    /// Toggle the pause
+    Pause,
+    /// Run a single frame and pause
+    FrameAdvance,
+    /// Run more instructions per frame
+    MoreInstructions,
+    /// Run fewer instructions per frame
+    FewerInstructions,
+    /// Stop the emulation
+    Quit,
+}
+
+/// Supplies the user input
+pub trait InputSource {
+    /// Next pending event, `None` once there are no more events this frame
+    fn poll(&mut self) -> Option<InputEvent>;
+}
+
+/// Input source for frontends that receive events through callbacks, like winit
+impl InputSource for VecDeque<InputEvent> {
+    fn poll(&mut self) -> Option<InputEvent> {
+        self.pop_front()
+    }
+}
+
+/// What a [`StepHook`] wants done with the next cpu cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookAction {
+    /// Run the cycle
+    Step,
+    /// Don't run it yet, the rest of the frame is skipped
+    Pause,
+    /// Stop the emulation
+    Stop,
+}
+
+/// Gets control around every cpu cycle, e.g. a debugger halting at breakpoints
+pub trait StepHook {
+    /// Called before a cycle, may inspect or change the machine
+    fn before_step(&mut self, chip8: &mut Chip8) -> HookAction;
+
+    /// Called after the cycle ran
+    fn after_step(&mut self, chip8: &Chip8);
+
+    /// Called instead of [`after_step`](Self::after_step) when the cycle faulted with
+    /// [`FaultAction::Break`], returns whether the hook took the fault, e.g. by stopping like
+    /// at a breakpoint. The fault is reported otherwise
+    fn fault(&mut self, _chip8: &Chip8, _fault: &Fault) -> bool {
+        false
+    }
+}
+
+/// Outcome of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameStatus {
+    /// The emulation goes on
+    Running,
+    /// The program executed `00FD`
+    Exited,
+    /// The user asked to quit
+    Quit,
+}
+
+/// Speed the emulation runs at, for the window title or a status bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeedStatus {
+    /// Cpu cycles run per 60 Hz frame, unused in VIP timing
+    pub instructions_per_frame: u32,
+    /// How long instructions take
+    pub timing: Timing,
+    /// Frames run per real time frame, 1 is real time
+    pub speed: f64,
+    /// Whether the emulation is paused
+    pub paused: bool,
+}
+
+impl fmt::Display for SpeedStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.timing {
+            Timing::Fixed => write!(f, "{} ipf", self.instructions_per_frame)?,
+            Timing::CosmacVip => write!(f, "VIP timing")?,
+        }
+        if self.speed != 1.0 {
+            write!(f, " x{}", self.speed)?;
+        }
+        if self.paused {
+            write!(f, " paused")?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings of the driver loop
+#[derive(Debug, Clone)]
+pub struct DriverOptions {
+    /// Rom path, save state slots are stored next to it as `<rom>.<slot>.state` and the crash
+    /// dump of a halted machine as `<rom>.crash`. Both are disabled if `None`
+    pub rom: Option<PathBuf>,
+    /// Memory for the rewind history in bytes, 0 disables rewinding
+    pub rewind_memory: usize,
+    /// Snapshots stepped back per frame while rewinding
+    pub rewind_speed: u32,
+    /// Cpu cycles run per 60 Hz frame
+    pub instructions_per_frame: u32,
+    /// How long instructions take
+    pub timing: Timing,
+    /// Speed while the turbo key is held, relative to real time
+    pub turbo_speed: f64,
+    /// Speed in slow motion, relative to real time
+    pub slow_motion_speed: f64,
+}
+
+impl Default for DriverOptions {
+    fn default() -> Self {
+        Self {
+            rom: None,
+            rewind_memory: 0,
+            rewind_speed: 0,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            timing: Timing::Fixed,
+            turbo_speed: DEFAULT_TURBO_SPEED,
+            slow_motion_speed: DEFAULT_SLOW_MOTION_SPEED,
+        }
+    }
+}
+
+/// Speed while the turbo key is held by default
+pub const DEFAULT_TURBO_SPEED: f64 = 4.0;
+
+/// Speed in slow motion by default
+pub const DEFAULT_SLOW_MOTION_SPEED: f64 = 0.25;
+
+/// Emulation loop generic over the frontend
+pub struct Driver<V, A, I> {
+    /// Machine being run
+    chip8: Chip8,
+    /// Screen
+    video: V,
+    /// Speaker
+    audio: A,
+    /// Keyboard
+    input: I,
+    /// Cycles per frame and frames due, on the wall clock unless replaced
+    scheduler: Scheduler<Box<dyn TimeSource>>,
+    /// Hash of the last presented picture
+    prev_display_hash: Option<u64>,
+    /// Where the save state slots are stored
+    rom: Option<PathBuf>,
+    /// History of the machine states, `None` if rewinding is disabled
+    #[cfg(feature = "rewind")]
+    rewind: Option<RewindBuffer>,
+    /// Snapshots stepped back per frame while rewinding
+    #[cfg(feature = "rewind")]
+    rewind_speed: u32,
+    /// Set while the user holds the rewind key
+    rewinding: bool,
+    /// Speed while the turbo key is held
+    turbo_speed: f64,
+    /// Speed in slow motion
+    slow_motion_speed: f64,
+    /// Set while the user holds the turbo key
+    turbo: bool,
+    /// Whether slow motion is on
+    slow_motion: bool,
+    /// Whether the emulation is paused
+    paused: bool,
+    /// Set when a paused emulation should run the next frame
+    advance: bool,
+    /// Sees every cpu cycle
+    hook: Option<Box<dyn StepHook>>,
+}
+
+impl<V: VideoSink, A: AudioSink, I: InputSource> Driver<V, A, I> {
+    /// Create a driver for a machine with a loaded program
+    pub fn new(chip8: Chip8, video: V, audio: A, input: I, options: &DriverOptions) -> Self {
+        let mut scheduler: Scheduler<Box<dyn TimeSource>> =
+            Scheduler::new(Box::new(WallClock::new()), options.instructions_per_frame);
+        scheduler.set_timing(options.timing);
+
+        Self {
+            chip8,
+            video,
+            audio,
+            input,
+            scheduler,
+            prev_display_hash: None,
+            rom: options.rom.clone(),
+            #[cfg(feature = "rewind")]
+            rewind: (options.rewind_memory > 0).then(|| RewindBuffer::new(options.rewind_memory)),
+            #[cfg(feature = "rewind")]
+            rewind_speed: options.rewind_speed,
+            rewinding: false,
+            turbo_speed: options.turbo_speed,
+            slow_motion_speed: options.slow_motion_speed,
+            turbo: false,
+            slow_motion: false,
+            paused: false,
+            advance: false,
+            hook: None,
+        }
+    }
+
+    /// Let the hook see and pause every cpu cycle
+    pub fn set_step_hook(&mut self, hook: Box<dyn StepHook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Pace the frames against another time source, e.g. a manual one in tests
+    ///
+    /// The emulated clock starts over and the first frame is due right away
+    pub fn set_time_source(&mut self, time: impl TimeSource + 'static) {
+        let mut scheduler = Scheduler::new(
+            Box::new(time) as Box<dyn TimeSource>,
+            self.scheduler.instructions_per_frame(),
+        );
+        scheduler.set_max_catch_up(self.scheduler.max_catch_up());
+        scheduler.set_speed(self.scheduler.speed());
+        scheduler.set_timing(self.scheduler.timing());
+        self.scheduler = scheduler;
+    }
+
+    /// Get the scheduler
+    pub fn scheduler(&self) -> &Scheduler<Box<dyn TimeSource>> {
+        &self.scheduler
+    }
+
+    /// Get the scheduler mutably, e.g. to change the instructions per frame
+    pub fn scheduler_mut(&mut self) -> &mut Scheduler<Box<dyn TimeSource>> {
+        &mut self.scheduler
+    }
+
+    /// Number of frames a real time frontend should run now with [`run_frame`](Self::run_frame)
+    pub fn frames_due(&mut self) -> u32 {
+        self.scheduler.frames_due()
+    }
+
+    /// Time a real time frontend can wait before the next frame is due
+    pub fn until_next_frame(&mut self) -> Duration {
+        self.scheduler.until_next_frame()
+    }
+
+    /// Current speed settings
+    pub fn speed_status(&self) -> SpeedStatus {
+        SpeedStatus {
+            instructions_per_frame: self.scheduler.instructions_per_frame(),
+            timing: self.scheduler.timing(),
+            speed: self.scheduler.speed(),
+            paused: self.paused,
+        }
+    }
+
+    /// Get the machine
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    /// Get the video sink
+    pub fn video(&self) -> &V {
+        &self.video
+    }
+
+    /// Get the video sink mutably, e.g. to attach a surface once the window exists
+    pub fn video_mut(&mut self) -> &mut V {
+        &mut self.video
+    }
+
+    /// Get the audio sink
+    pub fn audio(&self) -> &A {
+        &self.audio
+    }
+
+    /// Get the audio sink mutably
+    pub fn audio_mut(&mut self) -> &mut A {
+        &mut self.audio
+    }
+
+    /// Get the input source mutably, e.g. to queue events received from callbacks
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Force the next frame to be presented even if the picture didn't change
+    pub fn invalidate_video(&mut self) {
+        self.prev_display_hash = None;
+    }
+
+    /// Emulate one 60 Hz frame
+    ///
+    /// Handles the pending input, runs the cpu cycles of the frame, ticks the timers,
+    /// then updates the screen and the sound. While paused only the input is handled.
+    ///
+    /// A fault is returned as [`Chip8Error::Fault`], the frames after it still handle the input
+    /// so the user can rewind or load a state. If it breaks and the step hook doesn't take it,
+    /// the emulation is paused
+    pub fn run_frame(&mut self) -> Result<FrameStatus, Chip8Error> {
+        while let Some(event) = self.input.poll() {
+            match event {
+                InputEvent::Key { key, pressed } => self.chip8.set_key_state(key, pressed)?,
+                InputEvent::SaveSlot(slot) => self.save_slot(slot),
+                InputEvent::LoadSlot(slot) => self.load_slot(slot),
+                InputEvent::Rewind(rewinding) => self.rewinding = rewinding,
+                InputEvent::Turbo(turbo) => {
+                    self.turbo = turbo;
+                    self.update_speed();
+                }
+                InputEvent::SlowMotion => {
+                    self.slow_motion = !self.slow_motion;
+                    self.update_speed();
+                }
+                InputEvent::Pause => self.paused = !self.paused,
+                InputEvent::FrameAdvance => {
+                    self.paused = true;
+                    self.advance = true;
+                }
+                InputEvent::MoreInstructions => {
+                    let ipf = self.scheduler.instructions_per_frame();
+                    self.scheduler
+                        .set_instructions_per_frame(ipf.saturating_add((ipf / 4).max(1)));
+                }
+                InputEvent::FewerInstructions => {
+                    let ipf = self.scheduler.instructions_per_frame();
+                    self.scheduler
+                        .set_instructions_per_frame(ipf - (ipf / 5).max(1));
+                }
+                InputEvent::Quit => return Ok(FrameStatus::Quit),
+            }
+        }
+
+        if self.chip8.has_exited() {
+            return Ok(FrameStatus::Exited);
+        }
+
+        if self.paused && !std::mem::take(&mut self.advance) {
+            self.audio.update(None);
+        } else if self.rewinding {
+            self.rewind_frame();
+            self.audio.update(None);
+        } else {
+            if self.emulate_frame()? == FrameStatus::Quit {
+                return Ok(FrameStatus::Quit);
+            }
+            self.audio.update(self.tone());
+        }
+
+        self.present();
+
+        Ok(FrameStatus::Running)
+    }
+
+    /// Run frames until the program exits or the user quits, calling `pace` after every frame
+    ///
+    /// The frames run back to back unless `pace` waits, e.g. for [`until_next_frame`](Self::until_next_frame)
+    pub fn run(&mut self, mut pace: impl FnMut()) -> Result<FrameStatus, Chip8Error> {
+        loop {
+            match self.run_frame()? {
+                FrameStatus::Running => pace(),
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Snapshot the machine for rewinding, then run the cpu cycles of the frame and tick the
+    /// timers once
+    ///
+    /// The frame ends early if the step hook pauses, [`FrameStatus::Quit`] is returned if it stops
+    fn emulate_frame(&mut self) -> Result<FrameStatus, Chip8Error> {
+        #[cfg(feature = "rewind")]
+        if let Some(rewind) = &mut self.rewind {
+            rewind.push(&self.chip8);
+        }
+
+        loop {
+            if let Some(hook) = &mut self.hook {
+                match hook.before_step(&mut self.chip8) {
+                    HookAction::Step => {}
+                    HookAction::Pause => return Ok(FrameStatus::Running),
+                    HookAction::Stop => return Ok(FrameStatus::Quit),
+                }
+            }
+
+            let frame_ended = match self.scheduler.step(&mut self.chip8) {
+                Err(Chip8Error::Fault(fault)) if fault.action == FaultAction::Break => {
+                    if let Some(hook) = &mut self.hook
+                        && hook.fault(&self.chip8, &fault)
+                    {
+                        return Ok(FrameStatus::Running);
+                    }
+                    self.paused = true;
+                    return Err(Chip8Error::Fault(fault));
+                }
+                Err(error @ Chip8Error::Fault(_)) => {
+                    self.write_crash_dump();
+                    return Err(error);
+                }
+                result => result?,
+            };
+            if let Some(hook) = &mut self.hook {
+                hook.after_step(&self.chip8);
+            }
+
+            if frame_ended || self.chip8.has_exited() {
+                return Ok(FrameStatus::Running);
+            }
+        }
+    }
+
+    /// Apply the turbo and slow motion settings to the scheduler, turbo wins if both are on
+    fn update_speed(&mut self) {
+        let speed = if self.turbo {
+            self.turbo_speed
+        } else if self.slow_motion {
+            self.slow_motion_speed
+        } else {
+            1.0
+        };
+        self.scheduler.set_speed(speed);
+    }
+
+    /// Sound the machine makes right now
+    fn tone(&self) -> Option<Tone> {
+        if !self.chip8.is_sound_playing() {
+            return None;
+        }
+
+        let audio = self.chip8.audio();
+        match (self.chip8.platform(), audio.pattern()) {
+            (Platform::XoChip, Some(pattern)) => Some(Tone::Pattern {
+                pattern: *pattern,
+                rate: audio.playback_rate(),
+            }),
+            _ => Some(Tone::Buzzer),
+        }
+    }
+
+    /// Present the picture if it differs from the last presented one
+    fn present(&mut self) {
+        let resolution = self.chip8.resolution();
+        if let Some(planes) = self.chip8.planes_snapshot() {
+            let mut hasher = DefaultHasher::new();
+            planes.hash(&mut hasher);
+            resolution.hash(&mut hasher);
+            let cur_hash = hasher.finish();
+
+            if self.prev_display_hash != Some(cur_hash) {
+                self.video.present(planes, resolution);
+                self.prev_display_hash = Some(cur_hash);
+            }
+        }
+    }
+
+    /// Step back in time by the configured number of snapshots
+    fn rewind_frame(&mut self) {
+        #[cfg(feature = "rewind")]
+        if let Some(rewind) = &mut self.rewind {
+            for _ in 0..self.rewind_speed {
+                match rewind.rewind(&mut self.chip8) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(e) => {
+                        eprintln!("Could not rewind: {e}");
+                        rewind.clear();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write the machine state into the slot
+    fn save_slot(&self, slot: u8) {
+        let Some(path) = self.slot_path(slot) else {
+            return;
+        };
+
+        match std::fs::write(&path, self.chip8.save_state()) {
+            Ok(()) => info!(format!("Saved state to {}", path.display())),
+            Err(e) => eprintln!("Could not save state to {}: {e}", path.display()),
+        }
+    }
+
+    /// Restore the machine state from the slot, keeping the current one on any error
+    fn load_slot(&mut self, slot: u8) {
+        let Some(path) = self.slot_path(slot) else {
+            return;
+        };
+
+        let result = std::fs::read(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|state| Ok(self.chip8.load_state(&state)?));
+
+        match result {
+            Ok(()) => {
+                info!(format!("Loaded state from {}", path.display()));
+                self.prev_display_hash = None;
+                #[cfg(feature = "rewind")]
+                if let Some(rewind) = &mut self.rewind {
+                    rewind.clear();
+                }
+            }
+            Err(e) => {
+                let cause = e.root_cause();
+                eprintln!("Could not load state from {}: {cause}", path.display());
+            }
+        }
+    }
+
+    /// Path of the save state file for the slot
+    fn slot_path(&self, slot: u8) -> Option<PathBuf> {
+        self.rom.as_deref().map(|rom| slot_path(rom, slot))
+    }
+
+    /// Write the crash dump of the halted machine next to the rom
+    fn write_crash_dump(&self) {
+        let (Some(rom), Some(dump)) = (&self.rom, self.chip8.crash_dump()) else {
+            return;
+        };
+
+        let path = crash_dump_path(rom);
+        match std::fs::write(&path, dump) {
+            Ok(()) => info!(format!("Wrote crash dump to {}", path.display())),
+            Err(e) => eprintln!("Could not write crash dump to {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Path of the save state file for the slot of the rom, `<rom>.<slot>.state`
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    let mut file_name = rom.file_name().unwrap_or_default().to_owned();
+    file_name.push(format!(".{slot}.state"));
+    rom.with_file_name(file_name)
+}
+
+/// Path of the crash dump of the rom, `<rom>.crash`
+pub fn crash_dump_path(rom: &Path) -> PathBuf {
+    let mut file_name = rom.file_name().unwrap_or_default().to_owned();
+    file_name.push(".crash");
+    rom.with_file_name(file_name)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use chip8_core::{FaultPolicy, clock::TIMER_INTERVAL};
+
+    use super::*;
+
+    /// Video sink remembering every presented picture
+    #[derive(Default)]
+    struct RecordingVideo {
+        /// Presented pictures
+        frames: Vec<(Framebuffer, (usize, usize))>,
+    }
+
+    impl VideoSink for RecordingVideo {
+        fn present(&mut self, planes: &[Framebuffer; PLANE_COUNT], resolution: (usize, usize)) {
+            self.frames.push((planes[0], resolution));
+        }
+    }
+
+    /// Audio sink remembering the tone of every frame
+    #[derive(Default)]
+    struct RecordingAudio {
+        /// Tone of every frame
+        tones: Vec<Option<Tone>>,
+    }
+
+    impl AudioSink for RecordingAudio {
+        fn update(&mut self, tone: Option<Tone>) {
+            self.tones.push(tone);
+        }
+    }
+
+    /// Input source replaying a list of events, one list per frame
+    struct ScriptedInput {
+        /// Events per frame
+        frames: VecDeque<Vec<InputEvent>>,
+        /// Events of the current frame, `None` before the first poll of a frame
+        current: Option<VecDeque<InputEvent>>,
+    }
+
+    impl ScriptedInput {
+        fn new(frames: Vec<Vec<InputEvent>>) -> Self {
+            Self {
+                frames: frames.into(),
+                current: None,
+            }
+        }
+    }
+
+    impl InputSource for ScriptedInput {
+        fn poll(&mut self) -> Option<InputEvent> {
+            let frames = &mut self.frames;
+            let current = self
+                .current
+                .get_or_insert_with(|| frames.pop_front().unwrap_or_default().into());
+
+            let event = current.pop_front();
+            if event.is_none() {
+                self.current = None;
+            }
+            event
+        }
+    }
+
+    fn driver(
+        program: &[u8],
+        input: Vec<Vec<InputEvent>>,
+        options: &DriverOptions,
+    ) -> Driver<RecordingVideo, RecordingAudio, ScriptedInput> {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(program).unwrap();
+        let input = ScriptedInput::new(input);
+        Driver::new(
+            chip8,
+            RecordingVideo::default(),
+            RecordingAudio::default(),
+            input,
+            options,
+        )
+    }
+
+    #[test]
+    fn test_run_until_exit() {
+        // clear the screen, exit
+        let mut driver = driver(&[0x00, 0xE0, 0x00, 0xFD], vec![], &DriverOptions::default());
+
+        assert_eq!(driver.run(|| {}).unwrap(), FrameStatus::Exited);
+        assert_eq!(driver.video().frames.len(), 1);
+        assert_eq!(driver.video().frames[0].1, (64, 32));
+    }
+
+    #[test]
+    fn test_quit_and_keys() {
+        // wait for a key into V0, then loop
+        let program = [0xF0, 0x0A, 0x12, 0x02];
+        let input = vec![
+            vec![],
+            vec![InputEvent::Key {
+                key: 0xB,
+                pressed: true,
+            }],
+            vec![InputEvent::Quit],
+        ];
+        let mut driver = driver(&program, input, &DriverOptions::default());
+
+        assert_eq!(driver.run(|| {}).unwrap(), FrameStatus::Quit);
+        assert_eq!(driver.chip8().cpu().registers()[0], 0xB);
+    }
+
+    #[test]
+    fn test_buzzer() {
+        // V0 = 2, ST = V0, loop
+        let program = [0x60, 0x02, 0xF0, 0x18, 0x12, 0x04];
+        let input = vec![vec![], vec![], vec![], vec![InputEvent::Quit]];
+        let mut driver = driver(&program, input, &DriverOptions::default());
+
+        driver.run(|| {}).unwrap();
+        assert_eq!(driver.audio().tones, vec![Some(Tone::Buzzer), None, None]);
+    }
+
+    #[cfg(feature = "rewind")]
+    #[test]
+    fn test_rewind() {
+        // V0 += 1, loop
+        let program = [0x70, 0x01, 0x12, 0x00];
+        let options = DriverOptions {
+            rewind_memory: 1 << 20,
+            rewind_speed: 1,
+            ..Default::default()
+        };
+        let mut input: Vec<Vec<InputEvent>> = vec![vec![]; 4];
+        input.push(vec![InputEvent::Rewind(true)]);
+        input.push(vec![]);
+        input.push(vec![InputEvent::Quit]);
+        let mut driver = driver(&program, input, &options);
+
+        let mut values = vec![];
+        let mut status = FrameStatus::Running;
+        while status == FrameStatus::Running {
+            status = driver.run_frame().unwrap();
+            values.push(driver.chip8().cpu().registers()[0]);
+        }
+
+        // every frame runs 8 cycles, half of them are V0 += 1
+        assert_eq!(values[4], values[2]);
+        assert_eq!(values[5], values[1]);
+    }
+
+    /// Step hook pausing every other frame and stopping after a number of cycles
+    struct CountingHook {
+        /// Cycles run so far, shared with the test
+        steps: Rc<Cell<u32>>,
+        /// Cycles to run before stopping
+        limit: u32,
+        /// Whether the next `before_step` pauses
+        pause: bool,
+    }
+
+    impl StepHook for CountingHook {
+        fn before_step(&mut self, _chip8: &mut Chip8) -> HookAction {
+            if self.steps.get() == self.limit {
+                HookAction::Stop
+            } else if std::mem::take(&mut self.pause) {
+                HookAction::Pause
+            } else {
+                HookAction::Step
+            }
+        }
+
+        fn after_step(&mut self, _chip8: &Chip8) {
+            self.steps.set(self.steps.get() + 1);
+            self.pause = self.steps.get().is_multiple_of(4);
+        }
+    }
+
+    #[test]
+    fn test_step_hook() {
+        // V0 += 1, loop
+        let program = [0x70, 0x01, 0x12, 0x00];
+        let mut driver = driver(&program, vec![], &DriverOptions::default());
+        let steps = Rc::new(Cell::new(0));
+        driver.set_step_hook(Box::new(CountingHook {
+            steps: steps.clone(),
+            limit: 20,
+            pause: false,
+        }));
+
+        assert_eq!(driver.run_frame().unwrap(), FrameStatus::Running);
+        assert_eq!(steps.get(), 4);
+        assert_eq!(driver.run(|| {}).unwrap(), FrameStatus::Quit);
+        assert_eq!(steps.get(), 20);
+        assert_eq!(driver.chip8().cpu().registers()[0], 10);
+    }
+
+    /// Step hook taking every fault
+    struct FaultHook {
+        /// Faults taken so far, shared with the test
+        faults: Rc<Cell<u32>>,
+    }
+
+    impl StepHook for FaultHook {
+        fn before_step(&mut self, _chip8: &mut Chip8) -> HookAction {
+            HookAction::Step
+        }
+
+        fn after_step(&mut self, _chip8: &Chip8) {}
+
+        fn fault(&mut self, _chip8: &Chip8, _fault: &Fault) -> bool {
+            self.faults.set(self.faults.get() + 1);
+            true
+        }
+    }
+
+    #[test]
+    fn test_faults() {
+        // V0 += 1, return with an empty stack
+        let program = [0x70, 0x01, 0x00, 0xEE];
+
+        // breaking pauses, the machine stays at the faulting instruction
+        let mut breaking = driver(&program, vec![], &DriverOptions::default());
+        let Err(Chip8Error::Fault(fault)) = breaking.run_frame() else {
+            panic!("no fault");
+        };
+        assert_eq!((fault.pc, fault.action), (0x202, FaultAction::Break));
+        assert_eq!(breaking.run_frame().unwrap(), FrameStatus::Running);
+        assert!(breaking.speed_status().paused);
+        assert_eq!(breaking.chip8().cpu().program_counter(), 0x202);
+
+        // unless the hook takes the fault
+        let mut hooked = driver(&program, vec![], &DriverOptions::default());
+        let faults = Rc::new(Cell::new(0));
+        hooked.set_step_hook(Box::new(FaultHook {
+            faults: faults.clone(),
+        }));
+        assert_eq!(hooked.run_frame().unwrap(), FrameStatus::Running);
+        assert_eq!(hooked.run_frame().unwrap(), FrameStatus::Running);
+        assert_eq!(faults.get(), 2);
+        assert!(!hooked.speed_status().paused);
+
+        // a halted machine is reported once, the frames after it do nothing
+        let rom = std::env::temp_dir().join(format!("chip8-driver-{}.ch8", std::process::id()));
+        let mut chip8 = Chip8::new();
+        chip8.set_fault_policy(FaultPolicy::HALT);
+        chip8.load_program(&program).unwrap();
+        let mut halting = Driver::new(
+            chip8,
+            RecordingVideo::default(),
+            RecordingAudio::default(),
+            ScriptedInput::new(vec![]),
+            &DriverOptions {
+                rom: Some(rom.clone()),
+                ..Default::default()
+            },
+        );
+        assert!(halting.run_frame().is_err());
+        assert_eq!(halting.run_frame().unwrap(), FrameStatus::Running);
+        assert!(halting.chip8().halted().is_some());
+        assert!(!halting.speed_status().paused);
+
+        // and leaves a crash dump behind
+        let dump = std::fs::read(crash_dump_path(&rom)).unwrap();
+        std::fs::remove_file(crash_dump_path(&rom)).unwrap();
+        let mut restored = Chip8::new();
+        restored.load_crash_dump(&dump).unwrap();
+        assert_eq!(restored.halted().unwrap().pc, 0x202);
+    }
+
+    /// Time source the test moves forward while the driver owns it
+    struct SharedTime(Rc<Cell<Duration>>);
+
+    impl TimeSource for SharedTime {
+        fn now(&mut self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_frames_due() {
+        // V0 += 1, loop
+        let program = [0x70, 0x01, 0x12, 0x00];
+        let options = DriverOptions {
+            instructions_per_frame: 10,
+            ..Default::default()
+        };
+        let mut driver = driver(&program, vec![], &options);
+        let time = Rc::new(Cell::new(Duration::ZERO));
+        driver.set_time_source(SharedTime(time.clone()));
+
+        let run_due = |driver: &mut Driver<_, _, _>| {
+            for _ in 0..driver.frames_due() {
+                driver.run_frame().unwrap();
+            }
+        };
+        run_due(&mut driver);
+        assert_eq!(driver.scheduler().clock().cycles(), 10);
+
+        // a late wake up runs every frame that passed
+        time.set(TIMER_INTERVAL * 3);
+        run_due(&mut driver);
+        assert_eq!(driver.scheduler().clock().frames(), 4);
+        assert_eq!(driver.chip8().cpu().registers()[0], 20);
+        assert_eq!(driver.until_next_frame(), TIMER_INTERVAL);
+    }
+
+    #[test]
+    fn test_pause_and_frame_advance() {
+        // V0 += 1, loop
+        let program = [0x70, 0x01, 0x12, 0x00];
+        let input = vec![
+            vec![InputEvent::Pause],
+            vec![],
+            vec![InputEvent::FrameAdvance],
+            vec![],
+            vec![InputEvent::MoreInstructions, InputEvent::Pause],
+        ];
+        let mut driver = driver(&program, input, &DriverOptions::default());
+
+        let mut values = vec![];
+        for _ in 0..5 {
+            driver.run_frame().unwrap();
+            values.push(driver.chip8().cpu().registers()[0]);
+        }
+
+        // the advanced frame runs 8 cycles, the last one 10
+        assert_eq!(values, [0, 0, 4, 4, 9]);
+        assert_eq!(driver.speed_status().to_string(), "10 ipf");
+    }
+
+    #[test]
+    fn test_turbo_and_slow_motion() {
+        let input = vec![
+            vec![InputEvent::SlowMotion, InputEvent::FewerInstructions],
+            vec![InputEvent::Turbo(true)],
+            vec![InputEvent::Turbo(false), InputEvent::Pause],
+        ];
+        let mut driver = driver(&[0x12, 0x00], input, &DriverOptions::default());
+
+        driver.run_frame().unwrap();
+        assert_eq!(driver.speed_status().to_string(), "7 ipf x0.25");
+        driver.run_frame().unwrap();
+        assert_eq!(driver.scheduler().speed(), DEFAULT_TURBO_SPEED);
+        driver.run_frame().unwrap();
+        assert_eq!(driver.speed_status().to_string(), "7 ipf x0.25 paused");
+    }
+
+    #[test]
+    fn test_slot_path() {
+        assert_eq!(
+            slot_path(Path::new("/roms/pong.ch8"), 3),
+            PathBuf::from("/roms/pong.ch8.3.state")
+        );
+    }
+}
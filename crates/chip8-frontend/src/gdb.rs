@@ -0,0 +1,794 @@
+//! GDB remote serial protocol stub
+//!
+//! [`GdbStub`] listens on a localhost TCP port and is installed as a [`StepHook`], so gdb can
+//! attach to the window, the terminal or a headless run:
+//!
+//! ```text
+//! (gdb) target remote localhost:1234
+//! (gdb) break *0x2a4
+//! (gdb) continue
+//! (gdb) info registers
+//! (gdb) x/16xb 0x200
+//! ```
+//!
+//! Registers, in `g` packet order, multi-byte values are big endian like the rest of the machine:
+//!
+//! | number | name       | size |
+//! |--------|------------|------|
+//! | 0-15   | `v0`-`vf`  | 1    |
+//! | 16     | `i`        | 2    |
+//! | 17     | `pc`       | 2    |
+//! | 18     | `sp`       | 1    |
+//! | 19     | `dt`       | 1    |
+//! | 20     | `st`       | 1    |
+//!
+//! Registers are read only. Memory is the whole address space of the platform, writes below
+//! 0x200 are rejected like everywhere else. Faults that break stop with SIGILL for
+//! instructions and SIGSEGV for the stack and out of range accesses.
+
+use std::{
+    fmt::Write as _,
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+};
+
+use anyhow::Context;
+use tklog::info;
+
+use chip8_core::{
+    Chip8,
+    machine::fault::{Fault, FaultClass},
+};
+
+use crate::{
+    debugger::Breakpoints,
+    driver::{HookAction, StepHook},
+};
+
+/// Stop reply for a breakpoint or a finished step, SIGTRAP
+const STOPPED: &str = "S05";
+/// Stop reply after gdb interrupted the machine, SIGINT
+const INTERRUPTED: &str = "S02";
+/// Stop reply for an invalid or unsupported instruction, SIGILL
+const ILLEGAL_INSTRUCTION: &str = "S04";
+/// Stop reply for a stack fault or an access out of range, SIGSEGV
+const SEGMENTATION_FAULT: &str = "S0b";
+
+/// Largest packet gdb may send or expect, in bytes as advertised by `qSupported`
+const PACKET_SIZE: usize = 0x4000;
+
+/// Target description, gives gdb the register names
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.cpu">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// What the machine does while gdb is attached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Runs until a breakpoint or an interrupt
+    Running,
+    /// Waits for gdb
+    Halted,
+    /// Runs a single cycle, then halts
+    Stepping,
+}
+
+/// Connected gdb
+struct Connection {
+    /// Socket to gdb
+    stream: TcpStream,
+    /// Received bytes not forming a whole packet yet
+    buffer: Vec<u8>,
+    /// Set once gdb turned acknowledgements off
+    no_ack: bool,
+}
+
+/// Something gdb sent
+#[derive(Debug, PartialEq, Eq)]
+enum Incoming {
+    /// Packet with a valid checksum, without the framing
+    Packet(Vec<u8>),
+    /// Ctrl-C, stop the machine
+    Interrupt,
+    /// Packet with a wrong checksum, to be sent again
+    Corrupt,
+}
+
+impl Connection {
+    /// Read what gdb sent, waiting for data if `block` is set
+    ///
+    /// Returns `None` once gdb closed the connection
+    fn receive(&mut self, block: bool) -> io::Result<Option<Vec<Incoming>>> {
+        self.stream.set_nonblocking(!block)?;
+
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(None),
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&chunk[..n]);
+                    if block {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let incoming = split_incoming(&mut self.buffer);
+        for item in &incoming {
+            match item {
+                Incoming::Packet(_) if !self.no_ack => self.stream.write_all(b"+")?,
+                Incoming::Corrupt => self.stream.write_all(b"-")?,
+                _ => {}
+            }
+        }
+        Ok(Some(incoming))
+    }
+
+    /// Send a packet with its framing and checksum
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        self.stream.set_nonblocking(false)?;
+        self.stream.write_all(frame(data).as_bytes())
+    }
+}
+
+/// Wrap the data into `$data#checksum`
+fn frame(data: &str) -> String {
+    let checksum = data.bytes().fold(0_u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${data}#{checksum:02x}")
+}
+
+/// Take every complete packet and interrupt out of the buffer
+///
+/// Acknowledgements are dropped, an incomplete packet stays in the buffer
+fn split_incoming(buffer: &mut Vec<u8>) -> Vec<Incoming> {
+    let mut incoming = Vec::new();
+    let mut consumed = 0;
+
+    while consumed < buffer.len() {
+        match buffer[consumed] {
+            b'$' => {
+                let Some(hash) = buffer[consumed..].iter().position(|&b| b == b'#') else {
+                    break;
+                };
+                let end = consumed + hash + 3;
+                if end > buffer.len() {
+                    break;
+                }
+
+                let data = unescape(&buffer[consumed + 1..consumed + hash]);
+                let expected = std::str::from_utf8(&buffer[end - 2..end])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                let checksum = buffer[consumed + 1..consumed + hash]
+                    .iter()
+                    .fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+                incoming.push(if expected == Some(checksum) {
+                    Incoming::Packet(data)
+                } else {
+                    Incoming::Corrupt
+                });
+                consumed = end;
+            }
+            0x03 => {
+                incoming.push(Incoming::Interrupt);
+                consumed += 1;
+            }
+            // acknowledgements and noise
+            _ => consumed += 1,
+        }
+    }
+
+    buffer.drain(..consumed);
+    incoming
+}
+
+/// Undo the `}` escaping of binary packet data
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'}' {
+            if let Some(&escaped) = bytes.next() {
+                out.push(escaped ^ 0x20);
+            }
+        } else {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+/// Encode bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+/// Decode hex into bytes
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parse `ADDR,LEN` with both numbers in hex, the length is capped so that the reply fits in
+/// a packet
+fn parse_range(args: &str) -> Option<(u16, usize)> {
+    let (address, len) = args.split_once(',')?;
+    Some((
+        u16::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?.min(PACKET_SIZE / 2),
+    ))
+}
+
+/// Every register in `g` packet order
+fn registers(chip8: &Chip8) -> Vec<u8> {
+    let cpu = chip8.cpu();
+    let mut bytes = cpu.registers().to_vec();
+    bytes.extend_from_slice(&cpu.address().to_be_bytes());
+    bytes.extend_from_slice(&cpu.program_counter().to_be_bytes());
+    bytes.push(cpu.stack_pointer() as u8);
+    bytes.push(cpu.delay_timer());
+    bytes.push(cpu.sound_timer());
+    bytes
+}
+
+/// Bytes of a single register, `None` if there is no such register
+fn register(chip8: &Chip8, number: usize) -> Option<Vec<u8>> {
+    let all = registers(chip8);
+    let (start, len) = match number {
+        0..=15 => (number, 1),
+        16 => (16, 2),
+        17 => (18, 2),
+        18..=20 => (number + 2, 1),
+        _ => return None,
+    };
+    Some(all[start..start + len].to_vec())
+}
+
+/// GDB server controlling the machine through the step hook
+pub struct GdbStub {
+    /// Socket waiting for gdb, `None` once a session ended and no other one is allowed
+    listener: Option<TcpListener>,
+    /// Attached gdb
+    connection: Option<Connection>,
+    /// What the machine does
+    state: State,
+    /// Breakpoints set by gdb
+    breakpoints: Breakpoints,
+    /// PC the machine resumed from, its breakpoint is skipped once
+    resumed_from: Option<u16>,
+    /// Wait for gdb while halted and before it connects, instead of letting the frontend go on
+    wait: bool,
+    /// Set once gdb killed the machine
+    killed: bool,
+}
+
+impl GdbStub {
+    /// Listen on the localhost port, 0 picks a free one
+    ///
+    /// With `wait` set the machine doesn't run until gdb attached, and gdb pausing the machine
+    /// blocks the whole frontend. That suits headless runs, which have nothing else to do. After
+    /// gdb detaches the run goes on without accepting another connection.
+    /// Without `wait` the machine runs until gdb attaches and the frontend keeps presenting
+    /// frames while it is halted
+    pub fn bind(port: u16, wait: bool) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(!wait)?;
+
+        Ok(Self {
+            listener: Some(listener),
+            connection: None,
+            state: State::Running,
+            breakpoints: Breakpoints::new(),
+            resumed_from: None,
+            wait,
+            killed: false,
+        })
+    }
+
+    /// Bind like [`GdbStub::bind`] and tell the user where gdb can attach
+    pub fn listen(port: u16, wait: bool) -> anyhow::Result<Self> {
+        let stub =
+            Self::bind(port, wait).with_context(|| format!("listening for gdb on port {port}"))?;
+        if let Some(address) = stub.local_addr() {
+            eprintln!("gdb can attach with `target remote {address}`");
+        }
+        Ok(stub)
+    }
+
+    /// Address gdb should connect to
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+    }
+
+    /// Check for gdb connecting, the machine halts as soon as it does
+    fn accept(&mut self) -> io::Result<()> {
+        let Some(listener) = &self.listener else {
+            return Ok(());
+        };
+
+        match listener.accept() {
+            Ok((stream, address)) => {
+                info!(format!("gdb attached from {address}"));
+                stream.set_nodelay(true)?;
+                self.connection = Some(Connection {
+                    stream,
+                    buffer: Vec::new(),
+                    no_ack: false,
+                });
+                self.state = State::Halted;
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Forget the connection and let the machine run freely
+    fn disconnect(&mut self) {
+        info!("gdb detached");
+        self.connection = None;
+        self.state = State::Running;
+        if self.wait {
+            self.listener = None;
+        }
+    }
+
+    /// Send a packet, dropping the connection if gdb is gone
+    fn send(&mut self, data: &str) {
+        if let Some(connection) = &mut self.connection
+            && let Err(e) = connection.send(data)
+        {
+            eprintln!("Lost the gdb connection: {e}");
+            self.disconnect();
+        }
+    }
+
+    /// Handle what gdb sent and decide about the next cycle
+    fn poll(&mut self, chip8: &mut Chip8) -> io::Result<HookAction> {
+        if self.connection.is_none() {
+            self.accept()?;
+        }
+
+        loop {
+            let Some(connection) = &mut self.connection else {
+                return Ok(HookAction::Step);
+            };
+
+            let block = self.wait && self.state == State::Halted;
+            let Some(incoming) = connection.receive(block)? else {
+                self.disconnect();
+                continue;
+            };
+
+            for item in incoming {
+                match item {
+                    Incoming::Packet(packet) => {
+                        let packet = String::from_utf8_lossy(&packet).into_owned();
+                        if let Some(reply) = self.handle_packet(&packet, chip8) {
+                            self.send(&reply);
+                        }
+                    }
+                    Incoming::Interrupt if self.state == State::Running => {
+                        self.state = State::Halted;
+                        self.send(INTERRUPTED);
+                    }
+                    Incoming::Interrupt | Incoming::Corrupt => {}
+                }
+            }
+
+            if self.killed {
+                return Ok(HookAction::Stop);
+            }
+
+            match self.state {
+                State::Halted if self.wait => {}
+                State::Halted => return Ok(HookAction::Pause),
+                State::Stepping => return Ok(HookAction::Step),
+                State::Running => {
+                    let pc = chip8.cpu().program_counter();
+                    if self.resumed_from.take() != Some(pc) && self.breakpoints.hit(chip8).is_some()
+                    {
+                        self.state = State::Halted;
+                        self.send(STOPPED);
+                    } else {
+                        return Ok(HookAction::Step);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Handle a packet, returning the reply
+    ///
+    /// `c` and `s` have no immediate reply, the stop reply is sent once the machine stops.
+    /// Unsupported packets get the empty reply
+    fn handle_packet(&mut self, packet: &str, chip8: &mut Chip8) -> Option<String> {
+        let error = || String::from("E01");
+
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => String::from(STOPPED),
+            Some(b'g') => to_hex(&registers(chip8)),
+            Some(b'p') => usize::from_str_radix(&packet[1..], 16)
+                .ok()
+                .and_then(|number| register(chip8, number))
+                .map_or_else(error, |bytes| to_hex(&bytes)),
+            Some(b'm') => match parse_range(&packet[1..]) {
+                Some((address, len)) => {
+                    let memory = chip8.memory().bytes();
+                    let start = address as usize;
+                    if start >= memory.len() {
+                        error()
+                    } else {
+                        to_hex(&memory[start..start.saturating_add(len).min(memory.len())])
+                    }
+                }
+                None => error(),
+            },
+            Some(b'M') => {
+                let written = packet[1..].split_once(':').and_then(|(range, hex)| {
+                    let (address, len) = parse_range(range)?;
+                    let bytes = from_hex(hex).filter(|bytes| bytes.len() == len)?;
+                    chip8.write_memory(address, &bytes).ok()
+                });
+                written.map_or_else(error, |()| String::from("OK"))
+            }
+            Some(b'Z' | b'z') => {
+                let mut args = packet[1..].split(',');
+                let kind = args.next();
+                let address = args
+                    .next()
+                    .and_then(|address| u16::from_str_radix(address, 16).ok());
+                match (kind, address) {
+                    // software and hardware breakpoints are the same thing here
+                    (Some("0" | "1"), Some(address)) => {
+                        if packet.starts_with('Z') {
+                            self.breakpoints.add(address);
+                        } else {
+                            self.breakpoints.remove(address);
+                        }
+                        String::from("OK")
+                    }
+                    (Some("0" | "1"), None) => error(),
+                    _ => String::new(),
+                }
+            }
+            Some(b'c') => {
+                self.state = State::Running;
+                self.resumed_from = Some(chip8.cpu().program_counter());
+                return None;
+            }
+            Some(b's') => {
+                self.state = State::Stepping;
+                return None;
+            }
+            Some(b'k') => {
+                self.killed = true;
+                return None;
+            }
+            Some(b'D') => {
+                self.send("OK");
+                self.disconnect();
+                return None;
+            }
+            Some(b'H') => String::from("OK"),
+            _ => return self.handle_query(packet),
+        };
+
+        Some(reply)
+    }
+
+    /// Handle the general query and set packets
+    fn handle_query(&mut self, packet: &str) -> Option<String> {
+        if packet.starts_with("qSupported") {
+            return Some(format!(
+                "PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+"
+            ));
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_range(args) else {
+                return Some(String::from("E01"));
+            };
+            let offset = (offset as usize).min(TARGET_XML.len());
+            let end = offset.saturating_add(len).min(TARGET_XML.len());
+            let marker = if end == TARGET_XML.len() { 'l' } else { 'm' };
+            return Some(format!("{marker}{}", &TARGET_XML[offset..end]));
+        }
+
+        let reply = match packet {
+            "QStartNoAckMode" => {
+                // the OK itself is still acknowledged
+                self.send("OK");
+                if let Some(connection) = &mut self.connection {
+                    connection.no_ack = true;
+                }
+                return None;
+            }
+            "qAttached" => "1",
+            "qC" => "QC1",
+            "qfThreadInfo" => "m1",
+            "qsThreadInfo" => "l",
+            _ => "",
+        };
+        Some(String::from(reply))
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl StepHook for GdbStub {
+    fn before_step(&mut self, chip8: &mut Chip8) -> HookAction {
+        match self.poll(chip8) {
+            Ok(action) => action,
+            Err(e) => {
+                eprintln!("Lost the gdb connection: {e}");
+                self.disconnect();
+                HookAction::Step
+            }
+        }
+    }
+
+    fn after_step(&mut self, chip8: &Chip8) {
+        if self.connection.is_none() {
+            return;
+        }
+
+        if chip8.has_exited() {
+            self.send("W00");
+            self.disconnect();
+        } else if self.state == State::Stepping {
+            self.state = State::Halted;
+            self.send(STOPPED);
+        }
+    }
+
+    fn fault(&mut self, _chip8: &Chip8, fault: &Fault) -> bool {
+        if self.connection.is_none() {
+            return false;
+        }
+
+        self.state = State::Halted;
+        self.send(match fault.class {
+            FaultClass::InvalidOpcode | FaultClass::Unsupported => ILLEGAL_INSTRUCTION,
+            FaultClass::Stack | FaultClass::OutOfRange => SEGMENTATION_FAULT,
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::thread;
+
+    use chip8_core::clock::VirtualClock;
+
+    use super::*;
+    use crate::headless::{RunLength, run};
+
+    /// Calls a subroutine, then loops forever
+    const PROGRAM: [u8; 12] = [
+        0x22, 0x06, // 200: CALL 206
+        0x60, 0x01, // 202: LD V0, 1
+        0x12, 0x04, // 204: JP 204
+        0x61, 0x02, // 206: LD V1, 2
+        0xD0, 0x15, // 208: DRW V0, V1, 5
+        0x00, 0xEE, // 20A: RET
+    ];
+
+    /// Minimal gdb side of the protocol
+    struct Client {
+        /// Socket to the stub
+        stream: TcpStream,
+        /// Received bytes not forming a whole packet yet
+        buffer: Vec<u8>,
+    }
+
+    impl Client {
+        fn connect(address: SocketAddr) -> Self {
+            Self {
+                stream: TcpStream::connect(address).unwrap(),
+                buffer: Vec::new(),
+            }
+        }
+
+        fn send(&mut self, data: &str) {
+            self.stream.write_all(frame(data).as_bytes()).unwrap();
+        }
+
+        fn reply(&mut self) -> String {
+            loop {
+                if let Some(Incoming::Packet(packet)) = split_incoming(&mut self.buffer).pop() {
+                    self.stream.write_all(b"+").unwrap();
+                    return String::from_utf8(packet).unwrap();
+                }
+                let mut chunk = [0; 1024];
+                let n = self.stream.read(&mut chunk).unwrap();
+                assert_ne!(n, 0, "stub closed the connection");
+                self.buffer.extend_from_slice(&chunk[..n]);
+            }
+        }
+
+        fn request(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+    }
+
+    /// Run the program under a waiting stub in a thread, returning the machine once it is done
+    fn serve() -> (SocketAddr, thread::JoinHandle<Chip8>) {
+        let mut stub = GdbStub::bind(0, true).unwrap();
+        let address = stub.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut chip8 = Chip8::new();
+            chip8.load_program(&PROGRAM).unwrap();
+            let mut clock = VirtualClock::new();
+            run(
+                &mut chip8,
+                &mut clock,
+                RunLength::Cycles(1000),
+                &[],
+                Some(&mut stub),
+            )
+            .unwrap();
+            chip8
+        });
+
+        (address, handle)
+    }
+
+    #[test]
+    fn test_split_incoming() {
+        let mut buffer = b"+$g#67\x03$m0,1#00$qC#".to_vec();
+        assert_eq!(
+            split_incoming(&mut buffer),
+            vec![
+                Incoming::Packet(b"g".to_vec()),
+                Incoming::Interrupt,
+                Incoming::Corrupt,
+            ]
+        );
+        assert_eq!(buffer, b"$qC#");
+
+        buffer.extend_from_slice(b"b4");
+        assert_eq!(
+            split_incoming(&mut buffer),
+            vec![Incoming::Packet(b"qC".to_vec())]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_frame_and_escapes() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        assert_eq!(unescape(b"a}]b"), b"a}b");
+        assert_eq!(from_hex("00ff7a"), Some(vec![0x00, 0xFF, 0x7A]));
+        assert_eq!(from_hex("0"), None);
+        assert_eq!(to_hex(&[0xAB, 0x01]), "ab01");
+        assert_eq!(
+            parse_range("200,ffffffffffffffff"),
+            Some((0x200, PACKET_SIZE / 2))
+        );
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x6A, 0x42, 0xA3, 0x21]).unwrap();
+        chip8.step().unwrap();
+        chip8.step().unwrap();
+
+        let bytes = registers(&chip8);
+        assert_eq!(bytes.len(), 23);
+        assert_eq!(bytes[0xA], 0x42);
+        assert_eq!(register(&chip8, 16), Some(vec![0x03, 0x21]));
+        assert_eq!(register(&chip8, 17), Some(vec![0x02, 0x04]));
+        assert_eq!(register(&chip8, 18), Some(vec![0]));
+        assert_eq!(register(&chip8, 21), None);
+    }
+
+    #[test]
+    fn test_session() {
+        let (address, handle) = serve();
+        let mut gdb = Client::connect(address);
+
+        assert_eq!(gdb.request("?"), "S05");
+        assert!(
+            gdb.request("qSupported:multiprocess+")
+                .contains("qXfer:features:read+")
+        );
+        assert!(
+            gdb.request("qXfer:features:read:target.xml:0,fff")
+                .contains(r#"<reg name="pc" bitsize="16""#)
+        );
+
+        let registers = gdb.request("g");
+        assert_eq!(registers.len(), 46);
+        assert_eq!(&registers[36..40], "0200");
+
+        assert_eq!(gdb.request("Z0,208,2"), "OK");
+        assert_eq!(gdb.request("c"), "S05");
+        assert_eq!(gdb.request("p11"), "0208");
+        assert_eq!(gdb.request("p1"), "02");
+        assert_eq!(gdb.request("p12"), "01");
+
+        // continuing from the breakpoint doesn't stop at it again
+        assert_eq!(gdb.request("s"), "S05");
+        assert_eq!(gdb.request("p11"), "020a");
+        assert_eq!(gdb.request("z0,208,2"), "OK");
+
+        assert_eq!(gdb.request("m200,4"), "22066001");
+        assert_eq!(gdb.request("M300,2:abcd"), "OK");
+        assert_eq!(gdb.request("m300,2"), "abcd");
+        assert_eq!(gdb.request("M100,1:00"), "E01");
+        assert_eq!(gdb.request("mfff,ffffffffffffffff"), "00");
+        assert_eq!(
+            gdb.request("m200,ffffffffffffffff").len(),
+            (0x1000 - 0x200) * 2
+        );
+        assert_eq!(gdb.request("m200,zz"), "E01");
+        assert!(
+            gdb.request("qXfer:features:read:target.xml:10,ffffffffffffffff")
+                .starts_with('l')
+        );
+        assert_eq!(gdb.request("vCont?"), "");
+
+        assert_eq!(gdb.request("D"), "OK");
+        let chip8 = handle.join().unwrap();
+        assert_eq!(&chip8.memory().bytes()[0x300..0x302], &[0xAB, 0xCD]);
+        assert_eq!(chip8.cpu().program_counter(), 0x204);
+    }
+
+    #[test]
+    fn test_interrupt_and_kill() {
+        let (address, handle) = serve();
+        let mut gdb = Client::connect(address);
+
+        assert_eq!(gdb.request("QStartNoAckMode"), "OK");
+        gdb.send("c");
+        gdb.stream.write_all(&[0x03]).unwrap();
+        assert_eq!(gdb.reply(), "S02");
+
+        gdb.send("k");
+        let chip8 = handle.join().unwrap();
+        assert!(chip8.cpu().program_counter() >= 0x200);
+    }
+}
@@ -0,0 +1,485 @@
+//! Headless runner
+//!
+//! Runs a rom on a [`VirtualClock`] without a window or an audio device, feeding it scripted
+//! key presses, then dumps the framebuffer, the registers and a hash of the whole machine.
+//!
+//! A key script is a list of events separated by whitespace or commas, `FRAME:+KEY` presses
+//! and `FRAME:-KEY` releases the hex key at the start of the given frame. Everything after
+//! `#` on a line is a comment:
+//!
+//! ```text
+//! # hold 5 for half a second
+//! 60:+5 90:-5
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use thiserror::Error;
+
+use chip8_core::{
+    Chip8, Chip8Builder, Chip8Error, FaultPolicy, Platform, Quirks,
+    clock::{Timing, VirtualClock},
+    machine::{
+        display::{Framebuffer, PLANE_COUNT},
+        fault::FaultAction,
+        state::crc32,
+    },
+    trace::TraceOptions,
+};
+
+use crate::driver::{HookAction, StepHook, crash_dump_path};
+#[cfg(feature = "gdb")]
+use crate::gdb::GdbStub;
+
+/// Single scripted key event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// Frame at which the event happens
+    pub frame: u64,
+    /// Hex key
+    pub key: u8,
+    /// Pressed (true) or released (false)
+    pub pressed: bool,
+}
+
+/// Error in a key script
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("Invalid key event `{0}`, expected FRAME:+KEY or FRAME:-KEY")]
+pub struct KeyScriptError(String);
+
+/// When the run stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunLength {
+    /// After this many cpu cycles
+    Cycles(u64),
+    /// After this many timer ticks
+    Frames(u64),
+}
+
+/// Framebuffer dump formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// One character per pixel
+    Ascii,
+    /// Plain portable bitmap
+    Pbm,
+    /// Grayscale PNG
+    Png,
+}
+
+/// Settings of a headless run
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    /// Path to the rom to run, the crash dump is written next to it
+    pub rom: PathBuf,
+    /// Image to run instead of reading `rom`, e.g. compiled from Octo source
+    pub program: Option<Vec<u8>>,
+    /// Platform to emulate
+    pub platform: Platform,
+    /// Quirks to run with
+    pub quirks: Quirks,
+    /// What happens when the program faults
+    pub fault_policy: FaultPolicy,
+    /// When the run stops
+    pub length: RunLength,
+    /// Key script, see the module docs for the format
+    pub key_script: String,
+    /// Format of the framebuffer dump
+    pub dump_format: DumpFormat,
+    /// File for the framebuffer dump, stdout if `None`
+    pub dump: Option<PathBuf>,
+    /// File for the registers and the state hash, stdout if `None`
+    pub report: Option<PathBuf>,
+    /// Seed of the random number generator
+    pub seed: u64,
+    /// Cpu cycles run per 60 Hz frame
+    pub instructions_per_frame: u32,
+    /// How long instructions take
+    pub timing: Timing,
+    /// Trace of the executed instructions, `None` disables it
+    pub trace: Option<TraceOptions>,
+    /// Localhost port to wait for gdb on before running
+    #[cfg(feature = "gdb")]
+    pub gdb_port: Option<u16>,
+}
+
+/// Parse a key script, returning the events ordered by frame
+pub fn parse_key_script(script: &str) -> Result<Vec<KeyEvent>, KeyScriptError> {
+    let mut events = script
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+        .filter(|token| !token.is_empty())
+        .map(parse_key_event)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+/// Parse a single `FRAME:+KEY`/`FRAME:-KEY` event
+fn parse_key_event(token: &str) -> Result<KeyEvent, KeyScriptError> {
+    let invalid = || KeyScriptError(token.to_owned());
+
+    let (frame, action) = token.split_once(':').ok_or_else(invalid)?;
+    let frame = frame.parse().map_err(|_| invalid())?;
+    let pressed = match action.chars().next() {
+        Some('+') => true,
+        Some('-') => false,
+        _ => return Err(invalid()),
+    };
+    let key = u8::from_str_radix(&action[1..], 16).map_err(|_| invalid())?;
+    if key > 0xF {
+        return Err(invalid());
+    }
+
+    Ok(KeyEvent {
+        frame,
+        key,
+        pressed,
+    })
+}
+
+/// Run the machine until the clock reaches the length, the program exits or the hook stops it
+///
+/// Key events are applied at the start of their frame
+pub fn run(
+    chip8: &mut Chip8,
+    clock: &mut VirtualClock,
+    length: RunLength,
+    events: &[KeyEvent],
+    mut hook: Option<&mut dyn StepHook>,
+) -> Result<(), Chip8Error> {
+    let mut events = events.iter().peekable();
+
+    loop {
+        while let Some(event) = events.next_if(|event| event.frame <= clock.frames()) {
+            chip8.set_key_state(event.key, event.pressed)?;
+        }
+
+        let done = match length {
+            RunLength::Cycles(cycles) => clock.cycles() >= cycles,
+            RunLength::Frames(frames) => clock.frames() >= frames,
+        };
+        if done || chip8.has_exited() {
+            return Ok(());
+        }
+
+        if let Some(hook) = hook.as_deref_mut() {
+            match hook.before_step(chip8) {
+                HookAction::Step => {}
+                HookAction::Pause => continue,
+                HookAction::Stop => return Ok(()),
+            }
+        }
+
+        let ticks = match clock.step(chip8) {
+            Err(Chip8Error::Fault(fault)) if fault.action == FaultAction::Break => {
+                if let Some(hook) = hook.as_deref_mut()
+                    && hook.fault(chip8, &fault)
+                {
+                    continue;
+                }
+                return Err(Chip8Error::Fault(fault));
+            }
+            result => result?,
+        };
+        if let Some(hook) = hook.as_deref_mut() {
+            hook.after_step(chip8);
+        }
+        for _ in 0..ticks {
+            chip8.tick_timers();
+        }
+    }
+}
+
+/// Characters for every combination of the bitplanes, indexed by plane bits
+const ASCII_PALETTE: [char; 1 << PLANE_COUNT] = ['.', '#', '+', '@'];
+
+/// Gray levels for every combination of the bitplanes, same as the window colors
+const GRAY_PALETTE: [u8; 1 << PLANE_COUNT] = [0x00, 0xFF, 0xAA, 0x55];
+
+/// Combined plane bits of a pixel
+fn color_index(planes: &[Framebuffer; PLANE_COUNT], x: usize, y: usize) -> usize {
+    planes
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (i, plane)| acc | ((plane[y][x] as usize) << i))
+}
+
+/// Dump the visible part of the framebuffer as text, one character per pixel
+pub fn dump_ascii(planes: &[Framebuffer; PLANE_COUNT], (width, height): (usize, usize)) -> Vec<u8> {
+    let mut out = String::with_capacity((width + 1) * height);
+    for y in 0..height {
+        out.extend((0..width).map(|x| ASCII_PALETTE[color_index(planes, x, y)]));
+        out.push('\n');
+    }
+    out.into_bytes()
+}
+
+/// Dump the visible part of the framebuffer as a plain PBM, a pixel is black if set on any plane
+pub fn dump_pbm(planes: &[Framebuffer; PLANE_COUNT], (width, height): (usize, usize)) -> Vec<u8> {
+    let mut out = format!("P1\n{width} {height}\n");
+    for y in 0..height {
+        let row: Vec<&str> = (0..width)
+            .map(|x| {
+                if color_index(planes, x, y) != 0 {
+                    "1"
+                } else {
+                    "0"
+                }
+            })
+            .collect();
+        out.push_str(&row.join(" "));
+        out.push('\n');
+    }
+    out.into_bytes()
+}
+
+/// Dump the visible part of the framebuffer as an 8-bit grayscale PNG
+///
+/// The image data is stored uncompressed, the dumps are tiny anyway
+pub fn dump_png(planes: &[Framebuffer; PLANE_COUNT], (width, height): (usize, usize)) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for y in 0..height {
+        raw.push(0); // no filter
+        raw.extend((0..width).map(|x| GRAY_PALETTE[color_index(planes, x, y)]));
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]); // 8-bit grayscale, no interlacing
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_chunk(&mut png, b"IHDR", &header);
+    png_chunk(&mut png, b"IDAT", &zlib);
+    png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Append a PNG chunk with its length and checksum
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Adler-32 checksum used by zlib streams
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1_u32, 0_u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+/// Describe the registers, the run length and the state hash, one `name: value` per line
+pub fn report(chip8: &Chip8, clock: &VirtualClock) -> String {
+    let cpu = chip8.cpu();
+    let mut out = String::new();
+
+    // writing into a String never fails
+    let _ = writeln!(out, "cycles: {}", clock.cycles());
+    let _ = writeln!(out, "frames: {}", clock.frames());
+    let _ = writeln!(out, "exited: {}", chip8.has_exited());
+    let _ = writeln!(out, "PC: {:#06X}", cpu.program_counter());
+    let _ = writeln!(out, "I: {:#06X}", cpu.address());
+    let _ = writeln!(out, "SP: {}", cpu.stack_pointer());
+    let _ = writeln!(out, "DT: {}", cpu.delay_timer());
+    let _ = writeln!(out, "ST: {}", cpu.sound_timer());
+    for (i, value) in cpu.registers().iter().enumerate() {
+        let _ = writeln!(out, "V{i:X}: {value:#04X}");
+    }
+    let _ = writeln!(out, "state hash: {:08x}", crc32(&chip8.save_state()));
+
+    out
+}
+
+/// Write to the file, or to stdout if there is none
+fn write_output(path: Option<&Path>, bytes: &[u8]) -> anyhow::Result<()> {
+    match path {
+        Some(path) => {
+            std::fs::write(path, bytes).with_context(|| format!("writing {}", path.display()))
+        }
+        None => Ok(std::io::stdout().write_all(bytes)?),
+    }
+}
+
+/// Run the rom headlessly and dump the results
+///
+/// The dumps are written even if the machine failed, the error is returned afterwards.
+/// A machine halted by a fault also leaves a crash dump as `<rom>.crash`, the error is then
+/// the full fault report
+pub fn run_headless(options: &HeadlessOptions) -> anyhow::Result<()> {
+    if options.dump_format == DumpFormat::Png && options.dump.is_none() {
+        bail!("PNG dumps need a file, pass --dump");
+    }
+
+    let events = parse_key_script(&options.key_script)?;
+
+    let program = match &options.program {
+        Some(program) => program.clone(),
+        None => std::fs::read(&options.rom)
+            .with_context(|| format!("reading {}", options.rom.display()))?,
+    };
+    let mut chip8 = Chip8Builder::new()
+        .platform(options.platform)
+        .quirks(options.quirks)
+        .fault_policy(options.fault_policy)
+        .seed(options.seed)
+        .build()?;
+    chip8.load_program(&program)?;
+    if let Some(trace) = &options.trace {
+        let tracer = trace
+            .open()
+            .with_context(|| format!("creating {}", trace.path.display()))?;
+        chip8.set_tracer(tracer);
+    }
+
+    #[cfg(feature = "gdb")]
+    let mut gdb = options
+        .gdb_port
+        .map(|port| GdbStub::listen(port, true))
+        .transpose()?;
+    #[cfg(feature = "gdb")]
+    let hook = gdb.as_mut().map(|gdb| gdb as &mut dyn StepHook);
+    #[cfg(not(feature = "gdb"))]
+    let hook = None;
+
+    let mut clock = VirtualClock::with_instructions_per_frame(options.instructions_per_frame);
+    clock.set_timing(options.timing);
+    let result = run(&mut chip8, &mut clock, options.length, &events, hook);
+
+    let planes = chip8.planes();
+    let resolution = chip8.resolution();
+    let dump = match options.dump_format {
+        DumpFormat::Ascii => dump_ascii(planes, resolution),
+        DumpFormat::Pbm => dump_pbm(planes, resolution),
+        DumpFormat::Png => dump_png(planes, resolution),
+    };
+    write_output(options.dump.as_deref(), &dump)?;
+    write_output(options.report.as_deref(), report(&chip8, &clock).as_bytes())?;
+    if let Some(mut tracer) = chip8.take_tracer() {
+        tracer.flush()?;
+    }
+
+    if let (Some(fault), Some(dump)) = (chip8.halted(), chip8.crash_dump()) {
+        let path = crash_dump_path(&options.rom);
+        std::fs::write(&path, dump).with_context(|| format!("writing {}", path.display()))?;
+        bail!("{}Crash dump written to {}", fault.report(), path.display());
+    }
+
+    Ok(result?)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_script() {
+        let script = "# comment\n60:+5, 30:-A # trailing\n\n 10:+f";
+        assert_eq!(
+            parse_key_script(script).unwrap(),
+            vec![
+                KeyEvent {
+                    frame: 10,
+                    key: 0xF,
+                    pressed: true
+                },
+                KeyEvent {
+                    frame: 30,
+                    key: 0xA,
+                    pressed: false
+                },
+                KeyEvent {
+                    frame: 60,
+                    key: 0x5,
+                    pressed: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_key_script() {
+        for token in ["60", "60:5", "x:+5", "60:+10", "60:+"] {
+            assert_eq!(
+                parse_key_script(token),
+                Err(KeyScriptError(token.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_with_keys() {
+        let mut chip8 = Chip8::new();
+        // wait for a key into V0, then loop forever
+        chip8.load_program(&[0xF0, 0x0A, 0x12, 0x02]).unwrap();
+        let events = parse_key_script("2:+7").unwrap();
+
+        let mut clock = VirtualClock::new();
+        run(&mut chip8, &mut clock, RunLength::Frames(3), &events, None).unwrap();
+        assert_eq!(clock.frames(), 3);
+        assert_eq!(chip8.cpu().registers()[0], 7);
+        assert_eq!(chip8.cpu().program_counter(), 0x202);
+    }
+
+    #[test]
+    fn test_run_stops_on_exit() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x00, 0xFD]).unwrap();
+
+        let mut clock = VirtualClock::new();
+        run(&mut chip8, &mut clock, RunLength::Cycles(100), &[], None).unwrap();
+        assert_eq!(clock.cycles(), 1);
+    }
+
+    #[test]
+    fn test_run_error() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x00, 0x00]).unwrap();
+
+        let mut clock = VirtualClock::new();
+        assert!(run(&mut chip8, &mut clock, RunLength::Cycles(1), &[], None).is_err());
+    }
+
+    #[test]
+    fn test_dumps() {
+        let mut planes = [[[false; 128]; 64]; PLANE_COUNT];
+        planes[0][0][0] = true;
+        planes[1][1][1] = true;
+
+        assert_eq!(dump_ascii(&planes, (3, 2)), b"#..\n.+.\n");
+        assert_eq!(dump_pbm(&planes, (3, 2)), b"P1\n3 2\n1 0 0\n0 1 0\n");
+
+        let png = dump_png(&planes, (3, 2));
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0DIHDR"));
+        assert!(png.ends_with(b"IEND\xAE\x42\x60\x82"));
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
@@ -0,0 +1,50 @@
+//! Keyboard layout shared by the frontends
+//!
+//! The hex keypad is mapped onto the left side of a QWERTY keyboard:
+//!
+//! ```text
+//! 1 2 3 C      1 2 3 4
+//! 4 5 6 D  <-  Q W E R
+//! 7 8 9 E      A S D F
+//! A 0 B F      Z X C V
+//! ```
+
+/// Keyboard keys and the hex keys they stand for, row by row
+pub const LAYOUT: [[(char, u8); 4]; 4] = [
+    [('1', 0x1), ('2', 0x2), ('3', 0x3), ('4', 0xC)],
+    [('q', 0x4), ('w', 0x5), ('e', 0x6), ('r', 0xD)],
+    [('a', 0x7), ('s', 0x8), ('d', 0x9), ('f', 0xE)],
+    [('z', 0xA), ('x', 0x0), ('c', 0xB), ('v', 0xF)],
+];
+
+/// Hex key for a keyboard key from [`LAYOUT`], case insensitive
+pub fn hex_key(key: char) -> Option<u8> {
+    let key = key.to_ascii_lowercase();
+    LAYOUT
+        .iter()
+        .flatten()
+        .find(|(c, _)| *c == key)
+        .map(|&(_, hex)| hex)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_key() {
+        assert_eq!(hex_key('1'), Some(0x1));
+        assert_eq!(hex_key('4'), Some(0xC));
+        assert_eq!(hex_key('X'), Some(0x0));
+        assert_eq!(hex_key('v'), Some(0xF));
+        assert_eq!(hex_key('p'), None);
+    }
+
+    #[test]
+    fn test_layout_covers_keypad() {
+        let mut keys: Vec<u8> = LAYOUT.iter().flatten().map(|&(_, hex)| hex).collect();
+        keys.sort();
+        assert_eq!(keys, (0..16).collect::<Vec<u8>>());
+    }
+}
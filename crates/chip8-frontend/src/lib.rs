@@ -0,0 +1,30 @@
+//! Frontends for [`chip8_core`]
+//!
+//! [`driver`] holds the traits a frontend implements and the loop running over them.
+//!
+//! - `window` feature (default): interactive winit window with audio, save state slots and rewind
+//! - `tui` feature (default): terminal frontend drawing with half blocks or braille
+//! - `headless` feature (default): runs a rom on a virtual clock and dumps the results
+//! - `debugger` feature (default): breakpoints, stepping and memory inspection from a prompt
+//! - `gdb` feature (default): GDB remote serial protocol stub for every frontend
+//! - `dap` feature (default): Debug Adapter Protocol server for editors, with source maps
+//! - `rewind` feature (default): rewinding in the driver loop
+
+#![warn(missing_docs)]
+#![warn(clippy::missing_docs_in_private_items)]
+#![cfg_attr(coverage_nightly, feature(coverage_attribute))]
+
+#[cfg(feature = "dap")]
+pub mod dap;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+pub mod driver;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+#[cfg(feature = "headless")]
+pub mod headless;
+pub mod keymap;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "window")]
+pub mod window;
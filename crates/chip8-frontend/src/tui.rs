@@ -0,0 +1,720 @@
+//! Terminal frontend
+//!
+//! Draws the screen with Unicode half blocks (two pixels per cell, in color) or braille
+//! (eight pixels per cell, monochrome), so roms can be played over SSH. The first terminal line
+//! is a status bar, the buzzer shows up there or rings the terminal bell.
+//!
+//! Most terminals only report key presses, so a pressed key is released automatically after
+//! [`AUTO_RELEASE_FRAMES`] unless the terminal supports release events.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Stdout, Write},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use chip8_core::{
+    Chip8, Chip8Error, FaultPolicy, Platform, Quirks,
+    clock::Timing,
+    machine::display::{Framebuffer, PLANE_COUNT},
+    trace::TraceOptions,
+};
+use crossterm::{
+    cursor, event,
+    event::{
+        Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    queue,
+    style::{self, Color, Stylize},
+    terminal,
+};
+
+#[cfg(feature = "gdb")]
+use crate::gdb::GdbStub;
+use crate::{
+    driver::{
+        AudioSink, Driver, DriverOptions, FrameStatus, InputEvent, InputSource, SpeedStatus, Tone,
+        VideoSink,
+    },
+    keymap,
+};
+
+/// Frames a key stays pressed after a press event when the terminal doesn't report releases
+pub const AUTO_RELEASE_FRAMES: u8 = 6;
+
+/// Keys listed in the status bar
+const HELP: &str = "Esc quit, Backspace rewind, Tab turbo, P pause, N frame, M slow motion, \
+                    -/= speed, F1-F9 load, Shift+F1-F9 save";
+
+/// How pixels are packed into terminal cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Charset {
+    /// `▀` with foreground and background colors, 1x2 pixels per cell
+    #[default]
+    HalfBlock,
+    /// Braille patterns, 2x4 pixels per cell, a pixel is lit if set on any plane
+    Braille,
+}
+
+/// How the buzzer is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BuzzerStyle {
+    /// Highlight the status bar while the buzzer sounds
+    #[default]
+    Flash,
+    /// Ring the terminal bell when the buzzer starts
+    Bell,
+}
+
+/// Settings of the terminal frontend
+#[derive(Debug, Clone)]
+pub struct TuiOptions {
+    /// Path to the rom to run, save state slots are stored next to it
+    pub rom: PathBuf,
+    /// Image to run instead of reading `rom`, e.g. compiled from Octo source
+    pub program: Option<Vec<u8>>,
+    /// Platform to emulate
+    pub platform: Platform,
+    /// Quirks to run with
+    pub quirks: Quirks,
+    /// What happens when the program faults
+    pub fault_policy: FaultPolicy,
+    /// Memory for the rewind history in bytes, 0 disables rewinding
+    pub rewind_memory: usize,
+    /// Snapshots stepped back per frame while the rewind key is held
+    pub rewind_speed: u32,
+    /// Cpu cycles run per 60 Hz frame
+    pub instructions_per_frame: u32,
+    /// How long instructions take
+    pub timing: Timing,
+    /// Speed while the turbo key is held, relative to real time
+    pub turbo_speed: f64,
+    /// Speed in slow motion, relative to real time
+    pub slow_motion_speed: f64,
+    /// Trace of the executed instructions, `None` disables it
+    pub trace: Option<TraceOptions>,
+    /// Localhost port gdb can attach to
+    #[cfg(feature = "gdb")]
+    pub gdb_port: Option<u16>,
+    /// How pixels are drawn
+    pub charset: Charset,
+    /// How the buzzer is shown
+    pub buzzer: BuzzerStyle,
+}
+
+/// Colors for every combination of the bitplanes, same as the window ones
+const PALETTE: [Color; 1 << PLANE_COUNT] = [
+    Color::Rgb {
+        r: 0x00,
+        g: 0x00,
+        b: 0x00,
+    },
+    Color::Rgb {
+        r: 0xFF,
+        g: 0xFF,
+        b: 0xFF,
+    },
+    Color::Rgb {
+        r: 0xAA,
+        g: 0xAA,
+        b: 0xAA,
+    },
+    Color::Rgb {
+        r: 0x55,
+        g: 0x55,
+        b: 0x55,
+    },
+];
+
+/// Combined plane bits of a pixel
+fn color_index(planes: &[Framebuffer; PLANE_COUNT], x: usize, y: usize) -> usize {
+    planes
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (i, plane)| acc | ((plane[y][x] as usize) << i))
+}
+
+/// Color indices of the upper and lower pixel of every half block cell, row by row
+fn half_block_cells(
+    planes: &[Framebuffer; PLANE_COUNT],
+    (width, height): (usize, usize),
+) -> Vec<Vec<(usize, usize)>> {
+    (0..height / 2)
+        .map(|row| {
+            (0..width)
+                .map(|x| {
+                    (
+                        color_index(planes, x, 2 * row),
+                        color_index(planes, x, 2 * row + 1),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Braille lines of the screen
+fn braille_lines(
+    planes: &[Framebuffer; PLANE_COUNT],
+    (width, height): (usize, usize),
+) -> Vec<String> {
+    /// Dot bit for every pixel of a 2x4 cell, indexed by `[y][x]`
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    (0..height / 4)
+        .map(|row| {
+            (0..width / 2)
+                .map(|column| {
+                    let mut bits = 0;
+                    for (dy, dots) in DOTS.iter().enumerate() {
+                        for (dx, dot) in dots.iter().enumerate() {
+                            if color_index(planes, 2 * column + dx, 4 * row + dy) != 0 {
+                                bits |= dot;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + bits).unwrap_or(' ')
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Terminal cells needed to draw a screen of the given resolution, status bar included
+fn required_size(charset: Charset, (width, height): (usize, usize)) -> (usize, usize) {
+    match charset {
+        Charset::HalfBlock => (width, height / 2 + 1),
+        Charset::Braille => (width / 2, height / 4 + 1),
+    }
+}
+
+/// Planes and resolution of a presented picture
+type Picture = (Box<[Framebuffer; PLANE_COUNT]>, (usize, usize));
+
+/// Video sink drawing below the status bar
+struct TerminalVideo {
+    /// Output terminal
+    out: Stdout,
+    /// How pixels are drawn
+    charset: Charset,
+    /// Terminal size in cells
+    size: (u16, u16),
+    /// Last presented picture, redrawn after a resize
+    last: Option<Picture>,
+}
+
+impl TerminalVideo {
+    /// Adapt to the new terminal size and redraw the last picture
+    fn resize(&mut self, size: (u16, u16)) -> io::Result<()> {
+        self.size = size;
+        queue!(self.out, terminal::Clear(terminal::ClearType::All))?;
+        if let Some((planes, resolution)) = self.last.take() {
+            self.draw(&planes, resolution)?;
+            self.last = Some((planes, resolution));
+        }
+        Ok(())
+    }
+
+    /// Draw the picture, or a notice if the terminal is too small for it
+    fn draw(
+        &mut self,
+        planes: &[Framebuffer; PLANE_COUNT],
+        resolution: (usize, usize),
+    ) -> io::Result<()> {
+        let (columns, rows) = required_size(self.charset, resolution);
+        if (self.size.0 as usize) < columns || (self.size.1 as usize) < rows {
+            queue!(
+                self.out,
+                terminal::Clear(terminal::ClearType::All),
+                cursor::MoveTo(0, 1),
+                style::Print(format!("Terminal too small, need {columns}x{rows}")),
+            )?;
+            return self.out.flush();
+        }
+
+        match self.charset {
+            Charset::HalfBlock => {
+                for (row, cells) in half_block_cells(planes, resolution).iter().enumerate() {
+                    queue!(self.out, cursor::MoveTo(0, row as u16 + 1))?;
+                    let mut current = None;
+                    for &(upper, lower) in cells {
+                        if current != Some((upper, lower)) {
+                            queue!(
+                                self.out,
+                                style::SetForegroundColor(PALETTE[upper]),
+                                style::SetBackgroundColor(PALETTE[lower]),
+                            )?;
+                            current = Some((upper, lower));
+                        }
+                        queue!(self.out, style::Print('▀'))?;
+                    }
+                }
+                queue!(self.out, style::ResetColor)?;
+            }
+            Charset::Braille => {
+                for (row, line) in braille_lines(planes, resolution).iter().enumerate() {
+                    queue!(
+                        self.out,
+                        cursor::MoveTo(0, row as u16 + 1),
+                        style::Print(line)
+                    )?;
+                }
+            }
+        }
+
+        self.out.flush()
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl VideoSink for TerminalVideo {
+    fn present(&mut self, planes: &[Framebuffer; PLANE_COUNT], resolution: (usize, usize)) {
+        // a broken terminal shows up as a failed read in the input loop
+        let _ = self.draw(planes, resolution);
+        self.last = Some((Box::new(*planes), resolution));
+    }
+}
+
+/// Audio sink showing the buzzer in the status bar or ringing the bell
+struct TerminalAudio {
+    /// Output terminal
+    out: Stdout,
+    /// How the buzzer is shown
+    style: BuzzerStyle,
+    /// Rom name shown in the status bar
+    title: String,
+    /// Speed shown in the status bar
+    speed: Option<SpeedStatus>,
+    /// Fault shown in the status bar instead of the speed
+    fault: Option<String>,
+    /// Whether the buzzer sounded during the last frame
+    playing: bool,
+}
+
+impl TerminalAudio {
+    /// Draw the status bar, highlighted while the buzzer sounds
+    fn draw_status(&mut self) -> io::Result<()> {
+        let status = match (&self.fault, self.speed) {
+            (Some(fault), _) => format!(" {} | {fault} | {HELP}", self.title),
+            (None, Some(speed)) => format!(" {} | {speed} | {HELP}", self.title),
+            (None, None) => format!(" {} | {HELP}", self.title),
+        };
+        let flash = self.playing && self.style == BuzzerStyle::Flash;
+        let text = if flash {
+            format!("{status} ♪").reverse()
+        } else {
+            status.stylize()
+        };
+        queue!(
+            self.out,
+            cursor::MoveTo(0, 0),
+            style::PrintStyledContent(text),
+            terminal::Clear(terminal::ClearType::UntilNewLine)
+        )?;
+        self.out.flush()
+    }
+
+    /// Show the new speed and fault if they changed
+    fn update_status(&mut self, speed: SpeedStatus, fault: Option<String>) -> io::Result<()> {
+        if self.speed == Some(speed) && self.fault == fault {
+            return Ok(());
+        }
+        self.speed = Some(speed);
+        self.fault = fault;
+        self.draw_status()
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl AudioSink for TerminalAudio {
+    fn update(&mut self, tone: Option<Tone>) {
+        let playing = tone.is_some();
+        if playing == self.playing {
+            return;
+        }
+        self.playing = playing;
+
+        if playing && self.style == BuzzerStyle::Bell {
+            let _ = queue!(self.out, style::Print('\x07'));
+        }
+        let _ = self.draw_status();
+    }
+}
+
+/// Input queue releasing keys by itself on terminals without release events
+#[derive(Debug, Default)]
+struct TerminalInput {
+    /// Events for the next frame
+    queue: VecDeque<InputEvent>,
+    /// Frames left until every hex key is released, 0 if it is not held
+    held: [u8; 16],
+    /// Frames left until rewinding stops, 0 if it is not held
+    rewind_held: u8,
+    /// Frames left until the turbo stops, 0 if it is not held
+    turbo_held: u8,
+    /// Set once the terminal reported a release, auto release is turned off then
+    reports_releases: bool,
+}
+
+impl TerminalInput {
+    /// Handle a terminal key event, returns `false` if the user asked to quit
+    fn key(&mut self, event: KeyEvent) -> bool {
+        let pressed = match event.kind {
+            KeyEventKind::Press | KeyEventKind::Repeat => true,
+            KeyEventKind::Release => {
+                self.reports_releases = true;
+                false
+            }
+        };
+
+        match event.code {
+            KeyCode::Esc => return !pressed,
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return !pressed;
+            }
+            KeyCode::Backspace => {
+                self.rewind_held = if pressed { AUTO_RELEASE_FRAMES } else { 0 };
+                self.queue.push_back(InputEvent::Rewind(pressed));
+            }
+            KeyCode::Tab => {
+                self.turbo_held = if pressed { AUTO_RELEASE_FRAMES } else { 0 };
+                self.queue.push_back(InputEvent::Turbo(pressed));
+            }
+            KeyCode::Char('-') if pressed => self.queue.push_back(InputEvent::FewerInstructions),
+            KeyCode::Char('=' | '+') if pressed => {
+                self.queue.push_back(InputEvent::MoreInstructions);
+            }
+            KeyCode::Char(c @ ('p' | 'n' | 'm')) if event.kind == KeyEventKind::Press => {
+                self.queue.push_back(match c {
+                    'p' => InputEvent::Pause,
+                    'n' => InputEvent::FrameAdvance,
+                    _ => InputEvent::SlowMotion,
+                });
+            }
+            KeyCode::F(slot @ 1..=9) if event.kind == KeyEventKind::Press => {
+                self.queue
+                    .push_back(if event.modifiers.contains(KeyModifiers::SHIFT) {
+                        InputEvent::SaveSlot(slot)
+                    } else {
+                        InputEvent::LoadSlot(slot)
+                    });
+            }
+            KeyCode::Char(c) => {
+                if let Some(key) = keymap::hex_key(c) {
+                    self.held[key as usize] = if pressed { AUTO_RELEASE_FRAMES } else { 0 };
+                    self.queue.push_back(InputEvent::Key { key, pressed });
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Count down the held keys and release the expired ones
+    fn end_frame(&mut self) {
+        if self.reports_releases {
+            return;
+        }
+
+        for (key, frames) in self.held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    self.queue.push_back(InputEvent::Key {
+                        key: key as u8,
+                        pressed: false,
+                    });
+                }
+            }
+        }
+        if self.rewind_held > 0 {
+            self.rewind_held -= 1;
+            if self.rewind_held == 0 {
+                self.queue.push_back(InputEvent::Rewind(false));
+            }
+        }
+        if self.turbo_held > 0 {
+            self.turbo_held -= 1;
+            if self.turbo_held == 0 {
+                self.queue.push_back(InputEvent::Turbo(false));
+            }
+        }
+    }
+}
+
+impl InputSource for TerminalInput {
+    fn poll(&mut self) -> Option<InputEvent> {
+        self.queue.pop_front()
+    }
+}
+
+/// Puts the terminal into raw mode on the alternate screen and restores it when dropped
+struct TerminalGuard {
+    /// Whether keyboard enhancement flags were pushed and need popping
+    enhanced: bool,
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl TerminalGuard {
+    /// Prepare the terminal
+    fn new() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut out = io::stdout();
+        queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            queue!(
+                out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        out.flush()?;
+
+        Ok(Self { enhanced })
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.enhanced {
+            let _ = queue!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = queue!(
+            out,
+            style::ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Run the rom in the terminal until it exits or the user presses Esc
+#[cfg_attr(coverage_nightly, coverage(off))]
+pub fn run_tui(options: &TuiOptions) -> anyhow::Result<()> {
+    let program = match &options.program {
+        Some(program) => program.clone(),
+        None => std::fs::read(&options.rom)
+            .with_context(|| format!("reading {}", options.rom.display()))?,
+    };
+    let mut chip8 = Chip8::with_platform(options.platform, options.quirks);
+    chip8.set_fault_policy(options.fault_policy);
+    chip8.load_program(&program)?;
+    if let Some(trace) = &options.trace {
+        let tracer = trace
+            .open()
+            .with_context(|| format!("creating {}", trace.path.display()))?;
+        chip8.set_tracer(tracer);
+    }
+
+    #[cfg(feature = "gdb")]
+    let gdb = options
+        .gdb_port
+        .map(|port| GdbStub::listen(port, false))
+        .transpose()?;
+
+    let _guard = TerminalGuard::new()?;
+
+    let title = options
+        .rom
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let mut audio = TerminalAudio {
+        out: io::stdout(),
+        style: options.buzzer,
+        title,
+        speed: None,
+        fault: None,
+        playing: false,
+    };
+    audio.draw_status()?;
+
+    let video = TerminalVideo {
+        out: io::stdout(),
+        charset: options.charset,
+        size: terminal::size()?,
+        last: None,
+    };
+    let driver_options = DriverOptions {
+        rom: Some(options.rom.clone()),
+        rewind_memory: options.rewind_memory,
+        rewind_speed: options.rewind_speed,
+        instructions_per_frame: options.instructions_per_frame,
+        timing: options.timing,
+        turbo_speed: options.turbo_speed,
+        slow_motion_speed: options.slow_motion_speed,
+    };
+    let mut driver = Driver::new(
+        chip8,
+        video,
+        audio,
+        TerminalInput::default(),
+        &driver_options,
+    );
+    #[cfg(feature = "gdb")]
+    if let Some(gdb) = gdb {
+        driver.set_step_hook(Box::new(gdb));
+    }
+
+    let mut fault = None;
+    loop {
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) if !driver.input_mut().key(key) => return Ok(()),
+                Event::Resize(columns, rows) => {
+                    driver.video_mut().resize((columns, rows))?;
+                    driver.audio_mut().draw_status()?;
+                }
+                _ => {}
+            }
+        }
+
+        for _ in 0..driver.frames_due() {
+            match driver.run_frame() {
+                Ok(FrameStatus::Running) => {}
+                Ok(FrameStatus::Exited | FrameStatus::Quit) => return Ok(()),
+                Err(Chip8Error::Fault(error)) => fault = Some(error.to_string()),
+                Err(e) => return Err(e.into()),
+            }
+            driver.input_mut().end_frame();
+        }
+        let speed = driver.speed_status();
+        // the fault stays up until the machine runs again, e.g. after rewinding
+        if driver.chip8().halted().is_none() && !speed.paused {
+            fault = None;
+        }
+        driver.audio_mut().update_status(speed, fault.clone())?;
+
+        thread::sleep(driver.until_next_frame());
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use crossterm::event::KeyEventState;
+
+    use super::*;
+
+    fn planes() -> [Framebuffer; PLANE_COUNT] {
+        let mut planes = [[[false; 128]; 64]; PLANE_COUNT];
+        planes[0][0][0] = true;
+        planes[1][1][0] = true;
+        planes[0][3][3] = true;
+        planes
+    }
+
+    fn key(code: KeyCode, kind: KeyEventKind) -> KeyEvent {
+        KeyEvent {
+            code,
+            modifiers: KeyModifiers::NONE,
+            kind,
+            state: KeyEventState::NONE,
+        }
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let cells = half_block_cells(&planes(), (4, 4));
+        assert_eq!(cells.len(), 2);
+        assert_eq!(cells[0], vec![(1, 2), (0, 0), (0, 0), (0, 0)]);
+        assert_eq!(cells[1][3], (0, 1));
+    }
+
+    #[test]
+    fn test_braille() {
+        let lines = braille_lines(&planes(), (4, 4));
+        assert_eq!(lines, vec!["\u{2803}\u{2880}".to_owned()]);
+    }
+
+    #[test]
+    fn test_required_size() {
+        assert_eq!(required_size(Charset::HalfBlock, (64, 32)), (64, 17));
+        assert_eq!(required_size(Charset::Braille, (128, 64)), (64, 17));
+    }
+
+    #[test]
+    fn test_auto_release() {
+        let mut input = TerminalInput::default();
+        assert!(input.key(key(KeyCode::Char('w'), KeyEventKind::Press)));
+        assert_eq!(
+            input.poll(),
+            Some(InputEvent::Key {
+                key: 0x5,
+                pressed: true
+            })
+        );
+
+        for _ in 0..AUTO_RELEASE_FRAMES - 1 {
+            input.end_frame();
+            assert_eq!(input.poll(), None);
+        }
+        input.end_frame();
+        assert_eq!(
+            input.poll(),
+            Some(InputEvent::Key {
+                key: 0x5,
+                pressed: false
+            })
+        );
+    }
+
+    #[test]
+    fn test_release_events_disable_auto_release() {
+        let mut input = TerminalInput::default();
+        input.key(key(KeyCode::Char('w'), KeyEventKind::Release));
+        input.key(key(KeyCode::Char('w'), KeyEventKind::Press));
+        input.poll();
+        input.poll();
+
+        for _ in 0..AUTO_RELEASE_FRAMES {
+            input.end_frame();
+        }
+        assert_eq!(input.poll(), None);
+    }
+
+    #[test]
+    fn test_control_keys() {
+        let mut input = TerminalInput::default();
+        assert!(!input.key(key(KeyCode::Esc, KeyEventKind::Press)));
+
+        let mut save = key(KeyCode::F(3), KeyEventKind::Press);
+        save.modifiers = KeyModifiers::SHIFT;
+        input.key(save);
+        input.key(key(KeyCode::F(3), KeyEventKind::Press));
+        input.key(key(KeyCode::Backspace, KeyEventKind::Press));
+
+        assert_eq!(input.poll(), Some(InputEvent::SaveSlot(3)));
+        assert_eq!(input.poll(), Some(InputEvent::LoadSlot(3)));
+        assert_eq!(input.poll(), Some(InputEvent::Rewind(true)));
+    }
+
+    #[test]
+    fn test_speed_keys() {
+        let mut input = TerminalInput::default();
+        input.key(key(KeyCode::Char('p'), KeyEventKind::Press));
+        input.key(key(KeyCode::Char('n'), KeyEventKind::Repeat));
+        input.key(key(KeyCode::Char('='), KeyEventKind::Repeat));
+        input.key(key(KeyCode::Tab, KeyEventKind::Press));
+
+        assert_eq!(input.poll(), Some(InputEvent::Pause));
+        assert_eq!(input.poll(), Some(InputEvent::MoreInstructions));
+        assert_eq!(input.poll(), Some(InputEvent::Turbo(true)));
+
+        for _ in 0..AUTO_RELEASE_FRAMES {
+            input.end_frame();
+        }
+        assert_eq!(input.poll(), Some(InputEvent::Turbo(false)));
+    }
+}
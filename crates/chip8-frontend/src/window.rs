@@ -0,0 +1,503 @@
+//! Module that contains the window logic
+//!
+//! The winit/pixels/rodio implementation of the [`driver`](crate::driver) traits
+
+use std::{collections::VecDeque, path::PathBuf, sync::Arc, time::Instant};
+
+use anyhow::Context;
+use chip8_core::{
+    Chip8, Chip8Error, FaultPolicy, Platform, Quirks,
+    clock::{TIMER_INTERVAL, Timing},
+    machine::display::{Framebuffer, PLANE_COUNT},
+    trace::TraceOptions,
+};
+use pixels::{Pixels, SurfaceTexture};
+use rodio::{
+    OutputStream, OutputStreamBuilder, Sink, Source, buffer::SamplesBuffer, source::SineWave,
+};
+use tklog::{info, trace};
+use winit::{
+    application::ApplicationHandler,
+    event::{ElementState, KeyEvent, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    keyboard::{
+        KeyCode, ModifiersState,
+        PhysicalKey::{Code, Unidentified},
+    },
+    window::{Window, WindowAttributes, WindowId},
+};
+
+#[cfg(feature = "gdb")]
+use crate::gdb::GdbStub;
+use crate::{
+    driver::{AudioSink, Driver, DriverOptions, FrameStatus, InputEvent, Tone, VideoSink},
+    keymap,
+};
+
+/// Settings of the window frontend
+#[derive(Debug, Clone)]
+pub struct WindowOptions {
+    /// Path to the rom to run, save state slots are stored next to it
+    pub rom: PathBuf,
+    /// Image to run instead of reading `rom`, e.g. compiled from Octo source
+    pub program: Option<Vec<u8>>,
+    /// Platform to emulate
+    pub platform: Platform,
+    /// Quirks to run with
+    pub quirks: Quirks,
+    /// What happens when the program faults
+    pub fault_policy: FaultPolicy,
+    /// Memory for the rewind history in bytes, 0 disables rewinding
+    pub rewind_memory: usize,
+    /// Snapshots stepped back per frame while the rewind key is held
+    pub rewind_speed: u32,
+    /// Cpu cycles run per 60 Hz frame
+    pub instructions_per_frame: u32,
+    /// How long instructions take
+    pub timing: Timing,
+    /// Speed while the turbo key is held, relative to real time
+    pub turbo_speed: f64,
+    /// Speed in slow motion, relative to real time
+    pub slow_motion_speed: f64,
+    /// Trace of the executed instructions, `None` disables it
+    pub trace: Option<TraceOptions>,
+    /// Localhost port gdb can attach to
+    #[cfg(feature = "gdb")]
+    pub gdb_port: Option<u16>,
+}
+
+/// Video sink drawing into a pixels surface with phosphor persistence
+#[derive(Default)]
+struct PixelsVideo<'a> {
+    /// Pixels struct to draw on the window, `None` until the window is created
+    pixels: Option<Pixels<'a>>,
+}
+
+impl<'a> PixelsVideo<'a> {
+    /// Decay factor for the phosphor persitence
+    const DECAY: f32 = 0.25;
+
+    /// Colors for every combination of the bitplanes, indexed by plane bits
+    const PALETTE: [[u8; 4]; 1 << PLANE_COUNT] = [
+        [0x00, 0x00, 0x00, 0xFF],
+        [0xFF, 0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA, 0xFF],
+        [0x55, 0x55, 0x55, 0xFF],
+    ];
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<'a> VideoSink for PixelsVideo<'a> {
+    fn present(&mut self, planes: &[Framebuffer; PLANE_COUNT], (width, height): (usize, usize)) {
+        let Some(pixels) = &mut self.pixels else {
+            return;
+        };
+
+        let scale_x = 640 / width;
+        let scale_y = 320 / height;
+
+        let frame = pixels.frame_mut();
+
+        for y in 0..height {
+            for x in 0..width {
+                let color_index = planes
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (i, plane)| acc | ((plane[y][x] as usize) << i));
+                let color = Self::PALETTE[color_index];
+
+                for dy in 0..scale_y {
+                    for dx in 0..scale_x {
+                        let px = x * scale_x + dx;
+                        let py = y * scale_y + dy;
+                        let i = (py * 640 + px) * 4;
+
+                        // Phosphor persistence
+                        for c in 0..3 {
+                            let old = frame[i + c] as f32;
+                            frame[i + c] =
+                                ((old * Self::DECAY) + color[c] as f32 * (1.0 - Self::DECAY)) as u8;
+                        }
+                        frame[i + 3] = 0xFF; // Alpha channel
+                    }
+                }
+            }
+        }
+
+        let _ = pixels.render();
+    }
+}
+
+/// Audio sink playing through rodio
+#[derive(Default)]
+struct RodioAudio {
+    /// Stream handle for audio, has to stay alive while playing
+    stream_handle: Option<OutputStream>,
+    /// Audio sink
+    sink: Option<Sink>,
+    /// Tone currently queued in the sink
+    current_tone: Option<Tone>,
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl AudioSink for RodioAudio {
+    /// XO-CHIP programs that loaded an audio pattern hear it, everything else gets a sine buzzer
+    fn update(&mut self, tone: Option<Tone>) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+
+        let Some(tone) = tone else {
+            sink.pause();
+            return;
+        };
+
+        if Some(tone) != self.current_tone {
+            sink.clear();
+            self.current_tone = Some(tone);
+        }
+        if sink.is_paused() {
+            sink.play();
+        }
+        if sink.empty() {
+            match tone {
+                Tone::Pattern { pattern, rate } => {
+                    let samples = (0..128)
+                        .map(|bit| {
+                            let on = (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1;
+                            if on { 1.0 } else { -1.0 }
+                        })
+                        .collect::<Vec<f32>>();
+                    sink.append(
+                        SamplesBuffer::new(1, rate as u32, samples)
+                            .amplify(0.2)
+                            .repeat_infinite(),
+                    );
+                }
+                Tone::Buzzer => sink.append(SineWave::new(440.0).amplify(0.2).repeat_infinite()),
+            }
+        }
+    }
+}
+
+/// The main emulator application
+struct App<'a> {
+    /// Application's window
+    window: Option<Arc<Window>>,
+    /// Window's ID
+    window_id: Option<WindowId>,
+    /// Emulation loop, keyboard events are queued for it
+    driver: Driver<PixelsVideo<'a>, RodioAudio, VecDeque<InputEvent>>,
+    /// Currently held modifier keys
+    modifiers: ModifiersState,
+    /// Last fault, shown in the title until the machine runs again
+    fault: Option<String>,
+    /// Text of the window title
+    title: String,
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<'a> App<'a> {
+    /// Create an application struct from a ready chip8 instance
+    fn from_chip8(chip8: Chip8, options: &WindowOptions) -> Self {
+        let driver_options = DriverOptions {
+            rom: Some(options.rom.clone()),
+            rewind_memory: options.rewind_memory,
+            rewind_speed: options.rewind_speed,
+            instructions_per_frame: options.instructions_per_frame,
+            timing: options.timing,
+            turbo_speed: options.turbo_speed,
+            slow_motion_speed: options.slow_motion_speed,
+        };
+
+        Self {
+            window: None,
+            window_id: None,
+            driver: Driver::new(
+                chip8,
+                PixelsVideo::default(),
+                RodioAudio::default(),
+                VecDeque::new(),
+                &driver_options,
+            ),
+            modifiers: ModifiersState::empty(),
+            fault: None,
+            title: String::new(),
+        }
+    }
+
+    /// Show the speed in the window title, or the fault while the machine is stopped by it
+    fn update_title(&mut self) {
+        let speed = self.driver.speed_status();
+        if self.driver.chip8().halted().is_none() && !speed.paused {
+            self.fault = None;
+        }
+        let title = match &self.fault {
+            Some(fault) => format!("Chip8 emulator - {fault}"),
+            None => format!("Chip8 emulator - {speed}"),
+        };
+        if self.title == title {
+            return;
+        }
+
+        if let Some(window) = &self.window {
+            window.set_title(&title);
+            self.title = title;
+        }
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+impl<'a> ApplicationHandler for App<'a> {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        info!("Window created!");
+
+        // Window creation
+        let window_attributes = WindowAttributes::default()
+            .with_title("Chip8 emulator")
+            .with_inner_size(winit::dpi::LogicalSize::new(640, 320));
+        let window = Arc::new(
+            event_loop
+                .create_window(window_attributes)
+                .expect("create application window"),
+        );
+
+        self.window_id = Some(window.id());
+        self.window = Some(window.clone());
+
+        // Rendering initialization
+        let surface_texture = SurfaceTexture::new(640, 320, window);
+        let pixels =
+            Pixels::new(640, 320, surface_texture).expect("create a surface texture to draw");
+
+        self.driver.video_mut().pixels = Some(pixels);
+        self.driver.invalidate_video();
+
+        // Audio initialization
+        let stream_handle =
+            OutputStreamBuilder::open_default_stream().expect("open default audio stream");
+        let sink = Sink::connect_new(stream_handle.mixer());
+        sink.pause();
+
+        let audio = self.driver.audio_mut();
+        audio.stream_handle = Some(stream_handle);
+        audio.sink = Some(sink);
+    }
+
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let wait = self.driver.until_next_frame();
+        event_loop.set_control_flow(ControlFlow::WaitUntil(Instant::now() + wait));
+
+        if wait.is_zero()
+            && let Some(window) = &self.window
+        {
+            window.request_redraw();
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _window_id: winit::window::WindowId,
+        event: winit::event::WindowEvent,
+    ) {
+        match event {
+            WindowEvent::CloseRequested => {
+                info!("The close button was pressed; stopping");
+                event_loop.exit();
+                self.window = None;
+                self.window_id = None;
+                self.driver.video_mut().pixels = None;
+            }
+            WindowEvent::RedrawRequested => {
+                // every frame that passed since the last redraw, up to the catch up limit
+                for _ in 0..self.driver.frames_due() {
+                    match self.driver.run_frame() {
+                        Ok(FrameStatus::Running) => {}
+                        Ok(FrameStatus::Exited) => {
+                            info!("The program exited; stopping");
+                            event_loop.exit();
+                            break;
+                        }
+                        Ok(FrameStatus::Quit) => {
+                            event_loop.exit();
+                            break;
+                        }
+                        Err(e) => {
+                            match &e {
+                                Chip8Error::Fault(fault) => {
+                                    eprint!("CHIP-8 execution error: {}", fault.report())
+                                }
+                                e => eprintln!("CHIP-8 execution error: {e}"),
+                            }
+                            self.fault = Some(e.to_string());
+                            break;
+                        }
+                    }
+                }
+                self.update_title();
+            }
+            WindowEvent::KeyboardInput {
+                device_id: _,
+                event,
+                is_synthetic: _,
+            } => {
+                trace!(format!("Detected event: {event:#?}"));
+
+                if let Some(input) = input_event(event, self.modifiers) {
+                    self.driver.input_mut().push_back(input);
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Runs the main application of the emulator
+pub fn run_app(options: &WindowOptions) -> anyhow::Result<()> {
+    let program = match &options.program {
+        Some(program) => program.clone(),
+        None => std::fs::read(&options.rom).expect("Error occured when opening rom"),
+    };
+    let mut chip8 = load_program(&program, options.platform, options.quirks)?;
+    chip8.set_fault_policy(options.fault_policy);
+    if let Some(trace) = &options.trace {
+        let tracer = trace
+            .open()
+            .with_context(|| format!("creating {}", trace.path.display()))?;
+        chip8.set_tracer(tracer);
+    }
+
+    let event_loop = EventLoop::new().unwrap();
+
+    event_loop.set_control_flow(ControlFlow::WaitUntil(Instant::now() + TIMER_INTERVAL));
+
+    let mut app = App::from_chip8(chip8, options);
+    #[cfg(feature = "gdb")]
+    if let Some(port) = options.gdb_port {
+        app.driver
+            .set_step_hook(Box::new(GdbStub::listen(port, false)?));
+    }
+    let _ = event_loop.run_app(&mut app);
+
+    Ok(())
+}
+
+/// Load the program and return a ready chip8 instance
+fn load_program(program: &[u8], platform: Platform, quirks: Quirks) -> anyhow::Result<Chip8> {
+    let mut chip8 = Chip8::with_platform(platform, quirks);
+    chip8.load_program(program)?;
+
+    Ok(chip8)
+}
+
+/// Translate a key event into a driver event
+///
+/// Backspace rewinds, Tab runs at the turbo speed while held, F1-F9 load a save state slot
+/// and Shift+F1-F9 save it, see [`speed_event`] for the other speed keys, the rest goes
+/// through [`keymap`]
+fn input_event(event: KeyEvent, modifiers: ModifiersState) -> Option<InputEvent> {
+    match event.physical_key {
+        Code(KeyCode::Backspace) => return Some(InputEvent::Rewind(event.state.is_pressed())),
+        Code(KeyCode::Tab) => return Some(InputEvent::Turbo(event.state.is_pressed())),
+        _ => {}
+    }
+
+    if let Some(speed) = speed_event(&event) {
+        return Some(speed);
+    }
+
+    if let Some(slot) = state_slot(&event) {
+        return Some(if modifiers.shift_key() {
+            InputEvent::SaveSlot(slot)
+        } else {
+            InputEvent::LoadSlot(slot)
+        });
+    }
+
+    keymap(event).map(|(key, pressed)| InputEvent::Key { key, pressed })
+}
+
+/// Map the speed keys: P pauses, N advances a frame, M toggles slow motion,
+/// `-` and `=` change the instructions per frame
+fn speed_event(event: &KeyEvent) -> Option<InputEvent> {
+    if event.state != ElementState::Pressed {
+        return None;
+    }
+
+    match event.physical_key {
+        Code(KeyCode::Minus) => Some(InputEvent::FewerInstructions),
+        Code(KeyCode::Equal) => Some(InputEvent::MoreInstructions),
+        _ if event.repeat => None,
+        Code(KeyCode::KeyP | KeyCode::Pause) => Some(InputEvent::Pause),
+        Code(KeyCode::KeyN) => Some(InputEvent::FrameAdvance),
+        Code(KeyCode::KeyM) => Some(InputEvent::SlowMotion),
+        _ => None,
+    }
+}
+
+/// Map F1-F9 presses to the save state slots 1-9
+fn state_slot(event: &KeyEvent) -> Option<u8> {
+    if event.state != ElementState::Pressed || event.repeat {
+        return None;
+    }
+
+    match event.physical_key {
+        Code(KeyCode::F1) => Some(1),
+        Code(KeyCode::F2) => Some(2),
+        Code(KeyCode::F3) => Some(3),
+        Code(KeyCode::F4) => Some(4),
+        Code(KeyCode::F5) => Some(5),
+        Code(KeyCode::F6) => Some(6),
+        Code(KeyCode::F7) => Some(7),
+        Code(KeyCode::F8) => Some(8),
+        Code(KeyCode::F9) => Some(9),
+        _ => None,
+    }
+}
+
+/// Map the real input to hex keyboard of chip8, see [`keymap::LAYOUT`]
+///
+/// If the input is not present on the keyboard, returns Option::None
+/// Also returns if the key is pressed (true) or not (false) as a second tuple argument
+fn keymap(event: KeyEvent) -> Option<(u8, bool)> {
+    let is_pressed = event.state.is_pressed();
+
+    if let Unidentified(_) = event.physical_key {
+        return None;
+    }
+
+    match event.physical_key {
+        Code(key_code) => {
+            // physical position, so the layout works on any keyboard
+            let position = match key_code {
+                KeyCode::Digit1 => '1',
+                KeyCode::Digit2 => '2',
+                KeyCode::Digit3 => '3',
+                KeyCode::Digit4 => '4',
+
+                KeyCode::KeyQ => 'q',
+                KeyCode::KeyW => 'w',
+                KeyCode::KeyE => 'e',
+                KeyCode::KeyR => 'r',
+
+                KeyCode::KeyA => 'a',
+                KeyCode::KeyS => 's',
+                KeyCode::KeyD => 'd',
+                KeyCode::KeyF => 'f',
+
+                KeyCode::KeyZ => 'z',
+                KeyCode::KeyX => 'x',
+                KeyCode::KeyC => 'c',
+                KeyCode::KeyV => 'v',
+
+                _ => return None,
+            };
+            keymap::hex_key(position).map(|k| (k, is_pressed))
+        }
+        _ => None,
+    }
+}
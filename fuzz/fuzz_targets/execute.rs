@@ -0,0 +1,63 @@
+//! Random roms through `load_program` and `step`
+//!
+//! The first byte picks the platform and the quirks, the second one which of the even keys are
+//! held, the rest is the rom. Every failure has to come back as a `Chip8Error`, a panic is a bug.
+
+#![no_main]
+
+use chip8_core::{Chip8, Platform, Quirks};
+use libfuzzer_sys::fuzz_target;
+use tklog::{LEVEL, LOG};
+
+/// Cpu cycles run per input
+const CYCLES: usize = 10_000;
+
+/// Timer ticks come once per this many cycles
+const CYCLES_PER_FRAME: usize = 8;
+
+fuzz_target!(
+    init: {
+        // the core logs every instruction, that would be most of the run time
+        #[allow(clippy::borrow_interior_mutable_const)]
+        LOG.set_console(false).set_level(LEVEL::Off);
+    },
+    |data: &[u8]| run(data)
+);
+
+/// Run the rom encoded in `data` until it fails, exits or runs out of cycles
+fn run(data: &[u8]) {
+    let [config, keys, program @ ..] = data else {
+        return;
+    };
+
+    let platform = match config & 0b11 {
+        0 => Platform::Chip8,
+        1 => Platform::SuperChip,
+        _ => Platform::XoChip,
+    };
+    let quirks = match (config >> 2) & 0b111 {
+        0 => Quirks::COSMAC_VIP,
+        1 => Quirks::CHIP_48,
+        2 => Quirks::SUPER_CHIP,
+        3 => Quirks::XO_CHIP,
+        _ => Quirks::default(),
+    };
+
+    let mut chip8 = Chip8::with_platform(platform, quirks);
+    if chip8.load_program(program).is_err() {
+        return;
+    }
+    for key in 0..8 {
+        let _ = chip8.set_key_state(key * 2, keys & (1 << key) != 0);
+    }
+
+    for cycle in 0..CYCLES {
+        if chip8.step().is_err() || chip8.has_exited() {
+            break;
+        }
+        if cycle % CYCLES_PER_FRAME == 0 {
+            chip8.tick_timers();
+        }
+        let _ = chip8.planes_snapshot();
+    }
+}
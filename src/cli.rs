@@ -0,0 +1,756 @@
+//! Command line interface of the emulator
+
+use std::{
+    ops::{Range, RangeInclusive},
+    path::PathBuf,
+};
+
+use clap::{Args, Parser, ValueEnum};
+
+#[cfg(feature = "headless")]
+use anyhow::Context;
+#[cfg(any(feature = "window", feature = "tui"))]
+use chip8_core::clock::{MAX_SPEED, MIN_SPEED};
+use chip8_core::{
+    FaultPolicy, Platform, Quirks,
+    clock::{DEFAULT_INSTRUCTIONS_PER_FRAME, Timing},
+    machine::{fault::FaultAction, quirks::MemoryIncrement},
+    symbols::SymbolMap,
+    trace::{TraceFilter, TraceFormat, TraceOptions},
+};
+#[cfg(feature = "dap")]
+use chip8_frontend::dap::DapOptions;
+#[cfg(feature = "debugger")]
+use chip8_frontend::debugger::{DebuggerOptions, parse_address};
+#[cfg(any(feature = "window", feature = "tui"))]
+use chip8_frontend::driver::{DEFAULT_SLOW_MOTION_SPEED, DEFAULT_TURBO_SPEED};
+#[cfg(feature = "headless")]
+use chip8_frontend::headless::{DumpFormat, HeadlessOptions, RunLength};
+#[cfg(feature = "tui")]
+use chip8_frontend::tui::{BuzzerStyle, Charset, TuiOptions};
+#[cfg(feature = "window")]
+use chip8_frontend::window::WindowOptions;
+
+/// Chip8 emulator
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the rom to run, Octo source (`.8o`) is compiled in memory first.
+    /// A crash dump (`.crash`) prints its fault report, or is inspected with `--debug`
+    ///
+    /// With `--assemble`, the source to assemble
+    pub rom: PathBuf,
+
+    /// Print the disassembly of the rom instead of running it, it assembles back into the same rom
+    #[arg(long)]
+    pub disasm: bool,
+
+    /// Options of the assembler
+    #[command(flatten)]
+    pub asm: AsmArgs,
+
+    /// Platform to emulate
+    #[arg(long, value_enum, default_value_t = PlatformArg::Schip)]
+    pub platform: PlatformArg,
+
+    /// Quirks preset to start from, `xochip` for the XO-CHIP platform and `default` otherwise
+    #[arg(long, value_enum)]
+    pub quirks: Option<QuirksPreset>,
+
+    /// Override: 8XY6/8XYE shift VY instead of VX
+    #[arg(long)]
+    pub shift_uses_vy: Option<bool>,
+
+    /// Override: how FX55/FX65 advance I
+    #[arg(long, value_enum)]
+    pub memory_increment: Option<MemoryIncrementArg>,
+
+    /// Override: BNNN jumps to XNN + VX
+    #[arg(long)]
+    pub jump_uses_vx: Option<bool>,
+
+    /// Override: 8XY1/8XY2/8XY3 reset VF
+    #[arg(long)]
+    pub logic_resets_vf: Option<bool>,
+
+    /// Override: sprites wrap around the screen edges instead of being clipped
+    #[arg(long)]
+    pub wrap_sprites: Option<bool>,
+
+    /// Cpu cycles run per 60 Hz frame, the timers tick once per frame
+    #[arg(long, default_value_t = DEFAULT_INSTRUCTIONS_PER_FRAME, value_parser = clap::value_parser!(u32).range(1..))]
+    pub instructions_per_frame: u32,
+
+    /// How long instructions take, `vip` ignores --instructions-per-frame
+    #[arg(long, value_enum, default_value_t = TimingArg::Fixed)]
+    pub timing: TimingArg,
+
+    /// What happens when the program faults, `wrap` only applies to out of range accesses and
+    /// halts on the other faults
+    #[arg(long, value_enum, default_value_t = FaultActionArg::Halt)]
+    pub on_fault: FaultActionArg,
+
+    /// Override: what happens on an opcode that doesn't decode
+    #[arg(long, value_enum)]
+    pub on_invalid_opcode: Option<FaultActionArg>,
+
+    /// Override: what happens on an instruction the platform lacks
+    #[arg(long, value_enum)]
+    pub on_unsupported: Option<FaultActionArg>,
+
+    /// Override: what happens on a stack overflow or underflow
+    #[arg(long, value_enum)]
+    pub on_stack_fault: Option<FaultActionArg>,
+
+    /// Override: what happens when PC, I or a memory access is out of range
+    #[arg(long, value_enum)]
+    pub on_out_of_range: Option<FaultActionArg>,
+
+    /// Frontend to run the rom in
+    #[cfg(any(feature = "window", feature = "tui"))]
+    #[arg(long, value_enum, default_value_t = FrontendArg::default())]
+    pub frontend: FrontendArg,
+
+    /// Characters the terminal frontend draws pixels with
+    #[cfg(feature = "tui")]
+    #[arg(long, value_enum, default_value_t = CharsetArg::HalfBlock)]
+    pub tui_charset: CharsetArg,
+
+    /// How the terminal frontend shows the buzzer
+    #[cfg(feature = "tui")]
+    #[arg(long, value_enum, default_value_t = BuzzerArg::Flash)]
+    pub tui_buzzer: BuzzerArg,
+
+    /// Memory for the rewind history in MiB, 0 disables rewinding
+    #[cfg(any(feature = "window", feature = "tui"))]
+    #[arg(long, default_value_t = 16)]
+    pub rewind_memory: usize,
+
+    /// Snapshots stepped back per frame while the rewind key is held
+    #[cfg(any(feature = "window", feature = "tui"))]
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rewind_speed: u32,
+
+    /// Speed while Tab is held, relative to real time
+    #[cfg(any(feature = "window", feature = "tui"))]
+    #[arg(long, default_value_t = DEFAULT_TURBO_SPEED, value_parser = parse_speed)]
+    pub turbo_speed: f64,
+
+    /// Speed in slow motion (M), relative to real time
+    #[cfg(any(feature = "window", feature = "tui"))]
+    #[arg(long, default_value_t = DEFAULT_SLOW_MOTION_SPEED, value_parser = parse_speed)]
+    pub slow_motion_speed: f64,
+
+    /// Let gdb attach on this localhost port, headless runs wait for it before starting
+    #[cfg(feature = "gdb")]
+    #[arg(long, value_name = "PORT")]
+    pub gdb: Option<u16>,
+
+    /// Options of the tracer
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    /// Options of the trace diff
+    #[command(flatten)]
+    pub diff: DiffArgs,
+
+    /// Options of the headless mode
+    #[cfg(feature = "headless")]
+    #[command(flatten)]
+    pub headless: HeadlessArgs,
+
+    /// Options of the debugger
+    #[cfg(feature = "debugger")]
+    #[command(flatten)]
+    pub debugger: DebuggerArgs,
+
+    /// Options of the DAP server
+    #[cfg(feature = "dap")]
+    #[command(flatten)]
+    pub dap: DapArgs,
+
+    /// Image compiled from the Octo source, run instead of reading the rom
+    #[arg(skip)]
+    pub program: Option<Vec<u8>>,
+
+    /// Symbols of the compiled image
+    #[arg(skip)]
+    pub symbols: Option<SymbolMap>,
+}
+
+/// Options of the assembler, all of them require `--assemble`
+#[derive(Debug, Args)]
+pub struct AsmArgs {
+    /// Assemble the source into a rom instead of running it, `.8o` files are compiled as Octo
+    #[arg(long, conflicts_with = "disasm")]
+    pub assemble: bool,
+
+    /// Path of the assembled rom, the source with the `.ch8` extension by default
+    #[arg(long, value_name = "PATH", requires = "assemble")]
+    pub output: Option<PathBuf>,
+
+    /// Also write the symbol map next to the rom, with the `.sym` extension
+    #[arg(long, requires = "assemble")]
+    pub symbol_map: bool,
+}
+
+/// Options of the tracer, all of them require `--trace`
+#[derive(Debug, Args)]
+pub struct TraceArgs {
+    /// Write every executed instruction with the registers to a file, works with every frontend
+    #[arg(long, value_name = "PATH")]
+    pub trace: Option<PathBuf>,
+
+    /// Format of the trace
+    #[arg(long, value_enum, default_value_t = TraceFormatArg::Text, requires = "trace")]
+    pub trace_format: TraceFormatArg,
+
+    /// Only trace instructions in this hex address range, e.g. `200-2FF`, can be repeated
+    #[arg(long, value_name = "START-END", value_parser = parse_address_range, requires = "trace")]
+    pub trace_pc: Vec<RangeInclusive<u16>>,
+
+    /// Only trace cycles from START up to but excluding END, e.g. `1000-2000`, can be repeated
+    #[arg(long, value_name = "START-END", value_parser = parse_cycle_range, requires = "trace")]
+    pub trace_cycles: Vec<Range<u64>>,
+}
+
+/// Options of the trace diff, comparing two runs of the rom or two traces
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// Run the rom twice in lockstep instead of playing it and report the first cycle where the machines differ
+    ///
+    /// The second run takes the `--diff-platform` and `--diff-quirks` settings
+    #[arg(long = "diff", conflicts_with = "other_trace")]
+    pub lockstep: bool,
+
+    /// Platform of the second run, the same as the first by default
+    #[arg(long, value_enum, requires = "lockstep")]
+    pub diff_platform: Option<PlatformArg>,
+
+    /// Quirks of the second run, the same as the first by default
+    #[arg(long, value_enum, requires = "lockstep")]
+    pub diff_quirks: Option<QuirksPreset>,
+
+    /// Stop comparing after this many cycles
+    #[arg(long, default_value_t = 1_000_000, requires = "lockstep")]
+    pub diff_cycles: u64,
+
+    /// Compare the trace at the rom path with this one instead of running anything
+    #[arg(long = "diff-trace", value_name = "PATH")]
+    pub other_trace: Option<PathBuf>,
+
+    /// Records shown before and after the divergence
+    #[arg(long, default_value_t = 5)]
+    pub diff_context: usize,
+}
+
+/// Options of the DAP server, all of them require `--dap`
+#[cfg(feature = "dap")]
+#[derive(Debug, Args)]
+pub struct DapArgs {
+    /// Serve the Debug Adapter Protocol on stdio for an editor, the launch request can name another rom
+    #[arg(long = "dap")]
+    pub serve: bool,
+
+    /// Serve on this localhost port instead of stdio
+    #[arg(long = "dap-port", value_name = "PORT", requires = "serve")]
+    pub port: Option<u16>,
+
+    /// Symbol map of the rom, `<rom>.sym` is used if it exists
+    #[arg(long, requires = "serve")]
+    pub symbols: Option<PathBuf>,
+}
+
+/// Options of the debugger, all of them require `--debug`
+#[cfg(feature = "debugger")]
+#[derive(Debug, Args)]
+pub struct DebuggerArgs {
+    /// Debug the rom from a command prompt on stdin instead of running it, `help` lists the commands
+    #[arg(long = "debug")]
+    pub debug: bool,
+
+    /// Hex address to break at, can be repeated
+    #[arg(long = "break", value_parser = parse_address, requires = "debug")]
+    pub breakpoints: Vec<u16>,
+}
+
+/// Options of the headless mode, all of them require `--headless`
+#[cfg(feature = "headless")]
+#[derive(Debug, Args)]
+pub struct HeadlessArgs {
+    /// Run without a window and audio on a virtual clock, then dump the machine state
+    #[arg(long = "headless")]
+    pub enabled: bool,
+
+    /// Stop after this many cpu cycles
+    #[arg(long, requires = "enabled", conflicts_with = "frames")]
+    pub cycles: Option<u64>,
+
+    /// Stop after this many 60 Hz frames
+    #[arg(long, requires = "enabled")]
+    pub frames: Option<u64>,
+
+    /// Key script, e.g. `60:+5 90:-5` presses key 5 at frame 60 and releases it at frame 90
+    #[arg(long, requires = "enabled", conflicts_with = "keys_file")]
+    pub keys: Option<String>,
+
+    /// Read the key script from a file
+    #[arg(long, requires = "enabled")]
+    pub keys_file: Option<PathBuf>,
+
+    /// Format of the framebuffer dump
+    #[arg(long, value_enum, default_value_t = DumpFormatArg::Ascii, requires = "enabled")]
+    pub dump_format: DumpFormatArg,
+
+    /// Write the framebuffer dump to a file instead of stdout
+    #[arg(long, requires = "enabled")]
+    pub dump: Option<PathBuf>,
+
+    /// Write the registers and the state hash to a file instead of stdout
+    #[arg(long, requires = "enabled")]
+    pub report: Option<PathBuf>,
+
+    /// Seed of the random number generator
+    #[arg(long, default_value_t = 0, requires = "enabled")]
+    pub seed: u64,
+}
+
+/// Interactive frontends, the window is the default when it is built
+#[cfg(any(feature = "window", feature = "tui"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FrontendArg {
+    /// winit window with audio
+    #[cfg(feature = "window")]
+    #[cfg_attr(feature = "window", default)]
+    Window,
+    /// Terminal, usable over SSH
+    #[cfg(feature = "tui")]
+    #[cfg_attr(not(feature = "window"), default)]
+    Tui,
+}
+
+/// Command line mirror of [`Charset`]
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CharsetArg {
+    /// Colored half blocks, 1x2 pixels per cell
+    HalfBlock,
+    /// Monochrome braille, 2x4 pixels per cell
+    Braille,
+}
+
+/// Command line mirror of [`BuzzerStyle`]
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BuzzerArg {
+    /// Highlight the status bar
+    Flash,
+    /// Ring the terminal bell
+    Bell,
+}
+
+/// Command line mirror of [`DumpFormat`]
+#[cfg(feature = "headless")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DumpFormatArg {
+    /// One character per pixel
+    Ascii,
+    /// Plain portable bitmap
+    Pbm,
+    /// Grayscale PNG
+    Png,
+}
+
+/// Command line mirror of [`TraceFormat`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormatArg {
+    /// One line per instruction
+    Text,
+    /// Fixed size records, see the `trace` module docs
+    Binary,
+}
+
+/// Command line mirror of [`Timing`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimingArg {
+    /// Every instruction takes the same time
+    Fixed,
+    /// COSMAC VIP instruction costs, draws wait for the next frame
+    Vip,
+}
+
+/// Command line mirror of [`FaultAction`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FaultActionArg {
+    /// Stop the machine and show the fault
+    Halt,
+    /// Skip the faulting instruction
+    Nop,
+    /// Wrap addresses around the end of memory
+    Wrap,
+    /// Stop in the attached debugger, or pause
+    Break,
+}
+
+/// Command line mirror of [`Platform`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PlatformArg {
+    /// Original CHIP-8
+    Chip8,
+    /// CHIP-8 with the SUPER-CHIP 1.1 extensions
+    Schip,
+    /// XO-CHIP
+    Xochip,
+}
+
+/// Named quirk presets selectable from the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum QuirksPreset {
+    /// Behavior of this emulator before quirks were configurable
+    Default,
+    /// COSMAC VIP
+    Vip,
+    /// CHIP-48
+    Chip48,
+    /// SUPER-CHIP 1.1
+    Schip,
+    /// XO-CHIP
+    Xochip,
+}
+
+/// Command line mirror of [`MemoryIncrement`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MemoryIncrementArg {
+    /// I is left untouched
+    None,
+    /// I is advanced by X
+    X,
+    /// I is advanced by X + 1
+    XPlusOne,
+}
+
+impl From<QuirksPreset> for Quirks {
+    fn from(preset: QuirksPreset) -> Self {
+        match preset {
+            QuirksPreset::Default => Quirks::default(),
+            QuirksPreset::Vip => Quirks::COSMAC_VIP,
+            QuirksPreset::Chip48 => Quirks::CHIP_48,
+            QuirksPreset::Schip => Quirks::SUPER_CHIP,
+            QuirksPreset::Xochip => Quirks::XO_CHIP,
+        }
+    }
+}
+
+impl From<PlatformArg> for Platform {
+    fn from(arg: PlatformArg) -> Self {
+        match arg {
+            PlatformArg::Chip8 => Platform::Chip8,
+            PlatformArg::Schip => Platform::SuperChip,
+            PlatformArg::Xochip => Platform::XoChip,
+        }
+    }
+}
+
+impl From<MemoryIncrementArg> for MemoryIncrement {
+    fn from(arg: MemoryIncrementArg) -> Self {
+        match arg {
+            MemoryIncrementArg::None => MemoryIncrement::None,
+            MemoryIncrementArg::X => MemoryIncrement::ByX,
+            MemoryIncrementArg::XPlusOne => MemoryIncrement::ByXPlusOne,
+        }
+    }
+}
+
+#[cfg(feature = "headless")]
+impl From<DumpFormatArg> for DumpFormat {
+    fn from(arg: DumpFormatArg) -> Self {
+        match arg {
+            DumpFormatArg::Ascii => DumpFormat::Ascii,
+            DumpFormatArg::Pbm => DumpFormat::Pbm,
+            DumpFormatArg::Png => DumpFormat::Png,
+        }
+    }
+}
+
+impl From<TraceFormatArg> for TraceFormat {
+    fn from(arg: TraceFormatArg) -> Self {
+        match arg {
+            TraceFormatArg::Text => TraceFormat::Text,
+            TraceFormatArg::Binary => TraceFormat::Binary,
+        }
+    }
+}
+
+impl From<FaultActionArg> for FaultAction {
+    fn from(arg: FaultActionArg) -> Self {
+        match arg {
+            FaultActionArg::Halt => FaultAction::Halt,
+            FaultActionArg::Nop => FaultAction::Nop,
+            FaultActionArg::Wrap => FaultAction::Wrap,
+            FaultActionArg::Break => FaultAction::Break,
+        }
+    }
+}
+
+impl From<TimingArg> for Timing {
+    fn from(arg: TimingArg) -> Self {
+        match arg {
+            TimingArg::Fixed => Timing::Fixed,
+            TimingArg::Vip => Timing::CosmacVip,
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+impl From<CharsetArg> for Charset {
+    fn from(arg: CharsetArg) -> Self {
+        match arg {
+            CharsetArg::HalfBlock => Charset::HalfBlock,
+            CharsetArg::Braille => Charset::Braille,
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+impl From<BuzzerArg> for BuzzerStyle {
+    fn from(arg: BuzzerArg) -> Self {
+        match arg {
+            BuzzerArg::Flash => BuzzerStyle::Flash,
+            BuzzerArg::Bell => BuzzerStyle::Bell,
+        }
+    }
+}
+
+impl Cli {
+    /// Tracer settings, `None` without `--trace`
+    pub fn trace(&self) -> Option<TraceOptions> {
+        let args = &self.trace;
+        Some(TraceOptions {
+            path: args.trace.clone()?,
+            format: args.trace_format.into(),
+            filter: TraceFilter {
+                addresses: args.trace_pc.clone(),
+                cycles: args.trace_cycles.clone(),
+            },
+        })
+    }
+
+    /// Platform and quirks of the second `--diff` run
+    pub fn diff_settings(&self) -> (Platform, Quirks) {
+        let platform = self
+            .diff
+            .diff_platform
+            .map_or(self.platform(), Platform::from);
+        let quirks = self.diff.diff_quirks.map_or(self.quirks(), Quirks::from);
+        (platform, quirks)
+    }
+
+    /// Whether the rom is a crash dump written when a machine halted
+    pub fn is_crash_dump(&self) -> bool {
+        self.rom
+            .extension()
+            .is_some_and(|extension| extension == "crash")
+    }
+
+    /// Chosen platform
+    pub fn platform(&self) -> Platform {
+        self.platform.into()
+    }
+
+    /// Quirks from the chosen preset with the per-flag overrides applied
+    pub fn quirks(&self) -> Quirks {
+        let preset = self.quirks.unwrap_or(match self.platform {
+            PlatformArg::Xochip => QuirksPreset::Xochip,
+            PlatformArg::Chip8 | PlatformArg::Schip => QuirksPreset::Default,
+        });
+        let mut quirks = Quirks::from(preset);
+
+        if let Some(value) = self.shift_uses_vy {
+            quirks.shift_uses_vy = value;
+        }
+        if let Some(value) = self.memory_increment {
+            quirks.memory_increment = value.into();
+        }
+        if let Some(value) = self.jump_uses_vx {
+            quirks.jump_uses_vx = value;
+        }
+        if let Some(value) = self.logic_resets_vf {
+            quirks.logic_resets_vf = value;
+        }
+        if let Some(value) = self.wrap_sprites {
+            quirks.wrap_sprites = value;
+        }
+
+        quirks
+    }
+
+    /// Fault policy from `--on-fault` with the per-class overrides applied
+    pub fn fault_policy(&self) -> FaultPolicy {
+        let mut policy = FaultPolicy::all(self.on_fault.into());
+
+        if let Some(action) = self.on_invalid_opcode {
+            policy.invalid_opcode = action.into();
+        }
+        if let Some(action) = self.on_unsupported {
+            policy.unsupported = action.into();
+        }
+        if let Some(action) = self.on_stack_fault {
+            policy.stack = action.into();
+        }
+        if let Some(action) = self.on_out_of_range {
+            policy.out_of_range = action.into();
+        }
+
+        policy
+    }
+
+    /// Settings for the window frontend
+    #[cfg(feature = "window")]
+    pub fn window_options(&self) -> WindowOptions {
+        WindowOptions {
+            rom: self.rom.clone(),
+            program: self.program.clone(),
+            platform: self.platform(),
+            quirks: self.quirks(),
+            fault_policy: self.fault_policy(),
+            rewind_memory: self.rewind_memory << 20,
+            rewind_speed: self.rewind_speed,
+            instructions_per_frame: self.instructions_per_frame,
+            timing: self.timing.into(),
+            turbo_speed: self.turbo_speed,
+            slow_motion_speed: self.slow_motion_speed,
+            trace: self.trace(),
+            #[cfg(feature = "gdb")]
+            gdb_port: self.gdb,
+        }
+    }
+
+    /// Settings for the terminal frontend
+    #[cfg(feature = "tui")]
+    pub fn tui_options(&self) -> TuiOptions {
+        TuiOptions {
+            rom: self.rom.clone(),
+            program: self.program.clone(),
+            platform: self.platform(),
+            quirks: self.quirks(),
+            fault_policy: self.fault_policy(),
+            rewind_memory: self.rewind_memory << 20,
+            rewind_speed: self.rewind_speed,
+            charset: self.tui_charset.into(),
+            buzzer: self.tui_buzzer.into(),
+            instructions_per_frame: self.instructions_per_frame,
+            timing: self.timing.into(),
+            turbo_speed: self.turbo_speed,
+            slow_motion_speed: self.slow_motion_speed,
+            trace: self.trace(),
+            #[cfg(feature = "gdb")]
+            gdb_port: self.gdb,
+        }
+    }
+
+    /// Settings for the debugger
+    #[cfg(feature = "debugger")]
+    pub fn debugger_options(&self) -> DebuggerOptions {
+        DebuggerOptions {
+            rom: self.rom.clone(),
+            program: self.program.clone(),
+            platform: self.platform(),
+            quirks: self.quirks(),
+            fault_policy: self.fault_policy(),
+            breakpoints: self.debugger.breakpoints.clone(),
+            crash_dump: self.is_crash_dump(),
+        }
+    }
+
+    /// Settings for the DAP server
+    #[cfg(feature = "dap")]
+    pub fn dap_options(&self) -> DapOptions {
+        DapOptions {
+            rom: self.rom.clone(),
+            program: self.program.clone(),
+            platform: self.platform(),
+            quirks: self.quirks(),
+            fault_policy: self.fault_policy(),
+            symbols: self.dap.symbols.clone(),
+            symbol_map: self.symbols.clone(),
+            port: self.dap.port,
+        }
+    }
+
+    /// Settings for the headless runner, reading the key script file if one was given
+    #[cfg(feature = "headless")]
+    pub fn headless_options(&self) -> anyhow::Result<HeadlessOptions> {
+        let args = &self.headless;
+
+        let length = match (args.cycles, args.frames) {
+            (Some(cycles), _) => RunLength::Cycles(cycles),
+            (None, Some(frames)) => RunLength::Frames(frames),
+            (None, None) => anyhow::bail!("Headless mode needs --cycles or --frames"),
+        };
+
+        let key_script = match (&args.keys, &args.keys_file) {
+            (Some(script), _) => script.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .with_context(|| format!("reading {}", path.display()))?,
+            (None, None) => String::new(),
+        };
+
+        Ok(HeadlessOptions {
+            rom: self.rom.clone(),
+            program: self.program.clone(),
+            platform: self.platform(),
+            quirks: self.quirks(),
+            fault_policy: self.fault_policy(),
+            length,
+            key_script,
+            dump_format: args.dump_format.into(),
+            dump: args.dump.clone(),
+            report: args.report.clone(),
+            seed: args.seed,
+            instructions_per_frame: self.instructions_per_frame,
+            timing: self.timing.into(),
+            trace: self.trace(),
+            #[cfg(feature = "gdb")]
+            gdb_port: self.gdb,
+        })
+    }
+}
+
+/// Split `START-END` and parse both ends with `parse`
+fn parse_range<T>(s: &str, parse: impl Fn(&str) -> Option<T>) -> Result<(T, T), String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("expected START-END, got `{s}`"))?;
+    match (parse(start), parse(end)) {
+        (Some(start), Some(end)) => Ok((start, end)),
+        _ => Err(format!("invalid range `{s}`")),
+    }
+}
+
+/// Parse an inclusive range of hex addresses, e.g. `200-2FF`
+fn parse_address_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = parse_range(s, |s| {
+        let digits = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+        u16::from_str_radix(digits, 16).ok()
+    })?;
+    Ok(start..=end)
+}
+
+/// Parse a speed relative to real time, within the range the scheduler supports
+#[cfg(any(feature = "window", feature = "tui"))]
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s.parse().map_err(|_| format!("invalid speed `{s}`"))?;
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        return Err(format!("speed must be between {MIN_SPEED} and {MAX_SPEED}"));
+    }
+    Ok(speed)
+}
+
+/// Parse a half-open range of cycles, e.g. `1000-2000`
+fn parse_cycle_range(s: &str) -> Result<Range<u64>, String> {
+    let (start, end) = parse_range(s, |s| s.parse().ok())?;
+    Ok(start..end)
+}
@@ -2,17 +2,49 @@
 
 #![warn(missing_docs)]
 #![warn(clippy::missing_docs_in_private_items)]
-#![feature(custom_test_frameworks)]
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
-pub mod decoder;
-pub mod machine;
-pub mod types;
-pub mod window;
+#[cfg(not(any(
+    feature = "window",
+    feature = "tui",
+    feature = "headless",
+    feature = "debugger",
+    feature = "dap"
+)))]
+compile_error!(
+    "Enable the `window`, `tui`, `headless`, `debugger` or `dap` feature, there is no frontend to run otherwise"
+);
 
+pub mod cli;
+
+use std::{path::Path, process::ExitCode};
+
+use anyhow::Context;
+use chip8_asm::{asm::assemble_file, disasm::Disassembly, octo::compile_file};
+use chip8_core::{
+    Chip8,
+    trace::{
+        diff::{Divergence, compare_traces, lockstep},
+        read_trace,
+    },
+};
+use clap::Parser;
 use tklog::{Format, LEVEL, LOG};
 
-use crate::window::run_app;
+#[cfg(feature = "dap")]
+use chip8_frontend::dap::run_dap;
+#[cfg(feature = "debugger")]
+use chip8_frontend::debugger::run_debugger;
+#[cfg(feature = "headless")]
+use chip8_frontend::headless::run_headless;
+#[cfg(feature = "tui")]
+use chip8_frontend::tui::run_tui;
+#[cfg(feature = "window")]
+use chip8_frontend::window::run_app;
+
+use crate::cli::Cli;
+#[cfg(any(feature = "window", feature = "tui"))]
+use crate::cli::FrontendArg;
 
 #[cfg_attr(coverage_nightly, coverage(off))]
 #[allow(clippy::borrow_interior_mutable_const)] // As per docs of tklog, this is correct
@@ -26,14 +58,231 @@ fn log_init() {
 }
 
 #[cfg_attr(coverage_nightly, coverage(off))]
-fn main() {
-    let args = std::env::args();
+/// Image of the rom, the one compiled from Octo source if there is one
+fn read_program(cli: &Cli) -> anyhow::Result<Vec<u8>> {
+    match &cli.program {
+        Some(program) => Ok(program.clone()),
+        None => std::fs::read(&cli.rom).with_context(|| format!("reading {}", cli.rom.display())),
+    }
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Print the disassembly of a rom
+fn disassemble(cli: &Cli) -> anyhow::Result<()> {
+    print!("{}", Disassembly::new(&read_program(cli)?));
+    Ok(())
+}
+
+/// Whether `path` is Octo source rather than mnemonic assembly
+fn is_octo(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "8o")
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Assemble `source` into a rom at `output`, compiling it instead if it is Octo source
+fn build(source: &Path, output: &Path, symbol_map: bool) -> anyhow::Result<()> {
+    if output == source {
+        anyhow::bail!(
+            "{} would overwrite the source, pick another path with --output",
+            output.display()
+        );
+    }
+
+    let assembly = if is_octo(source) {
+        compile_file(source)?
+    } else {
+        assemble_file(source)?
+    };
+    std::fs::write(output, assembly.rom())
+        .with_context(|| format!("writing {}", output.display()))?;
+    if symbol_map {
+        let symbols = output.with_extension("sym");
+        std::fs::write(&symbols, assembly.symbols().to_string())
+            .with_context(|| format!("writing {}", symbols.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Print the fault report of a crash dump
+fn inspect_crash_dump(dump: &Path) -> anyhow::Result<()> {
+    let bytes = std::fs::read(dump).with_context(|| format!("reading {}", dump.display()))?;
+    let mut chip8 = Chip8::new();
+    chip8
+        .load_crash_dump(&bytes)
+        .with_context(|| format!("reading {}", dump.display()))?;
+    if let Some(fault) = chip8.halted() {
+        print!("{}", fault.report());
+    }
+    Ok(())
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+/// Find the first divergence of the two `--diff` runs or of the two traces
+fn diff(cli: &Cli) -> anyhow::Result<Option<Divergence>> {
+    let context = cli.diff.diff_context;
+
+    if let Some(other) = &cli.diff.other_trace {
+        let open = |path: &Path| -> anyhow::Result<_> {
+            let file =
+                std::fs::File::open(path).with_context(|| format!("reading {}", path.display()))?;
+            read_trace(std::io::BufReader::new(file))
+                .with_context(|| format!("reading {}", path.display()))
+        };
+        return Ok(compare_traces(open(&cli.rom)?, open(other)?, context)?);
+    }
+
+    let program = read_program(cli)?;
+    let machine = |(platform, quirks)| -> anyhow::Result<Chip8> {
+        let mut chip8 = Chip8::with_platform(platform, quirks);
+        chip8.load_program(&program)?;
+        chip8.seed_random(0);
+        Ok(chip8)
+    };
+    let mut left = machine((cli.platform(), cli.quirks()))?;
+    let mut right = machine(cli.diff_settings())?;
+    Ok(lockstep(
+        &mut left,
+        &mut right,
+        cli.diff.diff_cycles,
+        context,
+    ))
+}
+
+#[cfg_attr(coverage_nightly, coverage(off))]
+fn main() -> ExitCode {
+    let mut cli = Cli::parse();
     log_init();
 
-    if args.len() != 2 {
-        eprintln!("Usage: chip8-emulator /path/to/your/rom");
-        return;
+    if cli.asm.assemble {
+        let output = cli
+            .asm
+            .output
+            .clone()
+            .unwrap_or_else(|| cli.rom.with_extension("ch8"));
+        return match build(&cli.rom, &output, cli.asm.symbol_map) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    // Octo source is compiled in memory, the rom path still places save states and crash dumps
+    if is_octo(&cli.rom) {
+        match compile_file(&cli.rom) {
+            Ok(assembly) => {
+                cli.program = Some(assembly.rom().to_vec());
+                cli.symbols = Some(assembly.symbols().clone());
+            }
+            Err(e) => {
+                eprintln!("Error: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // crash dumps are inspected rather than run, the debugger restores the machine from them
+    #[cfg(feature = "debugger")]
+    let debugging = cli.debugger.debug;
+    #[cfg(not(feature = "debugger"))]
+    let debugging = false;
+    if cli.is_crash_dump() && !debugging {
+        return match inspect_crash_dump(&cli.rom) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if cli.diff.lockstep || cli.diff.other_trace.is_some() {
+        return match diff(&cli) {
+            Ok(None) => {
+                println!("No divergence");
+                ExitCode::SUCCESS
+            }
+            Ok(Some(divergence)) => {
+                print!("{divergence}");
+                ExitCode::FAILURE
+            }
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        };
     }
 
-    run_app(args).expect("Error occured when running the application");
+    if cli.disasm {
+        return match disassemble(&cli) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    #[cfg(feature = "headless")]
+    if cli.headless.enabled {
+        return match cli
+            .headless_options()
+            .and_then(|options| run_headless(&options))
+        {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    #[cfg(feature = "dap")]
+    if cli.dap.serve {
+        return match run_dap(&cli.dap_options()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    #[cfg(feature = "debugger")]
+    if cli.debugger.debug {
+        return match run_debugger(&cli.debugger_options()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    #[cfg(any(feature = "window", feature = "tui"))]
+    match cli.frontend {
+        #[cfg(feature = "window")]
+        FrontendArg::Window => {
+            run_app(&cli.window_options()).expect("Error occured when running the application");
+            ExitCode::SUCCESS
+        }
+        #[cfg(feature = "tui")]
+        FrontendArg::Tui => match run_tui(&cli.tui_options()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                ExitCode::FAILURE
+            }
+        },
+    }
+
+    #[cfg(not(any(feature = "window", feature = "tui")))]
+    {
+        eprintln!(
+            "Built without an interactive frontend, only --headless, --debug and --dap runs are possible"
+        );
+        ExitCode::FAILURE
+    }
 }